@@ -0,0 +1,83 @@
+//! Time to first byte of a range within a single chunk, which is passed
+//! through as it arrives, compared to a range of the same length spanning
+//! two chunks, which is reassembled chunk by chunk.
+//!
+//! ```text
+//! cargo bench -p jotta-osd --bench time_to_first_byte
+//! ```
+use std::{
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::TryStreamExt;
+use jotta::{mock::MockJottacloud, range::ClosedByteRange};
+use jotta_osd::{
+    concurrency::{ConcurrencyStrategy, DownloadMode},
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+const ITERATIONS: u32 = 50;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let server = MockJottacloud::start().await;
+    let ctx = Context::initialize(server.fs(), Config::new("bench"))
+        .await
+        .unwrap();
+    let ctx = Arc::new(ctx);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &vec![1; CHUNK_SIZE * 2][..],
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let chunk = CHUNK_SIZE as u64;
+
+    for (label, range) in [
+        ("single chunk", ClosedByteRange::new(0, chunk)),
+        ("two chunks", ClosedByteRange::new(chunk / 2, chunk)),
+    ] {
+        let mut first_byte = Duration::ZERO;
+        let mut total = Duration::ZERO;
+
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            let mut stream = pin!(object::stream_range(
+                ctx.clone(),
+                bucket.clone(),
+                name.clone(),
+                range,
+                2,
+                DownloadMode::Ordered,
+            ));
+
+            stream.try_next().await.unwrap();
+            first_byte += start.elapsed();
+
+            while stream.try_next().await.unwrap().is_some() {}
+            total += start.elapsed();
+        }
+
+        println!(
+            "{label}: {:.2?} to first byte, {:.2?} in total",
+            first_byte / ITERATIONS,
+            total / ITERATIONS
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! A synchronous wrapper around [`Context`] for consumers without an
+//! async runtime, such as scripts and command line tools.
+//!
+//! Every call blocks the current thread until it's done. The calls run on
+//! a current-thread Tokio runtime owned by the wrapper, so they must not
+//! be made from within another async runtime, and the wrapper must not be
+//! dropped there either. Both panic.
+//!
+//! Requires the `blocking` feature.
+use std::{future::Future, sync::Arc};
+
+use futures_util::TryStreamExt;
+use jotta::{auth::TokenStore, range::OpenByteRange, Fs};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    concurrency::ConcurrencyStrategy,
+    object::{
+        self,
+        meta::{Meta, Patch},
+    },
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+/// A blocking [`Context`]. Each method blocks the current thread.
+#[derive(Debug)]
+pub struct BlockingContext<S: TokenStore> {
+    ctx: Arc<Context<S>>,
+    rt: Runtime,
+}
+
+impl<S: TokenStore> BlockingContext<S> {
+    /// Initialize a new context. See [`Context::initialize`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Context::initialize`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime fails to initialize.
+    pub fn initialize(fs: Fs<S>, config: Config) -> crate::Result<Self> {
+        let rt = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build runtime");
+
+        let ctx = rt.block_on(Context::initialize(fs, config))?;
+
+        Ok(Self {
+            ctx: Arc::new(ctx),
+            rt,
+        })
+    }
+
+    /// The wrapped context.
+    #[must_use]
+    pub fn context(&self) -> &Arc<Context<S>> {
+        &self.ctx
+    }
+
+    /// Run any future on the runtime of this wrapper, for the operations
+    /// that have no blocking counterpart.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.rt.block_on(future)
+    }
+
+    /// List all objects in a bucket. See [`object::list`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`object::list`].
+    pub fn list(&self, bucket: &BucketName) -> crate::Result<Vec<ObjectName>> {
+        self.block_on(object::list(&self.ctx, bucket))
+    }
+
+    /// Create an empty object. See [`object::create`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`object::create`].
+    pub fn create(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+        meta: Patch,
+    ) -> crate::Result<Meta> {
+        self.block_on(object::create(&self.ctx, bucket, name, meta))
+    }
+
+    /// Create or replace an object with `data` as its contents. See
+    /// [`object::create_or_replace`] and [`object::upload_range`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`object::create_or_replace`] and [`object::upload_range`].
+    pub fn upload(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+        meta: Patch,
+        data: &[u8],
+        concurrency: ConcurrencyStrategy,
+    ) -> crate::Result<Meta> {
+        self.block_on(async {
+            object::create_or_replace(&self.ctx, bucket, name, meta, false).await?;
+            object::upload_range(&self.ctx, bucket, name, 0, data, concurrency, None).await
+        })
+    }
+
+    /// Download a whole object into memory. See [`object::stream_object`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`object::stream_object`], as well as any error during
+    /// the download.
+    pub fn download(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+        num_connections: usize,
+    ) -> crate::Result<(Meta, Vec<u8>)> {
+        self.block_on(async {
+            let (meta, stream) = object::stream_object(
+                Arc::clone(&self.ctx),
+                bucket.clone(),
+                name.clone(),
+                OpenByteRange::full(),
+                num_connections,
+            )
+            .await?;
+
+            let data = stream
+                .try_fold(Vec::new(), |mut data, bytes| async move {
+                    data.extend_from_slice(&bytes);
+                    Ok(data)
+                })
+                .await?;
+
+            Ok((meta, data))
+        })
+    }
+
+    /// Delete an object. See [`object::delete`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`object::delete`].
+    pub fn delete(&self, bucket: &BucketName, name: &ObjectName) -> crate::Result<()> {
+        self.block_on(object::delete(&self.ctx, bucket, name))
+    }
+}
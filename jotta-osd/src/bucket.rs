@@ -1,17 +1,42 @@
 //! A bucket contains one or more objects.
+//!
+//! Bucket attributes are stored in a small [`BucketMeta`] file inside the
+//! bucket folder. Buckets created before it existed have no such file and
+//! get the default attributes.
 use std::fmt::Debug;
 
-use crate::{path::BucketName, Context};
+use crate::{
+    errors::Error,
+    object::{
+        self,
+        meta::{encode, CacheControl, ContentType},
+    },
+    path::BucketName,
+    serde::{NullAsDefault, Timestamp},
+    Context,
+};
 
-use jotta::{auth::TokenStore, jfs::Folder, path::UserScopedPath};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use jotta::{auth::TokenStore, files::ConflictHandler, jfs::Folder, range::OpenByteRange};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, instrument};
+use serde_with::{serde_as, DisplayFromStr};
+use time::OffsetDateTime;
+use tracing::{debug, error, instrument, warn};
+
+/// Name of the file containing the [`BucketMeta`] of a bucket.
+pub(crate) const BUCKET_META: &str = ".bucket-meta";
+
+/// Number of bucket attribute files fetched at once when listing.
+const META_CONCURRENCY: usize = 10;
 
 /// A bucket contains one or more objects.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Bucket {
     /// Name of the bucket.
     pub name: BucketName,
+    /// Bucket attributes.
+    #[serde(flatten)]
+    pub meta: BucketMeta,
 }
 
 impl<F: Into<Folder>> From<F> for Bucket {
@@ -20,84 +45,377 @@ impl<F: Into<Folder>> From<F> for Bucket {
 
         Self {
             name: BucketName(f.name),
+            meta: BucketMeta::default(),
         }
     }
 }
 
-/// List all buckets.
+/// Attributes of a bucket.
 ///
-/// # Errors
-///
-/// Errors if something goes wrong with the underlying Jotta Filesystem.
-#[instrument(skip(ctx))]
-pub async fn list(ctx: &Context<impl TokenStore>) -> crate::Result<Vec<Bucket>> {
-    let index = ctx
+/// Like object metadata, it's encoded as an array, so new fields must be
+/// appended and have a default.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct BucketMeta {
+    /// Creation timestamp. Unknown for buckets created before bucket
+    /// attributes were stored.
+    #[serde_as(as = "Option<Timestamp>")]
+    #[serde(default)]
+    pub created: Option<OffsetDateTime>,
+    /// Content type for new objects in the bucket that don't specify one.
+    #[serde(default)]
+    pub default_content_type: Option<ContentType>,
+    /// Cache control for new objects in the bucket that don't specify one.
+    #[serde(default)]
+    pub default_cache_control: Option<CacheControl>,
+    /// Whether old revisions of objects should be kept. If set, replacing
+    /// an object never purges it (see
+    /// [`create_or_replace`](crate::object::create_or_replace)).
+    #[serde(default)]
+    pub versioning: bool,
+}
+
+impl BucketMeta {
+    /// Patch the attributes.
+    pub fn patch(&mut self, patch: BucketPatch) {
+        let BucketPatch {
+            default_content_type,
+            default_cache_control,
+            versioning,
+        } = patch;
+
+        if let Some(default_content_type) = default_content_type {
+            self.default_content_type = default_content_type;
+        }
+
+        if let Some(default_cache_control) = default_cache_control {
+            self.default_cache_control = default_cache_control;
+        }
+
+        if let Some(versioning) = versioning {
+            self.versioning = versioning;
+        }
+    }
+}
+
+/// A bucket attribute patch. Like [`Patch`](crate::object::meta::Patch),
+/// `null` resets a field while absent fields are left untouched.
+#[serde_as]
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BucketPatch {
+    /// Content type for new objects in the bucket.
+    #[serde_as(as = "NullAsDefault<Option<ContentType>, Option<DisplayFromStr>>")]
+    #[serde(default)]
+    pub default_content_type: Option<Option<ContentType>>,
+    /// Cache control for new objects in the bucket.
+    #[serde_as(as = "NullAsDefault<Option<CacheControl>, Option<DisplayFromStr>>")]
+    #[serde(default)]
+    pub default_cache_control: Option<Option<CacheControl>>,
+    /// Whether old revisions of objects should be kept.
+    #[serde_as(as = "NullAsDefault<bool>")]
+    #[serde(default)]
+    pub versioning: Option<bool>,
+}
+
+impl BucketPatch {
+    /// Is the patch empty?
+    ///
+    /// ```
+    /// use jotta_osd::bucket::BucketPatch;
+    ///
+    /// assert!(BucketPatch::default().is_empty());
+    /// assert!(!BucketPatch { versioning: Some(true), ..Default::default() }.is_empty());
+    /// ```
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Read the attributes of a bucket, falling back to the defaults if
+/// there are none.
+pub(crate) async fn get_meta(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+) -> crate::Result<BucketMeta> {
+    let msg = match ctx
         .fs
-        .index(&UserScopedPath(ctx.user_scoped_root()))
+        .file_to_bytes(&ctx.bucket_meta_path(bucket), OpenByteRange::full())
+        .await
+    {
+        Ok(msg) => msg,
+        Err(jotta::Error::NoSuchFileOrFolder) => return Ok(BucketMeta::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let meta = rmp_serde::from_slice(&msg).map_err(|e| {
+        error!("parse bucket metadata failed: {}", e);
+        e
+    })?;
+
+    Ok(meta)
+}
+
+/// Write the attributes of a bucket.
+async fn set_meta(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    meta: &BucketMeta,
+    conflict_handler: ConflictHandler,
+) -> crate::Result<()> {
+    let body = encode(meta, ctx.config.max_meta_size)?;
+
+    ctx.fs
+        .upload_bytes(
+            &ctx.bucket_meta_path_on_device(bucket),
+            body.into(),
+            conflict_handler,
+        )
         .await?;
 
+    Ok(())
+}
+
+/// Names of all buckets in the root.
+async fn names(ctx: &Context<impl TokenStore>) -> crate::Result<Vec<BucketName>> {
+    let index = match ctx.fs.index(&ctx.root_path()).await {
+        Ok(index) => index,
+        // roots from `Context::with_root` are created on the first write
+        Err(jotta::Error::NoSuchFileOrFolder) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
     let folders = index.folders.inner;
 
     debug!("listed {} folders", folders.len());
 
-    let buckets = folders
+    Ok(folders
         .into_iter()
         .filter(|f| !f.is_deleted())
-        .map(Into::into)
+        .filter_map(|f| f.name.parse::<BucketName>().ok()) // skip internal folders
+        .collect())
+}
+
+/// List all buckets.
+///
+/// # Errors
+///
+/// Errors if something goes wrong with the underlying Jotta Filesystem.
+#[instrument(skip(ctx))]
+pub async fn list(ctx: &Context<impl TokenStore>) -> crate::Result<Vec<Bucket>> {
+    let names = names(ctx).await?;
+
+    stream::iter(names)
+        .map(|name| async move {
+            let meta = get_meta(ctx, &name).await?;
+
+            Ok(Bucket { name, meta })
+        })
+        .buffered(META_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Object count and, if requested, total size of a bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BucketStats {
+    /// Name of the bucket.
+    pub name: BucketName,
+    /// Number of objects, counted like [`object::list`] does.
+    pub object_count: usize,
+    /// Sum of the sizes of all objects, or `None` if not requested.
+    pub total_size: Option<u64>,
+}
+
+/// List all buckets with the number of objects in each, indexing up to
+/// `concurrency` buckets at a time.
+///
+/// Counting objects costs a single request per bucket. Summing their
+/// sizes (`sizes`) requires the metadata of every object in every
+/// bucket, i.e. one more request per object unless it's cached, so it's
+/// only worth it for small stores or occasional reports. At most
+/// `concurrency` metadata files are fetched at once. Objects that
+/// disappear or have no metadata yet are left out of the sum.
+///
+/// # Errors
+///
+/// - [`Error::InvalidInput`] if `concurrency` is zero
+/// - errors listing the buckets or their objects
+/// - errors reading metadata, if `sizes` is set
+#[instrument(skip(ctx))]
+pub async fn list_with_stats(
+    ctx: &Context<impl TokenStore>,
+    concurrency: usize,
+    sizes: bool,
+) -> crate::Result<Vec<BucketStats>> {
+    object::check_connections(concurrency)?;
+
+    let buckets = stream::iter(names(ctx).await?)
+        .map(|name| async move {
+            let objects = object::list(ctx, &name).await?;
+
+            Ok::<_, Error>((name, objects))
+        })
+        .buffered(concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut stats = buckets
+        .iter()
+        .map(|(name, objects)| BucketStats {
+            name: name.clone(),
+            object_count: objects.len(),
+            total_size: sizes.then_some(0),
+        })
         .collect::<Vec<_>>();
 
-    Ok(buckets)
+    if sizes {
+        let mut metas = stream::iter(buckets.iter().enumerate().flat_map(
+            |(i, (bucket, objects))| objects.iter().map(move |object| (i, bucket, object)),
+        ))
+        .map(|(i, bucket, object)| async move {
+            match object::meta::get(ctx, bucket, object).await {
+                Ok(meta) => Ok((i, meta.size)),
+                Err(Error::Fs(jotta::Error::NoSuchFileOrFolder)) => Ok((i, 0)),
+                Err(e) => Err(e),
+            }
+        })
+        .buffer_unordered(concurrency);
+
+        while let Some((i, size)) = metas.try_next().await? {
+            if let Some(total) = &mut stats[i].total_size {
+                *total += size;
+            }
+        }
+    }
+
+    Ok(stats)
 }
 
 /// Create a new bucket.
 ///
 /// # Errors
 ///
-/// Your usual Jottacloud errors may happen, though.
-#[instrument(skip(ctx))]
+/// - [`jotta::Error::AlreadyExists`] if the bucket already exists, in
+///   which case its attributes are left untouched
+/// - your usual Jottacloud errors
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
 pub async fn create(ctx: &Context<impl TokenStore>, bucket: &BucketName) -> crate::Result<Bucket> {
-    let folder = ctx
-        .fs
-        .create_folder(&UserScopedPath(format!(
-            "{}/{}",
-            ctx.user_scoped_root(),
-            bucket
-        )))
-        .await?;
+    ctx.fs.create_new_folder(&ctx.bucket_path(bucket)).await?;
 
-    Ok(folder.into())
+    let meta = BucketMeta {
+        created: Some(OffsetDateTime::now_utc()),
+        ..Default::default()
+    };
+
+    set_meta(ctx, bucket, &meta, ConflictHandler::RejectConflicts).await?;
+
+    Ok(Bucket {
+        name: bucket.clone(),
+        meta,
+    })
 }
 
 /// Get details about a bucket by name.
-#[instrument(skip(ctx))]
+///
+/// # Errors
+///
+/// Errors if there is no such bucket or if its attributes can't be read.
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
 pub async fn get(ctx: &Context<impl TokenStore>, bucket: &BucketName) -> crate::Result<Bucket> {
-    let folder = ctx
-        .fs
-        .index(&UserScopedPath(format!(
-            "{}/{}",
-            ctx.user_scoped_root(),
-            bucket,
-        )))
+    // make sure that the bucket exists, even if it has no attributes
+    ctx.fs.index(&ctx.bucket_path(bucket)).await?;
+
+    let meta = get_meta(ctx, bucket).await?;
+
+    Ok(Bucket {
+        name: bucket.clone(),
+        meta,
+    })
+}
+
+/// Patch the attributes of a bucket. If the patch is empty, no patch
+/// is made.
+///
+/// # Errors
+///
+/// Errors if there is no such bucket or if the attributes can't be
+/// read or written.
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
+pub async fn patch(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    patch: BucketPatch,
+) -> crate::Result<Bucket> {
+    let mut bucket = get(ctx, bucket).await?;
+
+    if !patch.is_empty() {
+        bucket.meta.patch(patch);
+
+        set_meta(
+            ctx,
+            &bucket.name,
+            &bucket.meta,
+            ConflictHandler::CreateNewRevision,
+        )
         .await?;
+    }
 
-    Ok(folder.into())
+    Ok(bucket)
 }
 
-/// Delete a bucket.
+/// Remove the folders of objects in a bucket that have no metadata,
+/// usually left behind by interrupted uploads. Use [`object::gc`] to
+/// clean up individual objects.
+///
+/// Like [`object::gc`], this must not run concurrently with writes to
+/// the bucket.
 ///
 /// # Errors
 ///
-/// Your usual Jottacloud errors.
-#[instrument(skip(ctx))]
+/// Errors if there is no such bucket, and the usual Jottacloud errors.
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
+pub async fn gc(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+) -> crate::Result<object::GcReport> {
+    object::gc::gc_bucket(ctx, bucket).await
+}
+
+/// Delete a bucket and all of its objects.
+///
+/// Since the bucket folder is removed permanently, it's indexed first to
+/// make sure that its name is exactly that of the bucket. Otherwise
+/// nothing is removed.
+///
+/// # Errors
+///
+/// - [`jotta::Error::NoSuchFileOrFolder`] if there is no such bucket
+/// - your usual Jottacloud errors
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
 pub async fn delete(ctx: &Context<impl TokenStore>, bucket: &BucketName) -> crate::Result<()> {
-    let _res = ctx
-        .fs
-        .remove_folder(&UserScopedPath(format!(
-            "{}/{}",
-            ctx.user_scoped_root(),
-            bucket
-        )))
-        .await?;
+    let path = ctx.bucket_path(bucket);
+
+    let res = match ctx.fs.index(&path).await {
+        Ok(folder) if folder.name == bucket.to_string() => {
+            ctx.fs.remove_folder(&path).await.map(drop)
+        }
+        Ok(folder) => {
+            warn!(
+                folder = %folder.name,
+                "refusing to delete a folder that isn't the bucket"
+            );
+            Err(jotta::Error::NoSuchFileOrFolder)
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Some(cache) = &ctx.meta_cache {
+        cache.invalidate_bucket(bucket);
+    }
+
+    res?;
 
     Ok(())
 }
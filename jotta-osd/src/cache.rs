@@ -0,0 +1,264 @@
+//! Caching of object metadata.
+//!
+//! Metadata is read on almost every operation, and listing a bucket
+//! followed by a stat of every object would otherwise download every
+//! `meta` file again. The cache is opt-in (see [`Config::meta_cache`](crate::Config::meta_cache))
+//! since other clients writing to the same root make it stale. Feed
+//! websocket events to [`Context::handle_event`](crate::Context::handle_event),
+//! or use [`Context::watch`](crate::Context::watch), to invalidate entries
+//! changed by someone else.
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+use crate::{
+    object::meta::Meta,
+    path::{BucketName, ObjectName},
+};
+
+/// Default value of [`MetaCacheConfig::ttl`].
+pub const DEFAULT_META_CACHE_TTL: Duration = Duration::from_mins(1);
+
+/// Configuration of the metadata cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetaCacheConfig {
+    /// Maximum number of cached entries. The least recently used entry is
+    /// evicted when the cache is full.
+    pub capacity: NonZeroUsize,
+    /// How long an entry is valid.
+    pub ttl: Duration,
+}
+
+impl MetaCacheConfig {
+    /// Cache up to `capacity` entries for [`DEFAULT_META_CACHE_TTL`].
+    #[must_use]
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            capacity,
+            ttl: DEFAULT_META_CACHE_TTL,
+        }
+    }
+}
+
+type Key = (BucketName, ObjectName);
+
+/// A least recently used cache of object metadata with a time to live.
+#[derive(Debug)]
+pub(crate) struct MetaCache {
+    entries: Mutex<LruCache<Key, (Instant, Meta)>>,
+    ttl: Duration,
+    /// Bumped on every invalidation, so that metadata read before it
+    /// isn't inserted after it.
+    generation: AtomicU64,
+}
+
+impl MetaCache {
+    pub(crate) fn new(config: MetaCacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(config.capacity)),
+            ttl: config.ttl,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, LruCache<Key, (Instant, Meta)>> {
+        // the cache is always consistent, even if a thread panicked
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    pub(crate) fn get(&self, bucket: &BucketName, name: &ObjectName) -> Option<Meta> {
+        self.get_at(bucket, name, Instant::now())
+    }
+
+    fn get_at(&self, bucket: &BucketName, name: &ObjectName, now: Instant) -> Option<Meta> {
+        let mut entries = self.entries();
+        let key = (bucket.clone(), name.clone());
+
+        match entries.get(&key) {
+            Some((inserted, meta)) if now.duration_since(*inserted) < self.ttl => {
+                Some(meta.clone())
+            }
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// The current generation. Read it before fetching metadata and pass
+    /// it to [`MetaCache::insert`].
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Insert metadata read during `generation`. It's dropped if anything
+    /// has been invalidated since, since it may be stale then.
+    pub(crate) fn insert(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+        meta: Meta,
+        generation: u64,
+    ) {
+        self.insert_at(bucket, name, meta, generation, Instant::now());
+    }
+
+    fn insert_at(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+        meta: Meta,
+        generation: u64,
+        now: Instant,
+    ) {
+        let mut entries = self.entries();
+
+        // checked with the lock held, since invalidations hold it too
+        if self.generation() == generation {
+            entries.put((bucket.clone(), name.clone()), (now, meta));
+        }
+    }
+
+    /// Remove entries while holding the lock, and start a new generation.
+    fn remove(&self, f: impl FnOnce(&mut LruCache<Key, (Instant, Meta)>)) {
+        let mut entries = self.entries();
+        f(&mut entries);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn invalidate(&self, bucket: &BucketName, name: &ObjectName) {
+        self.remove(|entries| {
+            entries.pop(&(bucket.clone(), name.clone()));
+        });
+    }
+
+    pub(crate) fn invalidate_bucket(&self, bucket: &BucketName) {
+        self.remove(|entries| {
+            let keys = entries
+                .iter()
+                .filter(|((b, _), _)| b == bucket)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+
+            for key in keys {
+                entries.pop(&key);
+            }
+        });
+    }
+
+    pub(crate) fn clear(&self) {
+        self.remove(LruCache::clear);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        time::{Duration, Instant},
+    };
+
+    use super::{MetaCache, MetaCacheConfig};
+    use crate::{
+        object::meta::Meta,
+        path::{BucketName, ObjectName},
+    };
+
+    fn meta(size: u64) -> Meta {
+        Meta {
+            size,
+            ..Meta::default()
+        }
+    }
+
+    fn cache(capacity: usize) -> MetaCache {
+        MetaCache::new(MetaCacheConfig {
+            capacity: NonZeroUsize::new(capacity).unwrap(),
+            ttl: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = cache(10);
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let name: ObjectName = "a".parse().unwrap();
+        let now = Instant::now();
+
+        cache.insert_at(&bucket, &name, meta(1), 0, now);
+
+        assert_eq!(cache.get_at(&bucket, &name, now).unwrap().size, 1);
+        assert!(cache
+            .get_at(&bucket, &name, now + Duration::from_secs(10))
+            .is_none());
+        // expired entries are evicted
+        assert!(cache.get_at(&bucket, &name, now).is_none());
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let cache = cache(2);
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let [a, b, c]: [ObjectName; 3] = ["a", "b", "c"].map(|n| n.parse().unwrap());
+
+        cache.insert(&bucket, &a, meta(1), 0);
+        cache.insert(&bucket, &b, meta(2), 0);
+        assert!(cache.get(&bucket, &a).is_some());
+        cache.insert(&bucket, &c, meta(3), 0);
+
+        assert!(cache.get(&bucket, &a).is_some());
+        assert!(cache.get(&bucket, &b).is_none());
+        assert!(cache.get(&bucket, &c).is_some());
+    }
+
+    #[test]
+    fn invalidation() {
+        let cache = cache(10);
+        let [x, y]: [BucketName; 2] = ["xxx", "yyy"].map(|b| b.parse().unwrap());
+        let [a, b]: [ObjectName; 2] = ["a", "b"].map(|n| n.parse().unwrap());
+
+        for bucket in [&x, &y] {
+            for name in [&a, &b] {
+                cache.insert(bucket, name, meta(0), 0);
+            }
+        }
+
+        cache.invalidate(&x, &a);
+        assert!(cache.get(&x, &a).is_none());
+        assert!(cache.get(&x, &b).is_some());
+
+        cache.invalidate_bucket(&y);
+        assert!(cache.get(&y, &a).is_none());
+        assert!(cache.get(&y, &b).is_none());
+        assert!(cache.get(&x, &b).is_some());
+
+        cache.clear();
+        assert!(cache.get(&x, &b).is_none());
+    }
+
+    #[test]
+    fn stale_insert() {
+        let cache = cache(10);
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let name: ObjectName = "a".parse().unwrap();
+
+        // read before a write, inserted after it
+        let generation = cache.generation();
+        cache.invalidate(&bucket, &name);
+        cache.insert(&bucket, &name, meta(1), generation);
+        assert!(cache.get(&bucket, &name).is_none());
+
+        cache.insert(&bucket, &name, meta(2), cache.generation());
+        assert_eq!(cache.get(&bucket, &name).unwrap().size, 2);
+    }
+}
@@ -0,0 +1,455 @@
+//! How many chunks to transfer at once.
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use futures_util::{
+    stream::{self, FuturesUnordered},
+    FutureExt, Stream, StreamExt,
+};
+use tokio::sync::Semaphore;
+
+use crate::errors::Error;
+
+/// How many chunks to transfer at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyStrategy {
+    /// Always use the same number of connections.
+    Fixed(usize),
+    /// Start with `min` connections and adjust the number of connections
+    /// between `min` and `max` depending on the measured throughput. This
+    /// helps on fast links without flooding slow or lossy ones.
+    Adaptive {
+        /// Minimum (and initial) number of connections.
+        min: usize,
+        /// Maximum number of connections.
+        max: usize,
+    },
+}
+
+impl From<usize> for ConcurrencyStrategy {
+    fn from(n: usize) -> Self {
+        Self::Fixed(n)
+    }
+}
+
+impl ConcurrencyStrategy {
+    /// Make sure that at least one connection is allowed, since nothing
+    /// would ever be transferred otherwise.
+    pub(crate) fn check(self) -> crate::Result<()> {
+        match self {
+            Self::Fixed(0) | Self::Adaptive { min: 0, .. } => Err(Error::InvalidInput(
+                "the number of connections must be at least 1".into(),
+            )),
+            Self::Adaptive { min, max } if min > max => Err(Error::InvalidInput(format!(
+                "the minimum number of connections ({min}) exceeds the maximum ({max})"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Largest number of connections that may be used.
+    pub(crate) fn max(self) -> usize {
+        match self {
+            Self::Fixed(n) | Self::Adaptive { max: n, .. } => n,
+        }
+    }
+
+    fn min(self) -> usize {
+        match self {
+            Self::Fixed(n) | Self::Adaptive { min: n, .. } => n,
+        }
+    }
+}
+
+/// Hill climbing on throughput. After every window of transfers, the
+/// limit is moved one step in the current direction if the throughput
+/// improved, and in the other direction otherwise.
+#[derive(Debug)]
+struct Controller {
+    strategy: ConcurrencyStrategy,
+    limit: usize,
+    increasing: bool,
+    window_start: Instant,
+    window_bytes: u64,
+    window_transfers: usize,
+    last_rate: f64,
+}
+
+impl Controller {
+    fn new(strategy: ConcurrencyStrategy, now: Instant) -> Self {
+        Self {
+            strategy,
+            limit: strategy.min(),
+            increasing: true,
+            window_start: now,
+            window_bytes: 0,
+            window_transfers: 0,
+            last_rate: 0.0,
+        }
+    }
+
+    /// Record a finished transfer and return the new limit.
+    fn record(&mut self, bytes: u64, now: Instant) -> usize {
+        if let ConcurrencyStrategy::Fixed(_) = self.strategy {
+            return self.limit;
+        }
+
+        self.window_bytes += bytes;
+        self.window_transfers += 1;
+
+        if self.window_transfers < self.limit {
+            return self.limit;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let rate = self.window_bytes as f64 / (now - self.window_start).as_secs_f64();
+
+        if rate < self.last_rate {
+            self.increasing = !self.increasing;
+        }
+
+        self.limit = if self.increasing {
+            self.limit + 1
+        } else {
+            self.limit.saturating_sub(1)
+        }
+        .clamp(self.strategy.min(), self.strategy.max());
+
+        self.last_rate = rate;
+        self.window_start = now;
+        self.window_bytes = 0;
+        self.window_transfers = 0;
+
+        self.limit
+    }
+}
+
+/// Limits the number of concurrent transfers according to a
+/// [`ConcurrencyStrategy`].
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    semaphore: Arc<Semaphore>,
+    state: Arc<Mutex<(Controller, usize)>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(strategy: ConcurrencyStrategy) -> Self {
+        let controller = Controller::new(strategy, Instant::now());
+        let limit = controller.limit;
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            state: Arc::new(Mutex::new((controller, limit))),
+        }
+    }
+
+    /// Run a transfer of `bytes` bytes once there is room for it.
+    pub(crate) async fn run<T>(&self, bytes: u64, fut: impl std::future::Future<Output = T>) -> T {
+        let permit = self.semaphore.acquire().await.unwrap();
+
+        let res = fut.await;
+
+        let mut state = self.state.lock().unwrap();
+        let (controller, permits) = &mut *state;
+        let limit = controller.record(bytes, Instant::now());
+
+        if limit > *permits {
+            self.semaphore.add_permits(limit - *permits);
+            *permits = limit;
+        }
+
+        if limit < *permits {
+            permit.forget();
+            *permits -= 1;
+        }
+
+        res
+    }
+}
+
+/// How downloaded chunks are reassembled. Bytes are always emitted in
+/// order; the modes only differ in what happens while the chunk at the
+/// head of the stream is slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadMode {
+    /// Never fetch more than `num_connections` chunks ahead, so a slow
+    /// chunk blocks every connection until it finishes.
+    #[default]
+    Ordered,
+    /// Keep fetching chunks while the head is slow, buffering up to `max`
+    /// chunks (including the head) until they can be emitted. Memory use
+    /// is bounded by `max` chunks. Useful on high-latency links.
+    ///
+    /// A `max` smaller than the number of connections caps how far ahead
+    /// of a slow consumer chunks are prefetched, and thereby memory use,
+    /// regardless of the number of connections.
+    Windowed {
+        /// Maximum number of chunks buffered or in flight at once.
+        max: usize,
+    },
+}
+
+impl DownloadMode {
+    /// A window of zero chunks would never make any progress.
+    pub(crate) fn check(self) -> crate::Result<()> {
+        match self {
+            Self::Windowed { max: 0 } => Err(Error::InvalidInput(
+                "the reorder window must be at least 1 chunk".into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Maximum distance between the head of the stream and the last
+    /// chunk being fetched.
+    fn window(self, num_connections: usize) -> usize {
+        match self {
+            Self::Ordered => num_connections,
+            Self::Windowed { max } => max,
+        }
+    }
+}
+
+/// Run `futures` with at most `num_connections` in flight, emitting their
+/// outputs in order. Outputs that arrive early are kept in a reorder
+/// buffer bounded by [`DownloadMode`]. The stream ends after the first
+/// error.
+pub(crate) fn reassemble<F, T, E>(
+    futures: impl IntoIterator<Item = F>,
+    num_connections: usize,
+    mode: DownloadMode,
+) -> impl Stream<Item = Result<T, E>>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let window = mode.window(num_connections);
+    let state = (
+        futures.into_iter(),
+        FuturesUnordered::new(),
+        BTreeMap::new(),
+        0usize, // index of the next output to emit
+        0usize, // index of the next future to start
+        false,  // whether an error has been emitted
+    );
+
+    stream::unfold(
+        state,
+        // boxed to keep the stream `Unpin`, like `buffered` is
+        move |(mut pending, mut in_flight, mut done, mut next, mut started, failed)| {
+            Box::pin(async move {
+                if failed {
+                    return None;
+                }
+
+                loop {
+                    if let Some(output) = done.remove(&next) {
+                        next += 1;
+                        return Some((
+                            Ok(output),
+                            (pending, in_flight, done, next, started, false),
+                        ));
+                    }
+
+                    while in_flight.len() < num_connections && started < next + window {
+                        let Some(fut) = pending.next() else {
+                            break;
+                        };
+                        let index = started;
+                        in_flight.push(fut.map(move |res: Result<T, E>| (index, res)));
+                        started += 1;
+                    }
+
+                    match in_flight.next().await {
+                        Some((index, Ok(output))) => {
+                            done.insert(index, output);
+                        }
+                        Some((_, Err(e))) => {
+                            return Some((Err(e), (pending, in_flight, done, next, started, true)));
+                        }
+                        None => return None,
+                    }
+                }
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use futures_util::{StreamExt, TryStreamExt};
+    use rand::{thread_rng, Rng};
+
+    use super::{reassemble, ConcurrencyStrategy, Controller, DownloadMode};
+
+    #[test]
+    fn check_strategy() {
+        assert!(ConcurrencyStrategy::Fixed(1).check().is_ok());
+        assert!(ConcurrencyStrategy::Fixed(0).check().is_err());
+        assert!(ConcurrencyStrategy::Adaptive { min: 0, max: 4 }
+            .check()
+            .is_err());
+        assert!(ConcurrencyStrategy::Adaptive { min: 5, max: 4 }
+            .check()
+            .is_err());
+        assert!(ConcurrencyStrategy::Adaptive { min: 1, max: 4 }
+            .check()
+            .is_ok());
+    }
+
+    #[test]
+    fn fixed_concurrency() {
+        let now = Instant::now();
+        let mut controller = Controller::new(ConcurrencyStrategy::Fixed(3), now);
+
+        for i in 1..10 {
+            assert_eq!(controller.record(1000, now + Duration::from_millis(i)), 3);
+        }
+    }
+
+    /// Simulate a link that is saturated by `best` connections.
+    fn simulate(strategy: ConcurrencyStrategy, best: usize, rounds: usize) -> Vec<usize> {
+        let mut now = Instant::now();
+        let mut controller = Controller::new(strategy, now);
+        let mut limits = Vec::new();
+
+        for _ in 0..rounds {
+            let limit = controller.limit;
+            // more connections than the link can handle only add overhead
+            let rate = if limit <= best {
+                limit as u64
+            } else {
+                best as u64 - (limit - best) as u64
+            };
+
+            for _ in 0..limit {
+                now += Duration::from_millis(1000 / rate);
+                controller.record(1000, now);
+            }
+
+            limits.push(controller.limit);
+        }
+
+        limits
+    }
+
+    #[test]
+    fn adaptive_concurrency() {
+        let limits = simulate(ConcurrencyStrategy::Adaptive { min: 1, max: 8 }, 4, 20);
+
+        // ramps up from the minimum ...
+        assert_eq!(&limits[..3], [2, 3, 4]);
+        // ... and then hovers around the best number of connections
+        assert!(
+            limits[5..].iter().all(|&l| (3..=5).contains(&l)),
+            "{limits:?}"
+        );
+    }
+
+    #[test]
+    fn adaptive_bounds() {
+        let limits = simulate(ConcurrencyStrategy::Adaptive { min: 2, max: 4 }, 100, 10);
+
+        assert_eq!(limits[0], 3);
+        assert!(limits.iter().all(|&l| (2..=4).contains(&l)));
+        assert_eq!(limits.last(), Some(&4));
+    }
+
+    #[test]
+    fn check_download_mode() {
+        assert!(DownloadMode::Ordered.check().is_ok());
+        assert!(DownloadMode::Windowed { max: 0 }.check().is_err());
+        assert!(DownloadMode::Windowed { max: 1 }.check().is_ok());
+    }
+
+    #[tokio::test]
+    async fn reassembly_is_ordered() {
+        for mode in [
+            DownloadMode::Ordered,
+            DownloadMode::Windowed { max: 1 },
+            DownloadMode::Windowed { max: 8 },
+        ] {
+            let emitted = Arc::new(AtomicUsize::new(0));
+            let ahead = Arc::new(AtomicUsize::new(0));
+
+            let futures = (0..50).map(|i| {
+                let delay = Duration::from_millis(thread_rng().gen_range(0..5));
+                let emitted = emitted.clone();
+                let ahead = ahead.clone();
+
+                async move {
+                    ahead.fetch_max(i - emitted.load(Ordering::SeqCst), Ordering::SeqCst);
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, ()>(i)
+                }
+            });
+
+            let outputs = reassemble(futures, 3, mode)
+                .inspect_ok(|_| {
+                    emitted.fetch_add(1, Ordering::SeqCst);
+                })
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+            assert_eq!(outputs, (0..50).collect::<Vec<_>>(), "{mode:?}");
+
+            let window = match mode {
+                DownloadMode::Ordered => 3,
+                DownloadMode::Windowed { max } => max,
+            };
+            assert!(ahead.load(Ordering::SeqCst) < window, "{mode:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn prefetch_is_bounded() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let futures = (0..50).map(|i| {
+            let started = started.clone();
+
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, ()>(i)
+            }
+        });
+
+        let mut outputs = Box::pin(reassemble(futures, 8, DownloadMode::Windowed { max: 2 }));
+
+        assert_eq!(outputs.try_next().await, Ok(Some(0)));
+        // the consumer is slow, so nothing past the window is fetched
+        tokio::task::yield_now().await;
+        assert!(started.load(Ordering::SeqCst) <= 3);
+
+        assert_eq!(outputs.try_next().await, Ok(Some(1)));
+        assert!(started.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn reassembly_stops_at_error() {
+        let futures = (0..10).map(|i| async move {
+            if i == 4 {
+                Err(i)
+            } else {
+                Ok(i)
+            }
+        });
+
+        let outputs = reassemble(futures, 2, DownloadMode::Windowed { max: 4 })
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(outputs, [Ok(0), Ok(1), Ok(2), Ok(3), Err(4)]);
+    }
+}
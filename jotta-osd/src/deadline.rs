@@ -0,0 +1,82 @@
+//! Bounding operations by a deadline.
+//!
+//! Dropping an operation at its deadline stops it, but in the middle of
+//! an upload that leaves the metadata out of date. Uploads should be
+//! bounded by cancelling them instead (see [`upload_range`](crate::object::upload_range)),
+//! while these helpers work well for downloads and metadata operations.
+use std::{future::Future, time::Instant};
+
+use futures_util::{stream, Stream, StreamExt};
+
+use crate::errors::Error;
+
+/// Run `fut` until `deadline`, after which [`Error::Timeout`] is returned.
+///
+/// # Errors
+///
+/// Returns [`Error::Timeout`] if the deadline is exceeded, or the error
+/// of `fut` if it fails.
+pub async fn timeout_at<T>(
+    deadline: Instant,
+    fut: impl Future<Output = crate::Result<T>>,
+) -> crate::Result<T> {
+    tokio::time::timeout_at(deadline.into(), fut)
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+/// Read from `stream` until `deadline`. If the deadline is exceeded,
+/// [`Error::Timeout`] is yielded and the stream ends.
+pub fn stream_timeout_at<'a, T: 'a>(
+    deadline: Instant,
+    stream: impl Stream<Item = crate::Result<T>> + 'a,
+) -> impl Stream<Item = crate::Result<T>> + 'a {
+    let stream = Box::pin(stream);
+
+    stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+
+        match tokio::time::timeout_at(deadline.into(), stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((Err(Error::Timeout), None)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures_util::{future, stream, StreamExt};
+
+    use super::{stream_timeout_at, timeout_at};
+    use crate::errors::Error;
+
+    #[tokio::test]
+    async fn exceeded_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(10);
+
+        let res = timeout_at(deadline, future::pending::<crate::Result<()>>()).await;
+        assert!(matches!(res, Err(Error::Timeout)));
+
+        let items = stream_timeout_at(
+            deadline,
+            stream::iter([Ok(1), Ok(2)]).chain(stream::pending()),
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert!(matches!(
+            items.as_slice(),
+            [Ok(1), Ok(2), Err(Error::Timeout)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn met_deadline() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        assert_eq!(timeout_at(deadline, async { Ok(42) }).await.unwrap(), 42);
+    }
+}
@@ -21,11 +21,78 @@ pub enum Error {
     #[error("msgpack encode error: {0}")]
     MsgpackEncode(#[from] rmp_serde::encode::Error),
 
-    /// MsgPack decode error.
+    /// `MsgPack` decode error.
     #[error("msgpack decode error: {0}")]
     MsgpackDecode(#[from] rmp_serde::decode::Error),
 
     /// I/O error.
     #[error("io error")]
     IoError(#[from] std::io::Error),
+
+    /// The encoded metadata is larger than [`Config::max_meta_size`](crate::Config::max_meta_size).
+    #[error("metadata is too large ({size} bytes, max {max})")]
+    MetaTooLarge {
+        /// Size of the encoded metadata.
+        size: usize,
+        /// Maximum allowed size.
+        max: usize,
+    },
+
+    /// A precondition, such as an expected revision, was not met.
+    #[error("precondition failed")]
+    PreconditionFailed,
+
+    /// The operation was cancelled.
+    #[error("cancelled")]
+    Cancelled,
+
+    /// The deadline of the operation was exceeded.
+    #[error("deadline exceeded")]
+    Timeout,
+
+    /// The configured mount point doesn't exist on [`DEVICE`](crate::DEVICE).
+    #[error("no mount point `{mount_point}` on device `{}`", crate::DEVICE)]
+    NoSuchMountPoint {
+        /// Name of the mount point.
+        mount_point: String,
+    },
+
+    /// The root folder doesn't exist and
+    /// [`Config::create_root`](crate::Config::create_root) is disabled.
+    #[error("no root folder `{root}`")]
+    NoSuchRoot {
+        /// Name of the root folder.
+        root: String,
+    },
+
+    /// The account may not be written to, as reported by Jottacloud.
+    /// Only checked if [`Config::check_locks`](crate::Config::check_locks)
+    /// is enabled.
+    #[error("the Jottacloud account is write locked")]
+    WriteLocked,
+
+    /// The account may not be read from (or is locked entirely), as
+    /// reported by Jottacloud. Only checked if
+    /// [`Config::check_locks`](crate::Config::check_locks) is enabled.
+    #[error("the Jottacloud account is read locked")]
+    ReadLocked,
+
+    /// The source of [`upload_from_url`](crate::object::upload_from_url)
+    /// couldn't be fetched, or it responded with an error status.
+    #[error("failed to fetch the source: {0}")]
+    Fetch(#[source] reqwest::Error),
+
+    /// A chunk lacks the revision needed by
+    /// [`stream_revision`](crate::object::stream_revision).
+    #[error("chunk {chunk} has no revision {revision}")]
+    NoSuchRevision {
+        /// Index of the chunk.
+        chunk: u32,
+        /// The missing revision.
+        revision: u32,
+    },
+
+    /// An argument was invalid.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
 }
@@ -0,0 +1,144 @@
+//! Object level events, translated from the raw Jottacloud events of
+//! [`jotta::events`].
+//!
+//! Every object is a folder of files, so a single write causes several
+//! raw events: one for each uploaded chunk and one for the metadata.
+//! They're all reported as events of the same object. An object only
+//! appears when its metadata is first uploaded, and disappears when the
+//! metadata or the whole object folder is deleted.
+use futures_util::{stream, Stream, StreamExt};
+use jotta::{
+    auth::TokenStore,
+    events::{self, ServerEvent, ServerMessage},
+    path::{AbsolutePath, UserScopedPath},
+};
+use tracing::trace;
+
+use crate::{
+    path::{BucketName, ObjectName},
+    Context, Location,
+};
+
+/// Something that happened to an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectEvent {
+    /// The object was created, or restored after being deleted.
+    Created {
+        /// Bucket of the object.
+        bucket: BucketName,
+        /// Name of the object.
+        object: ObjectName,
+    },
+    /// The data or metadata of the object changed.
+    Updated {
+        /// Bucket of the object.
+        bucket: BucketName,
+        /// Name of the object.
+        object: ObjectName,
+    },
+    /// The object was deleted.
+    Deleted {
+        /// Bucket of the object.
+        bucket: BucketName,
+        /// Name of the object.
+        object: ObjectName,
+    },
+}
+
+/// What happened to a file or folder, before it's known whether it
+/// belongs to an object.
+#[derive(Clone, Copy)]
+enum Change {
+    Upload { revision: u32 },
+    Restore,
+    Delete,
+}
+
+impl<S: TokenStore> Context<S> {
+    /// Translate a raw event into the events of the objects it touches.
+    /// Events outside the root, or of buckets and the root itself, are
+    /// ignored.
+    ///
+    /// This doesn't invalidate any cached metadata; see
+    /// [`Context::handle_event`] and [`Context::watch`].
+    #[must_use]
+    pub fn object_events(&self, event: &ServerEvent) -> Vec<ObjectEvent> {
+        let changes = match event {
+            ServerEvent::NewUpload(file) => vec![(
+                &file.from,
+                Change::Upload {
+                    revision: file.revision,
+                },
+            )],
+            ServerEvent::Restore(file) => vec![(&file.from, Change::Restore)],
+            ServerEvent::Delete(file) => vec![(&file.from, Change::Delete)],
+            ServerEvent::Move(file) => {
+                let mut changes = vec![(&file.from, Change::Delete)];
+                changes.extend(file.to.iter().map(|to| (to, Change::Restore)));
+                changes
+            }
+            ServerEvent::HardDeleteDir(dir) => vec![(&dir.from, Change::Delete)],
+            ServerEvent::CreateDir(_) | ServerEvent::Pong(_) => Vec::new(),
+        };
+
+        changes
+            .into_iter()
+            .filter_map(|(path, change)| self.object_event(path, change))
+            .collect()
+    }
+
+    fn object_event(&self, path: &AbsolutePath, change: Change) -> Option<ObjectEvent> {
+        let Some(Location::Object(bucket, object)) = self.locate(path) else {
+            return None;
+        };
+
+        let relative = UserScopedPath::from(path.clone());
+        // the object folder itself, or its metadata
+        let whole = relative == self.object_path(&bucket, &object)
+            || relative == self.meta_path(&bucket, &object);
+
+        Some(match (change, whole) {
+            (Change::Upload { revision: 1 } | Change::Restore, true) => {
+                ObjectEvent::Created { bucket, object }
+            }
+            (Change::Delete, true) => ObjectEvent::Deleted { bucket, object },
+            _ => ObjectEvent::Updated { bucket, object },
+        })
+    }
+
+    /// Subscribe to the events of all objects in the root. Cached
+    /// metadata is invalidated along the way (see
+    /// [`Context::handle_event`]).
+    ///
+    /// Raw events that can't be parsed, such as those about shared
+    /// folders or photo albums, are skipped. The stream ends if the
+    /// connection is closed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the subscription can't be set up. Websocket errors are
+    /// yielded as items.
+    pub async fn watch(
+        &self,
+    ) -> crate::Result<impl Stream<Item = crate::Result<ObjectEvent>> + '_> {
+        let messages = events::subscribe(&self.fs).await?;
+
+        Ok(messages.flat_map(move |message| {
+            let items = match message {
+                Ok(ServerMessage::Event { inner, .. }) => {
+                    self.handle_event(&inner);
+
+                    self.object_events(&inner).into_iter().map(Ok).collect()
+                }
+                Ok(ServerMessage::Subscribe { .. }) => Vec::new(),
+                Err(events::Error::ParseMessageError(e)) => {
+                    trace!("skipping event: {e}");
+                    Vec::new()
+                }
+                Err(e) => vec![Err(jotta::Error::from(e).into())],
+            };
+
+            stream::iter(items)
+        }))
+    }
+}
@@ -5,33 +5,124 @@
     missing_docs,
     clippy::pedantic
 )]
+#![allow(clippy::result_large_err, clippy::non_std_lazy_statics)]
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod bucket;
+pub mod cache;
+pub mod concurrency;
+pub mod deadline;
 pub mod errors;
+pub mod events;
 pub mod object;
 pub mod path;
+pub mod s3;
 
 pub(crate) mod serde;
 
 pub(crate) type Result<T> = core::result::Result<T, errors::Error>;
 
-pub(crate) const DEVICE: &str = "Jotta";
-pub(crate) const MOUNT_POINT: &str = "Archive";
+/// Device everything is stored on. Jottacloud allocates all uploads on
+/// it, so only the [mount point](Config::mount_point) can be configured.
+pub const DEVICE: &str = "Jotta";
+/// Default value of [`Config::mount_point`].
+pub const DEFAULT_MOUNT_POINT: &str = jotta::path::ARCHIVE;
 
+use std::sync::Arc;
+
+use cache::{MetaCache, MetaCacheConfig};
 pub use jotta;
-use jotta::{auth::TokenStore, path::UserScopedPath, Fs};
+use jotta::events::ServerEvent;
+use jotta::{
+    auth::TokenStore,
+    jfs::PathKind,
+    path::{AbsolutePath, PathOnDevice, UserScopedPath},
+    Fs,
+};
+use object::meta::CacheControl;
+use path::{BucketName, ObjectName};
+
+/// Default value of [`Config::max_meta_size`].
+pub const DEFAULT_MAX_META_SIZE: usize = 64 * 1024;
+
+/// Default value of [`Config::max_object_name_len`].
+pub const DEFAULT_MAX_OBJECT_NAME_LEN: usize = 512;
+
+/// How object chunks are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkLayout {
+    /// Chunks are stored in the object folder and named after their index.
+    /// Overwriting a chunk replaces it.
+    #[default]
+    Indexed,
+    /// Chunks are stored in a shared folder and named after their content,
+    /// which deduplicates identical chunks and keeps old chunks around
+    /// after they have been overwritten.
+    ///
+    /// This only applies to objects created while it's configured, and
+    /// content-addressed objects can't be read with [`ChunkLayout::Indexed`].
+    ContentAddressed,
+}
 
 /// Jotta configuration.
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Root folder to store all buckets in.
+    /// Mount point of [`DEVICE`] to store everything in. It must
+    /// already exist, but doesn't have to be [`DEFAULT_MOUNT_POINT`]. See
+    /// [`jotta::path`] for the others.
+    pub mount_point: String,
+    /// Root folder to store all buckets in, relative to the mount point.
     pub root: String,
+    /// Create the root folder if it doesn't exist. Disable it to start
+    /// with read-only credentials, in which case the root must already
+    /// exist.
+    pub create_root: bool,
+    /// Maximum size of the encoded metadata of an object, in bytes.
+    /// Metadata is read on almost every operation, so it should be kept small.
+    pub max_meta_size: usize,
+    /// Maximum length of the names of new objects, in bytes. Names are
+    /// hex-encoded in paths, doubling their length, so names close to
+    /// [`path::MAX_OBJECT_NAME_LEN`] may be too long for Jottacloud.
+    /// Existing objects with longer names can still be read.
+    pub max_object_name_len: usize,
+    /// How chunks of new objects are stored.
+    pub chunk_layout: ChunkLayout,
+    /// Cache control of new objects that don't specify one. Use
+    /// [`CacheControl::none`] to omit the header entirely.
+    pub default_cache_control: CacheControl,
+    /// Cache object metadata in memory. Disabled by default, since
+    /// changes made by other clients aren't noticed until the entries
+    /// expire (see [`Context::handle_event`] and [`Context::watch`]).
+    pub meta_cache: Option<MetaCacheConfig>,
+    /// Guess the content type of new objects created without one from
+    /// the magic bytes of their first chunk, rather than from the
+    /// extension of their name. An explicit content type is never
+    /// overridden. Disabled by default.
+    pub sniff_content_type: bool,
+    /// Make [`Context::initialize`] fail if Jottacloud reports the
+    /// account as read or write locked, instead of failing on the first
+    /// request that isn't allowed. This costs an extra request on startup,
+    /// and a write lock is an error even if only reads are intended, so
+    /// it's disabled by default.
+    pub check_locks: bool,
 }
 
 impl Config {
     /// Create a new config.
     pub fn new(root: impl Into<String>) -> Self {
-        Self { root: root.into() }
+        Self {
+            mount_point: DEFAULT_MOUNT_POINT.into(),
+            root: root.into(),
+            create_root: true,
+            max_meta_size: DEFAULT_MAX_META_SIZE,
+            max_object_name_len: DEFAULT_MAX_OBJECT_NAME_LEN,
+            chunk_layout: ChunkLayout::default(),
+            default_cache_control: CacheControl::default(),
+            meta_cache: None,
+            sniff_content_type: false,
+            check_locks: false,
+        }
     }
 }
 
@@ -39,33 +130,283 @@ impl Config {
 /// is achieved by internal `Arc`s.
 #[derive(Debug)]
 pub struct Context<S: TokenStore> {
-    fs: Fs<S>,
+    fs: Arc<Fs<S>>,
     config: Config,
+    meta_cache: Option<MetaCache>,
 }
 
 impl<S: TokenStore> Context<S> {
-    /// Initialize a new context. This creates a root
-    /// directory if it does not already exist.
+    /// Initialize a new context. This creates a root directory if it
+    /// does not already exist, unless [`Config::create_root`] is disabled.
     ///
     /// # Errors
     ///
     /// - The usual suspects.
+    /// - [`Error::NoSuchMountPoint`](errors::Error::NoSuchMountPoint) if the
+    ///   configured mount point doesn't exist.
     /// - Failing to create the root directory.
+    /// - [`Error::NoSuchRoot`](errors::Error::NoSuchRoot) if the root
+    ///   directory doesn't exist and may not be created.
+    /// - [`Error::ReadLocked`](errors::Error::ReadLocked) or
+    ///   [`Error::WriteLocked`](errors::Error::WriteLocked) if the account
+    ///   is locked and [`Config::check_locks`] is enabled.
     pub async fn initialize(fs: Fs<S>, config: Config) -> crate::Result<Self> {
-        let ctx = Self { fs, config };
+        match fs.mount_point_path(DEVICE, &config.mount_point).await {
+            Ok(_) => {}
+            Err(jotta::Error::NoSuchFileOrFolder) => {
+                return Err(errors::Error::NoSuchMountPoint {
+                    mount_point: config.mount_point.clone(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        if config.check_locks {
+            let account = fs.account_info().await?;
+
+            if account.locked || account.read_locked {
+                return Err(errors::Error::ReadLocked);
+            }
+
+            if account.write_locked {
+                return Err(errors::Error::WriteLocked);
+            }
+        }
 
-        ctx.fs
-            .create_folder(&UserScopedPath(ctx.user_scoped_root()))
-            .await?;
+        let ctx = Self {
+            fs: Arc::new(fs),
+            meta_cache: config.meta_cache.map(MetaCache::new),
+            config,
+        };
+
+        if ctx.config.create_root {
+            ctx.fs.create_folder(&ctx.root_path()).await?;
+        } else if ctx.fs.path_kind(&ctx.root_path()).await? != Some(PathKind::Folder) {
+            return Err(errors::Error::NoSuchRoot {
+                root: ctx.config.root,
+            });
+        }
 
         Ok(ctx)
     }
 
-    fn user_scoped_root(&self) -> String {
-        format!("{DEVICE}/{MOUNT_POINT}/{}", self.config.root)
+    /// Derive a context that stores its buckets in another root folder,
+    /// for example one per tenant. The [`Fs`] is shared rather than
+    /// copied, so no new authentication is needed and the access token
+    /// cache is shared by all derived contexts.
+    ///
+    /// Unlike [`Context::initialize`], this makes no requests. The root
+    /// folder is created on the first write (such as
+    /// [`bucket::create`]) and appears empty until then. Metadata is
+    /// cached separately for each root.
+    #[must_use]
+    pub fn with_root(&self, root: impl Into<String>) -> Self {
+        let config = Config {
+            root: root.into(),
+            ..self.config.clone()
+        };
+
+        Self {
+            fs: Arc::clone(&self.fs),
+            meta_cache: config.meta_cache.map(MetaCache::new),
+            config,
+        }
+    }
+
+    /// Path to the root folder containing all buckets.
+    pub(crate) fn root_path(&self) -> UserScopedPath {
+        PathOnDevice::new(&self.config.mount_point, &self.config.root).on_device(DEVICE)
+    }
+
+    /// Path to something stored in the root, such as a chunk.
+    pub(crate) fn path(&self, relative: &str) -> UserScopedPath {
+        UserScopedPath(format!("{}/{relative}", self.root_path()))
+    }
+
+    /// Like [`Context::path`], but relative to the device. Needed for
+    /// allocating uploads, which always end up on [`DEVICE`].
+    pub(crate) fn path_on_device(&self, relative: &str) -> PathOnDevice {
+        PathOnDevice::new(
+            &self.config.mount_point,
+            &format!("{}/{relative}", self.config.root),
+        )
+    }
+
+    /// Path to the folder of a bucket.
+    pub(crate) fn bucket_path(&self, bucket: &BucketName) -> UserScopedPath {
+        self.path(&bucket.to_string())
+    }
+
+    /// Path to the attributes of a bucket.
+    pub(crate) fn bucket_meta_path(&self, bucket: &BucketName) -> UserScopedPath {
+        self.path(&bucket_meta_relative(bucket))
+    }
+
+    /// Like [`Context::bucket_meta_path`], but relative to the device.
+    pub(crate) fn bucket_meta_path_on_device(&self, bucket: &BucketName) -> PathOnDevice {
+        self.path_on_device(&bucket_meta_relative(bucket))
+    }
+
+    /// Path to the folder of an object, containing its metadata and
+    /// (indexed) chunks.
+    pub(crate) fn object_path(&self, bucket: &BucketName, name: &ObjectName) -> UserScopedPath {
+        self.path(&object_relative(bucket, name))
     }
 
-    fn root_on_device(&self) -> String {
-        format!("{MOUNT_POINT}/{}", self.config.root)
+    /// Path to the metadata file of an object.
+    pub(crate) fn meta_path(&self, bucket: &BucketName, name: &ObjectName) -> UserScopedPath {
+        self.path(&meta_relative(bucket, name))
+    }
+
+    /// Like [`Context::meta_path`], but relative to the device.
+    pub(crate) fn meta_path_on_device(
+        &self,
+        bucket: &BucketName,
+        name: &ObjectName,
+    ) -> PathOnDevice {
+        self.path_on_device(&meta_relative(bucket, name))
+    }
+
+    /// Bytes left on the account before its storage quota is reached,
+    /// or `None` if the account is unlimited. This is shared by all
+    /// roots and everything else stored on the account.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    pub async fn remaining_storage(&self) -> crate::Result<Option<u64>> {
+        let account = self.fs.account_info().await?;
+
+        Ok(account.remaining())
+    }
+
+    /// Renew the access token ahead of its expiry. See
+    /// [`jotta::auth::TokenStore::refresh_now`].
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - the token couldn't be renewed
+    pub async fn refresh_token(&self) -> crate::Result<()> {
+        self.fs.refresh_token().await?;
+
+        Ok(())
+    }
+
+    /// Invalidate cached metadata affected by an event from
+    /// [`jotta::events::subscribe`], such as another client uploading or
+    /// deleting an object. Does nothing if the cache is disabled.
+    pub fn handle_event(&self, event: &ServerEvent) {
+        let Some(cache) = &self.meta_cache else {
+            return;
+        };
+
+        let paths = match event {
+            ServerEvent::NewUpload(file)
+            | ServerEvent::Delete(file)
+            | ServerEvent::Restore(file)
+            | ServerEvent::Move(file) => {
+                let mut paths = vec![&file.from];
+                paths.extend(&file.to);
+                paths
+            }
+            ServerEvent::HardDeleteDir(dir) | ServerEvent::CreateDir(dir) => vec![&dir.from],
+            ServerEvent::Pong(_) => return,
+        };
+
+        for path in paths {
+            match self.locate(path) {
+                Some(Location::Root) => cache.clear(),
+                Some(Location::Bucket(bucket)) => cache.invalidate_bucket(&bucket),
+                Some(Location::Object(bucket, name)) => cache.invalidate(&bucket, &name),
+                None => {}
+            }
+        }
+    }
+
+    /// Find out what an absolute path refers to.
+    fn locate(&self, path: &AbsolutePath) -> Option<Location> {
+        if path.user() != self.fs.username() {
+            return None;
+        }
+
+        let path = UserScopedPath::from(path.clone());
+        let root = self.root_path();
+
+        if path == root {
+            return Some(Location::Root);
+        }
+
+        let mut segments = path.strip_prefix(&*root)?.strip_prefix('/')?.split('/');
+
+        let bucket = segments.next()?.parse().ok()?;
+
+        match segments.next() {
+            None => Some(Location::Bucket(bucket)),
+            Some(hex) => ObjectName::try_from_hex(hex)
+                .ok()
+                .map(|name| Location::Object(bucket, name)),
+        }
+    }
+}
+
+/// Something stored in the root.
+#[derive(Debug, PartialEq, Eq)]
+enum Location {
+    Root,
+    Bucket(BucketName),
+    Object(BucketName, ObjectName),
+}
+
+fn bucket_meta_relative(bucket: &BucketName) -> String {
+    format!("{bucket}/{}", bucket::BUCKET_META)
+}
+
+fn object_relative(bucket: &BucketName, name: &ObjectName) -> String {
+    format!("{bucket}/{}", name.to_hex())
+}
+
+fn meta_relative(bucket: &BucketName, name: &ObjectName) -> String {
+    format!("{}/meta", object_relative(bucket, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use jotta::{auth::MockTokenStore, Fs};
+
+    use crate::{Config, Context};
+
+    #[test]
+    fn paths() {
+        let ctx = Context {
+            fs: Arc::new(Fs::new(MockTokenStore::new("alice"))),
+            config: Config::new("root"),
+            meta_cache: None,
+        };
+        let bucket = "bucket".parse().unwrap();
+        let name = "a".parse().unwrap();
+
+        assert_eq!(ctx.root_path().0, "Jotta/Archive/root");
+        assert_eq!(ctx.bucket_path(&bucket).0, "Jotta/Archive/root/bucket");
+        assert_eq!(
+            ctx.object_path(&bucket, &name).0,
+            "Jotta/Archive/root/bucket/61"
+        );
+        assert_eq!(
+            ctx.meta_path(&bucket, &name).0,
+            "Jotta/Archive/root/bucket/61/meta"
+        );
+        assert_eq!(
+            ctx.meta_path_on_device(&bucket, &name).0,
+            "Archive/root/bucket/61/meta"
+        );
+
+        let tenant = ctx.with_root("tenants/a");
+        assert_eq!(tenant.root_path().0, "Jotta/Archive/tenants/a");
+        assert_eq!(ctx.root_path().0, "Jotta/Archive/root");
+        assert!(Arc::ptr_eq(&ctx.fs, &tenant.fs));
     }
 }
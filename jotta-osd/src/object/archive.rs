@@ -0,0 +1,158 @@
+//! Tar archive helpers used when exporting objects.
+use bytes::{BufMut, Bytes, BytesMut};
+use tar::{EntryType, Header};
+
+use crate::path::ObjectName;
+
+use super::meta::Meta;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Two empty blocks mark the end of an archive.
+pub(crate) const TRAILER: [u8; 2 * BLOCK_SIZE] = [0; 2 * BLOCK_SIZE];
+
+fn gnu_header(entry_type: EntryType, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header
+}
+
+/// Name of the entry for an object. Object names may be anything, so
+/// they are made relative and `.` and `..` components are removed to keep
+/// extracted files inside the destination. Names with nothing left are
+/// replaced by the [storage id](ObjectName::storage_id) of the object.
+pub(crate) fn entry_name(name: &ObjectName) -> String {
+    let components = name
+        .split('/')
+        .filter(|c| !matches!(*c, "" | "." | ".."))
+        .collect::<Vec<_>>();
+
+    if components.is_empty() {
+        name.storage_id()
+    } else {
+        components.join("/")
+    }
+}
+
+/// Create the header block(s) preceding an object in an archive.
+///
+/// The entry is named by [`entry_name`]. Names longer than 100 bytes are
+/// prefixed by a GNU long name entry.
+pub(crate) fn entry_header(name: &ObjectName, meta: &Meta) -> Bytes {
+    let name = entry_name(name);
+    let name = name.as_bytes();
+    let mut buf = BytesMut::with_capacity(BLOCK_SIZE);
+
+    let mut header = gnu_header(EntryType::Regular, meta.size);
+    header.set_mode(0o644);
+    header.set_mtime(meta.updated.unix_timestamp().try_into().unwrap_or_default());
+
+    let name_field = &mut header.as_old_mut().name;
+
+    if name.len() > name_field.len() {
+        let mut long_name = gnu_header(EntryType::GNULongName, name.len() as u64 + 1);
+        long_name.as_old_mut().name[..13].copy_from_slice(b"././@LongLink");
+        long_name.set_cksum();
+
+        buf.put_slice(long_name.as_bytes());
+        buf.put_slice(name);
+        buf.put_u8(0);
+        buf.put_bytes(0, padding(name.len() as u64 + 1));
+
+        let truncated = name_field.len();
+        name_field.copy_from_slice(&name[..truncated]);
+    } else {
+        name_field[..name.len()].copy_from_slice(name);
+    }
+
+    header.set_cksum();
+    buf.put_slice(header.as_bytes());
+
+    buf.freeze()
+}
+
+/// Number of zero bytes needed to pad `size` bytes of data to a full block.
+#[allow(clippy::cast_possible_truncation)] // always less than the block size
+pub(crate) fn padding(size: u64) -> usize {
+    ((BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use bytes::{BufMut, BytesMut};
+    use time::macros::datetime;
+
+    use super::{entry_header, entry_name, padding, TRAILER};
+    use crate::object::meta::Meta;
+
+    fn meta(size: u64) -> Meta {
+        Meta {
+            size,
+            created: datetime!(2022-01-01 0:00 UTC),
+            updated: datetime!(2022-04-01 12:00 UTC),
+            ..Meta::default()
+        }
+    }
+
+    #[test]
+    fn readable_archive() {
+        let long_name = "a/".repeat(100) + "long.txt";
+        let entries = [("short.txt", 5), (long_name.as_str(), 1000), ("empty", 0)];
+
+        let mut archive = BytesMut::new();
+
+        for (name, size) in entries {
+            archive.put(entry_header(&name.parse().unwrap(), &meta(size)));
+            archive.put_bytes(b'x', size.try_into().unwrap());
+            archive.put_bytes(0, padding(size));
+        }
+
+        archive.put_slice(&TRAILER);
+
+        let mut archive = tar::Archive::new(&archive[..]);
+
+        let mut read = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let mut e = e.unwrap();
+                let mut data = Vec::new();
+                e.read_to_end(&mut data).unwrap();
+
+                assert_eq!(e.header().mtime().unwrap(), 1_648_814_400);
+                assert!(data.iter().all(|b| *b == b'x'));
+
+                (
+                    String::from_utf8(e.path_bytes().into_owned()).unwrap(),
+                    data.len() as u64,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        read.sort();
+
+        let mut expected = entries
+            .iter()
+            .map(|(name, size)| ((*name).to_string(), *size))
+            .collect::<Vec<_>>();
+
+        expected.sort();
+
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn entry_names() {
+        let entry_name = |name: &str| entry_name(&name.parse().unwrap());
+
+        assert_eq!(entry_name("photos/cat.jpeg"), "photos/cat.jpeg");
+        assert_eq!(entry_name("/etc/passwd"), "etc/passwd");
+        assert_eq!(entry_name("../../x"), "x");
+        assert_eq!(entry_name("a/./b//../c/"), "a/b/c");
+        assert_eq!(entry_name(".."), "2e2e");
+        assert_eq!(entry_name("/"), "2f");
+    }
+}
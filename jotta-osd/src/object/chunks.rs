@@ -0,0 +1,205 @@
+//! Locating object chunks in the cloud.
+//!
+//! With [`ChunkLayout::Indexed`], chunks are stored in the object folder and
+//! named after their index. With [`ChunkLayout::ContentAddressed`], chunks are
+//! stored in a shared folder in the root and named after their content. The
+//! metadata then references the chunks by address, so identical chunks are
+//! only stored once and old chunks stay around after being overwritten.
+use jotta::auth::TokenStore;
+use md5::Digest;
+
+use crate::{
+    errors::Error,
+    path::{BucketName, ObjectName},
+    Context,
+};
+
+use super::meta::{self, Meta};
+
+/// Name of the folder in the root containing content-addressed chunks.
+pub(crate) const CHUNK_STORE: &str = ".chunks";
+
+/// Chunk digests are only recorded in [`Meta::chunk_md5s`] for objects
+/// with at most this many chunks, to keep the metadata small.
+pub const MAX_CHUNK_DIGESTS: usize = 1024;
+
+/// Content address of a chunk, made up of its checksum and size.
+pub(crate) fn content_address(md5: &Digest, size: u64) -> String {
+    format!("{md5:x}-{size}")
+}
+
+/// The checksum part of a [`content_address`].
+pub(crate) fn address_digest(address: &str) -> Option<Digest> {
+    let (md5, _size) = address.split_once('-')?;
+    let mut digest = [0; 16];
+
+    hex::decode_to_slice(md5, &mut digest).ok()?;
+
+    Some(Digest(digest))
+}
+
+/// Record the digest of an uploaded chunk in `digests`, forgetting all
+/// of them if that would leave a gap or make them too many.
+pub(crate) fn set_chunk_digest(digests: &mut Option<Vec<Digest>>, index: u32, md5: Digest) {
+    let Some(ref mut list) = digests else {
+        return;
+    };
+
+    let index = usize::try_from(index).unwrap();
+
+    if index < list.len() {
+        list[index] = md5;
+    } else if index == list.len() && index < MAX_CHUNK_DIGESTS {
+        list.push(md5);
+    } else {
+        *digests = None;
+    }
+}
+
+/// Where to find the chunks of an object.
+#[derive(Debug)]
+pub(crate) enum Chunks {
+    /// Chunks are named after their index.
+    Indexed,
+    /// Chunks are referenced by address, ordered by index.
+    ContentAddressed(Vec<String>),
+}
+
+impl Chunks {
+    pub(crate) fn from_meta(meta: &Meta) -> Self {
+        match meta.chunks {
+            Some(ref chunks) => Self::ContentAddressed(chunks.clone()),
+            None => Self::Indexed,
+        }
+    }
+
+    /// Find the chunks of an object from its metadata. The layout of an
+    /// object is recorded in its metadata, so it doesn't depend on the
+    /// [`ChunkLayout`](crate::ChunkLayout) the context is configured with.
+    pub(crate) async fn resolve(
+        ctx: &Context<impl TokenStore>,
+        bucket: &BucketName,
+        object: &ObjectName,
+    ) -> crate::Result<Self> {
+        Ok(Self::from_meta(&meta::get(ctx, bucket, object).await?))
+    }
+
+    /// Path to a chunk relative to the root, or `None` if the chunk doesn't exist.
+    pub(crate) fn path(
+        &self,
+        bucket: &BucketName,
+        object: &ObjectName,
+        index: u32,
+    ) -> Option<String> {
+        match self {
+            Chunks::Indexed => Some(format!("{}/{}", bucket, object.chunk_path(index))),
+            Chunks::ContentAddressed(chunks) => {
+                let address = chunks.get(usize::try_from(index).ok()?)?;
+                Some(format!("{CHUNK_STORE}/{address}"))
+            }
+        }
+    }
+}
+
+/// Record that chunk `index` now has the content address `address`.
+///
+/// # Errors
+///
+/// Fails if `index` would leave a gap in the chunk list.
+pub(crate) fn set_chunk(
+    chunks: &mut Vec<String>,
+    index: u32,
+    address: String,
+) -> crate::Result<()> {
+    let index = usize::try_from(index).unwrap();
+
+    match index.cmp(&chunks.len()) {
+        std::cmp::Ordering::Less => chunks[index] = address,
+        std::cmp::Ordering::Equal => chunks.push(address),
+        std::cmp::Ordering::Greater => {
+            return Err(Error::InvalidInput(format!(
+                "chunk {index} would leave a gap"
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        address_digest, content_address, set_chunk, set_chunk_digest, Chunks, MAX_CHUNK_DIGESTS,
+    };
+
+    #[test]
+    fn shared_chunks_are_deduplicated() {
+        let body = vec![42; 1000];
+        let a = content_address(&md5::compute(&body), body.len() as u64);
+        let b = content_address(&md5::compute(body.clone()), body.len() as u64);
+
+        assert_eq!(a, b);
+
+        let bucket = "bucket".parse().unwrap();
+        let first = Chunks::ContentAddressed(vec![a.clone()]);
+        let second = Chunks::ContentAddressed(vec![b.clone(), a.clone()]);
+
+        let path = first.path(&bucket, &"first".parse().unwrap(), 0);
+
+        assert_eq!(path, second.path(&bucket, &"second".parse().unwrap(), 0));
+        assert_eq!(path, second.path(&bucket, &"second".parse().unwrap(), 1));
+        assert_eq!(path.unwrap(), format!(".chunks/{a}"));
+        assert_eq!(first.path(&bucket, &"first".parse().unwrap(), 1), None);
+
+        let other = content_address(&md5::compute([0; 1000]), 1000);
+        assert_ne!(a, other);
+    }
+
+    #[test]
+    fn update_chunk_list() {
+        let mut chunks = vec![];
+
+        set_chunk(&mut chunks, 0, "a".into()).unwrap();
+        set_chunk(&mut chunks, 1, "b".into()).unwrap();
+        set_chunk(&mut chunks, 0, "c".into()).unwrap();
+
+        assert_eq!(chunks, vec!["c", "b"]);
+
+        assert!(set_chunk(&mut chunks, 3, "d".into()).is_err());
+        assert_eq!(chunks, vec!["c", "b"]);
+    }
+
+    #[test]
+    fn chunk_digests() {
+        let (a, b, c) = (md5::compute("a"), md5::compute("b"), md5::compute("c"));
+        let mut digests = Some(vec![]);
+
+        set_chunk_digest(&mut digests, 0, a);
+        set_chunk_digest(&mut digests, 1, b);
+        set_chunk_digest(&mut digests, 0, c);
+        assert_eq!(digests, Some(vec![c, b]));
+
+        // a gap means that some digests are unknown
+        set_chunk_digest(&mut digests, 3, a);
+        assert_eq!(digests, None);
+        set_chunk_digest(&mut digests, 0, a);
+        assert_eq!(digests, None);
+
+        let max = u32::try_from(MAX_CHUNK_DIGESTS).unwrap();
+        let mut digests = Some(vec![a; MAX_CHUNK_DIGESTS]);
+        set_chunk_digest(&mut digests, max - 1, b);
+        assert!(digests.is_some());
+        set_chunk_digest(&mut digests, max, b);
+        assert_eq!(digests, None);
+
+        assert_eq!(address_digest(&content_address(&a, 1)), Some(a));
+        assert_eq!(address_digest("nope"), None);
+    }
+
+    #[test]
+    fn indexed_paths() {
+        let path = Chunks::Indexed.path(&"bucket".parse().unwrap(), &"a".parse().unwrap(), 3);
+
+        assert_eq!(path.unwrap(), "bucket/61/3");
+    }
+}
@@ -0,0 +1,136 @@
+//! Removing chunks that no object refers to.
+//!
+//! Interrupted uploads and objects that are replaced without purging can
+//! leave chunks past the end of an object, and an object whose metadata
+//! was never written leaves a folder full of chunks that can't be read.
+//! Neither is harmful, but both take up storage.
+//!
+//! Garbage collection must not run concurrently with writes to the same
+//! objects (including open [`UploadTxn`](super::txn::UploadTxn)s), since
+//! chunks that are being uploaded may look orphaned.
+use futures_util::{stream, StreamExt, TryStreamExt};
+use jotta::{auth::TokenStore, jfs::ListedFile};
+use serde::Serialize;
+use tracing::{debug, instrument};
+
+use crate::{
+    path::{BucketName, ObjectName},
+    Context,
+};
+
+use super::{chunk_count, has_meta, meta, object_in_folder};
+
+/// Number of object folders inspected at once by [`gc_bucket`].
+const GC_CONCURRENCY: usize = 10;
+
+/// What garbage collection removed.
+#[derive(Debug, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcReport {
+    /// Chunks past the end of an object.
+    pub removed_chunks: usize,
+    /// Object folders without metadata, removed along with their chunks.
+    pub removed_objects: usize,
+}
+
+fn is_live(file: &ListedFile) -> bool {
+    file.deleted.is_none()
+}
+
+/// Remove the chunks of an object that are past its end, i.e. those with
+/// an index of at least `ceil(size / CHUNK_SIZE)`.
+///
+/// Content-addressed chunks may be shared between objects and are
+/// never removed.
+///
+/// # Errors
+///
+/// - no such object
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn gc(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> crate::Result<GcReport> {
+    let meta = meta::get(ctx, bucket, name).await?;
+
+    // content-addressed objects have no chunks of their own
+    let expected = if meta.chunks.is_none() {
+        chunk_count(meta.size)
+    } else {
+        0
+    };
+
+    let folder = ctx.fs.index(&ctx.object_path(bucket, name)).await?;
+
+    let orphans = folder
+        .files
+        .inner
+        .iter()
+        .filter(|f| is_live(f))
+        .filter_map(|f| f.name.parse::<u32>().ok())
+        .filter(|&index| u64::from(index) >= expected)
+        .collect::<Vec<_>>();
+
+    for &index in &orphans {
+        debug!("removing chunk {index}");
+
+        ctx.fs
+            .remove_file(&ctx.path(&format!("{bucket}/{}", name.chunk_path(index))))
+            .await?;
+    }
+
+    Ok(GcReport {
+        removed_chunks: orphans.len(),
+        removed_objects: 0,
+    })
+}
+
+/// Remove every object folder in a bucket that has no metadata. See
+/// [`crate::bucket::gc`].
+pub(crate) async fn gc_bucket(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+) -> crate::Result<GcReport> {
+    let index = ctx.fs.index(&ctx.bucket_path(bucket)).await?;
+
+    let names = index
+        .folders
+        .inner
+        .into_iter()
+        .filter(|f| !f.is_deleted())
+        .filter_map(|f| object_in_folder(&f.name))
+        .collect::<Vec<_>>();
+
+    let removed = stream::iter(names)
+        .map(|name| async move {
+            let folder = ctx.fs.index(&ctx.object_path(bucket, &name)).await?;
+
+            if has_meta(&folder) {
+                return Ok(false);
+            }
+
+            debug!(object = %name, "removing object without metadata");
+
+            ctx.fs
+                .remove_folder(&ctx.object_path(bucket, &name))
+                .await?;
+
+            if let Some(cache) = &ctx.meta_cache {
+                cache.invalidate(bucket, &name);
+            }
+
+            Ok::<_, crate::errors::Error>(true)
+        })
+        .buffer_unordered(GC_CONCURRENCY)
+        .try_fold(0, |n, removed| async move { Ok(n + usize::from(removed)) })
+        .await?;
+
+    Ok(GcReport {
+        removed_chunks: 0,
+        removed_objects: removed,
+    })
+}
@@ -0,0 +1,522 @@
+//! Object health checks, mostly useful for debugging broken objects.
+//!
+//! [`check`] compares a folder listing to the metadata, while [`verify`]
+//! inspects every chunk and [`repair`] re-uploads the bad ones.
+use std::{collections::HashMap, future::Future, io};
+
+use bytes::Bytes;
+use futures_util::{future, stream, StreamExt, TryStreamExt};
+use jotta::{
+    auth::TokenStore,
+    jfs::{FileDetail, ListedFile, RevisionState},
+    range::ClosedByteRange,
+};
+use serde::Serialize;
+use tracing::{instrument, warn};
+
+use crate::{
+    errors::Error,
+    path::{BucketName, ObjectName},
+    Context,
+};
+
+use super::{
+    chunk_count,
+    chunks::{content_address, Chunks, CHUNK_STORE},
+    meta::{self, Meta},
+    upload, Timestamps, CHUNK_SIZE,
+};
+
+/// Number of chunks inspected at once by [`verify`].
+const VERIFY_CONCURRENCY: usize = 10;
+
+/// State of a single chunk.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ChunkHealth {
+    /// Chunk index.
+    pub index: u32,
+    /// State of the latest revision of the chunk.
+    pub state: RevisionState,
+    /// Size of the current revision, if any.
+    pub size: Option<u64>,
+}
+
+/// Health report of an object.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Health {
+    /// Size according to the metadata.
+    pub size: u64,
+    /// Number of chunks expected from `size`.
+    pub expected_chunks: u64,
+    /// Chunks found in the cloud, ordered by index.
+    pub chunks: Vec<ChunkHealth>,
+    /// Does the combined size of all complete chunks match `size`?
+    pub size_matches: bool,
+    /// Are all chunks present, complete and of the right size?
+    pub healthy: bool,
+}
+
+impl ChunkHealth {
+    /// Get the health of a chunk file, unless it's deleted or has no revisions.
+    #[must_use]
+    pub fn from_file(index: u32, file: &ListedFile) -> Option<Self> {
+        if file.deleted.is_some() {
+            return None;
+        }
+
+        let rev = file
+            .latest_revision
+            .as_ref()
+            .or(file.current_revision.as_ref())?;
+
+        Some(Self {
+            index,
+            size: rev.size,
+            state: rev.state,
+        })
+    }
+}
+
+impl Health {
+    /// Assess the health of an object given its size and the chunks found.
+    #[must_use]
+    pub fn assess(size: u64, mut chunks: Vec<ChunkHealth>) -> Self {
+        chunks.sort_by_key(|c| c.index);
+
+        let expected_chunks = chunk_count(size);
+        let total: u64 = chunks.iter().filter_map(|c| c.size).sum();
+        let size_matches = total == size;
+
+        let healthy = size_matches
+            && chunks.len() as u64 == expected_chunks
+            && chunks
+                .iter()
+                .enumerate()
+                .all(|(i, c)| c.index as usize == i && c.state == RevisionState::Completed);
+
+        Self {
+            size,
+            expected_chunks,
+            chunks,
+            size_matches,
+            healthy,
+        }
+    }
+}
+
+/// Check the health of an object by comparing its chunks to its metadata.
+///
+/// # Errors
+///
+/// - no such object
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn check(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> crate::Result<Health> {
+    let meta = meta::get(ctx, bucket, name).await?;
+
+    let chunks = match meta.chunks {
+        None => {
+            let folder = ctx.fs.index(&ctx.object_path(bucket, name)).await?;
+
+            folder
+                .files
+                .inner
+                .iter()
+                .filter_map(|f| ChunkHealth::from_file(f.name.parse().ok()?, f))
+                .collect()
+        }
+        Some(ref addresses) => {
+            let store = ctx.fs.index(&ctx.path(CHUNK_STORE)).await?;
+
+            let files = store
+                .files
+                .inner
+                .iter()
+                .map(|f| (f.name.as_str(), f))
+                .collect::<HashMap<_, _>>();
+
+            addresses
+                .iter()
+                .zip(0..)
+                .filter_map(|(address, index)| {
+                    ChunkHealth::from_file(index, files.get(address.as_str())?)
+                })
+                .collect()
+        }
+    };
+
+    Ok(Health::assess(meta.size, chunks))
+}
+
+/// What is wrong with a chunk.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkProblem {
+    /// There is no chunk.
+    Missing,
+    /// The chunk has never been completely uploaded.
+    Incomplete,
+    /// Jottacloud considers the chunk corrupt, usually because of a
+    /// checksum mismatch.
+    Corrupt,
+    /// The chunk is complete but doesn't have the size implied by the
+    /// object metadata.
+    WrongSize {
+        /// Size according to the metadata.
+        expected: u64,
+        /// Size of the chunk, if known.
+        actual: Option<u64>,
+    },
+}
+
+impl ChunkProblem {
+    /// Inspect the revision that would be served, falling back to the
+    /// latest one if there is no current revision.
+    fn inspect(file: &FileDetail, expected: u64) -> Option<Self> {
+        let Some(rev) = file
+            .current_revision
+            .as_ref()
+            .or(file.latest_revision.as_ref())
+        else {
+            return Some(Self::Missing);
+        };
+
+        match rev.state {
+            RevisionState::Incomplete => Some(Self::Incomplete),
+            RevisionState::Corrupt => Some(Self::Corrupt),
+            RevisionState::Completed if rev.size == Some(expected) => None,
+            RevisionState::Completed => Some(Self::WrongSize {
+                expected,
+                actual: rev.size,
+            }),
+        }
+    }
+}
+
+/// A chunk that can't be served as is.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct BadChunk {
+    /// Chunk index.
+    pub index: u32,
+    /// What is wrong with it.
+    pub problem: ChunkProblem,
+}
+
+/// Result of [`verify`].
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Size according to the metadata.
+    pub size: u64,
+    /// Number of chunks inspected.
+    pub chunks: u64,
+    /// Chunks with problems, ordered by index.
+    pub bad_chunks: Vec<BadChunk>,
+}
+
+impl VerifyReport {
+    /// Are all chunks fine?
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.bad_chunks.is_empty()
+    }
+}
+
+/// Byte range of a chunk within an object of `size` bytes.
+fn chunk_range(size: u64, index: u32) -> ClosedByteRange {
+    let start = u64::from(index) * CHUNK_SIZE as u64;
+
+    ClosedByteRange::new(start, (size - start).min(CHUNK_SIZE as u64))
+}
+
+async fn verify_chunks(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: &Meta,
+) -> crate::Result<VerifyReport> {
+    let chunks = Chunks::from_meta(meta);
+    let count = chunk_count(meta.size);
+
+    #[allow(clippy::cast_possible_truncation)]
+    let bad_chunks = stream::iter(0..count as u32)
+        .map(|index| {
+            let chunks = &chunks;
+
+            async move {
+                let expected = chunk_range(meta.size, index).len();
+
+                let problem = match chunks.path(bucket, name, index) {
+                    None => Some(ChunkProblem::Missing),
+                    Some(path) => match ctx.fs.file_detail(&ctx.path(&path)).await {
+                        Ok(file) => ChunkProblem::inspect(&file, expected),
+                        Err(jotta::Error::NoSuchFileOrFolder) => Some(ChunkProblem::Missing),
+                        Err(e) => return Err(Error::from(e)),
+                    },
+                };
+
+                Ok(problem.map(|problem| BadChunk { index, problem }))
+            }
+        })
+        .buffered(VERIFY_CONCURRENCY)
+        .try_filter_map(future::ok)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    if !bad_chunks.is_empty() {
+        warn!("{} of {} chunks are bad", bad_chunks.len(), count);
+    }
+
+    Ok(VerifyReport {
+        size: meta.size,
+        chunks: count,
+        bad_chunks,
+    })
+}
+
+/// Inspect the state of every chunk of an object, so that corruption is
+/// surfaced rather than served.
+///
+/// # Errors
+///
+/// - no such object
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn verify(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> crate::Result<VerifyReport> {
+    let meta = meta::get(ctx, bucket, name).await?;
+
+    verify_chunks(ctx, bucket, name, &meta).await
+}
+
+/// [`verify`] an object and re-upload its bad chunks. `source` is called
+/// with the byte range of each bad chunk and must return the original
+/// bytes, for example from a local copy of the object.
+///
+/// The report from before the repair is returned.
+///
+/// # Errors
+///
+/// - no such object
+/// - `source` failing or returning the wrong number of bytes
+/// - bytes that don't match the content address of a
+///   [content-addressed](crate::ChunkLayout::ContentAddressed) chunk
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, name, source),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn repair<F, Fut>(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    mut source: F,
+) -> crate::Result<VerifyReport>
+where
+    F: FnMut(ClosedByteRange) -> Fut,
+    Fut: Future<Output = io::Result<Bytes>>,
+{
+    let meta = meta::get(ctx, bucket, name).await?;
+    let report = verify_chunks(ctx, bucket, name, &meta).await?;
+    let chunks = Chunks::from_meta(&meta);
+
+    for bad in &report.bad_chunks {
+        let range = chunk_range(meta.size, bad.index);
+        let body = source(range).await?;
+
+        if body.len() as u64 != range.len() {
+            return Err(Error::InvalidInput(format!(
+                "got {} bytes for chunk {}, expected {}",
+                body.len(),
+                bad.index,
+                range.len()
+            )));
+        }
+
+        let md5 = md5::compute(&body);
+
+        if let Chunks::ContentAddressed(ref addresses) = chunks {
+            let address = content_address(&md5, range.len());
+
+            if addresses.get(bad.index as usize) != Some(&address) {
+                return Err(Error::InvalidInput(format!(
+                    "bytes of chunk {} don't match its content address",
+                    bad.index
+                )));
+            }
+        }
+
+        upload(
+            ctx,
+            bucket,
+            name,
+            &chunks,
+            bad.index,
+            body,
+            md5,
+            Timestamps::default(),
+        )
+        .await?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use jotta::{
+        jfs::{FileDetail, ListedFile, Revision, RevisionState, Revisions},
+        path::AbsolutePath,
+        range::ClosedByteRange,
+    };
+    use uuid::Uuid;
+
+    use super::{chunk_range, ChunkHealth, ChunkProblem, Health};
+    use crate::object::CHUNK_SIZE;
+
+    fn file(name: &str, state: RevisionState, size: u64) -> ListedFile {
+        ListedFile {
+            name: name.into(),
+            uuid: Uuid::nil(),
+            deleted: None,
+            current_revision: Some(Revision {
+                number: 1,
+                state,
+                created: None,
+                modified: None,
+                mime: "APPLICATION_OCTET_STREAM".into(),
+                size: Some(size),
+                md5: md5::compute([]),
+                updated: None,
+            }),
+            latest_revision: None,
+        }
+    }
+
+    fn chunks(files: &[ListedFile]) -> Vec<ChunkHealth> {
+        files
+            .iter()
+            .filter_map(|f| ChunkHealth::from_file(f.name.parse().ok()?, f))
+            .collect()
+    }
+
+    #[test]
+    fn healthy_object() {
+        let size = CHUNK_SIZE as u64 + 100;
+        let files = vec![
+            file("meta", RevisionState::Completed, 64),
+            file("1", RevisionState::Completed, 100),
+            file("0", RevisionState::Completed, CHUNK_SIZE as u64),
+        ];
+
+        let health = Health::assess(size, chunks(&files));
+
+        assert_eq!(health.expected_chunks, 2);
+        assert_eq!(health.chunks[0].index, 0);
+        assert!(health.size_matches);
+        assert!(health.healthy);
+    }
+
+    #[test]
+    fn corrupt_object() {
+        let size = CHUNK_SIZE as u64 + 100;
+        let files = vec![
+            file("0", RevisionState::Completed, CHUNK_SIZE as u64),
+            file("1", RevisionState::Corrupt, 100),
+        ];
+
+        let health = Health::assess(size, chunks(&files));
+
+        assert!(health.size_matches);
+        assert!(!health.healthy);
+
+        let missing = Health::assess(size, chunks(&[file("1", RevisionState::Completed, 100)]));
+
+        assert!(!missing.size_matches);
+        assert!(!missing.healthy);
+    }
+
+    fn detail(current: Option<Revision>, latest: Option<Revision>) -> FileDetail {
+        FileDetail {
+            name: "0".into(),
+            uuid: Uuid::nil(),
+            path: AbsolutePath("/alice/Jotta/Archive".into()),
+            abspath: AbsolutePath("/alice/Jotta/Archive".into()),
+            latest_revision: latest,
+            current_revision: current,
+            revisions: Revisions::default(),
+        }
+    }
+
+    fn revision(state: RevisionState, size: Option<u64>) -> Revision {
+        Revision {
+            number: 1,
+            state,
+            created: None,
+            modified: None,
+            mime: "APPLICATION_OCTET_STREAM".into(),
+            size,
+            md5: md5::compute([]),
+            updated: None,
+        }
+    }
+
+    #[test]
+    fn chunk_problems() {
+        use RevisionState::{Completed, Corrupt, Incomplete};
+
+        let inspect = |current, latest| ChunkProblem::inspect(&detail(current, latest), 100);
+
+        assert_eq!(inspect(Some(revision(Completed, Some(100))), None), None);
+        // a failed upload doesn't affect the current revision
+        assert_eq!(
+            inspect(
+                Some(revision(Completed, Some(100))),
+                Some(revision(Corrupt, None))
+            ),
+            None
+        );
+        assert_eq!(
+            inspect(None, Some(revision(Corrupt, None))),
+            Some(ChunkProblem::Corrupt)
+        );
+        assert_eq!(
+            inspect(None, Some(revision(Incomplete, Some(50)))),
+            Some(ChunkProblem::Incomplete)
+        );
+        assert_eq!(
+            inspect(Some(revision(Completed, Some(99))), None),
+            Some(ChunkProblem::WrongSize {
+                expected: 100,
+                actual: Some(99)
+            })
+        );
+        assert_eq!(inspect(None, None), Some(ChunkProblem::Missing));
+    }
+
+    #[test]
+    fn chunk_ranges() {
+        let size = CHUNK_SIZE as u64 * 2 + 100;
+
+        assert_eq!(
+            chunk_range(size, 0),
+            ClosedByteRange::new(0, CHUNK_SIZE as u64)
+        );
+        assert_eq!(
+            chunk_range(size, 2),
+            ClosedByteRange::new(CHUNK_SIZE as u64 * 2, 100)
+        );
+    }
+}
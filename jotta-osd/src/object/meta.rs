@@ -1,32 +1,132 @@
 //! Object metadata.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
 use derive_more::Display;
-use jotta::{
-    auth::TokenStore,
-    files::{AllocReq, ConflictHandler, UploadRes},
-    path::{PathOnDevice, UserScopedPath},
-    range::OpenByteRange,
-};
+use jotta::{auth::TokenStore, files::ConflictHandler, range::OpenByteRange};
 use mime::Mime;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use time::OffsetDateTime;
-use tracing::{error, instrument, warn};
+use tracing::{error, instrument, trace, warn};
 
-use crate::{errors::Error, serde::NullAsDefault};
+use crate::{
+    errors::Error,
+    serde::{Md5Concat, Md5Hex, NullAsDefault, Timestamp},
+};
 use crate::{path::BucketName, Context};
 
-use super::ObjectName;
+use super::{chunk_count, chunks::address_digest, ObjectName};
 
-/// `Cache-Control` directive.
+/// `Cache-Control` directive. An empty directive means that no
+/// `Cache-Control` header should be sent at all.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct CacheControl(pub String);
 
+impl CacheControl {
+    /// No cache control at all.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::CacheControl;
+    ///
+    /// assert!(CacheControl::none().is_none());
+    /// assert!(!CacheControl::default().is_none());
+    /// ```
+    #[must_use]
+    pub fn none() -> Self {
+        Self(String::new())
+    }
+
+    /// Should the `Cache-Control` header be omitted?
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
 impl Default for CacheControl {
     fn default() -> Self {
         Self("public, max-age=3600".into())
     }
 }
 
+/// Maximum length of a [`CacheControl`] parsed from a string.
+pub const MAX_CACHE_CONTROL_LEN: usize = 256;
+
+/// Invalid `Cache-Control` value.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ParseCacheControlError {
+    /// The value is longer than [`MAX_CACHE_CONTROL_LEN`].
+    #[error("cache control is too long ({0} bytes, max {MAX_CACHE_CONTROL_LEN})")]
+    TooLong(usize),
+    /// Control characters and non-ASCII characters aren't allowed in headers.
+    #[error("cache control contains invalid characters")]
+    InvalidCharacter,
+    /// A directive isn't of the form `key` or `key=value`.
+    #[error("invalid cache control directive `{0}`")]
+    InvalidDirective(String),
+}
+
+/// Is `s` a token, as defined in RFC 7230?
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+impl FromStr for CacheControl {
+    type Err = ParseCacheControlError;
+
+    /// Parse and validate a list of `Cache-Control` directives. An empty
+    /// string means no cache control.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::CacheControl;
+    ///
+    /// assert!("public, max-age=60".parse::<CacheControl>().is_ok());
+    /// assert!("private=\"set-cookie\"".parse::<CacheControl>().is_ok());
+    /// assert!("".parse::<CacheControl>().unwrap().is_none());
+    ///
+    /// assert!("max-age=60\r\nx-evil: 1".parse::<CacheControl>().is_err());
+    /// assert!("max age".parse::<CacheControl>().is_err());
+    /// assert!("a".repeat(1000).parse::<CacheControl>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_CACHE_CONTROL_LEN {
+            return Err(ParseCacheControlError::TooLong(s.len()));
+        }
+
+        if s.chars().any(|c| !c.is_ascii() || c.is_ascii_control()) {
+            return Err(ParseCacheControlError::InvalidCharacter);
+        }
+
+        let s = s.trim();
+
+        if s.is_empty() {
+            return Ok(Self::none());
+        }
+
+        for directive in s.split(',').map(str::trim) {
+            let valid = match directive.split_once('=') {
+                None => is_token(directive),
+                Some((key, value)) => {
+                    is_token(key)
+                        && (is_token(value)
+                            || (value.len() >= 2
+                                && value.starts_with('"')
+                                && value.ends_with('"')
+                                && !value[1..value.len() - 1].contains('"')))
+                }
+            };
+
+            if !valid {
+                return Err(ParseCacheControlError::InvalidDirective(directive.into()));
+            }
+        }
+
+        Ok(Self(s.into()))
+    }
+}
+
 /// Object content type.
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Display)]
@@ -38,7 +138,129 @@ impl Default for ContentType {
     }
 }
 
+impl ContentType {
+    /// Guess the content type from the extension of an object name,
+    /// falling back to the default `application/octet-stream`.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::ContentType;
+    ///
+    /// let guess = |name: &str| ContentType::from_name(&name.parse().unwrap()).to_string();
+    ///
+    /// assert_eq!(guess("cat.jpeg"), "image/jpeg");
+    /// assert_eq!(guess("cat"), "application/octet-stream");
+    /// ```
+    #[must_use]
+    pub fn from_name(name: &ObjectName) -> Self {
+        super::sniff::guess_from_name(name).unwrap_or_default()
+    }
+}
+
+/// Maximum length of a [`ContentType`] parsed from a string.
+pub const MAX_CONTENT_TYPE_LEN: usize = 256;
+
+/// Invalid content type.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseContentTypeError {
+    /// The value is longer than [`MAX_CONTENT_TYPE_LEN`].
+    #[error("content type is too long ({0} bytes, max {MAX_CONTENT_TYPE_LEN})")]
+    TooLong(usize),
+    /// Not a valid media type.
+    #[error("invalid content type: {0}")]
+    Mime(#[from] mime::FromStrError),
+}
+
+impl FromStr for ContentType {
+    type Err = ParseContentTypeError;
+
+    /// Parse a media type of at most [`MAX_CONTENT_TYPE_LEN`] bytes.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::ContentType;
+    ///
+    /// assert_eq!("image/png".parse::<ContentType>().unwrap().to_string(), "image/png");
+    /// assert!("image".parse::<ContentType>().is_err());
+    /// assert!(format!("text/{}", "x".repeat(300)).parse::<ContentType>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_CONTENT_TYPE_LEN {
+            return Err(ParseContentTypeError::TooLong(s.len()));
+        }
+
+        Ok(Self(s.trim().parse()?))
+    }
+}
+
+/// Encoding of the stored bytes, e.g. compression applied before the
+/// object was uploaded. Ranges always refer to the stored bytes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentEncoding {
+    /// No encoding.
+    #[default]
+    Identity,
+    /// Gzip.
+    Gzip,
+    /// Brotli.
+    Br,
+    /// Zlib.
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The `Content-Encoding` token.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Br => "br",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Unsupported content encoding.
+#[derive(Debug, thiserror::Error)]
+#[error("unsupported content encoding `{0}`, expected gzip, br, deflate or identity")]
+pub struct ParseContentEncodingError(String);
+
+impl FromStr for ContentEncoding {
+    type Err = ParseContentEncodingError;
+
+    /// Parse a `Content-Encoding` token.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::ContentEncoding;
+    ///
+    /// assert_eq!("GZIP".parse::<ContentEncoding>().unwrap(), ContentEncoding::Gzip);
+    /// assert!("zstd".parse::<ContentEncoding>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ContentEncoding::Identity,
+            ContentEncoding::Gzip,
+            ContentEncoding::Br,
+            ContentEncoding::Deflate,
+        ]
+        .into_iter()
+        .find(|e| e.as_str().eq_ignore_ascii_case(s.trim()))
+        .ok_or_else(|| ParseContentEncodingError(s.into()))
+    }
+}
+
 /// Metadata associated with each object.
+///
+/// Unknown fields are ignored when deserializing, so metadata written by
+/// newer versions of this crate can still be read. New fields must be
+/// appended and have a default, since the metadata is encoded as an array.
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Meta {
     /// Size of the object in bytes.
@@ -46,69 +268,216 @@ pub struct Meta {
     // /// CRC32 checksum.
     // pub crc32c: u32,
     /// Creation timestamp.
-    #[serde(with = "time::serde::rfc3339")]
+    #[serde_as(as = "Timestamp")]
     pub created: OffsetDateTime,
     /// Update timestamp.
-    #[serde(with = "time::serde::rfc3339")]
+    #[serde_as(as = "Timestamp")]
     pub updated: OffsetDateTime,
     /// Media type of the object.
     pub content_type: ContentType,
     /// Cache control.
     pub cache_control: CacheControl,
+    /// Content addresses of the chunks if the object uses
+    /// [`ChunkLayout::ContentAddressed`](crate::ChunkLayout::ContentAddressed).
+    #[serde(default)]
+    pub chunks: Option<Vec<String>>,
+    /// Encoding of the stored bytes.
+    #[serde(default)]
+    pub content_encoding: ContentEncoding,
+    /// MD5 digest of the whole object, if known. Writes that don't cover
+    /// the entire object reset it, in which case it can be recomputed with
+    /// [`compute_digest`](crate::object::compute_digest).
+    #[serde_as(as = "Option<Md5Hex>")]
+    #[serde(default)]
+    pub md5: Option<md5::Digest>,
+    /// Arbitrary key-value pairs set by the user.
+    #[serde(default)]
+    pub user_metadata: BTreeMap<String, String>,
+    /// The content type wasn't specified when the object was created, so
+    /// it's guessed from the first bytes whenever they're written. See
+    /// [`Config::sniff_content_type`](crate::Config::sniff_content_type).
+    #[serde(default)]
+    pub sniff_content_type: bool,
+    /// MD5 digests of the chunks of an object with
+    /// [`ChunkLayout::Indexed`](crate::ChunkLayout::Indexed), in order.
+    /// `None` if they aren't all known, for example because the object
+    /// was written before they were recorded, has a gap or has more than
+    /// [`MAX_CHUNK_DIGESTS`](super::MAX_CHUNK_DIGESTS) chunks. See
+    /// [`Meta::etag`].
+    #[serde_as(as = "Option<Md5Concat>")]
+    #[serde(default)]
+    pub chunk_md5s: Option<Vec<md5::Digest>>,
+}
+
+/// An empty object with default attributes, created and updated at the
+/// Unix epoch. Mostly useful as a base for struct update syntax, since
+/// new fields then only need a default here.
+///
+/// ```
+/// use jotta_osd::object::meta::Meta;
+///
+/// let meta = Meta { size: 42, ..Meta::default() };
+///
+/// assert_eq!(meta.size, 42);
+/// assert_eq!(meta.md5, None);
+/// ```
+impl Default for Meta {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            created: OffsetDateTime::UNIX_EPOCH,
+            updated: OffsetDateTime::UNIX_EPOCH,
+            content_type: ContentType::default(),
+            cache_control: CacheControl::default(),
+            chunks: None,
+            content_encoding: ContentEncoding::default(),
+            md5: None,
+            user_metadata: BTreeMap::new(),
+            sniff_content_type: false,
+            chunk_md5s: None,
+        }
+    }
 }
 
 impl Meta {
+    /// MD5 digests of all chunks, if known.
+    fn chunk_digests(&self) -> Option<Vec<md5::Digest>> {
+        let digests = match self.chunks {
+            Some(ref addresses) => addresses
+                .iter()
+                .map(|a| address_digest(a))
+                .collect::<Option<Vec<_>>>()?,
+            None => self.chunk_md5s.clone()?,
+        };
+
+        let count = usize::try_from(chunk_count(self.size)).ok()?;
+
+        (digests.len() >= count).then(|| digests[..count].to_vec())
+    }
+
+    /// Entity tag in the format used by S3 for multipart uploads, with
+    /// each chunk as a part: the MD5 of the concatenated chunk digests
+    /// followed by the number of chunks. Objects with at most one chunk
+    /// get the plain MD5 of their contents.
+    ///
+    /// Unlike [`ObjectStat::etag`](super::ObjectStat::etag), it only
+    /// depends on the contents. `None` if the chunk digests aren't known
+    /// (see [`Meta::chunk_md5s`]).
+    #[must_use]
+    pub fn etag(&self) -> Option<String> {
+        let digests = self.chunk_digests()?;
+
+        match digests.as_slice() {
+            [] => Some(format!("{:x}", md5::compute([]))),
+            [digest] => Some(format!("{digest:x}")),
+            digests => {
+                let mut hasher = md5::Context::new();
+
+                for digest in digests {
+                    hasher.consume(digest.0);
+                }
+
+                Some(format!("{:x}-{}", hasher.compute(), digests.len()))
+            }
+        }
+    }
+
     /// Patch the metadata.
     pub fn patch(&mut self, patch: Patch) {
         let Patch {
             content_type,
             cache_control,
+            content_encoding,
+            user_metadata,
         } = patch;
 
         if let Some(content_type) = content_type {
             self.content_type = content_type;
+            self.sniff_content_type = false;
         }
 
         if let Some(cache_control) = cache_control {
             self.cache_control = cache_control;
         }
+
+        if let Some(content_encoding) = content_encoding {
+            self.content_encoding = content_encoding;
+        }
+
+        if let Some(user_metadata) = user_metadata {
+            self.user_metadata = user_metadata;
+        }
+    }
+}
+
+/// Encode metadata, making sure that it isn't larger than `max` bytes.
+pub(crate) fn encode(meta: &impl Serialize, max: usize) -> crate::Result<Vec<u8>> {
+    let body = rmp_serde::to_vec(meta)?;
+
+    if body.len() > max {
+        return Err(Error::MetaTooLarge {
+            size: body.len(),
+            max,
+        });
     }
+
+    Ok(body)
 }
 
 /// Set the metadata of an object.
+///
+/// If `expected_revision` is `Some`, the current revision of the
+/// metadata file is compared to it before anything is written. A
+/// mismatch yields [`Error::PreconditionFailed`].
+#[instrument(
+    skip(ctx, bucket, object, meta),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
 pub(crate) async fn set_raw(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     object: &ObjectName,
     meta: &Meta,
     conflict_handler: ConflictHandler,
+    expected_revision: Option<u32>,
 ) -> crate::Result<()> {
-    let body = rmp_serde::to_vec(&meta)?;
-    let bytes = body.len().try_into().unwrap();
+    if let Some(expected) = expected_revision {
+        let actual = revision(ctx, bucket, object).await?;
 
-    let req = AllocReq {
-        path: &PathOnDevice(format!(
-            "{}/{}/{}/meta",
-            ctx.root_on_device(),
-            bucket,
-            object.to_hex()
-        )),
-        bytes,
-        md5: md5::compute(&body),
-        conflict_handler,
-        created: None,
-        modified: None,
-    };
+        if actual != Some(expected) {
+            warn!("expected revision {}, found {:?}", expected, actual);
+            return Err(Error::PreconditionFailed);
+        }
+    }
 
-    let upload_url = ctx.fs.allocate(&req).await?.upload_url;
+    let res = upload(ctx, bucket, object, meta, conflict_handler).await;
 
-    match ctx.fs.upload_range(&upload_url, body, 0..=bytes).await? {
-        UploadRes::Complete(_) => Ok(()),
-        UploadRes::Incomplete(_) => {
-            warn!("metadata did not completely upload");
-            Err(Error::Fs(jotta::Error::IncompleteUpload))
-        }
+    // invalidate even if the upload failed, since it may have gotten through
+    if let Some(cache) = &ctx.meta_cache {
+        cache.invalidate(bucket, object);
     }
+
+    res
+}
+
+async fn upload(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+    meta: &Meta,
+    conflict_handler: ConflictHandler,
+) -> crate::Result<()> {
+    let body = encode(meta, ctx.config.max_meta_size)?;
+
+    ctx.fs
+        .upload_bytes(
+            &ctx.meta_path_on_device(bucket, object),
+            body.into(),
+            conflict_handler,
+        )
+        .await?;
+
+    Ok(())
 }
 
 /// A object metadata patch.
@@ -116,18 +485,30 @@ pub(crate) async fn set_raw(
 /// `null` will be converted to `Some(Default::Default)` while absent
 /// fields are treated as `None`. This way, `null` can be used to
 /// reset field values.
+///
+/// Content types and cache control directives are validated (see
+/// [`ContentType::from_str`] and [`CacheControl::from_str`]) when
+/// deserializing.
 #[serde_as]
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq, Default)]
 #[serde(deny_unknown_fields)] // don't make clients think that read-only fields are writable
 pub struct Patch {
     /// Media type of the object.
-    #[serde_as(as = "NullAsDefault<ContentType>")]
+    #[serde_as(as = "NullAsDefault<ContentType, DisplayFromStr>")]
     #[serde(default)]
     pub content_type: Option<ContentType>,
     /// Cache control.
-    #[serde_as(as = "NullAsDefault<CacheControl>")]
+    #[serde_as(as = "NullAsDefault<CacheControl, DisplayFromStr>")]
     #[serde(default)]
     pub cache_control: Option<CacheControl>,
+    /// Encoding of the stored bytes.
+    #[serde_as(as = "NullAsDefault<ContentEncoding>")]
+    #[serde(default)]
+    pub content_encoding: Option<ContentEncoding>,
+    /// User metadata. It replaces all existing user metadata.
+    #[serde_as(as = "NullAsDefault<BTreeMap<String, String>>")]
+    #[serde(default)]
+    pub user_metadata: Option<BTreeMap<String, String>>,
 }
 
 impl Patch {
@@ -136,12 +517,40 @@ impl Patch {
     /// ```
     /// use jotta_osd::object::meta::Patch;
     ///
-    /// assert!(Patch { content_type: None, cache_control: None }.is_empty());
+    /// assert!(Patch { content_type: None, cache_control: None, content_encoding: None, user_metadata: None }.is_empty());
     /// ```
     #[must_use]
     pub fn is_empty(&self) -> bool {
         *self == Self::default()
     }
+
+    /// Combine two patches into one that has the same effect as applying
+    /// `self` and then `other`, so that independent changes can be made
+    /// with a single write. Fields set in `other`, including those reset
+    /// with `null`, take precedence.
+    ///
+    /// ```
+    /// use jotta_osd::object::meta::{CacheControl, Patch};
+    ///
+    /// let reset: Patch = serde_json::from_str(r#"{ "content_type": null }"#).unwrap();
+    /// let cache = Patch {
+    ///     cache_control: Some(CacheControl("no-cache".into())),
+    ///     ..Patch::default()
+    /// };
+    ///
+    /// let merged = reset.clone().merge(cache.clone());
+    /// assert_eq!(merged.content_type, reset.content_type);
+    /// assert_eq!(merged.cache_control, cache.cache_control);
+    /// ```
+    #[must_use]
+    pub fn merge(self, other: Patch) -> Patch {
+        Self {
+            content_type: other.content_type.or(self.content_type),
+            cache_control: other.cache_control.or(self.cache_control),
+            content_encoding: other.content_encoding.or(self.content_encoding),
+            user_metadata: other.user_metadata.or(self.user_metadata),
+        }
+    }
 }
 
 impl From<Meta> for Patch {
@@ -152,28 +561,61 @@ impl From<Meta> for Patch {
             updated: _,
             content_type,
             cache_control,
+            chunks: _,
+            content_encoding,
+            md5: _,
+            user_metadata,
+            sniff_content_type: _,
+            chunk_md5s: _,
         } = m;
 
         Self {
             content_type: Some(content_type),
             cache_control: Some(cache_control),
+            content_encoding: Some(content_encoding),
+            user_metadata: Some(user_metadata),
         }
     }
 }
 
 /// Patch metadata. If the patch is empty, no patch is made.
 ///
+/// Pass an `expected_etag` (see [`etag`](super::etag)) to make the patch
+/// conditional. This prevents concurrent patches from silently
+/// overwriting each other.
+///
+/// The metadata is always read from Jottacloud rather than from the
+/// cache, since patching stale metadata would revert the fields that
+/// aren't patched. The revision is read before the metadata and checked
+/// again before writing, so a write in between is caught.
+///
 /// # Errors
 ///
 /// - network errors
 /// - no remote metadata to patch
+/// - `expected_etag` doesn't match the current metadata
 pub async fn patch(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     object: &ObjectName,
     patch: Patch,
+    expected_etag: Option<&str>,
 ) -> crate::Result<Meta> {
-    let mut meta = get(ctx, bucket, object).await?;
+    let expected_revision = match expected_etag {
+        Some(_) => revision(ctx, bucket, object).await?,
+        None => None,
+    };
+
+    let mut meta = fetch(ctx, bucket, object).await?;
+
+    if let Some(expected) = expected_etag {
+        let actual = super::etag(&meta);
+
+        if actual != expected {
+            warn!("expected entity tag {}, found {}", expected, actual);
+            return Err(Error::PreconditionFailed);
+        }
+    }
 
     if !patch.is_empty() {
         meta.patch(patch);
@@ -186,6 +628,7 @@ pub async fn patch(
             object,
             &meta,
             ConflictHandler::CreateNewRevision,
+            expected_revision,
         )
         .await?;
     }
@@ -193,24 +636,65 @@ pub async fn patch(
     Ok(meta)
 }
 
-/// Get metadata associated with an object.
-#[instrument(skip(ctx))]
+/// Get the current revision number of the metadata. It is incremented
+/// every time the metadata is written.
+///
+/// # Errors
+///
+/// - network errors
+/// - no such object
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+pub async fn revision(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+) -> crate::Result<Option<u32>> {
+    let detail = ctx.fs.file_detail(&ctx.meta_path(bucket, object)).await?;
+
+    Ok(detail.current_revision.map(|r| r.number))
+}
+
+/// Get metadata associated with an object. It's served from the cache
+/// if [`Config::meta_cache`](crate::Config::meta_cache) is enabled.
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
 pub async fn get(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     name: &ObjectName,
+) -> crate::Result<Meta> {
+    let Some(cache) = &ctx.meta_cache else {
+        return fetch(ctx, bucket, name).await;
+    };
+
+    if let Some(meta) = cache.get(bucket, name) {
+        trace!("metadata cache hit");
+        return Ok(meta);
+    }
+
+    // writes that finish while fetching invalidate what is fetched
+    let generation = cache.generation();
+    let meta = fetch(ctx, bucket, name).await?;
+
+    cache.insert(bucket, name, Meta::clone(&meta), generation);
+
+    Ok(meta)
+}
+
+/// Download the metadata of an object, bypassing the cache.
+async fn fetch(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
 ) -> crate::Result<Meta> {
     let msg = ctx
         .fs
-        .file_to_bytes(
-            &UserScopedPath(format!(
-                "{}/{}/{}/meta",
-                ctx.user_scoped_root(),
-                bucket,
-                name.to_hex()
-            )),
-            OpenByteRange::full(),
-        )
+        .file_to_bytes(&ctx.meta_path(bucket, name), OpenByteRange::full())
         .await?;
 
     let meta = rmp_serde::from_slice(&msg).map_err(|e| {
@@ -220,3 +704,194 @@ pub async fn get(
 
     Ok(meta)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{encode, CacheControl, ContentEncoding, ContentType, Meta, Patch};
+    use crate::{
+        errors::Error,
+        object::{chunks::content_address, CHUNK_SIZE},
+    };
+
+    #[test]
+    fn meta_size_cap() {
+        let mut meta = Meta::default();
+
+        assert!(encode(&meta, 1024).is_ok());
+
+        meta.cache_control = CacheControl("a".repeat(2048));
+
+        assert!(matches!(
+            encode(&meta, 1024),
+            Err(Error::MetaTooLarge { max: 1024, .. })
+        ));
+    }
+
+    #[test]
+    fn etag() {
+        let mut meta = Meta::default();
+
+        assert_eq!(meta.etag(), None);
+
+        meta.chunk_md5s = Some(vec![]);
+        assert_eq!(
+            meta.etag().as_deref(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+
+        let (a, b) = (md5::compute("a"), md5::compute("b"));
+        meta.chunk_md5s = Some(vec![a, b]);
+        meta.size = 42;
+        assert_eq!(meta.etag(), Some(format!("{a:x}")));
+
+        meta.size = CHUNK_SIZE as u64 + 1;
+        let mut expected = md5::Context::new();
+        expected.consume(a.0);
+        expected.consume(b.0);
+        let expected = format!("{:x}-2", expected.compute());
+        assert_eq!(meta.etag(), Some(expected.clone()));
+
+        // content-addressed chunks carry their digests
+        meta.chunk_md5s = None;
+        meta.chunks = Some(vec![
+            content_address(&a, CHUNK_SIZE as u64),
+            content_address(&b, 1),
+        ]);
+        assert_eq!(meta.etag(), Some(expected));
+
+        meta.size = CHUNK_SIZE as u64 * 3;
+        assert_eq!(meta.etag(), None);
+    }
+
+    #[test]
+    fn unknown_fields() {
+        #[derive(serde::Serialize)]
+        struct Future {
+            size: u64,
+            created: &'static str,
+            updated: &'static str,
+            content_type: &'static str,
+            cache_control: &'static str,
+            chunks: Option<Vec<String>>,
+            content_encoding: &'static str,
+            md5: Option<&'static str>,
+            user_metadata: BTreeMap<&'static str, &'static str>,
+            sniff_content_type: bool,
+            chunk_md5s: Option<()>,
+            storage_class: &'static str,
+        }
+
+        let future = Future {
+            size: 42,
+            created: "2022-04-01T12:00:00Z",
+            updated: "2022-04-01T12:00:00Z",
+            content_type: "text/plain",
+            cache_control: "no-cache",
+            chunks: None,
+            content_encoding: "gzip",
+            md5: None,
+            user_metadata: BTreeMap::new(),
+            sniff_content_type: false,
+            chunk_md5s: None,
+            storage_class: "cold",
+        };
+
+        let meta: Meta = rmp_serde::from_slice(&rmp_serde::to_vec(&future).unwrap()).unwrap();
+        assert_eq!(meta.size, 42);
+
+        let meta: Meta = rmp_serde::from_slice(&rmp_serde::to_vec_named(&future).unwrap()).unwrap();
+        assert_eq!(meta.cache_control, CacheControl("no-cache".into()));
+    }
+
+    #[test]
+    fn patch_merge() {
+        let patch = |json| serde_json::from_value::<Patch>(json).unwrap();
+        let original = Meta {
+            content_type: ContentType(mime::IMAGE_PNG),
+            cache_control: CacheControl("no-store".into()),
+            content_encoding: ContentEncoding::Gzip,
+            user_metadata: BTreeMap::from([("a".into(), "b".into())]),
+            sniff_content_type: true,
+            ..Meta::default()
+        };
+
+        let cases = [
+            // reset followed by an unrelated change
+            (
+                serde_json::json!({ "content_type": null }),
+                serde_json::json!({ "cache_control": "max-age=60" }),
+            ),
+            // reset overridden by a later value, and the other way around
+            (
+                serde_json::json!({ "content_type": null, "user_metadata": { "c": "d" } }),
+                serde_json::json!({ "content_type": "text/plain", "user_metadata": null }),
+            ),
+            (
+                serde_json::json!({ "cache_control": "no-cache" }),
+                serde_json::json!({ "cache_control": null, "content_encoding": "br" }),
+            ),
+            (serde_json::json!({}), serde_json::json!({})),
+        ];
+
+        for (first, second) in cases {
+            let mut sequential = original.clone();
+            sequential.patch(patch(first.clone()));
+            sequential.patch(patch(second.clone()));
+
+            let mut merged = original.clone();
+            merged.patch(patch(first.clone()).merge(patch(second.clone())));
+
+            assert_eq!(
+                merged.sniff_content_type, sequential.sniff_content_type,
+                "{first} then {second}"
+            );
+            assert_eq!(
+                Patch::from(merged),
+                Patch::from(sequential),
+                "{first} then {second}"
+            );
+        }
+
+        let merged = patch(serde_json::json!({ "content_type": null }))
+            .merge(patch(serde_json::json!({ "cache_control": "max-age=60" })));
+        assert_eq!(merged.content_type, Some(ContentType::default()));
+        assert_eq!(
+            merged.cache_control,
+            Some(CacheControl("max-age=60".into()))
+        );
+        assert!(merged.content_encoding.is_none());
+        assert!(Patch::default().merge(Patch::default()).is_empty());
+    }
+
+    #[test]
+    fn patch_validation() {
+        let patch: Patch = serde_json::from_value(serde_json::json!({
+            "cache_control": "no-cache, max-age=0",
+            "content_type": "text/plain; charset=utf-8",
+        }))
+        .unwrap();
+        assert_eq!(
+            patch.cache_control,
+            Some(CacheControl("no-cache, max-age=0".into()))
+        );
+
+        let patch: Patch =
+            serde_json::from_value(serde_json::json!({ "cache_control": null })).unwrap();
+        assert_eq!(patch.cache_control, Some(CacheControl::default()));
+
+        for invalid in [
+            serde_json::json!({ "cache_control": "a".repeat(300) }),
+            serde_json::json!({ "cache_control": "no-cache\u{0}" }),
+            serde_json::json!({ "cache_control": "max-age=\"60" }),
+            serde_json::json!({ "content_type": "text" }),
+            serde_json::json!({ "content_type": format!("text/{}", "x".repeat(300)) }),
+        ] {
+            assert!(
+                serde_json::from_value::<Patch>(invalid.clone()).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -2,34 +2,65 @@
 //! a folder containing some files:
 //!
 //! - A `meta` file with metadata about the object.
-//! - One or more binary data chunks.
-use std::{iter, sync::Arc, time::Instant};
+//! - One or more binary data chunks, unless the chunks are content-addressed
+//!   (see [`ChunkLayout`](crate::ChunkLayout)).
+use std::{
+    collections::BTreeSet,
+    io::{self, SeekFrom},
+    num::NonZeroU32,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use crate::{
+    bucket::{self, BucketMeta},
+    concurrency::{reassemble, ConcurrencyStrategy, DownloadMode, Limiter},
+    errors::Error,
     object::meta::get,
-    path::{BucketName, ObjectName},
-    Context,
+    path::{BucketName, ObjectName, ParseObjectNameError},
+    ChunkLayout, Context,
 };
 use bytes::{Bytes, BytesMut};
 
 use futures_util::{
+    future::{self, Either},
     stream::{self},
-    AsyncBufRead, AsyncReadExt, Stream, StreamExt, TryStreamExt,
+    AsyncBufRead, AsyncBufReadExt, Stream, StreamExt, TryStreamExt,
 };
 
 use jotta::{
     auth::TokenStore,
     files::{AllocReq, ConflictHandler, UploadRes},
-    path::{PathOnDevice, UserScopedPath},
+    jfs::{FolderDetail, RevisionState},
+    path::PathOnDevice,
     range::{ByteRange, ClosedByteRange, OpenByteRange},
 };
 
-use time::OffsetDateTime;
-use tracing::{debug, instrument, trace, warn};
+use md5::Digest;
+use reqwest::{header, Body, IntoUrl, Response};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use tokio::fs::File;
+use tokio_util::{io::ReaderStream, sync::CancellationToken};
+use tracing::{debug, instrument, trace, trace_span, warn, Instrument};
 
-use self::meta::{set_raw, Meta, Patch};
+use self::{
+    chunks::{content_address, set_chunk, set_chunk_digest, Chunks, CHUNK_STORE},
+    meta::{set_raw, ContentType, Meta, Patch},
+};
 
+mod archive;
+mod chunks;
+pub(crate) mod gc;
+pub mod health;
 pub mod meta;
+mod sniff;
+pub mod txn;
+
+pub use self::chunks::MAX_CHUNK_DIGESTS;
+pub use self::gc::{gc, GcReport};
+pub use self::health::{repair, verify};
 
 /// Chunk size in bytes.
 ///
@@ -41,152 +72,817 @@ pub mod meta;
 /// for each chunk.
 pub const CHUNK_SIZE: usize = 1 << 20;
 
-/// List all objects in a bucket.
+/// How far into the future [`Timestamps`] may be, to allow for clocks
+/// that are a bit off.
+pub const MAX_TIMESTAMP_SKEW: Duration = Duration::days(1);
+
+/// Creation and modification times of a write, such as the original
+/// times of imported files. Unset times are left alone and the time of
+/// the write is used as the modification time.
+///
+/// The times are also passed on to Jottacloud for every uploaded chunk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timestamps {
+    /// Replaces [`Meta::created`].
+    pub created: Option<OffsetDateTime>,
+    /// Replaces [`Meta::updated`].
+    pub modified: Option<OffsetDateTime>,
+}
+
+impl Timestamps {
+    /// Make sure that neither time is more than [`MAX_TIMESTAMP_SKEW`]
+    /// in the future.
+    fn check(self) -> crate::Result<()> {
+        let limit = OffsetDateTime::now_utc() + MAX_TIMESTAMP_SKEW;
+
+        for time in [self.created, self.modified].into_iter().flatten() {
+            if time > limit {
+                return Err(Error::InvalidInput(format!(
+                    "timestamp {time} is in the future"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The object stored in a bucket subfolder, or `None` if the folder
+/// name isn't the hex of a valid [`ObjectName`], which means that the
+/// folder doesn't belong to an object.
+pub(crate) fn object_in_folder(folder: &str) -> Option<ObjectName> {
+    match parse_folder_name(folder) {
+        Ok(name) => Some(name),
+        Err(e) => {
+            warn!(folder, "skipping folder that isn't an object: {e}");
+            None
+        }
+    }
+}
+
+/// Whether a folder contains live object metadata.
+pub(crate) fn has_meta(folder: &FolderDetail) -> bool {
+    folder
+        .files
+        .inner
+        .iter()
+        .any(|f| f.deleted.is_none() && f.name == "meta")
+}
+
+fn parse_folder_name(folder: &str) -> Result<ObjectName, ParseObjectNameError> {
+    ObjectName::try_from_hex(folder).and_then(|name| name.parse())
+}
+
+/// List all objects in a bucket. Folders that don't belong to objects
+/// are skipped with a warning. Use [`list_strict`] to fail instead.
 ///
 /// # Errors
 ///
 /// Returns an error if there is no bucket with the specified name.
-#[instrument(skip(ctx))]
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
 pub async fn list(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
 ) -> crate::Result<Vec<ObjectName>> {
-    let folders = ctx
-        .fs
-        .index(&UserScopedPath(format!(
-            "{}/{}",
-            ctx.user_scoped_root(),
-            bucket,
-        )))
-        .await?
-        .folders
-        .inner;
+    let folders = ctx.fs.index(&ctx.bucket_path(bucket)).await?.folders.inner;
+
+    Ok(folders
+        .into_iter()
+        .filter_map(|f| object_in_folder(&f.name))
+        .collect())
+}
+
+/// Like [`list`], but fails if any folder in the bucket doesn't belong
+/// to an object.
+///
+/// # Errors
+///
+/// - no such bucket
+/// - [`Error::ParseObjectName`] if a folder name isn't the hex of a
+///   valid object name
+pub async fn list_strict(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+) -> crate::Result<Vec<ObjectName>> {
+    let folders = ctx.fs.index(&ctx.bucket_path(bucket)).await?.folders.inner;
 
     folders
         .into_iter()
-        .map(|f| {
-            ObjectName::try_from_hex(&f.name)
-                .map(Into::into)
-                .map_err(Into::into)
+        .map(|f| parse_folder_name(&f.name).map_err(Into::into))
+        .collect()
+}
+
+/// List the objects in a bucket incrementally, fetching `page_size`
+/// entries of the bucket folder at a time. Like [`list`], folders that
+/// don't belong to objects are skipped.
+///
+/// Errors, such as the bucket being deleted while it's being listed,
+/// are yielded as items. The stream ends after the first error.
+pub fn stream_list<'a, S: TokenStore>(
+    ctx: &'a Context<S>,
+    bucket: &'a BucketName,
+    page_size: NonZeroU32,
+) -> impl Stream<Item = crate::Result<ObjectName>> + 'a {
+    let pages = stream::try_unfold(Some(0), move |first| async move {
+        let Some(first) = first else {
+            return crate::Result::Ok(None);
+        };
+
+        let page = ctx
+            .fs
+            .index_page(&ctx.bucket_path(bucket), first, page_size.get())
+            .await?;
+        let entries = page.folders.inner.len() + page.files.inner.len();
+        let next = first.saturating_add(u32::try_from(entries).unwrap_or(u32::MAX));
+
+        let done = match page.metadata {
+            Some(ref metadata) => next >= metadata.total,
+            None => entries < page_size.get() as usize,
+        };
+
+        trace!(first, entries, done, "listed page");
+
+        let names = page
+            .folders
+            .inner
+            .into_iter()
+            .filter_map(|f| object_in_folder(&f.name).map(Ok))
+            .collect::<Vec<_>>();
+
+        Ok(Some((names, (!done && entries > 0).then_some(next))))
+    });
+
+    pages
+        .map(|page| match page {
+            Ok(names) => Either::Left(stream::iter(names)),
+            Err(e) => Either::Right(stream::once(future::err(e))),
+        })
+        .flatten()
+}
+
+/// List all objects in a bucket whose names start with `prefix`.
+///
+/// # Errors
+///
+/// Returns an error if there is no bucket with the specified name.
+pub async fn list_prefixed(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    prefix: &str,
+) -> crate::Result<Vec<ObjectName>> {
+    let mut names = list(ctx, bucket).await?;
+
+    names.retain(|n| n.starts_with(prefix));
+
+    Ok(names)
+}
+
+/// Objects and common prefixes, similar to what S3 returns when listing
+/// objects with a delimiter.
+#[derive(Debug, Serialize, PartialEq, Eq, Default)]
+pub struct Listing {
+    /// Objects that don't contain the delimiter after the prefix.
+    pub objects: Vec<ObjectName>,
+    /// Distinct prefixes up to and including the first delimiter after
+    /// the prefix. These can be thought of as folders.
+    pub common_prefixes: Vec<String>,
+}
+
+impl Listing {
+    /// Group object names by `delimiter`. Names not starting with `prefix`
+    /// are ignored.
+    ///
+    /// ```
+    /// use jotta_osd::object::Listing;
+    ///
+    /// let names = ["a.txt", "photos/cat.jpeg", "photos/2022/dog.jpeg", "b.txt"]
+    ///     .iter()
+    ///     .map(|n| n.parse().unwrap());
+    ///
+    /// let listing = Listing::group(names, "photos/", "/");
+    ///
+    /// assert_eq!(listing.objects, vec!["photos/cat.jpeg".parse().unwrap()]);
+    /// assert_eq!(listing.common_prefixes, vec!["photos/2022/"]);
+    /// ```
+    #[must_use]
+    pub fn group(
+        names: impl IntoIterator<Item = ObjectName>,
+        prefix: &str,
+        delimiter: &str,
+    ) -> Self {
+        let mut objects = Vec::new();
+        let mut common_prefixes = BTreeSet::new();
+
+        for name in names {
+            let Some(rest) = name.strip_prefix(prefix) else {
+                continue;
+            };
+
+            match rest.find(delimiter) {
+                Some(i) if !delimiter.is_empty() => {
+                    let common = &name[..prefix.len() + i + delimiter.len()];
+
+                    if !common_prefixes.contains(common) {
+                        common_prefixes.insert(common.to_owned());
+                    }
+                }
+                _ => objects.push(name),
+            }
+        }
+
+        objects.sort();
+
+        Self {
+            objects,
+            common_prefixes: common_prefixes.into_iter().collect(),
+        }
+    }
+}
+
+/// List objects in a bucket starting with `prefix`, grouped by `delimiter`.
+///
+/// # Errors
+///
+/// Returns an error if there is no bucket with the specified name.
+pub async fn list_delimited(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    prefix: &str,
+    delimiter: &str,
+) -> crate::Result<Listing> {
+    let names = list(ctx, bucket).await?;
+
+    Ok(Listing::group(names, prefix, delimiter))
+}
+
+/// List all objects in a bucket along with their metadata. Only objects
+/// updated after `since` are returned, if specified. At most
+/// `num_connections` metadata files are fetched at once.
+///
+/// The objects are returned in no particular order. Use
+/// [`sort_by_updated`] to get the most recently changed ones first.
+///
+/// # Errors
+///
+/// Returns an error if there is no bucket with the specified name, if
+/// `num_connections` is zero or if any metadata couldn't be fetched.
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
+pub async fn list_detailed(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    since: Option<OffsetDateTime>,
+    num_connections: usize,
+) -> crate::Result<Vec<(ObjectName, Meta)>> {
+    check_connections(num_connections)?;
+
+    let names = list(ctx, bucket).await?;
+
+    stream::iter(names)
+        .map(|name| async move {
+            let meta = get(ctx, bucket, &name).await?;
+            crate::Result::Ok((name, meta))
         })
-        .collect::<crate::Result<Vec<_>>>()
+        .buffer_unordered(num_connections)
+        .try_filter(|(_, meta)| future::ready(since.is_none_or(|since| meta.updated > since)))
+        .try_collect()
+        .await
+}
+
+/// Sort objects by when they were last updated, most recent first.
+pub fn sort_by_updated(objects: &mut [(ObjectName, Meta)]) {
+    objects.sort_by_key(|(_, meta)| std::cmp::Reverse(meta.updated));
+}
+
+/// Make sure that at least one connection is allowed, since nothing
+/// would ever be transferred otherwise.
+pub(crate) fn check_connections(num_connections: usize) -> crate::Result<()> {
+    if num_connections == 0 {
+        return Err(Error::InvalidInput(
+            "the number of connections must be at least 1".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Number of chunks needed to store `size` bytes.
+pub(crate) fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(CHUNK_SIZE as u64)
+}
+
+/// Everything needed to describe an object without downloading it.
+#[derive(Debug, Serialize, Clone)]
+pub struct ObjectStat {
+    /// Object metadata.
+    #[serde(flatten)]
+    pub meta: Meta,
+    /// Number of chunks the object is split into.
+    pub chunk_count: u64,
+    /// Entity tag. It changes whenever the metadata, and therefore the
+    /// object, changes.
+    pub etag: String,
+}
+
+impl From<Meta> for ObjectStat {
+    fn from(meta: Meta) -> Self {
+        Self {
+            chunk_count: chunk_count(meta.size),
+            etag: etag(&meta),
+            meta,
+        }
+    }
+}
+
+/// Entity tag of an object with the given metadata. It is the MD5 of the
+/// encoded metadata, so it changes whenever the metadata (including
+/// [`Meta::updated`]) changes. See [`ObjectStat::etag`].
+///
+/// # Panics
+///
+/// Never, since metadata is always serializable.
+#[must_use]
+pub fn etag(meta: &Meta) -> String {
+    let encoded = rmp_serde::to_vec(meta).expect("metadata is always serializable");
+
+    format!("{:x}", md5::compute(encoded))
+}
+
+/// Get the metadata of an object along with some computed properties.
+///
+/// # Errors
+///
+/// - network errors
+/// - no such object
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn stat(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> crate::Result<ObjectStat> {
+    get(ctx, bucket, name).await.map(Into::into)
 }
 
 /// Create an object. This does not upload any actual binary data, only metadata.
-#[instrument(skip(ctx))]
+///
+/// Fails if the object already exists. Use [`create_or_replace`] to
+/// overwrite it instead. Names longer than
+/// [`Config::max_object_name_len`](crate::Config::max_object_name_len)
+/// are rejected before anything is sent.
+///
+/// A content type or cache control missing from `meta` is taken from
+/// the [bucket attributes](crate::bucket::BucketMeta). Without a content
+/// type there either, it's guessed from the extension of the name (see
+/// [`ContentType::from_name`]), unless
+/// [`Config::sniff_content_type`](crate::Config::sniff_content_type) is
+/// enabled.
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
 pub async fn create(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     name: &ObjectName,
     meta: Patch,
 ) -> crate::Result<Meta> {
+    name.check_len(ctx.config.max_object_name_len)?;
+
+    let defaults = bucket::get_meta(ctx, bucket).await?;
+
+    create_with(
+        ctx,
+        bucket,
+        name,
+        meta,
+        defaults,
+        ConflictHandler::RejectConflicts,
+    )
+    .await
+}
+
+/// Create an object, or replace it if it already exists. The replaced
+/// object is truncated to zero bytes, but its old chunks are kept
+/// (and overwritten by subsequent writes) unless `purge` is set, in
+/// which case the old object is deleted entirely before it's replaced.
+/// Buckets with [versioning](crate::bucket::BucketMeta::versioning)
+/// enabled are never purged, so the old data stays available as earlier
+/// revisions of the chunks.
+///
+/// Since the chunks are removed before the new metadata is written,
+/// concurrent readers may briefly see the object as missing or corrupt
+/// when purging.
+///
+/// Names are checked like in [`create`].
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn create_or_replace(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: Patch,
+    purge: bool,
+) -> crate::Result<Meta> {
+    name.check_len(ctx.config.max_object_name_len)?;
+
+    let defaults = bucket::get_meta(ctx, bucket).await?;
+
+    // keep the old chunks around as revisions
+    if purge && !defaults.versioning {
+        match delete(ctx, bucket, name).await {
+            Ok(()) | Err(Error::Fs(jotta::Error::NoSuchFileOrFolder)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    create_with(
+        ctx,
+        bucket,
+        name,
+        meta,
+        defaults,
+        ConflictHandler::CreateNewRevision,
+    )
+    .await
+}
+
+/// Delete the chunks past the end of an object that was replaced
+/// without being purged, when it used to be `previous_size` bytes long.
+/// Replacing an object with a smaller one otherwise leaves the old
+/// chunks behind.
+///
+/// Only indexed chunks are deleted, since content-addressed chunks may
+/// be shared with other objects. Buckets with
+/// [versioning](crate::bucket::BucketMeta::versioning) enabled keep the
+/// chunks as revisions.
+#[instrument(
+    skip(ctx, bucket, name, meta),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub(crate) async fn remove_chunks_past_end(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: &Meta,
+    previous_size: u64,
+) -> crate::Result<()> {
+    let locator = Chunks::from_meta(meta);
+
+    if !matches!(locator, Chunks::Indexed) || chunk_count(previous_size) <= chunk_count(meta.size) {
+        return Ok(());
+    }
+
+    if bucket::get_meta(ctx, bucket).await?.versioning {
+        return Ok(());
+    }
+
+    let paths = (chunk_count(meta.size)..chunk_count(previous_size))
+        .filter_map(|i| locator.path(bucket, name, u32::try_from(i).ok()?));
+
+    for path in paths {
+        match ctx.fs.remove_file(&ctx.path(&path)).await {
+            Ok(()) | Err(jotta::Error::NoSuchFileOrFolder) => {}
+            Err(e) => return Err(Error::Fs(e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Create an object. Fields missing from `meta` are taken from the
+/// bucket `defaults`, and then from the [`Config`](crate::Config).
+async fn create_with(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: Patch,
+    defaults: BucketMeta,
+    conflict_handler: ConflictHandler,
+) -> crate::Result<Meta> {
+    let meta = empty_meta(ctx, name, meta, defaults, ctx.config.chunk_layout);
+
+    set_raw(ctx, bucket, name, &meta, conflict_handler, None).await?;
+
+    Ok(meta)
+}
+
+/// Metadata of an empty object, without writing it.
+fn empty_meta(
+    ctx: &Context<impl TokenStore>,
+    name: &ObjectName,
+    mut meta: Patch,
+    defaults: BucketMeta,
+    layout: ChunkLayout,
+) -> Meta {
     let now = OffsetDateTime::now_utc();
 
-    let meta = Meta {
+    meta.content_type = meta.content_type.or(defaults.default_content_type);
+    meta.cache_control = meta.cache_control.or(defaults.default_cache_control);
+
+    Meta {
         size: 0,
         created: now,
         updated: now,
-        content_type: meta.content_type.unwrap_or_default(),
-        cache_control: meta.cache_control.unwrap_or_default(),
-    };
+        content_type: meta.content_type.clone().unwrap_or_else(|| {
+            if ctx.config.sniff_content_type {
+                ContentType::default()
+            } else {
+                ContentType::from_name(name)
+            }
+        }),
+        cache_control: meta
+            .cache_control
+            .unwrap_or_else(|| ctx.config.default_cache_control.clone()),
+        chunks: match layout {
+            ChunkLayout::Indexed => None,
+            ChunkLayout::ContentAddressed => Some(Vec::new()),
+        },
+        content_encoding: meta.content_encoding.unwrap_or_default(),
+        md5: Some(md5::compute([])),
+        user_metadata: meta.user_metadata.unwrap_or_default(),
+        sniff_content_type: meta.content_type.is_none() && ctx.config.sniff_content_type,
+        chunk_md5s: match layout {
+            ChunkLayout::Indexed => Some(Vec::new()),
+            ChunkLayout::ContentAddressed => None,
+        },
+    }
+}
 
-    set_raw(ctx, bucket, name, &meta, ConflictHandler::RejectConflicts).await?;
+/// A chunk that has been uploaded.
+#[derive(Debug)]
+struct UploadedChunk {
+    index: u32,
+    size: u64,
+    md5: Digest,
+    address: String,
+}
 
-    Ok(meta)
+/// Chunks uploaded by [`upload_chunks`].
+#[derive(Debug)]
+struct UploadedChunks {
+    chunks: Vec<UploadedChunk>,
+    /// Whether the upload was cancelled, in which case only the
+    /// consecutive chunks are kept.
+    cancelled: bool,
+    /// Why the upload failed, if it did. The chunks that were uploaded
+    /// before the failure are still listed.
+    failed: Option<Error>,
+    /// MD5 digest and length of the object up to the end of the last
+    /// uploaded chunk, if the upload started at the first chunk.
+    head_md5: Option<(Digest, u64)>,
+    /// Content type guessed from the first chunk, if it was written and
+    /// [`Meta::sniff_content_type`] is set.
+    sniffed: Option<ContentType>,
 }
 
-#[instrument(level = "trace", skip(ctx, bucket, object, body))]
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    level = "trace",
+    skip(ctx, bucket, object, chunks, index, body, md5, times),
+    fields(bucket = %bucket, object = %object, chunk = index, size = body.len())
+)]
 async fn upload(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     object: &ObjectName,
+    chunks: &Chunks,
     index: u32,
     body: Bytes, // there is no point accepting a stream since a checksum needs to be calculated prior to allocation anyway
-) -> crate::Result<u64> {
-    let md5 = md5::compute(&body);
+    md5: Digest,
+    times: Timestamps,
+) -> crate::Result<UploadedChunk> {
     let size = body.len().try_into().unwrap();
+    let chunk = UploadedChunk {
+        index,
+        size,
+        md5,
+        address: content_address(&md5, size),
+    };
 
     trace!("uploading {} bytes", size);
 
     let req = AllocReq {
-        path: &PathOnDevice(format!(
-            "{}/{}/{}",
-            ctx.root_on_device(),
-            bucket,
-            object.chunk_path(index)
-        )),
+        path: &chunk_path_on_device(ctx, bucket, object, chunks, &chunk),
         bytes: size,
         md5,
         conflict_handler: ConflictHandler::CreateNewRevision,
-        created: None,
-        modified: None,
+        created: times.created,
+        modified: times.modified,
     };
 
-    let upload_url = ctx.fs.allocate(&req).await?.upload_url;
-
-    let res = ctx.fs.upload_range(&upload_url, body, 0..=size).await?;
+    ctx.fs.upload_bytes_with(&req, body).await?;
 
-    assert!(matches!(res, UploadRes::Complete(_)));
-
-    Ok(size)
+    Ok(chunk)
 }
 
-async fn get_complete_chunk<R: AsyncBufRead + Unpin>(
+/// Upload a chunk, resuming it for as long as Jottacloud reports it as
+/// incomplete. `body` returns the bytes of the chunk from a position.
+#[allow(clippy::too_many_arguments)]
+async fn upload_resumable<F, Fut, B>(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     object: &ObjectName,
-    mut cursor: usize,
-    chunk_no: u32,
-    file: &mut R,
-) -> crate::Result<Option<Bytes>> {
-    let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
-    let chunk_path = &UserScopedPath(format!(
-        "{}/{}/{}",
-        ctx.user_scoped_root(),
-        bucket,
-        object.chunk_path(chunk_no)
-    ));
+    chunks: &Chunks,
+    chunk: &UploadedChunk,
+    md5: Digest,
+    times: Timestamps,
+    mut body: F,
+) -> crate::Result<()>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: std::future::Future<Output = io::Result<B>>,
+    B: Into<Body>,
+{
+    let mut alloc = allocate_chunk(ctx, bucket, object, chunks, chunk, md5, times).await?;
+    let mut stalled = 0;
+    let mut last_pos = None;
 
-    if cursor != 0 {
-        let b = ctx
+    while let Some((upload_url, resume_pos)) = alloc {
+        if last_pos.is_some_and(|last| resume_pos <= last) {
+            stalled += 1;
+
+            if stalled > jotta::MAX_STALLED_RESUMES {
+                return Err(jotta::Error::IncompleteUpload.into());
+            }
+        } else {
+            stalled = 0;
+        }
+
+        last_pos = Some(resume_pos);
+
+        let res = ctx
             .fs
-            .file_to_bytes(
-                chunk_path,
-                ClosedByteRange::new_to_including(cursor as u64 - 1),
+            .upload_range(
+                &upload_url,
+                body(resume_pos).await?,
+                resume_pos..=chunk.size,
             )
             .await?;
 
-        buf.extend_from_slice(&b);
+        let pool = res.pool().map(|pool| pool.0.as_str());
+
+        match res {
+            UploadRes::Complete(ref complete) => {
+                if let Err(e) = complete.verify(chunk.size, md5) {
+                    warn!(
+                        pool,
+                        bytes = complete.bytes,
+                        "chunk {} doesn't match what was uploaded",
+                        chunk.index
+                    );
+                    return Err(e.into());
+                }
+
+                trace!(pool, "chunk {} uploaded", chunk.index);
+                break;
+            }
+            UploadRes::Incomplete(_) => {
+                debug!(
+                    resume_pos,
+                    pool, "chunk {} upload incomplete, resuming", chunk.index
+                );
+
+                alloc = allocate_chunk(ctx, bucket, object, chunks, chunk, md5, times).await?;
+            }
+        }
     }
 
-    buf.resize(CHUNK_SIZE, 0);
+    Ok(())
+}
 
-    loop {
-        let n = file.read(&mut buf[cursor..]).await?;
+/// Where a chunk is stored, relative to the device.
+fn chunk_path_on_device(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+    chunks: &Chunks,
+    chunk: &UploadedChunk,
+) -> PathOnDevice {
+    let path = match chunks {
+        Chunks::Indexed => format!("{}/{}", bucket, object.chunk_path(chunk.index)),
+        Chunks::ContentAddressed(_) => format!("{CHUNK_STORE}/{}", chunk.address),
+    };
 
-        if n == 0 {
-            // The buffer is full or the reader is empty, or both.
+    ctx.path_on_device(&path)
+}
+
+/// Allocate a chunk and get its upload url and the position to resume
+/// the upload from, or `None` if Jottacloud already has the content, in
+/// which case the upload can be skipped.
+async fn allocate_chunk(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+    chunks: &Chunks,
+    chunk: &UploadedChunk,
+    md5: Digest,
+    times: Timestamps,
+) -> crate::Result<Option<(String, u64)>> {
+    let req = AllocReq {
+        path: &chunk_path_on_device(ctx, bucket, object, chunks, chunk),
+        bytes: chunk.size,
+        md5,
+        conflict_handler: ConflictHandler::CreateNewRevision,
+        created: times.created,
+        modified: times.modified,
+    };
+
+    let alloc = ctx.fs.allocate(&req).await?;
+
+    if alloc.state == RevisionState::Completed || alloc.resume_pos >= chunk.size {
+        trace!("chunk {} already exists", chunk.address);
+        Ok(None)
+    } else {
+        Ok(Some((alloc.upload_url, alloc.resume_pos)))
+    }
+}
+
+/// Append bytes from `file` to `buf` until it holds a whole chunk or
+/// the reader is exhausted, hashing them along the way. Only the bytes
+/// that are read are ever written to, so a small write doesn't touch a
+/// whole chunk of memory.
+async fn fill_chunk<R: AsyncBufRead + Unpin>(
+    file: &mut R,
+    buf: &mut BytesMut,
+    md5: &mut md5::Context,
+) -> io::Result<()> {
+    while buf.len() < CHUNK_SIZE {
+        let available = file.fill_buf().await?;
+
+        if available.is_empty() {
             break;
         }
 
-        cursor += n;
+        let n = available.len().min(CHUNK_SIZE - buf.len());
+        buf.extend_from_slice(&available[..n]);
+        md5.consume(&available[..n]);
+        file.consume_unpin(n);
     }
 
-    buf.truncate(cursor);
+    Ok(())
+}
 
-    if buf.is_empty() {
-        // No bytes were written to the buffer, so there's no need to upload anything.
-        return Ok(None);
+/// Read a chunk from `file`, starting at `cursor` within the chunk. The head
+/// before `cursor` and the tail after the new data are downloaded if they
+/// are among the `existing` bytes of the chunk. If `cursor` is past the
+/// existing bytes, the gap is filled with zeros.
+///
+/// The chunk is hashed while it's being read, so the returned digest
+/// doesn't require another pass over the buffer. `None` is returned if
+/// `file` is empty, since nothing needs to be uploaded then.
+async fn get_complete_chunk<R: AsyncBufRead + Unpin>(
+    ctx: &Context<impl TokenStore>,
+    chunk_path: Option<String>,
+    cursor: usize,
+    existing: usize,
+    file: &mut R,
+) -> crate::Result<Option<(Bytes, Digest)>> {
+    let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+    let mut md5 = md5::Context::new();
+    let chunk_path = chunk_path.map(|path| ctx.path(&path));
+
+    let head = cursor.min(existing);
+
+    if head != 0 {
+        let chunk_path = chunk_path
+            .as_ref()
+            .ok_or(jotta::Error::NoSuchFileOrFolder)?;
+
+        let b = ctx
+            .fs
+            .file_to_bytes(chunk_path, ClosedByteRange::new(0, head as u64))
+            .await?;
+
+        buf.extend_from_slice(&b);
+        md5.consume(&b);
+    }
+
+    if buf.len() < cursor {
+        let gap = buf.len();
+        buf.resize(cursor, 0);
+        md5.consume(&buf[gap..]);
     }
 
-    if buf.len() < CHUNK_SIZE {
-        // Either we're writing to the tail of the object, or we're writing in the middle of it.
-        // If the case is the latter, we need to download the tail of this chunk in order not to
-        // accidentally truncate the file.
+    fill_chunk(file, &mut buf, &mut md5).await?;
+
+    let end = buf.len();
+
+    if end == cursor {
+        // nothing was read, so there's no need to upload anything
+        return Ok(None);
+    }
 
+    if let (true, Some(chunk_path)) = (end < existing, chunk_path) {
+        // The new data ends before the existing data does, which means that
+        // we're writing in the middle of the chunk. The tail must be kept,
+        // or the chunk (and possibly the object) would be truncated.
         let tail = match ctx
             .fs
-            .file_to_bytes(chunk_path, OpenByteRange::new(cursor as u64))
+            .file_to_bytes(&chunk_path, OpenByteRange::new(end as u64))
             .await
         {
             Ok(bytes) => bytes,
@@ -195,51 +891,552 @@ async fn get_complete_chunk<R: AsyncBufRead + Unpin>(
         };
 
         buf.extend_from_slice(&tail);
+        md5.consume(&tail);
     }
 
-    Ok(Some(buf.freeze()))
+    Ok(Some((buf.freeze(), md5.compute())))
 }
 
 /// Upload a range of bytes. The remote object will
 /// be overwritten but not truncated.
-#[instrument(skip(ctx, file))]
+///
+/// The upload can be cancelled with `cancel`. Chunks that are being
+/// uploaded when the token is cancelled are abandoned, and the metadata
+/// is updated to include the chunks uploaded before them, if any. The
+/// object is therefore left consistent, but only some of the data may
+/// have been written.
+///
+/// Chunks are uploaded concurrently according to `concurrency`. Use
+/// [`ConcurrencyStrategy::Fixed`] for a constant number of connections.
+/// Every chunk in flight is kept in memory until it has been uploaded,
+/// so peak memory use is about one [`CHUNK_SIZE`] per connection (plus
+/// the chunk being read). Fewer connections use less memory at the cost
+/// of throughput.
+///
+/// # Errors
+///
+/// - network errors
+/// - no such object
+/// - invalid `concurrency`
+/// - [`jotta::Error::CorruptUpload`] if Jottacloud reports a different
+///   size or checksum for a chunk than what was uploaded
+/// - [`Error::Cancelled`](crate::errors::Error::Cancelled) if `cancel` was cancelled
+#[instrument(
+    skip(ctx, file, cancel, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
 pub async fn upload_range<R: AsyncBufRead + Unpin>(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     name: &ObjectName,
     offset: u64,
     file: R,
-    num_connections: usize,
+    concurrency: ConcurrencyStrategy,
+    cancel: Option<&CancellationToken>,
 ) -> crate::Result<Meta> {
-    let before = Instant::now();
-
+    upload_range_with_timestamps(
+        ctx,
+        bucket,
+        name,
+        offset,
+        file,
+        concurrency,
+        cancel,
+        Timestamps::default(),
+    )
+    .await
+}
+
+/// Like [`upload_range`], but with custom creation and modification
+/// times instead of the time of the upload.
+///
+/// # Errors
+///
+/// Same as [`upload_range`], as well as
+/// [`Error::InvalidInput`](crate::errors::Error::InvalidInput) if a time
+/// is more than [`MAX_TIMESTAMP_SKEW`] in the future.
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip(ctx, file, cancel, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn upload_range_with_timestamps<R: AsyncBufRead + Unpin>(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    offset: u64,
+    file: R,
+    concurrency: ConcurrencyStrategy,
+    cancel: Option<&CancellationToken>,
+    times: Timestamps,
+) -> crate::Result<Meta> {
+    concurrency.check()?;
+    times.check()?;
+
+    let meta = get(ctx, bucket, name).await?;
+
+    write(
+        ctx,
+        bucket,
+        name,
+        meta,
+        offset,
+        file,
+        concurrency,
+        cancel,
+        times,
+    )
+    .await
+}
+
+/// Append bytes to the end of an object. Only the last chunk of the
+/// object is downloaded and uploaded again, and only if it's partial.
+///
+/// Concurrent appends to the same object are not safe; one of them
+/// will be overwritten.
+///
+/// # Errors
+///
+/// - network errors
+/// - no such object
+#[instrument(
+    skip(ctx, file, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn append<R: AsyncBufRead + Unpin>(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    file: R,
+    num_connections: usize,
+) -> crate::Result<Meta> {
+    check_connections(num_connections)?;
+
+    let meta = get(ctx, bucket, name).await?;
+    let offset = meta.size;
+
+    write(
+        ctx,
+        bucket,
+        name,
+        meta,
+        offset,
+        file,
+        ConcurrencyStrategy::Fixed(num_connections),
+        None,
+        Timestamps::default(),
+    )
+    .await
+}
+
+/// Checksum every chunk of `file` while only keeping a small buffer in memory.
+async fn checksum_chunks<R: tokio::io::AsyncRead + Unpin>(
+    mut file: R,
+) -> io::Result<(Vec<(Digest, u64)>, Digest)> {
+    use tokio::io::AsyncReadExt as _;
+
+    let mut checksums = Vec::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut md5 = md5::Context::new();
+    let mut whole = md5::Context::new();
+    let mut size = 0;
+
+    loop {
+        let len = buf.len().min(CHUNK_SIZE - size);
+        let n = file.read(&mut buf[..len]).await?;
+
+        if n == 0 {
+            break;
+        }
+
+        md5.consume(&buf[..n]);
+        whole.consume(&buf[..n]);
+        size += n;
+
+        if size == CHUNK_SIZE {
+            let md5 = std::mem::replace(&mut md5, md5::Context::new());
+            checksums.push((md5.compute(), size as u64));
+            size = 0;
+        }
+    }
+
+    if size > 0 {
+        checksums.push((md5.compute(), size as u64));
+    }
+
+    Ok((checksums, whole.compute()))
+}
+
+/// Replace the contents of an object with a local file.
+///
+/// Unlike [`upload_range`], no chunk is ever buffered in memory. The file
+/// is read twice instead: once to checksum the chunks and once to upload
+/// them. The file must therefore not be modified during the upload.
+///
+/// If the file is smaller than the object, the chunks past its end are
+/// deleted once the new metadata has been written, like when committing
+/// a [replacing](txn::UploadTxn::replace) transaction.
+///
+/// # Errors
+///
+/// - the file can't be read
+/// - network errors
+/// - no such object
+#[instrument(
+    skip(ctx, path, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn upload_file(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    path: impl AsRef<Path>,
+) -> crate::Result<Meta> {
+    use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _};
+
+    let path = path.as_ref();
+    let (checksums, digest) = checksum_chunks(File::open(path).await?).await?;
+
+    let meta = get(ctx, bucket, name).await?;
+    let locator = Chunks::from_meta(&meta);
+    let mut uploaded = Vec::with_capacity(checksums.len());
+
+    for (index, (md5, size)) in (0..).zip(checksums) {
+        let chunk = UploadedChunk {
+            index,
+            size,
+            md5,
+            address: content_address(&md5, size),
+        };
+
+        let times = Timestamps::default();
+
+        upload_resumable(
+            ctx,
+            bucket,
+            name,
+            &locator,
+            &chunk,
+            md5,
+            times,
+            |pos| async move {
+                let mut file = File::open(path).await?;
+                file.seek(SeekFrom::Start(u64::from(index) * CHUNK_SIZE as u64 + pos))
+                    .await?;
+
+                Ok(Body::wrap_stream(ReaderStream::new(file.take(size - pos))))
+            },
+        )
+        .await?;
+
+        uploaded.push(chunk);
+    }
+
+    let chunk_md5s = match meta.chunks {
+        None if uploaded.len() <= MAX_CHUNK_DIGESTS => {
+            Some(uploaded.iter().map(|c| c.md5).collect())
+        }
+        _ => None,
+    };
+
+    let previous_size = meta.size;
+
+    let meta = Meta {
+        size: uploaded.iter().map(|c| c.size).sum(),
+        updated: OffsetDateTime::now_utc(),
+        chunk_md5s,
+        chunks: meta
+            .chunks
+            .map(|_| uploaded.into_iter().map(|c| c.address).collect()),
+        md5: Some(digest),
+        ..meta
+    };
+
+    set_raw(
+        ctx,
+        bucket,
+        name,
+        &meta,
+        ConflictHandler::CreateNewRevision,
+        None,
+    )
+    .await?;
+
+    remove_chunks_past_end(ctx, bucket, name, &meta, previous_size).await?;
+
+    Ok(meta)
+}
+
+/// Create or replace an object with the body of a `GET` request to
+/// `url`, such as a file on another server. The body is streamed through
+/// [`upload_range`] without being staged anywhere.
+///
+/// Redirects are followed. The content type and content encoding of the
+/// response are used unless they're set in `meta`. The body is stored as
+/// is, so compressed responses remain compressed.
+///
+/// # Errors
+///
+/// - [`Error::Fetch`] if the request fails or the response status isn't
+///   successful, in which case nothing is created
+/// - errors reading the body
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, name, url, meta),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn upload_from_url(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    url: impl IntoUrl,
+    mut meta: Patch,
+    num_connections: usize,
+) -> crate::Result<Meta> {
+    check_connections(num_connections)?;
+
+    let res = reqwest::get(url)
+        .await
+        .and_then(Response::error_for_status)
+        .map_err(Error::Fetch)?;
+
+    let header = |name| res.headers().get(name).and_then(|v| v.to_str().ok());
+
+    if meta.content_type.is_none() {
+        meta.content_type = header(header::CONTENT_TYPE).and_then(|v| v.parse().ok());
+    }
+
+    if meta.content_encoding.is_none() {
+        meta.content_encoding = header(header::CONTENT_ENCODING).and_then(|v| v.parse().ok());
+    }
+
+    debug!(url = %res.url(), "importing");
+
+    create_or_replace(ctx, bucket, name, meta, false).await?;
+
+    let body = res
+        .bytes_stream()
+        .map_err(io::Error::other)
+        .into_async_read();
+
+    upload_range(
+        ctx,
+        bucket,
+        name,
+        0,
+        futures_util::io::BufReader::new(body),
+        ConcurrencyStrategy::Fixed(num_connections),
+        None,
+    )
+    .await
+}
+
+/// Size of an object after a chunk has been written to it.
+fn size_after_write(size: u64, chunk: &UploadedChunk) -> u64 {
+    size.max(u64::from(chunk.index) * CHUNK_SIZE as u64 + chunk.size)
+}
+
+/// Only keep the chunks forming an unbroken sequence from `first`.
+fn retain_consecutive(chunks: &mut Vec<UploadedChunk>, first: u32) {
+    chunks.sort_by_key(|c| c.index);
+
+    let len = chunks
+        .iter()
+        .zip(first..)
+        .take_while(|(chunk, index)| chunk.index == *index)
+        .count();
+
+    chunks.truncate(len);
+}
+
+/// Write `file` to an object at `offset` and update its metadata.
+#[allow(clippy::too_many_arguments)]
+async fn write<R: AsyncBufRead + Unpin>(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: Meta,
+    offset: u64,
+    file: R,
+    concurrency: ConcurrencyStrategy,
+    cancel: Option<&CancellationToken>,
+    times: Timestamps,
+) -> crate::Result<Meta> {
+    let uploaded = upload_chunks(
+        ctx,
+        bucket,
+        name,
+        &meta,
+        offset,
+        file,
+        concurrency,
+        cancel,
+        times,
+    )
+    .await?;
+    let cancelled = uploaded.cancelled;
+
+    if let Some(e) = uploaded.failed {
+        return Err(e);
+    }
+
+    let meta = apply_chunks(meta, uploaded, times)?;
+
+    set_raw(
+        ctx,
+        bucket,
+        name,
+        &meta,
+        ConflictHandler::CreateNewRevision,
+        None,
+    )
+    .await?;
+
+    if cancelled {
+        return Err(Error::Cancelled);
+    }
+
+    Ok(meta)
+}
+
+/// Upload the chunks needed to write `file` at `offset`, without updating
+/// the metadata. Failures are reported in [`UploadedChunks::failed`]
+/// rather than as errors, so that the chunks uploaded before them aren't
+/// lost track of.
+#[allow(clippy::too_many_arguments)]
+async fn upload_chunks<R: AsyncBufRead + Unpin>(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    meta: &Meta,
+    offset: u64,
+    file: R,
+    concurrency: ConcurrencyStrategy,
+    cancel: Option<&CancellationToken>,
+    times: Timestamps,
+) -> crate::Result<UploadedChunks> {
+    let before = Instant::now();
+
+    let locator = Chunks::from_meta(meta);
+    let size = meta.size;
+
+    if matches!(locator, Chunks::ContentAddressed(_)) && offset > meta.size {
+        return Err(Error::InvalidInput(
+            "cannot write beyond the end of a content-addressed object".into(),
+        ));
+    }
+
+    let locator = &locator;
+
+    // chunks are read in order, so the data can be hashed (and the
+    // content type sniffed from the first chunk) along the way
+    let hasher = Mutex::new((md5::Context::new(), 0, None));
+    let hasher = &hasher;
+
     let chunks = stream::try_unfold((file, offset), move |(mut file, pos)| async move {
         #[allow(clippy::cast_possible_truncation)] // won't truncate the u64 remainder of an usize
         let chunk_align = (pos % (CHUNK_SIZE as u64)) as usize;
         let chunk_no: u32 = (pos / CHUNK_SIZE as u64).try_into().unwrap();
+        let chunk_path = locator.path(bucket, name, chunk_no);
+        #[allow(clippy::cast_possible_truncation)] // at most the chunk size
+        let existing = size
+            .saturating_sub(u64::from(chunk_no) * CHUNK_SIZE as u64)
+            .min(CHUNK_SIZE as u64) as usize;
+
+        let span =
+            trace_span!("get_complete_chunk", bucket = %bucket, object = %name, chunk = chunk_no);
+
+        match get_complete_chunk(ctx, chunk_path, chunk_align, existing, &mut file)
+            .instrument(span)
+            .await?
+        {
+            Some((buf, md5)) => {
+                let mut hasher = hasher.lock().unwrap();
+                hasher.0.consume(&buf);
+                hasher.1 += buf.len() as u64;
+
+                if chunk_no == 0 && meta.sniff_content_type {
+                    hasher.2 = sniff::sniff(&buf);
+                }
 
-        match get_complete_chunk(ctx, bucket, name, chunk_align, chunk_no, &mut file).await? {
-            Some(buf) => Ok(Some((
-                (chunk_no, buf),
-                (file, (CHUNK_SIZE as u64) * u64::from(chunk_no + 1)),
-            ))),
+                Ok(Some((
+                    (chunk_no, buf, md5),
+                    (file, (CHUNK_SIZE as u64) * u64::from(chunk_no + 1)),
+                )))
+            }
             None => Ok(None),
         }
     });
 
+    let limiter = Limiter::new(concurrency);
+    let limiter = &limiter;
+
     let mut futs = Box::pin(
         chunks
-            .map(|res| res.map(|(chunk_no, buf)| upload(ctx, bucket, name, chunk_no, buf)))
-            .try_buffer_unordered(num_connections),
+            .map(|res| {
+                res.map(|(chunk_no, buf, md5)| {
+                    let len = buf.len() as u64;
+                    limiter.run(
+                        len,
+                        upload(ctx, bucket, name, locator, chunk_no, buf, md5, times),
+                    )
+                })
+            })
+            .try_buffer_unordered(concurrency.max()),
     );
 
-    let mut bytes_uploaded = 0;
+    let mut uploaded = Vec::new();
+    let mut cancelled = false;
+    let mut failed = None;
 
-    while let Some(res) = futs.next().await {
-        bytes_uploaded += res?;
+    loop {
+        let next = match cancel {
+            Some(cancel) => match future::select(futs.next(), Box::pin(cancel.cancelled())).await {
+                Either::Left((next, _)) => next,
+                Either::Right(_) => {
+                    cancelled = true;
+                    break;
+                }
+            },
+            None => futs.next().await,
+        };
+
+        match next {
+            Some(Ok(chunk)) => uploaded.push(chunk),
+            Some(Err(e)) => {
+                warn!("upload failed after {} chunks: {e}", uploaded.len());
+                failed = Some(e);
+                break;
+            }
+            None => break,
+        }
+    }
+
+    drop(futs);
+
+    if cancelled {
+        let first = (offset / CHUNK_SIZE as u64).try_into().unwrap();
+        retain_consecutive(&mut uploaded, first);
+        warn!("upload cancelled after {} chunks", uploaded.len());
     }
 
-    let time = before.elapsed();
+    log_throughput(uploaded.iter().map(|c| c.size).sum(), before);
+
+    let (md5, len, sniffed) = hasher.lock().unwrap().clone();
+    let complete = !cancelled && failed.is_none();
+    let head_md5 = (offset < CHUNK_SIZE as u64 && complete).then(|| (md5.compute(), len));
+
+    Ok(UploadedChunks {
+        chunks: uploaded,
+        cancelled,
+        failed,
+        head_md5,
+        sniffed,
+    })
+}
+
+fn log_throughput(bytes_uploaded: u64, since: Instant) {
+    let time = since.elapsed();
     #[allow(clippy::cast_precision_loss)]
     let bytes_per_second = bytes_uploaded as f64 / time.as_secs_f64();
 
@@ -249,149 +1446,798 @@ pub async fn upload_range<R: AsyncBufRead + Unpin>(
         time,
         bytes_per_second * 8.0 / 1_000_000.0
     );
+}
 
-    let meta = get(ctx, bucket, name).await?;
+/// Metadata of an object after `uploaded` has been written to it.
+///
+/// # Errors
+///
+/// Fails if a content-addressed chunk would leave a gap in the chunk list.
+fn apply_chunks(meta: Meta, uploaded: UploadedChunks, times: Timestamps) -> crate::Result<Meta> {
+    let UploadedChunks {
+        chunks: mut uploaded,
+        head_md5,
+        sniffed,
+        ..
+    } = uploaded;
 
-    let meta = Meta {
-        size: meta.size.max(bytes_uploaded + offset),
-        updated: OffsetDateTime::now_utc(),
+    let size = uploaded.iter().fold(meta.size, size_after_write);
+
+    let mut meta = Meta {
+        size,
+        created: times.created.unwrap_or(meta.created),
+        updated: times.modified.unwrap_or_else(OffsetDateTime::now_utc),
+        // only known if nothing changed or the whole object was just hashed
+        md5: match head_md5 {
+            _ if uploaded.is_empty() => meta.md5,
+            Some((md5, len)) if len == size => Some(md5),
+            _ => None,
+        },
         ..meta
     };
 
-    set_raw(ctx, bucket, name, &meta, ConflictHandler::CreateNewRevision).await?;
+    // the first chunk might not have been uploaded if the upload was cancelled
+    if let (Some(content_type), true) = (sniffed, uploaded.iter().any(|c| c.index == 0)) {
+        meta.content_type = content_type;
+    }
+
+    uploaded.sort_by_key(|c| c.index);
+
+    for chunk in uploaded {
+        match meta.chunks {
+            Some(ref mut chunks) => set_chunk(chunks, chunk.index, chunk.address)?,
+            None => set_chunk_digest(&mut meta.chunk_md5s, chunk.index, chunk.md5),
+        }
+    }
 
     Ok(meta)
 }
 
+/// Split `range` into the ranges of each chunk it covers, relative to
+/// the start of the chunk.
 fn aligned_chunked_byte_range(
-    range: impl ByteRange,
+    range: &impl ByteRange,
 ) -> impl Iterator<Item = (u32, ClosedByteRange)> {
-    let mut pos = range.start();
+    let chunk_size = CHUNK_SIZE as u64;
+    // open ranges extend to the end of the address space
+    let range = ClosedByteRange::new(
+        range.start(),
+        range.len().unwrap_or(u64::MAX - range.start()),
+    );
 
-    iter::from_fn(move || {
-        #[allow(clippy::cast_possible_truncation)]
-        let chunk_no = (pos / (CHUNK_SIZE as u64)) as u32;
-        let chunk_start = pos % (CHUNK_SIZE as u64);
+    range
+        .split_at_chunk_boundaries(chunk_size)
+        .map(move |part| {
+            let chunk_no = part.start() / chunk_size;
 
-        let chunk_end = (range.end().unwrap_or(u64::MAX) - pos).min(CHUNK_SIZE as _);
+            #[allow(clippy::cast_possible_truncation)]
+            (
+                chunk_no as u32,
+                ClosedByteRange::new(part.start() - chunk_no * chunk_size, part.len()),
+            )
+        })
+}
 
-        if chunk_end == 0 {
-            return None;
-        }
+/// Open a stream to an object. Chunks are downloaded with up to
+/// `num_connections` concurrent connections and reassembled according
+/// to `mode`. The bytes are the same regardless of the mode.
+///
+/// Chunks are only fetched as far ahead of the consumer as `mode`
+/// allows, so a slow consumer holds at most that many chunks in memory.
+/// Use [`DownloadMode::Windowed`] with a `max` below `num_connections`
+/// to cap that independently of the number of connections.
+///
+/// A range that lies within a single chunk bypasses the reassembly
+/// entirely: the response body is forwarded as it arrives rather than
+/// being buffered chunk by chunk, so the first byte reaches the caller
+/// as soon as Jottacloud sends it. Ranges spanning several chunks wait
+/// for the whole first chunk instead; compare the two with
+/// `cargo bench -p jotta-osd --bench time_to_first_byte`.
+///
+/// **The integrity of the data is not checked by this function.**
+///
+/// # Errors
+///
+/// The stream will eventually return an error if `range` extends past
+/// the end of the object, since there won't be enough chunks in the
+/// cloud to satisfy the range. Use [`stream_object`] if the size of
+/// the object isn't known in advance.
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+#[allow(clippy::manual_async_fn)] // lifetimes don't allow async syntax
+pub fn stream_range<'a, S: TokenStore + 'a>(
+    ctx: Arc<Context<S>>,
+    bucket: BucketName,
+    object: ObjectName,
+    range: ClosedByteRange,
+    num_connections: usize,
+    mode: DownloadMode,
+) -> impl Stream<Item = crate::Result<Bytes>> + 'a {
+    if let Err(e) = check_connections(num_connections).and_then(|()| mode.check()) {
+        return stream::once(future::err(e)).left_stream();
+    }
 
-        let chunk = ClosedByteRange::try_from_bounds(chunk_start, chunk_end).unwrap();
+    let locator = {
+        let ctx = ctx.clone();
+        let bucket = bucket.clone();
+        let object = object.clone();
 
-        pos += chunk_end - chunk_start;
+        Box::pin(async move { Chunks::resolve(&ctx, &bucket, &object).await })
+    };
 
-        Some((chunk_no, chunk))
-    })
+    stream::once(locator)
+        .map_ok(move |locator| {
+            stream_chunks(
+                ctx.clone(),
+                bucket.clone(),
+                object.clone(),
+                locator,
+                range,
+                num_connections,
+                mode,
+            )
+        })
+        .try_flatten()
+        .right_stream()
 }
 
-/// Open a stream to an object.
+/// Like [`stream_range`], but the chunks are located using `meta`
+/// instead of fetching the metadata again. Use this if the metadata has
+/// already been fetched, for example to check the size of the object.
+///
+/// # Errors
+///
+/// See [`stream_range`].
+#[instrument(
+    skip(ctx, bucket, object, meta),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+pub fn stream_range_with_meta<'a, S: TokenStore + 'a>(
+    ctx: Arc<Context<S>>,
+    bucket: BucketName,
+    object: ObjectName,
+    meta: &Meta,
+    range: ClosedByteRange,
+    num_connections: usize,
+    mode: DownloadMode,
+) -> impl Stream<Item = crate::Result<Bytes>> + 'a {
+    if let Err(e) = check_connections(num_connections).and_then(|()| mode.check()) {
+        return stream::once(future::err(e)).left_stream();
+    }
+
+    stream_chunks(
+        ctx,
+        bucket,
+        object,
+        Chunks::from_meta(meta),
+        range,
+        num_connections,
+        mode,
+    )
+    .right_stream()
+}
+
+fn stream_chunks<'a, S: TokenStore + 'a>(
+    ctx: Arc<Context<S>>,
+    bucket: BucketName,
+    object: ObjectName,
+    locator: Chunks,
+    range: ClosedByteRange,
+    num_connections: usize,
+    mode: DownloadMode,
+) -> impl Stream<Item = crate::Result<Bytes>> + 'a {
+    let mut parts = aligned_chunked_byte_range(&range);
+
+    // a range within a single chunk is passed through as it arrives
+    // instead of being buffered and reassembled, which lowers the time
+    // to first byte of small objects considerably
+    if let (Some((chunk_no, range)), None) = (parts.next(), parts.next()) {
+        let path = locator.path(&bucket, &object, chunk_no);
+
+        let stream = async move {
+            let path = path.ok_or(jotta::Error::NoSuchFileOrFolder)?;
+
+            ctx.fs.file_to_stream(&ctx.path(&path), range).await
+        };
+
+        return Box::pin(stream::once(stream).try_flatten().map_err(Into::into)).left_stream();
+    }
+
+    let locator = Arc::new(locator);
+
+    let downloads = aligned_chunked_byte_range(&range).map(move |(chunk_no, range)| {
+        let ctx = ctx.clone();
+        let path = locator.path(&bucket, &object, chunk_no);
+
+        async move {
+            let path = path.ok_or(jotta::Error::NoSuchFileOrFolder)?;
+
+            ctx.fs.file_to_bytes(&ctx.path(&path), range).await
+        }
+    });
+
+    reassemble(downloads, num_connections, mode)
+        .map_err(Into::into)
+        .right_stream()
+}
+
+/// Open a stream to an old revision of an object.
+///
+/// Every chunk file gets a new revision whenever it's written, so this
+/// reads revision `revision` of each chunk covered by `range`. Keep in
+/// mind that chunk revisions only line up with object versions if every
+/// version rewrote the same chunks. If the object grew or shrank between
+/// versions, or was only partially overwritten, chunks may have different
+/// numbers of revisions and the bytes may mix versions.
 ///
 /// **The integrity of the data is not checked by this function.**
 ///
 /// # Errors
 ///
-/// The stream will eventually return an error if `range` is infinite,
-/// since there won't be enough chunks in the cloud to satisfy the
-/// range.
-#[instrument(skip(ctx))]
-#[allow(clippy::manual_async_fn)] // lifetimes don't allow async syntax
-pub fn stream_range<'a, S: TokenStore + 'a>(
+/// The stream returns [`Error::NoSuchRevision`] if a chunk in `range`
+/// lacks the revision, and an error if the object uses
+/// [`ChunkLayout::ContentAddressed`], since its chunks are never revised.
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+pub fn stream_revision<'a, S: TokenStore + 'a>(
     ctx: Arc<Context<S>>,
     bucket: BucketName,
     object: ObjectName,
+    revision: u32,
     range: ClosedByteRange,
     num_connections: usize,
 ) -> impl Stream<Item = crate::Result<Bytes>> + 'a {
-    stream::iter(aligned_chunked_byte_range(range))
-        .map(move |(chunk_no, range)| {
+    if let Err(e) = check_connections(num_connections) {
+        return stream::once(future::err(e)).left_stream();
+    }
+
+    let locator = {
+        let ctx = ctx.clone();
+        let bucket = bucket.clone();
+        let object = object.clone();
+
+        Box::pin(async move { Chunks::resolve(&ctx, &bucket, &object).await })
+    };
+
+    stream::once(locator)
+        .map_ok(move |locator| {
+            if let Chunks::ContentAddressed(_) = locator {
+                return stream::once(future::err(Error::InvalidInput(
+                    "content-addressed chunks have no revisions".into(),
+                )))
+                .left_stream();
+            }
+
             let ctx = ctx.clone();
             let bucket = bucket.clone();
             let object = object.clone();
 
+            stream::iter(aligned_chunked_byte_range(&range))
+                .map(move |(chunk_no, range)| {
+                    let ctx = ctx.clone();
+                    let path = ctx.path(&format!("{bucket}/{}", object.chunk_path(chunk_no)));
+
+                    async move {
+                        match ctx.fs.file_revision_to_bytes(&path, revision, range).await {
+                            Ok(bytes) => Ok(bytes),
+                            Err(jotta::Error::NoSuchFileOrFolder) => Err(Error::NoSuchRevision {
+                                chunk: chunk_no,
+                                revision,
+                            }),
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                })
+                .buffered(num_connections)
+                .right_stream()
+        })
+        .try_flatten()
+        .right_stream()
+}
+
+/// Clamp `range` to `[start, size)`, or `None` if nothing is left.
+fn clamp_range(range: &impl ByteRange, size: u64) -> Option<ClosedByteRange> {
+    let start = range.start();
+    let end = range
+        .end()
+        .map_or(size, |end| end.saturating_add(1).min(size));
+
+    (start < end).then(|| ClosedByteRange::new(start, end - start))
+}
+
+/// Open a stream to an object, without having to know its size in advance.
+///
+/// The metadata is fetched once and `range` is clamped to the size of the
+/// object, so infinite ranges such as [`OpenByteRange::full`] are fine.
+/// Both the metadata and the stream are returned.
+///
+/// **The integrity of the data is not checked by this function.**
+///
+/// # Errors
+///
+/// Returns an error if the metadata can't be fetched, most notably if
+/// there is no such object.
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+pub async fn stream_object<'a, S: TokenStore + 'a>(
+    ctx: Arc<Context<S>>,
+    bucket: BucketName,
+    object: ObjectName,
+    range: impl ByteRange,
+    num_connections: usize,
+) -> crate::Result<(Meta, impl Stream<Item = crate::Result<Bytes>> + 'a)> {
+    check_connections(num_connections)?;
+
+    let meta = get(&ctx, &bucket, &object).await?;
+
+    let stream = match clamp_range(&range, meta.size) {
+        Some(range) => stream_chunks(
+            ctx,
+            bucket,
+            object,
+            Chunks::from_meta(&meta),
+            range,
+            num_connections,
+            DownloadMode::Ordered,
+        )
+        .left_stream(),
+        None => stream::empty().right_stream(),
+    };
+
+    Ok((meta, stream))
+}
+
+/// Stream multiple objects as a single tar archive. Each entry is named after
+/// its object and has a modification time equal to [`Meta::updated`].
+/// Absolute names and `.` and `..` components are made safe to extract,
+/// so `../notes.txt` is exported as `notes.txt`.
+///
+/// Objects are exported one after another, but the chunks of each object
+/// are downloaded with up to `num_connections` concurrent connections.
+///
+/// # Errors
+///
+/// If an error occurs, it is yielded and the stream ends without the
+/// archive trailer, so the archive is recognizably truncated.
+pub fn stream_tar<'a, S: TokenStore + 'a>(
+    ctx: Arc<Context<S>>,
+    bucket: BucketName,
+    names: Vec<ObjectName>,
+    num_connections: usize,
+) -> impl Stream<Item = crate::Result<Bytes>> + 'a {
+    stream::iter(names)
+        .then(move |name| {
+            let ctx = ctx.clone();
+            let bucket = bucket.clone();
+
             async move {
-                ctx.fs
-                    .file_to_bytes(
-                        &UserScopedPath(format!(
-                            "{}/{}/{}",
-                            ctx.user_scoped_root(),
-                            bucket,
-                            object.chunk_path(chunk_no)
-                        )),
-                        range,
-                    )
-                    .await
+                let (meta, body) = stream_object(
+                    ctx,
+                    bucket,
+                    name.clone(),
+                    OpenByteRange::full(),
+                    num_connections,
+                )
+                .await?;
+
+                let header = archive::entry_header(&name, &meta);
+                let padding = Bytes::from(vec![0; archive::padding(meta.size)]);
+                let size = meta.size;
+
+                // the size in the header must match the number of bytes that follow
+                let body = body
+                    .map_ok(Some)
+                    .chain(stream::once(async { Ok(None) }))
+                    .scan(0, move |read, res| {
+                        let res = res.and_then(|bytes| match bytes {
+                            Some(bytes) => {
+                                *read += bytes.len() as u64;
+
+                                if *read > size {
+                                    Err(io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        "object too large",
+                                    )
+                                    .into())
+                                } else {
+                                    Ok(bytes)
+                                }
+                            }
+                            None if *read == size => Ok(padding.clone()),
+                            None => Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "object too small",
+                            )
+                            .into()),
+                        });
+
+                        future::ready(Some(res))
+                    });
+
+                crate::Result::Ok(stream::once(async { Ok(header) }).chain(body))
             }
         })
-        .buffered(num_connections)
-        .map_err(Into::into)
+        .try_flatten()
+        .chain(stream::once(async {
+            Ok(Bytes::from_static(&archive::TRAILER))
+        }))
+        .scan(false, |failed, res: crate::Result<Bytes>| {
+            if *failed {
+                return future::ready(None);
+            }
+
+            *failed = res.is_err();
+            future::ready(Some(res))
+        })
 }
 
-/// Delete an object.
-#[instrument(skip(ctx))]
+/// Compute the MD5 digest of an object by downloading all of it, one
+/// chunk at a time. Compare it with [`Meta::md5`] to verify the object.
+///
+/// # Errors
+///
+/// - network errors
+/// - no such object
+/// - missing chunks
+#[instrument(
+    skip(ctx, bucket, name),
+    fields(bucket = %bucket, object = %name, storage_id = %name.storage_id())
+)]
+pub async fn compute_digest(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> crate::Result<Digest> {
+    let meta = get(ctx, bucket, name).await?;
+    let locator = Chunks::from_meta(&meta);
+    let mut md5 = md5::Context::new();
+
+    if let Some(range) = clamp_range(&OpenByteRange::full(), meta.size) {
+        for (chunk_no, range) in aligned_chunked_byte_range(&range) {
+            let path = locator
+                .path(bucket, name, chunk_no)
+                .ok_or(jotta::Error::NoSuchFileOrFolder)?;
+
+            let mut stream = Box::pin(ctx.fs.file_to_stream(&ctx.path(&path), range).await?);
+
+            while let Some(bytes) = stream.try_next().await? {
+                md5.consume(&bytes);
+            }
+        }
+    }
+
+    Ok(md5.compute())
+}
+
+/// Delete an object permanently. See [`soft_delete`] for a recoverable
+/// alternative.
+///
+/// Content-addressed chunks may be shared with other objects and are
+/// therefore left in place.
+///
+/// Since the whole object folder is removed permanently, it's indexed
+/// first to make sure that it really is the folder of the object, with
+/// the expected name and a metadata file. Otherwise nothing is removed.
+///
+/// # Errors
+///
+/// - [`jotta::Error::NoSuchFileOrFolder`] if there is no such object, or
+///   the folder doesn't look like one
+/// - the usual network and Jottacloud errors
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
 pub async fn delete(
     ctx: &Context<impl TokenStore>,
     bucket: &BucketName,
     object: &ObjectName,
 ) -> crate::Result<()> {
-    let _res = ctx
-        .fs
-        .remove_folder(&UserScopedPath(format!(
-            "{}/{}/{}",
-            ctx.user_scoped_root(),
-            bucket,
-            object.to_hex()
-        )))
-        .await?;
+    remove(ctx, bucket, object, true).await
+}
+
+/// Delete an object by moving it to the Jottacloud trash. Unlike
+/// [`delete`], it can be restored from there until the trash is emptied,
+/// but it keeps counting towards the storage quota until then.
+///
+/// The same checks as in [`delete`] are made first.
+///
+/// # Errors
+///
+/// Same as [`delete`].
+#[instrument(
+    skip(ctx, bucket, object),
+    fields(bucket = %bucket, object = %object, storage_id = %object.storage_id())
+)]
+pub async fn soft_delete(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+) -> crate::Result<()> {
+    remove(ctx, bucket, object, false).await
+}
+
+async fn remove(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    object: &ObjectName,
+    permanent: bool,
+) -> crate::Result<()> {
+    let path = ctx.object_path(bucket, object);
+
+    let res = match ctx.fs.index(&path).await {
+        Ok(folder) if folder.name == object.to_hex() && has_meta(&folder) => {
+            if permanent {
+                ctx.fs.remove_folder(&path).await.map(drop)
+            } else {
+                ctx.fs.trash_folder(&path).await.map(drop)
+            }
+        }
+        Ok(folder) => {
+            warn!(
+                folder = %folder.name,
+                "refusing to delete a folder that isn't an object"
+            );
+            Err(jotta::Error::NoSuchFileOrFolder)
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Some(cache) = &ctx.meta_cache {
+        cache.invalidate(bucket, object);
+    }
+
+    res?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+
+    use bytes::BytesMut;
+    use futures_util::io::{BufReader, Cursor};
     use jotta::range::{ClosedByteRange, OpenByteRange};
 
-    use crate::object::{aligned_chunked_byte_range, CHUNK_SIZE};
+    use crate::object::{
+        aligned_chunked_byte_range, check_connections, checksum_chunks, clamp_range, fill_chunk,
+        meta::Meta, retain_consecutive, size_after_write, Listing, ObjectStat, UploadedChunk,
+        CHUNK_SIZE,
+    };
+
+    #[test]
+    fn group_common_prefixes() {
+        let names = (0..1000)
+            .rev()
+            .map(|i| format!("photos/{}/{i}.jpeg", i % 3).parse().unwrap())
+            .chain(["photos/cat.jpeg".parse().unwrap()]);
+
+        let listing = Listing::group(names, "photos/", "/");
+
+        assert_eq!(listing.objects, vec!["photos/cat.jpeg".parse().unwrap()]);
+        assert_eq!(
+            listing.common_prefixes,
+            ["photos/0/", "photos/1/", "photos/2/"]
+        );
+    }
 
     #[test]
     fn create_aligned_chunks() {
-        let mut iter = aligned_chunked_byte_range(OpenByteRange::full());
+        let mut iter = aligned_chunked_byte_range(&OpenByteRange::full());
 
         assert_eq!(
             iter.next().unwrap(),
-            (0, ClosedByteRange::new_to_including(CHUNK_SIZE as _))
+            (0, ClosedByteRange::new(0, CHUNK_SIZE as _))
         );
         assert_eq!(
             iter.next().unwrap(),
-            (1, ClosedByteRange::new_to_including(CHUNK_SIZE as _))
+            (1, ClosedByteRange::new(0, CHUNK_SIZE as _))
         );
         assert_eq!(
             iter.next().unwrap(),
-            (2, ClosedByteRange::new_to_including(CHUNK_SIZE as _))
+            (2, ClosedByteRange::new(0, CHUNK_SIZE as _))
         );
 
         assert_eq!(
-            aligned_chunked_byte_range(ClosedByteRange::try_from(40..=2_500_000).unwrap())
+            aligned_chunked_byte_range(&ClosedByteRange::try_from(40..=2_500_000).unwrap())
                 .collect::<Vec<_>>(),
             vec![
-                (0, ClosedByteRange::try_from_bounds(40, 1_048_576).unwrap()),
-                (1, ClosedByteRange::new_to_including(1_048_576)),
+                (0, ClosedByteRange::try_from_bounds(40, 1_048_575).unwrap()),
+                (1, ClosedByteRange::new_to_including(1_048_575)),
                 (2, ClosedByteRange::new_to_including(402_848))
             ]
         );
 
         assert_eq!(
-            aligned_chunked_byte_range(ClosedByteRange::try_from(69_420_000..=71_000_000).unwrap())
-                .collect::<Vec<_>>(),
+            aligned_chunked_byte_range(
+                &ClosedByteRange::try_from(69_420_000..=71_000_000).unwrap()
+            )
+            .collect::<Vec<_>>(),
             vec![
                 (
                     66,
-                    ClosedByteRange::try_from_bounds(213_984, 1_048_576).unwrap()
+                    ClosedByteRange::try_from_bounds(213_984, 1_048_575).unwrap()
                 ),
                 (67, ClosedByteRange::new_to_including(745_408))
             ]
         );
+
+        // within a single chunk
+        assert_eq!(
+            aligned_chunked_byte_range(&ClosedByteRange::try_from(500..=999).unwrap())
+                .collect::<Vec<_>>(),
+            vec![(0, ClosedByteRange::try_from_bounds(500, 999).unwrap())]
+        );
+
+        // ending in the first byte of a chunk
+        assert_eq!(
+            aligned_chunked_byte_range(&ClosedByteRange::new(500, CHUNK_SIZE as u64 - 499))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, ClosedByteRange::try_from_bounds(500, 1_048_575).unwrap()),
+                (1, ClosedByteRange::new_to_including(0)),
+            ]
+        );
+
+        assert_eq!(
+            aligned_chunked_byte_range(&ClosedByteRange::new(10, 0)).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn clamp_to_size() {
+        assert_eq!(
+            clamp_range(&OpenByteRange::full(), 100),
+            Some(ClosedByteRange::new(0, 100))
+        );
+        assert_eq!(
+            clamp_range(&OpenByteRange::new(40), 100),
+            Some(ClosedByteRange::new(40, 60))
+        );
+        assert_eq!(
+            clamp_range(&ClosedByteRange::new(90, 50), 100),
+            Some(ClosedByteRange::new(90, 10))
+        );
+        assert_eq!(
+            clamp_range(&ClosedByteRange::new(10, 5), 100),
+            Some(ClosedByteRange::new(10, 5))
+        );
+        assert_eq!(clamp_range(&OpenByteRange::full(), 0), None);
+        assert_eq!(clamp_range(&OpenByteRange::new(100), 100), None);
+    }
+
+    #[test]
+    fn object_stat() {
+        let mut meta = Meta {
+            size: CHUNK_SIZE as u64 * 2 + 1,
+            ..Meta::default()
+        };
+
+        let stat = ObjectStat::from(meta.clone());
+
+        assert_eq!(stat.chunk_count, 3);
+        assert_eq!(stat.etag, ObjectStat::from(meta.clone()).etag);
+
+        meta.size = 0;
+        let empty = ObjectStat::from(meta);
+
+        assert_eq!(empty.chunk_count, 0);
+        assert_ne!(stat.etag, empty.etag);
+    }
+
+    #[test]
+    fn size_after_append() {
+        let chunk = |index, size| UploadedChunk {
+            index,
+            size,
+            md5: md5::compute([]),
+            address: String::new(),
+        };
+
+        // appending 100 bytes to a 1000 byte object re-uploads the first chunk
+        assert_eq!(size_after_write(1000, &chunk(0, 1100)), 1100);
+        // overwriting the middle of an object doesn't change its size
+        assert_eq!(
+            size_after_write(3_000_000, &chunk(0, CHUNK_SIZE as _)),
+            3_000_000
+        );
+        assert_eq!(
+            size_after_write(CHUNK_SIZE as _, &chunk(1, 10)),
+            CHUNK_SIZE as u64 + 10
+        );
+    }
+
+    #[tokio::test]
+    async fn checksum_large_file() {
+        let size = CHUNK_SIZE * 5 / 2;
+        let data = (0..=250).cycle().take(size).collect::<Vec<u8>>();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&data).unwrap();
+
+        let (checksums, whole) = checksum_chunks(tokio::fs::File::open(file.path()).await.unwrap())
+            .await
+            .unwrap();
+
+        let expected = data
+            .chunks(CHUNK_SIZE)
+            .map(|c| (md5::compute(c), c.len() as u64))
+            .collect::<Vec<_>>();
+
+        assert_eq!(checksums, expected);
+        assert_eq!(checksums.len(), 3);
+        assert_eq!(whole, md5::compute(&data));
+    }
+
+    #[tokio::test]
+    async fn incremental_chunk_digest() {
+        let data = (0..=250)
+            .cycle()
+            .take(CHUNK_SIZE + 1234)
+            .collect::<Vec<u8>>();
+        let mut file = BufReader::with_capacity(1000, Cursor::new(&data));
+
+        let mut chunks = Vec::new();
+
+        loop {
+            let mut buf = BytesMut::new();
+            let mut md5 = md5::Context::new();
+            fill_chunk(&mut file, &mut buf, &mut md5).await.unwrap();
+
+            if buf.is_empty() {
+                break;
+            }
+
+            chunks.push((buf, md5.compute()));
+        }
+
+        assert_eq!(chunks.len(), 2);
+
+        for ((buf, md5), expected) in chunks.iter().zip(data.chunks(CHUNK_SIZE)) {
+            assert_eq!(buf[..], *expected);
+            assert_eq!(*md5, md5::compute(expected));
+        }
+    }
+
+    #[test]
+    fn consecutive_chunks() {
+        let chunk = |index| UploadedChunk {
+            index,
+            size: CHUNK_SIZE as _,
+            md5: md5::compute([]),
+            address: String::new(),
+        };
+
+        let mut chunks = vec![chunk(4), chunk(2), chunk(6), chunk(3)];
+        retain_consecutive(&mut chunks, 2);
+
+        assert_eq!(
+            chunks.iter().map(|c| c.index).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+
+        let mut chunks = vec![chunk(1)];
+        retain_consecutive(&mut chunks, 0);
+
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn zero_connections() {
+        let err = check_connections(0).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "invalid input: the number of connections must be at least 1"
+        );
+        assert!(check_connections(1).is_ok());
     }
 }
@@ -0,0 +1,197 @@
+//! Guessing the content type of an object from its first bytes, or
+//! from the extension of its name.
+//!
+//! Only well-known binary formats with unambiguous signatures are
+//! recognized by their bytes. Anything else, including all text, is left
+//! alone. Extensions are looked up in a short table of common formats.
+use mime::Mime;
+
+use super::meta::ContentType;
+use crate::path::ObjectName;
+
+/// A magic byte sequence found at `offset` and the media type it signals.
+struct Signature {
+    offset: usize,
+    magic: &'static [u8],
+    media_type: &'static str,
+}
+
+const fn sig(offset: usize, magic: &'static [u8], media_type: &'static str) -> Signature {
+    Signature {
+        offset,
+        magic,
+        media_type,
+    }
+}
+
+/// Checked in order, so signatures that are prefixes of others (like
+/// `RIFF`) must be more specific or come last.
+const SIGNATURES: &[Signature] = &[
+    sig(0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    sig(0, b"\xff\xd8\xff", "image/jpeg"),
+    sig(0, b"GIF87a", "image/gif"),
+    sig(0, b"GIF89a", "image/gif"),
+    sig(8, b"WEBP", "image/webp"),
+    sig(4, b"ftypavif", "image/avif"),
+    sig(0, b"II*\0", "image/tiff"),
+    sig(0, b"MM\0*", "image/tiff"),
+    sig(0, b"\0\0\x01\0", "image/vnd.microsoft.icon"),
+    sig(0, b"%PDF-", "application/pdf"),
+    sig(0, b"PK\x03\x04", "application/zip"),
+    sig(0, b"\x1f\x8b", "application/gzip"),
+    sig(0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    sig(257, b"ustar", "application/x-tar"),
+    sig(0, b"\0asm", "application/wasm"),
+    sig(0, b"ID3", "audio/mpeg"),
+    sig(0, b"fLaC", "audio/flac"),
+    sig(0, b"OggS", "audio/ogg"),
+    sig(8, b"WAVE", "audio/wav"),
+    sig(4, b"ftyp", "video/mp4"),
+    sig(0, b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+/// Guess the content type of an object from its first bytes, or `None`
+/// if the format isn't recognized.
+pub(crate) fn sniff(head: &[u8]) -> Option<ContentType> {
+    let signature = SIGNATURES.iter().find(|s| {
+        head.get(s.offset..s.offset + s.magic.len())
+            .is_some_and(|bytes| bytes == s.magic)
+    })?;
+
+    let media_type: Mime = signature.media_type.parse().ok()?;
+
+    Some(ContentType(media_type))
+}
+
+/// Lowercase extensions and their media types.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("heic", "image/heic"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("7z", "application/x-7z-compressed"),
+    ("tar", "application/x-tar"),
+    ("wasm", "application/wasm"),
+    ("mp3", "audio/mpeg"),
+    ("flac", "audio/flac"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("mov", "video/quicktime"),
+    ("webm", "video/webm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+];
+
+/// Guess the content type of an object from the extension of the last
+/// segment of its name, or `None` if there is no known extension.
+pub(crate) fn guess_from_name(name: &ObjectName) -> Option<ContentType> {
+    let file_name = name.rsplit('/').next()?;
+    let (stem, extension) = file_name.rsplit_once('.')?;
+
+    // dotfiles like `.bashrc` have no extension
+    if stem.is_empty() {
+        return None;
+    }
+
+    let (_, media_type) = EXTENSIONS
+        .iter()
+        .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))?;
+
+    let media_type: Mime = media_type.parse().ok()?;
+
+    Some(ContentType(media_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{guess_from_name, sniff, EXTENSIONS, SIGNATURES};
+
+    fn sniffed(head: &[u8]) -> Option<String> {
+        sniff(head).map(|t| t.to_string())
+    }
+
+    #[test]
+    fn signatures() {
+        for s in SIGNATURES {
+            assert!(
+                s.media_type.parse::<mime::Mime>().is_ok(),
+                "{}",
+                s.media_type
+            );
+        }
+
+        assert_eq!(
+            sniffed(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(sniffed(b"%PDF-1.7\n").as_deref(), Some("application/pdf"));
+        assert_eq!(
+            sniffed(b"RIFF\0\0\0\0WEBPVP8 ").as_deref(),
+            Some("image/webp")
+        );
+        assert_eq!(
+            sniffed(b"RIFF\0\0\0\0WAVEfmt ").as_deref(),
+            Some("audio/wav")
+        );
+
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniffed(&tar).as_deref(), Some("application/x-tar"));
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(sniffed(b""), None);
+        assert_eq!(sniffed(b"hello world"), None);
+        assert_eq!(sniffed(b"<!DOCTYPE html>"), None);
+        // too short for the signature
+        assert_eq!(sniffed(b"\x89PNG"), None);
+        assert_eq!(sniffed(b"RIFF"), None);
+    }
+
+    #[test]
+    fn extensions() {
+        for (_, media_type) in EXTENSIONS {
+            assert!(media_type.parse::<mime::Mime>().is_ok(), "{media_type}");
+        }
+
+        let guessed = |name: &str| guess_from_name(&name.parse().unwrap()).map(|t| t.to_string());
+
+        assert_eq!(guessed("cat.jpeg").as_deref(), Some("image/jpeg"));
+        assert_eq!(guessed("photos/CAT.JPG").as_deref(), Some("image/jpeg"));
+        assert_eq!(
+            guessed("archive.tar.gz").as_deref(),
+            Some("application/gzip")
+        );
+        assert_eq!(guessed("notes.txt").as_deref(), Some("text/plain"));
+
+        assert_eq!(guessed("README"), None);
+        assert_eq!(guessed(".bashrc"), None);
+        assert_eq!(guessed("dir.png/file"), None);
+        assert_eq!(guessed("file.unknown"), None);
+        assert_eq!(guessed("trailing."), None);
+    }
+}
@@ -0,0 +1,273 @@
+//! Uploads with an explicit lifecycle.
+//!
+//! [`upload_range`](super::upload_range) uploads chunks and updates the
+//! metadata in one go. If the process dies in between, the chunks are
+//! written but the metadata is stale. An [`UploadTxn`] instead stages any
+//! number of writes and must be finished with either [`UploadTxn::commit`]
+//! or [`UploadTxn::abort`].
+//!
+//! Staging only hides data that the current metadata doesn't cover: data
+//! appended to an object, or anything written to a content-addressed
+//! one. Indexed chunks are overwritten in place.
+use futures_util::AsyncBufRead;
+use jotta::{auth::TokenStore, files::ConflictHandler};
+use tracing::{instrument, warn};
+
+use crate::{
+    bucket,
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    path::{BucketName, ObjectName},
+    ChunkLayout, Context,
+};
+
+use super::{
+    apply_chunks, check_connections, chunk_count,
+    chunks::Chunks,
+    empty_meta,
+    meta::{self, set_raw, Meta, Patch},
+    remove_chunks_past_end, upload_chunks, Timestamps,
+};
+
+/// A staged upload to an object.
+///
+/// Async destructors don't exist, so a transaction must be finished by
+/// calling [`UploadTxn::commit`] or [`UploadTxn::abort`]. A transaction
+/// that is dropped without being finished leaves the uploaded chunks
+/// behind without updating the metadata, just like an interrupted
+/// [`upload_range`](super::upload_range).
+///
+/// ```no_run
+/// # use jotta_osd::{Context, object::txn::UploadTxn};
+/// # async fn f(ctx: &Context<jotta::auth::LegacyAuth>) -> Result<(), jotta_osd::errors::Error> {
+/// let bucket = "bucket".parse().unwrap();
+/// let name = "object".parse().unwrap();
+///
+/// let mut txn = UploadTxn::begin(ctx, &bucket, &name, 10).await?;
+///
+/// match txn.write(0, &b"hello"[..]).await {
+///     Ok(()) => {
+///         txn.commit().await?;
+///     }
+///     Err(e) => {
+///         txn.abort().await?;
+///         return Err(e);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use = "a transaction must be finished with `commit` or `abort`"]
+pub struct UploadTxn<'a, S: TokenStore> {
+    ctx: &'a Context<S>,
+    bucket: BucketName,
+    name: ObjectName,
+    original: Meta,
+    staged: Meta,
+    /// Indices of all chunks uploaded so far.
+    written: Vec<u32>,
+    num_connections: usize,
+    finished: bool,
+}
+
+impl<'a, S: TokenStore> UploadTxn<'a, S> {
+    /// Start a transaction on an existing object.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - no such object
+    /// - `num_connections` is zero
+    pub async fn begin(
+        ctx: &'a Context<S>,
+        bucket: &BucketName,
+        name: &ObjectName,
+        num_connections: usize,
+    ) -> crate::Result<UploadTxn<'a, S>> {
+        check_connections(num_connections)?;
+
+        let meta = meta::get(ctx, bucket, name).await?;
+
+        Ok(Self {
+            ctx,
+            bucket: bucket.clone(),
+            name: name.clone(),
+            original: meta.clone(),
+            staged: meta,
+            written: Vec::new(),
+            num_connections,
+            finished: false,
+        })
+    }
+
+    /// Start a transaction that replaces the contents of an existing
+    /// object, like [`create_or_replace`](super::create_or_replace)
+    /// without purging. Fields missing from `patch` are taken from the
+    /// bucket defaults, and the object keeps its chunk layout.
+    ///
+    /// Readers see the original object until the transaction is
+    /// committed, except for indexed chunks that are overwritten. The
+    /// chunks past the new end are deleted when committing.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - no such object
+    /// - `num_connections` is zero
+    pub async fn replace(
+        ctx: &'a Context<S>,
+        bucket: &BucketName,
+        name: &ObjectName,
+        patch: Patch,
+        num_connections: usize,
+    ) -> crate::Result<UploadTxn<'a, S>> {
+        let mut txn = Self::begin(ctx, bucket, name, num_connections).await?;
+
+        let layout = match Chunks::from_meta(&txn.original) {
+            Chunks::Indexed => ChunkLayout::Indexed,
+            Chunks::ContentAddressed(_) => ChunkLayout::ContentAddressed,
+        };
+
+        name.check_len(ctx.config.max_object_name_len)?;
+
+        let defaults = bucket::get_meta(ctx, bucket).await?;
+        txn.staged = empty_meta(ctx, name, patch, defaults, layout);
+
+        Ok(txn)
+    }
+
+    /// Metadata that will be written when committing.
+    #[must_use]
+    pub fn staged(&self) -> &Meta {
+        &self.staged
+    }
+
+    /// Upload `file` at `offset`. Data written past the original end of
+    /// the object isn't visible to readers until the transaction is
+    /// committed. Neither is anything written to a content-addressed
+    /// object, but indexed chunks are overwritten in place, so readers
+    /// see changes within the original size right away.
+    ///
+    /// If the write fails, the chunks uploaded before the failure are
+    /// still part of the transaction and removed by [`UploadTxn::abort`].
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - writing beyond the end of a content-addressed object
+    #[instrument(
+        skip(self, file),
+        fields(bucket = %self.bucket, object = %self.name, storage_id = %self.name.storage_id())
+    )]
+    pub async fn write<R: AsyncBufRead + Unpin>(
+        &mut self,
+        offset: u64,
+        file: R,
+    ) -> crate::Result<()> {
+        let uploaded = upload_chunks(
+            self.ctx,
+            &self.bucket,
+            &self.name,
+            &self.staged,
+            offset,
+            file,
+            ConcurrencyStrategy::Fixed(self.num_connections),
+            None,
+            Timestamps::default(),
+        )
+        .await?;
+
+        self.written.extend(uploaded.chunks.iter().map(|c| c.index));
+
+        if let Some(e) = uploaded.failed {
+            return Err(e);
+        }
+
+        self.staged = apply_chunks(self.staged.clone(), uploaded, Timestamps::default())?;
+
+        Ok(())
+    }
+
+    /// Finish the transaction by writing the staged metadata. If the
+    /// object was [replaced](UploadTxn::replace) by a smaller one, the
+    /// chunks past its end are deleted afterwards.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    pub async fn commit(mut self) -> crate::Result<Meta> {
+        self.finished = true;
+
+        set_raw(
+            self.ctx,
+            &self.bucket,
+            &self.name,
+            &self.staged,
+            ConflictHandler::CreateNewRevision,
+            None,
+        )
+        .await?;
+
+        remove_chunks_past_end(
+            self.ctx,
+            &self.bucket,
+            &self.name,
+            &self.staged,
+            self.original.size,
+        )
+        .await?;
+
+        Ok(self.staged.clone())
+    }
+
+    /// Finish the transaction without changing the metadata, deleting the
+    /// chunks that were added past the original end of the object.
+    ///
+    /// Chunks that were overwritten can't be restored, and
+    /// content-addressed chunks are left alone since other objects may
+    /// share them.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    pub async fn abort(mut self) -> crate::Result<()> {
+        self.finished = true;
+
+        if !matches!(Chunks::from_meta(&self.original), Chunks::Indexed) {
+            return Ok(());
+        }
+
+        let first_new = chunk_count(self.original.size);
+
+        self.written.sort_unstable();
+        self.written.dedup();
+
+        let paths = self
+            .written
+            .iter()
+            .filter(|&&i| u64::from(i) >= first_new)
+            .filter_map(|&i| Chunks::Indexed.path(&self.bucket, &self.name, i));
+
+        for path in paths {
+            match self.ctx.fs.remove_file(&self.ctx.path(&path)).await {
+                Ok(()) | Err(jotta::Error::NoSuchFileOrFolder) => {}
+                Err(e) => return Err(Error::Fs(e)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: TokenStore> Drop for UploadTxn<'_, S> {
+    fn drop(&mut self) {
+        if !self.finished {
+            warn!(
+                "upload to {} dropped without commit or abort, leaving {} chunks behind",
+                self.name.storage_id(),
+                self.written.len()
+            );
+        }
+    }
+}
@@ -7,6 +7,15 @@ use regex::Regex;
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use std::{fmt::Display, str::FromStr, string::FromUtf8Error};
 
+/// Maximum length of an [`ObjectName`], in bytes.
+///
+/// Objects are stored in folders named after the hexadecimal encoding of
+/// their names, which is twice as long, and these folder names end up in
+/// request URLs. Long names may therefore exceed the path limits of
+/// Jottacloud even if they're accepted here. New objects are held to the
+/// lower [`Config::max_object_name_len`](crate::Config::max_object_name_len).
+pub const MAX_OBJECT_NAME_LEN: usize = 1024;
+
 /// A human-readable object name.
 ///
 /// ```
@@ -16,6 +25,7 @@ use std::{fmt::Display, str::FromStr, string::FromUtf8Error};
 /// assert!(ObjectName::from_str("").is_err());
 /// assert!(ObjectName::from_str("hello\nworld").is_err());
 /// assert!(ObjectName::from_str("bye\r\nlword").is_err());
+/// assert!(ObjectName::from_str(&"a".repeat(1025)).is_err());
 /// ```
 #[derive(
     Debug,
@@ -26,6 +36,7 @@ use std::{fmt::Display, str::FromStr, string::FromUtf8Error};
     Eq,
     PartialOrd,
     Ord,
+    Hash,
     Deref,
     DerefMut,
     AsRef,
@@ -51,6 +62,23 @@ impl ObjectName {
         hex::encode(&self.0)
     }
 
+    /// Identifier of the object in the cloud, i.e. the name of the
+    /// folder the object is stored in. Useful for correlating logs with
+    /// what's shown in the Jottacloud web interface.
+    ///
+    /// ```
+    /// use jotta_osd::path::ObjectName;
+    ///
+    /// let name: ObjectName = "logs/2022-04-01.txt".parse().unwrap();
+    ///
+    /// assert_eq!(name.storage_id(), name.to_hex());
+    /// assert_eq!(ObjectName::try_from_hex(&name.storage_id()).unwrap(), name);
+    /// ```
+    #[must_use]
+    pub fn storage_id(&self) -> String {
+        self.to_hex()
+    }
+
     /// Convert a hexadecimal string to an [`ObjectName`].
     ///
     /// # Errors
@@ -63,6 +91,31 @@ impl ObjectName {
         Ok(Self(text))
     }
 
+    /// Check that the name is at most `max` bytes long.
+    ///
+    /// ```
+    /// use jotta_osd::path::ObjectName;
+    ///
+    /// let name: ObjectName = "cat.jpeg".parse().unwrap();
+    ///
+    /// assert!(name.check_len(8).is_ok());
+    /// assert!(name.check_len(7).is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`ParseObjectNameError::InvalidLength`] if the name is too long.
+    pub fn check_len(&self, max: usize) -> Result<(), ParseObjectNameError> {
+        if self.len() > max {
+            return Err(ParseObjectNameError::InvalidLength {
+                len: self.len(),
+                max,
+            });
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn chunk_path(&self, index: u32) -> String {
         format!("{}/{}", self.to_hex(), index)
     }
@@ -78,8 +131,11 @@ impl FromStr for ObjectName {
     type Err = ParseObjectNameError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !(1..=1024).contains(&s.len()) {
-            return Err(Self::Err::InvalidLength);
+        if !(1..=MAX_OBJECT_NAME_LEN).contains(&s.len()) {
+            return Err(Self::Err::InvalidLength {
+                len: s.len(),
+                max: MAX_OBJECT_NAME_LEN,
+            });
         }
 
         for c in s.chars() {
@@ -108,9 +164,15 @@ pub enum ParseObjectNameError {
     #[error("invalid character: `{0}`")]
     IllegalChar(char),
 
-    /// The object name must be between 1 and 1024 characters long.
-    #[error("invalid name length")]
-    InvalidLength,
+    /// The object name must be between 1 and `max` bytes long. See
+    /// [`MAX_OBJECT_NAME_LEN`].
+    #[error("object names must be between 1 and {max} bytes long, not {len}")]
+    InvalidLength {
+        /// Length of the name.
+        len: usize,
+        /// Maximum length.
+        max: usize,
+    },
 }
 
 /// A bucket name
@@ -135,6 +197,7 @@ pub enum ParseObjectNameError {
     Eq,
     PartialOrd,
     Ord,
+    Hash,
     Deref,
     DerefMut,
     AsRef,
@@ -0,0 +1,188 @@
+//! Building blocks for an S3 compatible API on top of the object store.
+//!
+//! The structs mirror the S3 XML schema, so serializing them with an XML
+//! serializer yields responses that S3 clients understand.
+use futures_util::{stream, StreamExt, TryStreamExt};
+use jotta::auth::TokenStore;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::instrument;
+
+use crate::{
+    errors::Error,
+    object::{self, meta, Listing, ObjectStat},
+    path::{BucketName, ObjectName},
+    Context,
+};
+
+/// Default (and maximum) number of keys returned by S3.
+pub const DEFAULT_MAX_KEYS: usize = 1000;
+
+/// Number of metadata files fetched at once when listing.
+const META_CONCURRENCY: usize = 10;
+
+/// Response of `ListObjectsV2`.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename = "ListBucketResult", rename_all = "PascalCase")]
+pub struct ListObjectsV2 {
+    /// Name of the bucket.
+    pub name: BucketName,
+    /// Keys start with this prefix.
+    pub prefix: String,
+    /// Keys are grouped by this delimiter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    /// Maximum number of keys (objects and common prefixes) returned.
+    pub max_keys: usize,
+    /// Number of keys returned.
+    pub key_count: usize,
+    /// Are there more keys to list?
+    pub is_truncated: bool,
+    /// The continuation token of the request, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    /// Pass this to get the next page, if the listing is truncated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_continuation_token: Option<String>,
+    /// Objects.
+    pub contents: Vec<S3Object>,
+    /// Common prefixes, which can be thought of as folders.
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+/// An object in a [`ListObjectsV2`] response.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct S3Object {
+    /// Object name.
+    pub key: ObjectName,
+    /// When the object was last updated.
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_modified: OffsetDateTime,
+    /// Quoted entity tag. See [`Meta::etag`](crate::object::meta::Meta::etag).
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Always `STANDARD`.
+    pub storage_class: &'static str,
+}
+
+/// A common prefix in a [`ListObjectsV2`] response.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CommonPrefix {
+    /// The prefix, including the delimiter.
+    pub prefix: String,
+}
+
+/// An object or a common prefix, ordered by key.
+enum Key {
+    Object(ObjectName),
+    Prefix(String),
+}
+
+impl Key {
+    fn as_str(&self) -> &str {
+        match self {
+            Key::Object(name) => name,
+            Key::Prefix(prefix) => prefix,
+        }
+    }
+}
+
+/// List objects like S3's `ListObjectsV2`. At most `max_keys` objects
+/// and common prefixes are returned, in lexicographical order. The
+/// continuation token is opaque and should be taken from
+/// [`ListObjectsV2::next_continuation_token`].
+///
+/// # Errors
+///
+/// Returns an error if the bucket doesn't exist, if the continuation
+/// token is invalid or if the metadata of any object couldn't be fetched.
+#[instrument(skip(ctx, bucket), fields(bucket = %bucket))]
+pub async fn list_objects_v2(
+    ctx: &Context<impl TokenStore>,
+    bucket: &BucketName,
+    prefix: &str,
+    delimiter: Option<&str>,
+    continuation_token: Option<&str>,
+    max_keys: usize,
+) -> crate::Result<ListObjectsV2> {
+    let start_after = continuation_token
+        .map(|token| {
+            hex::decode(token)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .ok_or_else(|| Error::InvalidInput("invalid continuation token".into()))
+        })
+        .transpose()?;
+
+    let names = object::list(ctx, bucket).await?;
+    let Listing {
+        objects,
+        common_prefixes,
+    } = Listing::group(names, prefix, delimiter.unwrap_or_default());
+
+    let mut keys = objects
+        .into_iter()
+        .map(Key::Object)
+        .chain(common_prefixes.into_iter().map(Key::Prefix))
+        .filter(|key| {
+            start_after
+                .as_deref()
+                .is_none_or(|after| key.as_str() > after)
+        })
+        .collect::<Vec<_>>();
+    keys.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    let is_truncated = max_keys > 0 && keys.len() > max_keys;
+    keys.truncate(max_keys);
+
+    let next_continuation_token = if is_truncated {
+        keys.last().map(|key| hex::encode(key.as_str()))
+    } else {
+        None
+    };
+
+    let key_count = keys.len();
+    let mut objects = Vec::new();
+    let mut common_prefixes = Vec::new();
+
+    for key in keys {
+        match key {
+            Key::Object(name) => objects.push(name),
+            Key::Prefix(prefix) => common_prefixes.push(CommonPrefix { prefix }),
+        }
+    }
+
+    let contents = stream::iter(objects)
+        .map(|name| async move {
+            let stat = ObjectStat::from(meta::get(ctx, bucket, &name).await?);
+            let etag = stat.meta.etag().unwrap_or(stat.etag);
+
+            crate::Result::Ok(S3Object {
+                key: name,
+                last_modified: stat.meta.updated,
+                etag: format!("\"{etag}\""),
+                size: stat.meta.size,
+                storage_class: "STANDARD",
+            })
+        })
+        .buffered(META_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(ListObjectsV2 {
+        name: bucket.clone(),
+        prefix: prefix.into(),
+        delimiter: delimiter.map(Into::into),
+        max_keys,
+        key_count,
+        is_truncated,
+        continuation_token: continuation_token.map(Into::into),
+        next_continuation_token,
+        contents,
+        common_prefixes,
+    })
+}
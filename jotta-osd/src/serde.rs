@@ -1,31 +1,162 @@
-use std::marker::PhantomData;
+use std::{fmt, marker::PhantomData};
 
-use serde::Deserialize;
-use serde_with::DeserializeAs;
+use serde::{de::Visitor, Deserialize, Deserializer, Serializer};
+use serde_with::{de::DeserializeAsWrap, Bytes, DeserializeAs, Same, SerializeAs};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
-/// Treat `null` values as defaults.
-pub(crate) struct NullAsDefault<T>(PhantomData<T>);
+/// Treat `null` values as defaults. Other values are deserialized as `U`.
+pub(crate) struct NullAsDefault<T, U = Same>(PhantomData<(T, U)>);
 
-impl<'de, T> DeserializeAs<'de, Option<T>> for NullAsDefault<T>
+impl<'de, T, U> DeserializeAs<'de, Option<T>> for NullAsDefault<T, U>
 where
-    T: Deserialize<'de> + Default + std::fmt::Debug,
+    T: Default + std::fmt::Debug,
+    U: DeserializeAs<'de, T>,
 {
     fn deserialize_as<D>(deserializer: D) -> Result<Option<T>, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let value = Option::<T>::deserialize(deserializer)?.unwrap_or_default();
+        let value = Option::<DeserializeAsWrap<T, U>>::deserialize(deserializer)?
+            .map(DeserializeAsWrap::into_inner)
+            .unwrap_or_default();
 
         Ok(Some(value))
     }
 }
 
+/// A timestamp that is serialized as an RFC 3339 string, but can be
+/// deserialized from either an RFC 3339 string or a number of milliseconds
+/// since the Unix epoch. This way, metadata written with either encoding
+/// remains readable.
+pub(crate) struct Timestamp;
+
+impl SerializeAs<OffsetDateTime> for Timestamp {
+    fn serialize_as<S>(source: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        time::serde::rfc3339::serialize(source, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, OffsetDateTime> for Timestamp {
+    fn deserialize_as<D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TimestampVisitor;
+
+        impl Visitor<'_> for TimestampVisitor {
+            type Value = OffsetDateTime;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an RFC 3339 timestamp or unix milliseconds")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                OffsetDateTime::parse(v, &Rfc3339).map_err(E::custom)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                OffsetDateTime::from_unix_timestamp_nanos(i128::from(v) * 1_000_000)
+                    .map_err(E::custom)
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                OffsetDateTime::from_unix_timestamp_nanos(i128::from(v) * 1_000_000)
+                    .map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+/// An MD5 digest as a hexadecimal string.
+pub(crate) struct Md5Hex;
+
+impl SerializeAs<md5::Digest> for Md5Hex {
+    fn serialize_as<S>(source: &md5::Digest, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{source:x}"))
+    }
+}
+
+impl<'de> DeserializeAs<'de, md5::Digest> for Md5Hex {
+    fn deserialize_as<D>(deserializer: D) -> Result<md5::Digest, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let mut digest = [0; 16];
+
+        hex::decode_to_slice(s, &mut digest).map_err(serde::de::Error::custom)?;
+
+        Ok(md5::Digest(digest))
+    }
+}
+
+/// MD5 digests concatenated into a single byte string, which is much
+/// smaller than a list of [`Md5Hex`] strings. Human-readable formats
+/// such as JSON get the list of strings instead.
+pub(crate) struct Md5Concat;
+
+impl SerializeAs<Vec<md5::Digest>> for Md5Concat {
+    fn serialize_as<S>(source: &Vec<md5::Digest>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            return serializer.collect_seq(source.iter().map(|d| format!("{d:x}")));
+        }
+
+        let bytes = source.iter().flat_map(|d| d.0).collect::<Vec<u8>>();
+
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de> DeserializeAs<'de, Vec<md5::Digest>> for Md5Concat {
+    fn deserialize_as<D>(deserializer: D) -> Result<Vec<md5::Digest>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            return Vec::<String>::deserialize(deserializer)?
+                .into_iter()
+                .map(|s| {
+                    let mut digest = [0; 16];
+                    hex::decode_to_slice(s, &mut digest).map_err(serde::de::Error::custom)?;
+                    Ok(md5::Digest(digest))
+                })
+                .collect();
+        }
+
+        let bytes: Vec<u8> = Bytes::deserialize_as(deserializer)?;
+
+        if !bytes.len().is_multiple_of(16) {
+            return Err(serde::de::Error::invalid_length(
+                bytes.len(),
+                &"a multiple of 16 bytes",
+            ));
+        }
+
+        Ok(bytes
+            .chunks_exact(16)
+            .map(|d| md5::Digest(d.try_into().unwrap()))
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::NullAsDefault;
-    use serde::Deserialize;
+    use super::{Md5Concat, Md5Hex, NullAsDefault, Timestamp};
+    use serde::{Deserialize, Serialize};
     use serde_json::json;
     use serde_with::serde_as;
+    use time::{macros::datetime, OffsetDateTime};
 
     #[test]
     fn null_as_default() {
@@ -48,4 +179,71 @@ mod tests {
             assert_eq!(params, expected);
         }
     }
+
+    #[test]
+    fn timestamp_encodings() {
+        #[serde_as]
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+        struct Ts(#[serde_as(as = "Timestamp")] OffsetDateTime);
+
+        let expected = Ts(datetime!(2022-03-24 13:37:00.5 UTC));
+
+        let rfc3339: Ts = serde_json::from_value(json!("2022-03-24T13:37:00.5Z")).unwrap();
+        let millis: Ts = serde_json::from_value(json!(1_648_129_020_500_i64)).unwrap();
+
+        assert_eq!(rfc3339, expected);
+        assert_eq!(millis, expected);
+
+        // msgpack roundtrip (new encoding)
+        let buf = rmp_serde::to_vec(&expected).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Ts>(&buf).unwrap(), expected);
+
+        // msgpack with the old encoding
+        let buf = rmp_serde::to_vec(&1_648_129_020_500_u64).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Ts>(&buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn md5_hex() {
+        #[serde_as]
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+        struct Digest(#[serde_as(as = "Md5Hex")] md5::Digest);
+
+        let digest = Digest(md5::compute("hello"));
+        let value = serde_json::to_value(&digest).unwrap();
+
+        assert_eq!(value, json!("5d41402abc4b2a76b9719d911017c592"));
+        assert_eq!(serde_json::from_value::<Digest>(value).unwrap(), digest);
+        assert!(serde_json::from_value::<Digest>(json!("5d41")).is_err());
+    }
+
+    #[test]
+    fn md5_concat() {
+        #[serde_as]
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+        struct Digests(#[serde_as(as = "Md5Concat")] Vec<md5::Digest>);
+
+        #[serde_as]
+        #[derive(Serialize)]
+        struct Raw(#[serde_as(as = "serde_with::Bytes")] Vec<u8>);
+
+        let digests = Digests(vec![md5::compute("hello"), md5::compute("world")]);
+
+        let buf = rmp_serde::to_vec(&digests).unwrap();
+        assert_eq!(buf.len(), 2 + 32); // bin8 header
+        assert_eq!(rmp_serde::from_slice::<Digests>(&buf).unwrap(), digests);
+
+        let value = serde_json::to_value(&digests).unwrap();
+        assert_eq!(
+            value,
+            json!([
+                "5d41402abc4b2a76b9719d911017c592",
+                "7d793037a0760186574b0282f2f435e7"
+            ])
+        );
+        assert_eq!(serde_json::from_value::<Digests>(value).unwrap(), digests);
+
+        let truncated = rmp_serde::to_vec(&Raw(vec![0; 17])).unwrap();
+        assert!(rmp_serde::from_slice::<Digests>(&truncated).is_err());
+    }
 }
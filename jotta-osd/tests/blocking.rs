@@ -0,0 +1,81 @@
+//! The synchronous wrapper, used without an async runtime.
+#![cfg(feature = "blocking")]
+use std::{sync::mpsc, thread};
+
+use jotta_osd::{
+    blocking::BlockingContext,
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::{data, FakeJottacloud};
+
+/// Run the fake server on a runtime in a thread of its own, since the
+/// test itself has none.
+fn start_fake() -> FakeJottacloud {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async move {
+            tx.send(FakeJottacloud::start().await).unwrap();
+            std::future::pending::<()>().await;
+        });
+    });
+
+    rx.recv().unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let fake = start_fake();
+    let ctx = BlockingContext::initialize(fake.fs(), Config::new("blocking")).unwrap();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let data = data(CHUNK_SIZE + 10);
+
+    let meta = ctx
+        .upload(
+            &bucket,
+            &name,
+            Patch::default(),
+            &data,
+            ConcurrencyStrategy::Fixed(2),
+        )
+        .unwrap();
+    assert_eq!(meta.size, data.len() as u64);
+
+    assert_eq!(ctx.list(&bucket).unwrap(), std::slice::from_ref(&name));
+
+    let (meta, downloaded) = ctx.download(&bucket, &name, 2).unwrap();
+    assert_eq!(meta.size, data.len() as u64);
+    assert!(downloaded == data, "contents differ");
+
+    // replacing truncates
+    ctx.upload(
+        &bucket,
+        &name,
+        Patch::default(),
+        b"hello",
+        ConcurrencyStrategy::Fixed(2),
+    )
+    .unwrap();
+    assert_eq!(ctx.download(&bucket, &name, 1).unwrap().1, b"hello");
+
+    assert!(matches!(
+        ctx.create(&bucket, &name, Patch::default()),
+        Err(Error::Fs(jotta_osd::jotta::Error::AlreadyExists))
+    ));
+
+    ctx.delete(&bucket, &name).unwrap();
+    assert!(ctx.list(&bucket).unwrap().is_empty());
+}
@@ -0,0 +1,216 @@
+//! Bucket attributes and statistics.
+use jotta::path::UserScopedPath;
+use jotta_osd::{
+    bucket::{self, BucketMeta, BucketPatch},
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{
+        self,
+        meta::{CacheControl, Patch},
+    },
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn create_get_patch() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+    let name: BucketName = "bucket".parse().unwrap();
+
+    let created = bucket::create(&ctx, &name).await.unwrap();
+    assert!(created.meta.created.is_some());
+    assert!(!created.meta.versioning);
+    assert_eq!(fake.files("Jotta/Archive/buckets/bucket"), [".bucket-meta"]);
+
+    assert_eq!(bucket::get(&ctx, &name).await.unwrap(), created);
+
+    let patch: BucketPatch = serde_json::from_value(serde_json::json!({
+        "default_cache_control": "no-store",
+        "versioning": true,
+    }))
+    .unwrap();
+    let patched = bucket::patch(&ctx, &name, patch).await.unwrap();
+
+    assert_eq!(patched.meta.created, created.meta.created);
+    assert_eq!(
+        patched.meta.default_cache_control,
+        Some(CacheControl("no-store".into()))
+    );
+    assert!(patched.meta.versioning);
+    assert_eq!(bucket::get(&ctx, &name).await.unwrap(), patched);
+
+    // null resets
+    let patch: BucketPatch =
+        serde_json::from_value(serde_json::json!({ "default_cache_control": null })).unwrap();
+    let patched = bucket::patch(&ctx, &name, patch).await.unwrap();
+    assert_eq!(patched.meta.default_cache_control, None);
+    assert!(patched.meta.versioning);
+}
+
+#[tokio::test]
+async fn buckets_without_attributes() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+
+    // created before bucket attributes existed
+    fake.fs()
+        .create_folder(&UserScopedPath("Jotta/Archive/buckets/legacy".into()))
+        .await
+        .unwrap();
+    let new = bucket::create(&ctx, &"new".parse().unwrap()).await.unwrap();
+
+    let buckets = bucket::list(&ctx).await.unwrap();
+    assert_eq!(buckets.len(), 2);
+
+    let legacy = buckets
+        .iter()
+        .find(|b| b.name.to_string() == "legacy")
+        .unwrap();
+    assert_eq!(legacy.meta, BucketMeta::default());
+    assert!(buckets.contains(&new));
+
+    let legacy = bucket::get(&ctx, &"legacy".parse().unwrap()).await.unwrap();
+    assert_eq!(legacy.meta, BucketMeta::default());
+
+    assert!(bucket::get(&ctx, &"missing".parse().unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn stats() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+    let a: BucketName = "a-bucket".parse().unwrap();
+    let b: BucketName = "b-bucket".parse().unwrap();
+
+    bucket::create(&ctx, &a).await.unwrap();
+    bucket::create(&ctx, &b).await.unwrap();
+
+    for (name, data) in [("one", &b"hello"[..]), ("two", &b"world!"[..])] {
+        let name = name.parse().unwrap();
+        object_with(&ctx, &a, &name, data).await;
+    }
+
+    let stats = |sizes| bucket::list_with_stats(&ctx, 2, sizes);
+
+    let counted = stats(false).await.unwrap();
+    assert_eq!(
+        counted
+            .iter()
+            .map(|s| (s.name.to_string(), s.object_count, s.total_size))
+            .collect::<Vec<_>>(),
+        [
+            ("a-bucket".to_owned(), 2, None),
+            ("b-bucket".to_owned(), 0, None)
+        ]
+    );
+
+    let summed = stats(true).await.unwrap();
+    assert_eq!(
+        summed.iter().map(|s| s.total_size).collect::<Vec<_>>(),
+        [Some(11), Some(0)]
+    );
+
+    assert!(matches!(
+        bucket::list_with_stats(&ctx, 0, false).await,
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn create_existing() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+    let name: BucketName = "bucket".parse().unwrap();
+
+    bucket::create(&ctx, &name).await.unwrap();
+    let patch = BucketPatch {
+        versioning: Some(true),
+        ..Default::default()
+    };
+    let patched = bucket::patch(&ctx, &name, patch).await.unwrap();
+
+    assert!(matches!(
+        bucket::create(&ctx, &name).await,
+        Err(Error::Fs(jotta::Error::AlreadyExists))
+    ));
+    assert_eq!(bucket::get(&ctx, &name).await.unwrap(), patched);
+}
+
+#[tokio::test]
+async fn object_defaults() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+    let name: BucketName = "bucket".parse().unwrap();
+
+    bucket::create(&ctx, &name).await.unwrap();
+    let patch: BucketPatch = serde_json::from_value(serde_json::json!({
+        "default_content_type": "text/plain",
+        "default_cache_control": "no-store",
+    }))
+    .unwrap();
+    bucket::patch(&ctx, &name, patch).await.unwrap();
+
+    let meta = object::create(&ctx, &name, &"image.png".parse().unwrap(), Patch::default())
+        .await
+        .unwrap();
+    assert_eq!(meta.content_type.to_string(), "text/plain");
+    assert_eq!(meta.cache_control, CacheControl("no-store".into()));
+
+    // explicit values win
+    let patch = Patch {
+        cache_control: Some(CacheControl("max-age=60".into())),
+        ..Default::default()
+    };
+    let meta = object::create_or_replace(&ctx, &name, &"other".parse().unwrap(), patch, true)
+        .await
+        .unwrap();
+    assert_eq!(meta.content_type.to_string(), "text/plain");
+    assert_eq!(meta.cache_control, CacheControl("max-age=60".into()));
+}
+
+#[tokio::test]
+async fn versioning() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("buckets")).await;
+    let name: BucketName = "bucket".parse().unwrap();
+    let object: ObjectName = "object".parse().unwrap();
+    let chunk = format!("Jotta/Archive/buckets/bucket/{}/0", object.storage_id());
+
+    bucket::create(&ctx, &name).await.unwrap();
+
+    for versioning in [false, true] {
+        let patch = BucketPatch {
+            versioning: Some(versioning),
+            ..Default::default()
+        };
+        bucket::patch(&ctx, &name, patch).await.unwrap();
+
+        object::create_or_replace(&ctx, &name, &object, Patch::default(), true)
+            .await
+            .unwrap();
+        object::upload_range(
+            &ctx,
+            &name,
+            &object,
+            0,
+            &b"hello"[..],
+            ConcurrencyStrategy::Fixed(1),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // replacing purges the chunks, unless they're kept as revisions
+        object::create_or_replace(&ctx, &name, &object, Patch::default(), true)
+            .await
+            .unwrap();
+        assert_eq!(fake.file(&chunk).is_some(), versioning);
+    }
+}
@@ -0,0 +1,196 @@
+//! Concurrent writers racing against each other on the same object.
+//!
+//! Jottacloud has no transactions, so the guarantees are weak: the
+//! metadata is last-writer-wins. What must hold is that the object is
+//! always readable, that its size matches its chunks and that the data
+//! is something one of the writers could have produced.
+use std::sync::Arc;
+
+use futures_util::future;
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{
+        self,
+        meta::{self, ContentType, Patch},
+        CHUNK_SIZE,
+    },
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config,
+};
+
+mod fake;
+
+use fake::{object_folder, object_with, read, FakeJottacloud};
+
+const ROOT: &str = "consistency";
+
+/// `top` written over `bottom` at offset 0.
+fn overlay(bottom: &[u8], top: &[u8]) -> Vec<u8> {
+    let mut data = bottom.to_vec();
+    data.resize(bottom.len().max(top.len()), 0);
+    data[..top.len()].copy_from_slice(top);
+    data
+}
+
+#[tokio::test]
+async fn concurrent_overwrites() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(
+        fake.context(Config {
+            chunk_layout: ChunkLayout::ContentAddressed,
+            ..Config::new(ROOT)
+        })
+        .await,
+    );
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let a = vec![1; CHUNK_SIZE * 2 + 100];
+    let b = vec![2; CHUNK_SIZE + 5];
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let (res_a, res_b) = future::join(
+        object::upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &a[..],
+            ConcurrencyStrategy::Fixed(2),
+            None,
+        ),
+        object::upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &b[..],
+            ConcurrencyStrategy::Fixed(2),
+            None,
+        ),
+    )
+    .await;
+
+    res_a.unwrap();
+    res_b.unwrap();
+
+    let (meta, data) = read(&ctx, &bucket, &name).await;
+
+    assert_eq!(meta.size, data.len() as u64);
+    assert!(
+        [a.clone(), b.clone(), overlay(&a, &b), overlay(&b, &a)].contains(&data),
+        "torn object of {} bytes",
+        data.len()
+    );
+
+    // every referenced chunk must still be around
+    let stored = fake.files(&format!("Jotta/Archive/{ROOT}/.chunks"));
+
+    for address in meta.chunks.unwrap() {
+        assert!(stored.contains(&address), "missing chunk {address}");
+    }
+}
+
+#[tokio::test]
+async fn concurrent_single_chunk_overwrites() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(
+        fake.context(Config {
+            chunk_layout: ChunkLayout::Indexed,
+            ..Config::new(ROOT)
+        })
+        .await,
+    );
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    // indexed chunks are overwritten in place, so only single chunks
+    // of equal size are atomic
+    let a = vec![1; 1000];
+    let b = vec![2; 1000];
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let (res_a, res_b) = future::join(
+        object::upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &a[..],
+            ConcurrencyStrategy::Fixed(2),
+            None,
+        ),
+        object::upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &b[..],
+            ConcurrencyStrategy::Fixed(2),
+            None,
+        ),
+    )
+    .await;
+
+    res_a.unwrap();
+    res_b.unwrap();
+
+    let (meta, data) = read(&ctx, &bucket, &name).await;
+
+    assert_eq!(meta.size, 1000);
+    assert!(data == a || data == b);
+
+    let mut files = fake.files(&object_folder(ROOT, &bucket, &name));
+    files.sort();
+
+    assert_eq!(files, ["0", "meta"], "orphaned chunks");
+}
+
+#[tokio::test]
+async fn concurrent_conditional_patches() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(
+        fake.context(Config {
+            chunk_layout: ChunkLayout::Indexed,
+            ..Config::new(ROOT)
+        })
+        .await,
+    );
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let body = vec![3; 2000];
+
+    object_with(&ctx, &bucket, &name, &body).await;
+
+    let etag = object::stat(&ctx, &bucket, &name).await.unwrap().etag;
+
+    let patch = |mime: mime::Mime| Patch {
+        content_type: Some(ContentType(mime)),
+        ..Patch::default()
+    };
+
+    let (res_a, res_b) = future::join(
+        meta::patch(&ctx, &bucket, &name, patch(mime::TEXT_PLAIN), Some(&etag)),
+        meta::patch(&ctx, &bucket, &name, patch(mime::IMAGE_PNG), Some(&etag)),
+    )
+    .await;
+
+    let applied = [res_a, res_b]
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|meta| meta.content_type)
+        .collect::<Vec<_>>();
+
+    assert!(!applied.is_empty());
+
+    let (meta, data) = read(&ctx, &bucket, &name).await;
+
+    assert!(applied.contains(&meta.content_type));
+    assert_eq!(data, body);
+}
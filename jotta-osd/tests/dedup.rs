@@ -0,0 +1,56 @@
+//! Skipping uploads of content that Jottacloud already has.
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{self, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config,
+};
+
+mod fake;
+
+use fake::{data, object_with, FakeJottacloud};
+
+async fn reupload(chunk_layout: ChunkLayout) {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake
+        .context(Config {
+            chunk_layout,
+            ..Config::new("dedup")
+        })
+        .await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let body = data(CHUNK_SIZE * 2 + 10);
+
+    object_with(&ctx, &bucket, &name, &body).await;
+
+    let before = fake.uploads().len();
+
+    object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &body[..],
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+
+    // only the metadata, with its new modification time, is uploaded
+    let uploads = fake.uploads().split_off(before);
+    assert_eq!(uploads.len(), 1, "{uploads:?}");
+    assert!(uploads[0].ends_with("/meta"));
+}
+
+#[tokio::test]
+async fn indexed_reupload() {
+    reupload(ChunkLayout::Indexed).await;
+}
+
+#[tokio::test]
+async fn content_addressed_reupload() {
+    reupload(ChunkLayout::ContentAddressed).await;
+}
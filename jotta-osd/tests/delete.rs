@@ -0,0 +1,95 @@
+//! Only folders that look right are removed, permanently or to the trash.
+use jotta_osd::{
+    bucket,
+    errors::Error,
+    jotta::{self, path::UserScopedPath},
+    object::{self, meta::Patch},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+fn is_not_found<T: std::fmt::Debug>(res: Result<T, Error>) -> bool {
+    matches!(res, Err(Error::Fs(jotta::Error::NoSuchFileOrFolder)))
+}
+
+#[tokio::test]
+async fn objects() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("delete")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let stray: ObjectName = "stray".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    // a folder named like an object, but without metadata
+    fake.fs()
+        .create_folder(&UserScopedPath(format!(
+            "Jotta/Archive/delete/bucket/{}/0",
+            stray.to_hex()
+        )))
+        .await
+        .unwrap();
+
+    assert!(is_not_found(object::delete(&ctx, &bucket, &stray).await));
+    assert_eq!(
+        fake.folders(&format!("Jotta/Archive/delete/bucket/{}", stray.to_hex())),
+        ["0"]
+    );
+
+    assert!(is_not_found(
+        object::delete(&ctx, &bucket, &"missing".parse().unwrap()).await
+    ));
+
+    object::delete(&ctx, &bucket, &name).await.unwrap();
+    assert!(is_not_found(object::meta::get(&ctx, &bucket, &name).await));
+    assert!(is_not_found(object::delete(&ctx, &bucket, &name).await));
+}
+
+#[tokio::test]
+async fn buckets() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("delete")).await;
+    let name: BucketName = "bucket".parse().unwrap();
+
+    bucket::create(&ctx, &name).await.unwrap();
+    assert!(is_not_found(
+        bucket::delete(&ctx, &"missing".parse().unwrap()).await
+    ));
+    assert_eq!(fake.folders("Jotta/Archive/delete"), ["bucket"]);
+
+    bucket::delete(&ctx, &name).await.unwrap();
+    assert!(fake.folders("Jotta/Archive/delete").is_empty());
+}
+
+#[tokio::test]
+async fn objects_to_trash() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("delete")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let folder = format!("Jotta/Archive/delete/bucket/{}", name.to_hex());
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    object::soft_delete(&ctx, &bucket, &name).await.unwrap();
+    assert!(is_not_found(object::meta::get(&ctx, &bucket, &name).await));
+    assert_eq!(fake.trash(), [folder.clone(), format!("{folder}/meta")]);
+    assert!(is_not_found(
+        object::soft_delete(&ctx, &bucket, &name).await
+    ));
+
+    // permanent deletes skip the trash
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    object::delete(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(fake.trash().len(), 2);
+}
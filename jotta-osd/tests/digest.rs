@@ -0,0 +1,101 @@
+//! Whole-object MD5 digests.
+use std::io::Write;
+
+use jotta::auth::MockTokenStore;
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config, Context,
+};
+
+mod fake;
+
+use fake::{data, FakeJottacloud};
+
+async fn write(ctx: &Context<MockTokenStore>, name: &ObjectName, offset: u64, data: &[u8]) {
+    let bucket: BucketName = "bucket".parse().unwrap();
+
+    object::upload_range(
+        ctx,
+        &bucket,
+        name,
+        offset,
+        data,
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn tracked_digest() {
+    for layout in [ChunkLayout::Indexed, ChunkLayout::ContentAddressed] {
+        let fake = FakeJottacloud::start().await;
+        let ctx = fake
+            .context(Config {
+                chunk_layout: layout,
+                ..Config::new("digest")
+            })
+            .await;
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let name: ObjectName = "object".parse().unwrap();
+
+        let meta = object::create(&ctx, &bucket, &name, Patch::default())
+            .await
+            .unwrap();
+        assert_eq!(meta.md5, Some(md5::compute([])));
+
+        let mut data = vec![1; CHUNK_SIZE * 2 + 100];
+        write(&ctx, &name, 0, &data).await;
+
+        let meta = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+        assert_eq!(meta.md5, Some(md5::compute(&data)));
+
+        // the digest can't be tracked when only the end is rewritten ...
+        write(&ctx, &name, CHUNK_SIZE as u64 + 5, &[2; 10]).await;
+        data[CHUNK_SIZE + 5..CHUNK_SIZE + 15].fill(2);
+
+        let meta = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+        assert_eq!(meta.md5, None);
+
+        let computed = object::compute_digest(&ctx, &bucket, &name).await.unwrap();
+        assert_eq!(computed, md5::compute(&data));
+
+        // ... nor when the tail is left alone
+        write(&ctx, &name, 0, &[3; 10]).await;
+        let meta = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+        assert_eq!(meta.md5, None);
+
+        // but it can when everything is rewritten
+        write(&ctx, &name, 0, &data).await;
+        let meta = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+        assert_eq!(meta.md5, Some(md5::compute(&data)));
+    }
+}
+
+#[tokio::test]
+async fn uploaded_file() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("digest")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "file".parse().unwrap();
+
+    let data = data(CHUNK_SIZE + 1234);
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    let meta = object::upload_file(&ctx, &bucket, &name, file.path())
+        .await
+        .unwrap();
+
+    assert_eq!(meta.md5, Some(md5::compute(&data)));
+    assert_eq!(
+        object::compute_digest(&ctx, &bucket, &name).await.unwrap(),
+        md5::compute(&data)
+    );
+}
@@ -0,0 +1,60 @@
+//! Chunks are reassembled in order regardless of the download mode.
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use jotta::range::ClosedByteRange;
+use jotta_osd::{
+    concurrency::DownloadMode,
+    object::{self, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+use rand::{rngs::OsRng, RngCore};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn modes_yield_identical_bytes() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("modes")).await;
+    let ctx = Arc::new(ctx);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let mut data = vec![0; CHUNK_SIZE * 5 + 1234];
+    OsRng.fill_bytes(&mut data);
+
+    object_with(&ctx, &bucket, &name, &data).await;
+
+    // unaligned on both ends
+    let range = ClosedByteRange::new(100, data.len() as u64 - 200);
+    let expected = &data[100..data.len() - 100];
+
+    for mode in [
+        DownloadMode::Ordered,
+        DownloadMode::Windowed { max: 1 },
+        DownloadMode::Windowed { max: 4 },
+    ] {
+        let chunks =
+            object::stream_range(ctx.clone(), bucket.clone(), name.clone(), range, 3, mode)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+        assert_eq!(chunks.concat(), expected, "{mode:?}");
+    }
+
+    let res = object::stream_range(
+        ctx,
+        bucket,
+        name,
+        range,
+        3,
+        DownloadMode::Windowed { max: 0 },
+    )
+    .try_collect::<Vec<_>>()
+    .await;
+    assert!(res.is_err());
+}
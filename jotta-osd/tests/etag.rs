@@ -0,0 +1,91 @@
+//! S3 multipart style entity tags derived from the chunk digests.
+use bytes::Bytes;
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config,
+};
+
+mod fake;
+
+use fake::{data, FakeJottacloud};
+
+fn multipart_etag(data: &[u8]) -> String {
+    let mut hasher = md5::Context::new();
+    let mut parts = 0;
+
+    for chunk in data.chunks(CHUNK_SIZE) {
+        hasher.consume(md5::compute(chunk).0);
+        parts += 1;
+    }
+
+    format!("{:x}-{parts}", hasher.compute())
+}
+
+async fn etags(chunk_layout: ChunkLayout) {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake
+        .context(Config {
+            chunk_layout,
+            ..Config::new("etag")
+        })
+        .await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let write = |offset: u64, body: Bytes| {
+        let (ctx, bucket, name) = (&ctx, &bucket, &name);
+
+        async move {
+            object::upload_range(
+                ctx,
+                bucket,
+                name,
+                offset,
+                &body[..],
+                ConcurrencyStrategy::Fixed(2),
+                None,
+            )
+            .await
+            .unwrap()
+        }
+    };
+
+    let meta = object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        meta.etag().as_deref(),
+        Some("d41d8cd98f00b204e9800998ecf8427e")
+    );
+
+    let meta = write(0, Bytes::from_static(b"hello")).await;
+    assert_eq!(meta.etag(), Some(format!("{:x}", md5::compute("hello"))));
+
+    let mut data = data(CHUNK_SIZE * 2 + 10);
+    let meta = write(0, Bytes::from(data.clone())).await;
+    assert_eq!(meta.etag(), Some(multipart_etag(&data)));
+
+    // the digests of partially overwritten chunks are updated too
+    data[CHUNK_SIZE + 5..CHUNK_SIZE + 10].copy_from_slice(b"world");
+    let meta = write(CHUNK_SIZE as u64 + 5, Bytes::from_static(b"world")).await;
+    assert_eq!(meta.etag(), Some(multipart_etag(&data)));
+    assert_eq!(
+        object::meta::get(&ctx, &bucket, &name)
+            .await
+            .unwrap()
+            .etag(),
+        meta.etag()
+    );
+}
+
+#[tokio::test]
+async fn indexed() {
+    etags(ChunkLayout::Indexed).await;
+}
+
+#[tokio::test]
+async fn content_addressed() {
+    etags(ChunkLayout::ContentAddressed).await;
+}
@@ -0,0 +1,153 @@
+//! Translating raw events into object events.
+use jotta::events::ServerEvent;
+use jotta_osd::{
+    events::ObjectEvent,
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+const ROOT: &str = "events";
+
+fn file_event(kind: &str, from: &str, to: Option<&str>, revision: u32) -> ServerEvent {
+    serde_json::from_value(serde_json::json!({
+        "ST": kind,
+        "D": {
+            "FROM": from,
+            "TO": to,
+            "actorDevice": "WEBAPP",
+            "created": "2016-02-04-T07:56:43Z",
+            "dfs": "04KZFaGU",
+            "fileuuid": "da635047-34dd-46e2-99c3-091762fe20d0",
+            "md5": "02588fb184ae4930cf998b8af2e613e7",
+            "mimeType": "APPLICATION_OCTET_STREAM",
+            "modified": "2016-02-04-T07:56:43Z",
+            "revision": revision.to_string(),
+            "size": "17",
+            "updated": "2016-02-04-T07:58:46Z",
+            "uuid": "a2f5e550-cb15-11e5-b530-002590c0b00c"
+        }
+    }))
+    .unwrap()
+}
+
+fn dir_event(kind: &str, from: &str) -> ServerEvent {
+    serde_json::from_value(serde_json::json!({
+        "ST": kind,
+        "D": {
+            "FROM": from,
+            "actorDevice": "WEBAPP",
+            "uuid": "a2f5e550-cb15-11e5-b530-002590c0b00c"
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn meta_and_chunks() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new(ROOT)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let object: ObjectName = "object".parse().unwrap();
+    let folder = format!("/alice/Jotta/Archive/{ROOT}/{bucket}/{}", object.to_hex());
+    let meta = format!("{folder}/meta");
+    let chunk = format!("{folder}/0");
+
+    let created = ObjectEvent::Created {
+        bucket: bucket.clone(),
+        object: object.clone(),
+    };
+    let updated = ObjectEvent::Updated {
+        bucket: bucket.clone(),
+        object: object.clone(),
+    };
+    let deleted = ObjectEvent::Deleted {
+        bucket: bucket.clone(),
+        object: object.clone(),
+    };
+
+    for (event, expected) in [
+        (file_event("NEW_UPLOAD", &meta, None, 1), &created),
+        (file_event("NEW_UPLOAD", &meta, None, 2), &updated),
+        (file_event("NEW_UPLOAD", &chunk, None, 1), &updated),
+        (file_event("RESTORE", &meta, None, 3), &created),
+        (file_event("RESTORE", &chunk, None, 3), &updated),
+        (file_event("DELETE", &meta, None, 3), &deleted),
+        (file_event("DELETE", &chunk, None, 3), &updated),
+        (dir_event("HARD_DELETE_DIR", &folder), &deleted),
+    ] {
+        assert_eq!(
+            ctx.object_events(&event),
+            std::slice::from_ref(expected),
+            "{event:?}"
+        );
+    }
+
+    assert!(ctx
+        .object_events(&dir_event("CREATE_DIR", &folder))
+        .is_empty());
+}
+
+#[tokio::test]
+async fn moves() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new(ROOT)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let a: ObjectName = "a".parse().unwrap();
+    let b: ObjectName = "b".parse().unwrap();
+    let meta = |name: &ObjectName| {
+        format!(
+            "/alice/Jotta/Archive/{ROOT}/{bucket}/{}/meta",
+            name.to_hex()
+        )
+    };
+
+    assert_eq!(
+        ctx.object_events(&file_event("MOVE", &meta(&a), Some(&meta(&b)), 1)),
+        [
+            ObjectEvent::Deleted {
+                bucket: bucket.clone(),
+                object: a.clone(),
+            },
+            ObjectEvent::Created {
+                bucket: bucket.clone(),
+                object: b,
+            }
+        ]
+    );
+
+    // moved out of the root
+    assert_eq!(
+        ctx.object_events(&file_event(
+            "MOVE",
+            &meta(&a),
+            Some("/alice/Jotta/Archive/elsewhere/meta"),
+            1
+        )),
+        [ObjectEvent::Deleted { bucket, object: a }]
+    );
+}
+
+#[tokio::test]
+async fn unrelated() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new(ROOT)).await;
+    let hex = "object".parse::<ObjectName>().unwrap().to_hex();
+
+    for path in [
+        format!("/bob/Jotta/Archive/{ROOT}/bucket/{hex}/meta"),
+        format!("/alice/Jotta/Sync/{ROOT}/bucket/{hex}/meta"),
+        format!("/alice/Jotta/Archive/{ROOT}-2/bucket/{hex}/meta"),
+        format!("/alice/Jotta/Archive/{ROOT}/bucket"),
+        format!("/alice/Jotta/Archive/{ROOT}"),
+    ] {
+        assert!(
+            ctx.object_events(&file_event("NEW_UPLOAD", &path, None, 1))
+                .is_empty(),
+            "{path}"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Test helpers around [`MockJottacloud`], an in-memory Jottacloud that
+//! lets `jotta-osd` run without credentials.
+#![allow(dead_code)] // not every test uses every helper
+
+use std::{ops::Deref, sync::Arc};
+
+use futures_util::{future, TryStreamExt};
+use jotta::{
+    auth::MockTokenStore,
+    mock::{MockJottacloud, MOCK_USERNAME},
+    range::OpenByteRange,
+};
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{
+        self,
+        meta::{Meta, Patch},
+    },
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+pub const USERNAME: &str = MOCK_USERNAME;
+
+/// A [`MockJottacloud`] that can also set up contexts.
+#[derive(Debug, Clone)]
+pub struct FakeJottacloud(MockJottacloud);
+
+impl FakeJottacloud {
+    /// Start a server on a random port.
+    pub async fn start() -> Self {
+        Self(MockJottacloud::start().await)
+    }
+
+    /// A context on this server, failing the test if it can't be set up.
+    pub async fn context(&self, config: Config) -> Context<MockTokenStore> {
+        Context::initialize(self.fs(), config).await.unwrap()
+    }
+}
+
+impl Deref for FakeJottacloud {
+    type Target = MockJottacloud;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// `len` bytes of test data. The pattern doesn't line up with the chunk
+/// size, so neighbouring chunks differ.
+pub fn data(len: usize) -> Vec<u8> {
+    (0..=250).cycle().take(len).collect()
+}
+
+/// Create an object and write `data` to it.
+pub async fn object_with(
+    ctx: &Context<MockTokenStore>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    data: &[u8],
+) -> Meta {
+    object::create(ctx, bucket, name, Patch::default())
+        .await
+        .unwrap();
+
+    object::upload_range(
+        ctx,
+        bucket,
+        name,
+        0,
+        data,
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+/// A context on `root` with an object `bucket/object` containing `data`.
+pub async fn setup(
+    fake: &FakeJottacloud,
+    root: &str,
+    data: &[u8],
+) -> (Context<MockTokenStore>, BucketName, ObjectName) {
+    let ctx = fake.context(Config::new(root)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object_with(&ctx, &bucket, &name, data).await;
+
+    (ctx, bucket, name)
+}
+
+/// Read an entire object.
+pub async fn read(
+    ctx: &Arc<Context<MockTokenStore>>,
+    bucket: &BucketName,
+    name: &ObjectName,
+) -> (Meta, Vec<u8>) {
+    let (meta, stream) = object::stream_object(
+        ctx.clone(),
+        bucket.clone(),
+        name.clone(),
+        OpenByteRange::full(),
+        2,
+    )
+    .await
+    .unwrap();
+
+    let data = stream
+        .try_fold(Vec::new(), |mut data, bytes| {
+            data.extend_from_slice(&bytes);
+            future::ok(data)
+        })
+        .await
+        .unwrap();
+
+    (meta, data)
+}
+
+/// Path of the folder of an object in the bucket `bucket` of `root`.
+pub fn object_folder(root: &str, bucket: &BucketName, name: &ObjectName) -> String {
+    format!("Jotta/Archive/{root}/{bucket}/{}", name.storage_id())
+}
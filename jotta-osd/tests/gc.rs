@@ -0,0 +1,101 @@
+//! Cleaning up chunks that no object refers to.
+use bytes::Bytes;
+use jotta_osd::{
+    bucket,
+    concurrency::ConcurrencyStrategy,
+    jotta::path::UserScopedPath,
+    object::{self, meta::Patch, GcReport, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn chunks_past_the_end() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("gc")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let folder = format!("Jotta/Archive/gc/bucket/{}", name.to_hex());
+
+    let upload = |body: Bytes| {
+        let (ctx, bucket, name) = (&ctx, &bucket, &name);
+
+        async move {
+            object::create_or_replace(ctx, bucket, name, Patch::default(), false)
+                .await
+                .unwrap();
+            object::upload_range(
+                ctx,
+                bucket,
+                name,
+                0,
+                &body[..],
+                ConcurrencyStrategy::Fixed(2),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+    };
+
+    upload(Bytes::from(vec![1; CHUNK_SIZE * 3])).await;
+    assert_eq!(fake.files(&folder).len(), 4);
+
+    upload(Bytes::from(vec![2; CHUNK_SIZE + 1])).await;
+    assert_eq!(fake.files(&folder).len(), 4);
+
+    let report = object::gc(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(
+        report,
+        GcReport {
+            removed_chunks: 1,
+            removed_objects: 0,
+        }
+    );
+
+    let mut files = fake.files(&folder);
+    files.sort();
+    assert_eq!(files, ["0", "1", "meta"]);
+
+    let report = object::gc(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(report, GcReport::default());
+
+    let meta = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(meta.size, CHUNK_SIZE as u64 + 1);
+}
+
+#[tokio::test]
+async fn objects_without_meta() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("gc")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let kept: ObjectName = "kept".parse().unwrap();
+    let orphan: ObjectName = "orphan".parse().unwrap();
+
+    object::create(&ctx, &bucket, &kept, Patch::default())
+        .await
+        .unwrap();
+    fake.fs()
+        .create_folder(&UserScopedPath(format!(
+            "Jotta/Archive/gc/bucket/{}",
+            orphan.to_hex()
+        )))
+        .await
+        .unwrap();
+
+    let report = bucket::gc(&ctx, &bucket).await.unwrap();
+    assert_eq!(
+        report,
+        GcReport {
+            removed_chunks: 0,
+            removed_objects: 1,
+        }
+    );
+
+    assert_eq!(fake.folders("Jotta/Archive/gc/bucket"), [kept.to_hex()]);
+    object::meta::get(&ctx, &bucket, &kept).await.unwrap();
+}
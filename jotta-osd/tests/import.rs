@@ -0,0 +1,138 @@
+//! Importing objects from other servers.
+use std::convert::Infallible;
+
+use futures_util::TryStreamExt;
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use jotta::range::OpenByteRange;
+use jotta_osd::{
+    errors::Error,
+    jotta,
+    object::{
+        self,
+        meta::{ContentType, Patch},
+        CHUNK_SIZE,
+    },
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+fn data() -> Vec<u8> {
+    fake::data(CHUNK_SIZE * 2 + 7)
+}
+
+/// Serve [`data`] at `/data`, a redirect to it at `/redirect` and 404
+/// everywhere else.
+fn source() -> String {
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            let res = match req.uri().path() {
+                "/data" => Response::builder()
+                    .header(header::CONTENT_TYPE, "image/png")
+                    .body(Body::from(data())),
+                "/redirect" => Response::builder()
+                    .status(StatusCode::FOUND)
+                    .header(header::LOCATION, "/data")
+                    .body(Body::empty()),
+                _ => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty()),
+            };
+
+            Ok::<_, Infallible>(res.unwrap())
+        }))
+    });
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+
+    tokio::spawn(server);
+
+    url
+}
+
+#[tokio::test]
+async fn upload_from_url() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("import")).await;
+    let source = source();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let meta = object::upload_from_url(
+        &ctx,
+        &bucket,
+        &name,
+        format!("{source}/redirect"),
+        Patch::default(),
+        2,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(meta.size, data().len() as u64);
+    assert_eq!(meta.content_type.to_string(), "image/png");
+
+    let (_, stream) = object::stream_object(
+        ctx.into(),
+        bucket.clone(),
+        name.clone(),
+        OpenByteRange::full(),
+        2,
+    )
+    .await
+    .unwrap();
+    let downloaded = stream.try_collect::<Vec<_>>().await.unwrap().concat();
+    assert!(downloaded == data(), "contents differ");
+}
+
+#[tokio::test]
+async fn overrides_and_errors() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("import")).await;
+    let source = source();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let meta = object::upload_from_url(
+        &ctx,
+        &bucket,
+        &name,
+        format!("{source}/data"),
+        Patch {
+            content_type: Some(ContentType(mime::TEXT_PLAIN)),
+            ..Patch::default()
+        },
+        1,
+    )
+    .await
+    .unwrap();
+    assert_eq!(meta.content_type.to_string(), "text/plain");
+
+    let missing: ObjectName = "missing".parse().unwrap();
+    let err = object::upload_from_url(
+        &ctx,
+        &bucket,
+        &missing,
+        format!("{source}/nope"),
+        Patch::default(),
+        1,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, Error::Fetch(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND)),
+        "{err:?}"
+    );
+    assert!(matches!(
+        object::meta::get(&ctx, &bucket, &missing).await,
+        Err(Error::Fs(jotta::Error::NoSuchFileOrFolder))
+    ));
+}
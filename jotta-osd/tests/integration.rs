@@ -1,12 +1,19 @@
-use std::sync::Arc;
+use std::{io::Write, sync::Arc};
 
 use async_once::AsyncOnce;
 use bytes::{BufMut, BytesMut};
 use futures_util::StreamExt;
-use jotta::{auth::LegacyAuth, path::UserScopedPath, range::ClosedByteRange, Fs};
+use jotta::{
+    auth::LegacyAuth,
+    path::UserScopedPath,
+    range::{ClosedByteRange, OpenByteRange},
+    Fs,
+};
 use jotta_osd::{
-    bucket::{self, Bucket},
-    object::{self, meta::Patch},
+    bucket,
+    concurrency::{ConcurrencyStrategy, DownloadMode},
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
     Config, Context,
 };
 use lazy_static::lazy_static;
@@ -51,50 +58,184 @@ async fn create_bucket() {
 
     let name = "my-bucket".parse().unwrap();
     let bucket = bucket::create(&ctx, &name).await.unwrap();
-    assert_eq!(bucket, Bucket { name });
+    assert_eq!(bucket.name, name);
 
     assert_eq!(bucket::list(&ctx).await.unwrap(), vec![bucket]);
 }
 
+/// Object sizes to round-trip, around the chunk boundaries.
+const SIZES: &[usize] = &[
+    0,
+    1,
+    CHUNK_SIZE - 1,
+    CHUNK_SIZE,
+    CHUNK_SIZE + 1,
+    CHUNK_SIZE * 3 + 12_345,
+];
+
+/// Numbers of connections to upload and download with.
+const CONNECTIONS: &[usize] = &[1, 2, 5];
+
+fn random_bytes(len: usize) -> BytesMut {
+    let mut data = BytesMut::new();
+    data.resize(len, 0);
+    OsRng.fill_bytes(&mut data[..]);
+    data
+}
+
+async fn download(
+    ctx: &Arc<Context<LegacyAuth>>,
+    bucket: &BucketName,
+    name: &ObjectName,
+    num_connections: usize,
+) -> BytesMut {
+    let size = object::meta::get(ctx, bucket, name).await.unwrap().size;
+
+    let mut stream = object::stream_range(
+        ctx.clone(),
+        bucket.clone(),
+        name.clone(),
+        ClosedByteRange::new(0, size),
+        num_connections,
+        DownloadMode::Ordered,
+    );
+
+    let mut remote = BytesMut::with_capacity(size.try_into().unwrap());
+
+    while let Some(chunk) = stream.next().await {
+        remote.put(chunk.unwrap());
+    }
+
+    remote
+}
+
 #[tokio::test]
-async fn simple_upload() {
-    let ctx = test_context("simple_upload").await;
+async fn round_trips() {
+    let ctx = Arc::new(test_context("round_trips").await);
 
     let bucket = bucket::create(&ctx, &"can".parse().unwrap()).await.unwrap();
 
-    let name = "random".parse().unwrap();
+    for &size in SIZES {
+        for &num_connections in CONNECTIONS {
+            let name = format!("random-{size}-{num_connections}").parse().unwrap();
+            object::create(&ctx, &bucket.name, &name, Patch::default())
+                .await
+                .unwrap();
+
+            let data = random_bytes(size);
+
+            object::upload_range(
+                &ctx,
+                &bucket.name,
+                &name,
+                0,
+                data.as_ref(),
+                ConcurrencyStrategy::Fixed(num_connections),
+                None,
+            )
+            .await
+            .unwrap();
+
+            let meta = object::meta::get(&ctx, &bucket.name, &name).await.unwrap();
+            assert_eq!(meta.size, size as u64, "{name}");
+
+            let remote = download(&ctx, &bucket.name, &name, num_connections).await;
+
+            assert!(
+                data == remote,
+                "{name}: uploaded file does not match local copy"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn offset_write() {
+    let ctx = Arc::new(test_context("offset_write").await);
+
+    let bucket = bucket::create(&ctx, &"can".parse().unwrap()).await.unwrap();
+    let name = "patched".parse().unwrap();
     object::create(&ctx, &bucket.name, &name, Patch::default())
         .await
         .unwrap();
 
-    let filesize = 4_000_000;
-    let mut data = BytesMut::new();
-    data.resize(filesize, 0);
-    OsRng.fill_bytes(&mut data[..]);
+    let mut data = random_bytes(CHUNK_SIZE * 3);
+
+    object::upload_range(
+        &ctx,
+        &bucket.name,
+        &name,
+        0,
+        data.as_ref(),
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+
+    // in the middle of the second chunk, so both its head and tail must
+    // be downloaded to complete the chunk
+    let offset = CHUNK_SIZE + 1000;
+    let patch = random_bytes(5000);
+    data[offset..offset + patch.len()].copy_from_slice(&patch);
+
+    let meta = object::upload_range(
+        &ctx,
+        &bucket.name,
+        &name,
+        offset as u64,
+        patch.as_ref(),
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(meta.size, data.len() as u64);
+
+    for &num_connections in CONNECTIONS {
+        let remote = download(&ctx, &bucket.name, &name, num_connections).await;
+
+        assert!(
+            data == remote,
+            "patched file does not match local copy ({num_connections} connections)"
+        );
+    }
+}
 
-    object::upload_range(&ctx, &bucket.name, &name, 0, data.as_ref(), 2)
+#[tokio::test]
+async fn file_upload() {
+    let ctx = test_context("file_upload").await;
+
+    let bucket = bucket::create(&ctx, &"box".parse().unwrap()).await.unwrap();
+
+    let name = "file".parse().unwrap();
+    object::create(&ctx, &bucket.name, &name, Patch::default())
         .await
         .unwrap();
 
-    let meta = object::meta::get(&ctx, &bucket.name, &name).await.unwrap();
+    let mut data = vec![0; 5_000_000];
+    OsRng.fill_bytes(&mut data[..]);
 
-    assert_eq!(meta.size, filesize as u64);
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
 
-    let mut stream = object::stream_range(
-        Arc::new(ctx),
-        bucket.name,
-        name,
-        ClosedByteRange::new_to_including(filesize as u64 - 1),
-        2,
-    );
+    let meta = object::upload_file(&ctx, &bucket.name, &name, file.path())
+        .await
+        .unwrap();
 
-    let mut remote = BytesMut::with_capacity(filesize);
+    assert_eq!(meta.size, data.len() as u64);
 
-    while let Some(chunk) = stream.next().await {
-        remote.put(chunk.unwrap());
-    }
+    let (_, stream) =
+        object::stream_object(Arc::new(ctx), bucket.name, name, OpenByteRange::full(), 2)
+            .await
+            .unwrap();
 
-    if data != remote {
-        panic!("uploaded file does not match local copy")
-    }
+    let remote = stream
+        .map(Result::unwrap)
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+    assert!(remote == data, "uploaded file does not match local copy");
 }
@@ -0,0 +1,79 @@
+//! Reading objects written with another chunk layout than the configured one.
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use jotta::range::ClosedByteRange;
+use jotta_osd::{
+    concurrency::DownloadMode,
+    object::{self, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config,
+};
+
+mod fake;
+
+use fake::{data, object_with, FakeJottacloud};
+
+async fn read_back(written_with: ChunkLayout, read_with: ChunkLayout) {
+    let fake = FakeJottacloud::start().await;
+    let writer = fake
+        .context(Config {
+            chunk_layout: written_with,
+            ..Config::new("layout")
+        })
+        .await;
+    let reader = Arc::new(
+        fake.context(Config {
+            chunk_layout: read_with,
+            ..Config::new("layout")
+        })
+        .await,
+    );
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let body = data(CHUNK_SIZE * 2 + 10);
+
+    let meta = object_with(&writer, &bucket, &name, &body).await;
+
+    let range = ClosedByteRange::new(0, body.len() as u64);
+
+    let resolved = object::stream_range(
+        reader.clone(),
+        bucket.clone(),
+        name.clone(),
+        range,
+        2,
+        DownloadMode::Ordered,
+    )
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap()
+    .concat();
+    assert_eq!(resolved, body);
+
+    let located = object::stream_range_with_meta(
+        reader,
+        bucket,
+        name,
+        &meta,
+        range,
+        2,
+        DownloadMode::Ordered,
+    )
+    .try_collect::<Vec<_>>()
+    .await
+    .unwrap()
+    .concat();
+    assert_eq!(located, body);
+}
+
+#[tokio::test]
+async fn content_addressed_read_as_indexed() {
+    read_back(ChunkLayout::ContentAddressed, ChunkLayout::Indexed).await;
+}
+
+#[tokio::test]
+async fn indexed_read_as_content_addressed() {
+    read_back(ChunkLayout::Indexed, ChunkLayout::ContentAddressed).await;
+}
@@ -0,0 +1,48 @@
+//! Listing objects along with their metadata.
+use std::time::Duration;
+
+use jotta_osd::{
+    object::{self, meta::Patch},
+    path::{BucketName, ObjectName},
+    Config,
+};
+use time::OffsetDateTime;
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn recently_changed() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("detailed")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let [a, b, c]: [ObjectName; 3] = ["a", "b", "c"].map(|n| n.parse().unwrap());
+
+    object::create(&ctx, &bucket, &a, Patch::default())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let since = OffsetDateTime::now_utc();
+
+    for name in [&b, &c] {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        object::create(&ctx, &bucket, name, Patch::default())
+            .await
+            .unwrap();
+    }
+
+    let mut objects = object::list_detailed(&ctx, &bucket, None, 2).await.unwrap();
+    object::sort_by_updated(&mut objects);
+    let names = objects.into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+    assert_eq!(names, [c.clone(), b.clone(), a]);
+
+    let mut objects = object::list_detailed(&ctx, &bucket, Some(since), 2)
+        .await
+        .unwrap();
+    object::sort_by_updated(&mut objects);
+    let names = objects.into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+    assert_eq!(names, [c, b]);
+
+    assert!(object::list_detailed(&ctx, &bucket, None, 0).await.is_err());
+}
@@ -0,0 +1,36 @@
+//! Refusing to start with a locked account.
+use jotta_osd::{errors::Error, Config, Context};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+fn config() -> Config {
+    let mut config = Config::new("locks");
+    config.check_locks = true;
+    config
+}
+
+#[tokio::test]
+async fn unlocked() {
+    let fake = FakeJottacloud::start().await;
+
+    fake.context(config()).await;
+}
+
+#[tokio::test]
+async fn locked() {
+    let fake = FakeJottacloud::start().await;
+
+    fake.lock(false, true);
+    let err = Context::initialize(fake.fs(), config()).await.unwrap_err();
+    assert!(matches!(err, Error::WriteLocked), "{err:?}");
+    assert!(fake.folders("Jotta/Archive").is_empty());
+
+    fake.lock(true, false);
+    let err = Context::initialize(fake.fs(), config()).await.unwrap_err();
+    assert!(matches!(err, Error::ReadLocked), "{err:?}");
+
+    // opt-in only
+    fake.context(Config::new("locks")).await;
+}
@@ -0,0 +1,217 @@
+//! Cached metadata and its invalidation.
+use std::num::NonZeroUsize;
+
+use jotta::events::ServerEvent;
+use jotta_osd::{
+    cache::MetaCacheConfig,
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{
+        self,
+        meta::{self, CacheControl, Patch},
+    },
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+const ROOT: &str = "cached";
+
+/// A configuration with the metadata cache enabled.
+fn cached_config() -> Config {
+    Config {
+        meta_cache: Some(MetaCacheConfig::new(NonZeroUsize::new(100).unwrap())),
+        ..Config::new(ROOT)
+    }
+}
+
+fn new_upload(path: &str) -> ServerEvent {
+    serde_json::from_value(serde_json::json!({
+        "ST": "NEW_UPLOAD",
+        "D": {
+            "FROM": path,
+            "actorDevice": "WEBAPP",
+            "created": "2016-02-04-T07:56:43Z",
+            "dfs": "04KZFaGU",
+            "fileuuid": "da635047-34dd-46e2-99c3-091762fe20d0",
+            "md5": "02588fb184ae4930cf998b8af2e613e7",
+            "mimeType": "APPLICATION_OCTET_STREAM",
+            "modified": "2016-02-04-T07:56:43Z",
+            "revision": "2",
+            "size": "17",
+            "updated": "2016-02-04-T07:58:46Z",
+            "uuid": "a2f5e550-cb15-11e5-b530-002590c0b00c"
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn stale_until_invalidated() {
+    let fake = FakeJottacloud::start().await;
+    let cached = fake.context(cached_config()).await;
+    let other = fake.context(Config::new(ROOT)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object_with(&cached, &bucket, &name, &[1; 5]).await;
+
+    // own writes are never stale
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        5
+    );
+
+    object::upload_range(
+        &other,
+        &bucket,
+        &name,
+        0,
+        &[2; 10][..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap();
+
+    // someone else's are until we hear about them
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        5
+    );
+
+    cached.handle_event(&new_upload(&format!(
+        "/alice/Jotta/Archive/{ROOT}/{bucket}/{}/meta",
+        name.storage_id()
+    )));
+
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        10
+    );
+
+    object::delete(&cached, &bucket, &name).await.unwrap();
+
+    assert!(object::stat(&cached, &bucket, &name).await.is_err());
+}
+
+#[tokio::test]
+async fn unrelated_events() {
+    let fake = FakeJottacloud::start().await;
+    let cached = fake.context(cached_config()).await;
+    let other = fake.context(Config::new(ROOT)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&cached, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        0
+    );
+
+    object::append(&other, &bucket, &name, &[1; 3][..], 1)
+        .await
+        .unwrap();
+
+    for path in [
+        "/bob/Jotta/Archive/cached/bucket".to_owned(),
+        format!("/alice/Jotta/Sync/{ROOT}/{bucket}"),
+        format!("/alice/Jotta/Archive/{ROOT}-2/{bucket}"),
+    ] {
+        cached.handle_event(&new_upload(&path));
+    }
+
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        0
+    );
+
+    // a whole bucket
+    cached.handle_event(&new_upload(&format!(
+        "/alice/Jotta/Archive/{ROOT}/{bucket}"
+    )));
+
+    assert_eq!(
+        object::stat(&cached, &bucket, &name)
+            .await
+            .unwrap()
+            .meta
+            .size,
+        3
+    );
+}
+
+#[tokio::test]
+async fn patch_bypasses_cache() {
+    let fake = FakeJottacloud::start().await;
+    let cached = fake.context(cached_config()).await;
+    let other = fake.context(Config::new(ROOT)).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&cached, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+    // fill the cache
+    let before = object::stat(&cached, &bucket, &name).await.unwrap().etag;
+
+    let cache_control = Patch {
+        cache_control: Some(CacheControl("no-store".into())),
+        ..Default::default()
+    };
+    meta::patch(&other, &bucket, &name, cache_control, None)
+        .await
+        .unwrap();
+
+    let content_type = || Patch {
+        content_type: Some("text/plain".parse().unwrap()),
+        ..Default::default()
+    };
+
+    // the entity tag read before the other write is outdated, even though
+    // the cache still serves it
+    assert_eq!(
+        object::stat(&cached, &bucket, &name).await.unwrap().etag,
+        before
+    );
+    assert!(matches!(
+        meta::patch(&cached, &bucket, &name, content_type(), Some(&before)).await,
+        Err(Error::PreconditionFailed)
+    ));
+
+    let current = object::stat(&other, &bucket, &name).await.unwrap().etag;
+    let patched = meta::patch(&cached, &bucket, &name, content_type(), Some(&current))
+        .await
+        .unwrap();
+
+    // the other write isn't reverted
+    assert_eq!(patched.cache_control, CacheControl("no-store".into()));
+    let stored = object::stat(&other, &bucket, &name).await.unwrap().meta;
+    assert_eq!(stored.content_type, patched.content_type);
+    assert_eq!(stored.cache_control, patched.cache_control);
+}
@@ -0,0 +1,131 @@
+//! Where objects are stored: mount points and the root folder.
+use std::sync::Arc;
+
+use futures_util::{future, TryStreamExt};
+use jotta_osd::{
+    errors::Error,
+    jotta::{
+        self,
+        path::{PathOnDevice, UserScopedPath, SHARED, SYNC},
+        range::OpenByteRange,
+    },
+    object::{self},
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+fn config(mount_point: &str) -> Config {
+    Config {
+        mount_point: mount_point.into(),
+        ..Config::new("root")
+    }
+}
+
+#[tokio::test]
+async fn default_mount_point() {
+    let fake = FakeJottacloud::start().await;
+
+    fake.context(Config::new("root")).await;
+
+    assert_eq!(fake.folders("Jotta/Archive"), ["root"]);
+}
+
+#[tokio::test]
+async fn missing_mount_point() {
+    let fake = FakeJottacloud::start().await;
+
+    for mount_point in ["Sync", "Backup"] {
+        let err = Context::initialize(fake.fs(), config(mount_point))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::NoSuchMountPoint { .. }),
+            "{mount_point}: {err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("no mount point `{mount_point}` on device `Jotta`")
+        );
+    }
+
+    assert!(fake.folders("Jotta/Sync").is_empty());
+}
+
+#[tokio::test]
+async fn existing_root_only() {
+    let fake = FakeJottacloud::start().await;
+    let mut config = Config::new("root");
+    config.create_root = false;
+
+    let err = Context::initialize(fake.fs(), config.clone())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::NoSuchRoot { ref root } if root == "root"));
+    assert!(fake.folders("Jotta/Archive").is_empty());
+
+    fake.context(Config::new("root")).await;
+    fake.context(config).await;
+}
+
+#[tokio::test]
+async fn sync_mount_point() {
+    let fake = FakeJottacloud::start().await;
+    let fs = fake.fs();
+
+    fs.create_folder(&UserScopedPath("Jotta/Sync/Documents".into()))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fs.mount_point_path("Jotta", "sync").await.unwrap().0,
+        "Jotta/Sync"
+    );
+    assert!(matches!(
+        fs.mount_point_path("Jotta", SHARED).await,
+        Err(jotta::Error::NoSuchFileOrFolder)
+    ));
+
+    let path = PathOnDevice::new(SYNC, "Documents").on_device("Jotta");
+    fs.index(&path).await.unwrap();
+
+    fake.context(config(SYNC)).await;
+
+    assert_eq!(fake.folders("Jotta/Sync"), ["Documents", "root"]);
+}
+
+#[tokio::test]
+async fn other_mount_point() {
+    let fake = FakeJottacloud::start().await;
+    fake.fs()
+        .create_folder(&UserScopedPath("Jotta/Sync".into()))
+        .await
+        .unwrap();
+
+    let ctx = Arc::new(fake.context(config(SYNC)).await);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object_with(&ctx, &bucket, &name, b"hello").await;
+
+    let (meta, stream) = object::stream_object(ctx, bucket, name, OpenByteRange::full(), 1)
+        .await
+        .unwrap();
+    let data = stream
+        .try_fold(Vec::new(), |mut data, bytes| {
+            data.extend_from_slice(&bytes);
+            future::ok(data)
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(meta.size, 5);
+    assert_eq!(data, b"hello");
+    assert_eq!(fake.folders("Jotta/Sync/root"), ["bucket"]);
+    assert!(fake.folders("Jotta/Archive").is_empty());
+}
@@ -0,0 +1,138 @@
+//! Writes in the middle of objects keep the surrounding data.
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use jotta::{auth::MockTokenStore, range::OpenByteRange};
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config, Context,
+};
+
+mod fake;
+
+use fake::{data, FakeJottacloud};
+
+struct Object {
+    ctx: Arc<Context<MockTokenStore>>,
+    bucket: BucketName,
+    name: ObjectName,
+    /// What the object should contain.
+    expected: Vec<u8>,
+}
+
+impl Object {
+    async fn new(fake: &FakeJottacloud, chunk_layout: ChunkLayout, len: usize) -> Self {
+        let mut config = Config::new("overwrite");
+        config.chunk_layout = chunk_layout;
+        let ctx = Arc::new(fake.context(config).await);
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let name: ObjectName = "object".parse().unwrap();
+
+        object::create(&ctx, &bucket, &name, Patch::default())
+            .await
+            .unwrap();
+
+        let mut object = Self {
+            ctx,
+            bucket,
+            name,
+            expected: Vec::new(),
+        };
+
+        let data = data(len);
+        object.write(0, &data).await;
+
+        object
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) {
+        let meta = object::upload_range(
+            &self.ctx,
+            &self.bucket,
+            &self.name,
+            offset as u64,
+            data,
+            ConcurrencyStrategy::Fixed(3),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let end = offset + data.len();
+
+        if self.expected.len() < end {
+            self.expected.resize(end, 0);
+        }
+
+        self.expected[offset..end].copy_from_slice(data);
+
+        assert_eq!(meta.size, self.expected.len() as u64);
+    }
+
+    async fn check(&self, context: &str) {
+        let (_, stream) = object::stream_object(
+            self.ctx.clone(),
+            self.bucket.clone(),
+            self.name.clone(),
+            OpenByteRange::full(),
+            2,
+        )
+        .await
+        .unwrap();
+
+        let data = stream.try_collect::<Vec<_>>().await.unwrap().concat();
+
+        assert_eq!(data.len(), self.expected.len(), "{context}");
+        assert!(data == self.expected, "{context}: contents differ");
+    }
+}
+
+async fn overwrites(chunk_layout: ChunkLayout) {
+    let fake = FakeJottacloud::start().await;
+    let mut object = Object::new(&fake, chunk_layout, CHUNK_SIZE * 3 + 500).await;
+
+    object.write(1000, &[1; 10]).await;
+    object.check("inside the first chunk").await;
+
+    object.write(CHUNK_SIZE - 100, &[2; 200]).await;
+    object.check("across a chunk boundary").await;
+
+    object.write(CHUNK_SIZE / 2, &vec![3; CHUNK_SIZE * 2]).await;
+    object.check("across several chunks").await;
+
+    object.write(CHUNK_SIZE * 3 + 100, &[4; 10]).await;
+    object.check("inside the last chunk").await;
+
+    object.write(CHUNK_SIZE * 3 + 400, &[5; 200]).await;
+    object.check("past the end").await;
+
+    object.write(CHUNK_SIZE, &[]).await;
+    object.check("nothing").await;
+}
+
+#[tokio::test]
+async fn indexed() {
+    overwrites(ChunkLayout::Indexed).await;
+}
+
+#[tokio::test]
+async fn content_addressed() {
+    overwrites(ChunkLayout::ContentAddressed).await;
+}
+
+#[tokio::test]
+async fn gap_in_last_chunk() {
+    let fake = FakeJottacloud::start().await;
+    let mut object = Object::new(&fake, ChunkLayout::Indexed, 100).await;
+
+    object.write(500, b"hello").await;
+    object.check("after the gap").await;
+
+    let fake = FakeJottacloud::start().await;
+    let mut empty = Object::new(&fake, ChunkLayout::Indexed, 0).await;
+
+    empty.write(5, b"hello").await;
+    empty.check("into an empty object").await;
+}
@@ -0,0 +1,93 @@
+//! Ranges within a single chunk are streamed straight from the response.
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use jotta::range::ClosedByteRange;
+use jotta_osd::{
+    concurrency::DownloadMode,
+    object::{self, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+use rand::{rngs::OsRng, RngCore};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn single_chunk_ranges() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("passthrough")).await;
+    let ctx = Arc::new(ctx);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let mut data = vec![0; CHUNK_SIZE * 3 + 1234];
+    OsRng.fill_bytes(&mut data);
+
+    object_with(&ctx, &bucket, &name, &data).await;
+
+    let chunk = CHUNK_SIZE as u64;
+
+    for (start, len) in [
+        (0, 1),
+        (0, chunk),
+        (100, 5000),
+        (2 * chunk + 17, chunk - 17),
+        (3 * chunk, 1234),
+    ] {
+        let range = ClosedByteRange::new(start, len);
+        let bytes = object::stream_range(
+            ctx.clone(),
+            bucket.clone(),
+            name.clone(),
+            range,
+            3,
+            DownloadMode::Ordered,
+        )
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap()
+        .concat();
+
+        #[allow(clippy::cast_possible_truncation)]
+        let expected = &data[start as usize..(start + len) as usize];
+
+        assert_eq!(bytes, expected, "{start}+{len}");
+    }
+}
+
+#[tokio::test]
+async fn single_chunk_is_not_buffered() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(fake.context(Config::new("passthrough")).await);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object_with(&ctx, &bucket, &name, &vec![1; CHUNK_SIZE * 2]).await;
+
+    let items = |range| {
+        object::stream_range(
+            ctx.clone(),
+            bucket.clone(),
+            name.clone(),
+            range,
+            2,
+            DownloadMode::Ordered,
+        )
+        .map_ok(|bytes| bytes.len())
+        .try_collect::<Vec<_>>()
+    };
+
+    let chunk = CHUNK_SIZE as u64;
+
+    // the response is forwarded as it arrives, in more than one piece
+    let passed_through = items(ClosedByteRange::new(0, chunk)).await.unwrap();
+    assert!(passed_through.len() > 1, "{passed_through:?}");
+    assert_eq!(passed_through.iter().sum::<usize>(), CHUNK_SIZE);
+
+    // whereas ranges across chunks are buffered chunk by chunk
+    let reassembled = items(ClosedByteRange::new(chunk / 2, chunk)).await.unwrap();
+    assert_eq!(reassembled, [CHUNK_SIZE / 2, CHUNK_SIZE / 2]);
+}
@@ -0,0 +1,45 @@
+//! Files and folders are told apart without parsing errors.
+use jotta_osd::{
+    jotta::{jfs::PathKind, path::UserScopedPath},
+    object::{self, meta::Patch},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn files_and_folders() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("kinds")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let folder = format!(
+        "Jotta/Archive/kinds/bucket/{}",
+        fake.folders("Jotta/Archive/kinds/bucket")[0]
+    );
+    let file = format!("{folder}/{}", fake.files(&folder)[0]);
+
+    let fs = fake.fs();
+    let kind = |path: &str| {
+        let path = UserScopedPath(path.into());
+        let fs = &fs;
+
+        async move { fs.path_kind(&path).await }
+    };
+
+    assert_eq!(
+        kind("Jotta/Archive/kinds").await.unwrap(),
+        Some(PathKind::Folder)
+    );
+    assert_eq!(kind(&folder).await.unwrap(), Some(PathKind::Folder));
+    assert_eq!(kind(&file).await.unwrap(), Some(PathKind::File));
+    assert_eq!(kind("Jotta/Archive/kinds/missing").await.unwrap(), None);
+}
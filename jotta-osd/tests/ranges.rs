@@ -0,0 +1,47 @@
+//! Ranged reads of raw files.
+use jotta::{
+    path::UserScopedPath,
+    range::{OpenByteRange, SuffixByteRange},
+};
+use jotta_osd::{
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn suffix_range() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("ranges")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object_with(&ctx, &bucket, &name, b"hello world").await;
+
+    let fs = fake.fs();
+    let chunk = UserScopedPath(format!(
+        "Jotta/Archive/ranges/bucket/{}/0",
+        name.storage_id()
+    ));
+
+    let tail = fs
+        .file_to_bytes(&chunk, SuffixByteRange::new(5))
+        .await
+        .unwrap();
+    assert_eq!(&tail[..], b"world");
+
+    // longer than the file
+    let all = fs
+        .file_to_bytes(&chunk, SuffixByteRange::new(100))
+        .await
+        .unwrap();
+    assert_eq!(
+        all,
+        fs.file_to_bytes(&chunk, OpenByteRange::full())
+            .await
+            .unwrap()
+    );
+}
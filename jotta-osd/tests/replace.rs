@@ -0,0 +1,98 @@
+//! Replacing existing objects.
+use std::{io::Write, sync::Arc};
+
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::ObjectName,
+};
+
+mod fake;
+
+use fake::{object_folder, read, setup, FakeJottacloud};
+
+#[tokio::test]
+async fn create_rejects_conflicts() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "replace", &[1; CHUNK_SIZE + 10]).await;
+    let ctx = Arc::new(ctx);
+
+    assert!(object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .is_err());
+    assert_eq!(read(&ctx, &bucket, &name).await.1.len(), CHUNK_SIZE + 10);
+}
+
+#[tokio::test]
+async fn replace_keeping_chunks() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "replace", &[1; CHUNK_SIZE + 10]).await;
+    let ctx = Arc::new(ctx);
+
+    let meta = object::create_or_replace(&ctx, &bucket, &name, Patch::default(), false)
+        .await
+        .unwrap();
+    assert_eq!(meta.size, 0);
+    assert!(read(&ctx, &bucket, &name).await.1.is_empty());
+
+    let mut files = fake.files(&object_folder("replace", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "1", "meta"]);
+
+    // the stale tail of the first chunk must not reappear
+    object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &[2; 5][..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&ctx, &bucket, &name).await.1, [2; 5]);
+}
+
+#[tokio::test]
+async fn replace_purging_chunks() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "replace", &[1; CHUNK_SIZE + 10]).await;
+    let ctx = Arc::new(ctx);
+
+    let meta = object::create_or_replace(&ctx, &bucket, &name, Patch::default(), true)
+        .await
+        .unwrap();
+    assert_eq!(meta.size, 0);
+    assert_eq!(
+        fake.files(&object_folder("replace", &bucket, &name)),
+        ["meta"]
+    );
+
+    // nothing to purge
+    let other: ObjectName = "other".parse().unwrap();
+    object::create_or_replace(&ctx, &bucket, &other, Patch::default(), true)
+        .await
+        .unwrap();
+    assert!(read(&ctx, &bucket, &other).await.1.is_empty());
+}
+
+#[tokio::test]
+async fn smaller_file() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "replace", &[1; CHUNK_SIZE * 2 + 10]).await;
+    let ctx = Arc::new(ctx);
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&[2; 10]).unwrap();
+
+    object::upload_file(&ctx, &bucket, &name, file.path())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fake.files(&object_folder("replace", &bucket, &name)),
+        ["0", "meta"]
+    );
+    assert_eq!(read(&ctx, &bucket, &name).await.1, [2; 10]);
+}
@@ -0,0 +1,169 @@
+//! Uploads that Jottacloud only partially receives.
+use std::io::Write;
+
+use bytes::Bytes;
+use jotta::{
+    files::ConflictHandler,
+    path::{PathOnDevice, ARCHIVE},
+};
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{self, meta::Patch, CHUNK_SIZE},
+};
+use std::sync::Arc;
+
+mod fake;
+
+use fake::{data, read, setup, FakeJottacloud};
+
+#[tokio::test]
+async fn resumed_upload() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "resume", &[]).await;
+    let ctx = Arc::new(ctx);
+    let data = data(CHUNK_SIZE + 100);
+
+    fake.limit_upload_requests(CHUNK_SIZE / 3);
+
+    object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &data[..],
+        ConcurrencyStrategy::Fixed(2),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(read(&ctx, &bucket, &name).await.1, data);
+}
+
+#[tokio::test]
+async fn resume_sends_remainder() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "resume", &[]).await;
+    let ctx = Arc::new(ctx);
+    let data = data(1000);
+    let before = fake.upload_requests().len();
+
+    fake.limit_upload_requests(400);
+
+    object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &data[..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap();
+
+    // every re-allocation resumes where the previous request stopped
+    assert_eq!(
+        fake.upload_requests()[before..before + 3],
+        [(0, 1000), (400, 600), (800, 200)]
+    );
+    assert_eq!(read(&ctx, &bucket, &name).await.1, data);
+}
+
+#[tokio::test]
+async fn resumed_file_upload() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "resume", &[]).await;
+    let ctx = Arc::new(ctx);
+    let data = data(CHUNK_SIZE + 100);
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
+
+    fake.limit_upload_requests(CHUNK_SIZE / 3);
+
+    object::upload_file(&ctx, &bucket, &name, file.path())
+        .await
+        .unwrap();
+
+    assert_eq!(read(&ctx, &bucket, &name).await.1, data);
+}
+
+#[tokio::test]
+async fn stalled_upload() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "resume", &[]).await;
+    let ctx = Arc::new(ctx);
+
+    fake.limit_upload_requests(0);
+
+    let res = object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &[1; 1000][..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await;
+
+    assert!(matches!(
+        res,
+        Err(Error::Fs(jotta::Error::IncompleteUpload))
+    ));
+}
+
+#[tokio::test]
+async fn resumed_metadata_upload() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "resume", &[]).await;
+    let ctx = Arc::new(ctx);
+
+    fake.limit_upload_requests(10);
+
+    let patch = Patch {
+        content_type: Some("text/plain".parse().unwrap()),
+        ..Patch::default()
+    };
+    object::meta::patch(&ctx, &bucket, &name, patch, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        object::meta::get(&ctx, &bucket, &name)
+            .await
+            .unwrap()
+            .content_type
+            .to_string(),
+        "text/plain"
+    );
+}
+
+#[tokio::test]
+async fn upload_bytes() {
+    let fake = FakeJottacloud::start().await;
+    let fs = fake.fs();
+    let path = PathOnDevice::new(ARCHIVE, "bytes");
+    let data = Bytes::from_static(b"hello world");
+
+    fake.limit_upload_requests(4);
+
+    let res = fs
+        .upload_bytes(&path, data.clone(), ConflictHandler::RejectConflicts)
+        .await
+        .unwrap();
+
+    assert_eq!((res.md5, res.bytes), (md5::compute(&data), 11));
+    assert_eq!(fake.file("Jotta/Archive/bytes").unwrap(), data);
+
+    // nothing is sent again
+    let before = fake.upload_requests().len();
+    let res = fs
+        .upload_bytes(&path, data.clone(), ConflictHandler::CreateNewRevision)
+        .await
+        .unwrap();
+
+    assert_eq!(res.bytes, 11);
+    assert_eq!(fake.upload_requests().len(), before);
+}
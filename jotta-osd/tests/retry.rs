@@ -0,0 +1,120 @@
+//! Transient allocation failures in the middle of uploads.
+use std::time::Duration;
+
+use jotta::{auth::MockTokenStore, Fs, FsOptions, RetryPolicy};
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+mod fake;
+
+use fake::{FakeJottacloud, USERNAME};
+
+fn fs(fake: &FakeJottacloud, max_retries: u32) -> Fs<MockTokenStore> {
+    let allocate_retry = match max_retries {
+        0 => RetryPolicy::NONE,
+        max_retries => RetryPolicy {
+            max_retries,
+            backoff: Duration::from_millis(10),
+        },
+    };
+    let options = FsOptions {
+        allocate_retry,
+        ..FsOptions::default()
+    };
+
+    Fs::with_options(
+        MockTokenStore::new(USERNAME).with_base_url(&fake.base_url()),
+        options,
+    )
+}
+
+#[tokio::test]
+async fn retried_mid_upload() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Context::initialize(fs(&fake, 3), Config::new("retry"))
+        .await
+        .unwrap();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+    let data = (0..3 * CHUNK_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let before = fake.uploads().len();
+    let allocations = fake.allocations();
+
+    // the first chunk gets through, then the next allocation fails twice
+    fake.fail_allocations(1, 2);
+
+    let meta = object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &data[..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(meta.size, data.len() as u64);
+    // three chunks and the metadata, each uploaded once
+    assert_eq!(fake.uploads().len() - before, 4);
+    assert_eq!(fake.allocations() - allocations, 4 + 2);
+}
+
+#[tokio::test]
+async fn retries_exhausted() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Context::initialize(fs(&fake, 1), Config::new("retry"))
+        .await
+        .unwrap();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let allocations = fake.allocations();
+    fake.fail_allocations(0, 2);
+
+    let err = object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(&err, Error::Fs(e) if e.is_retryable()), "{err:?}");
+    assert_eq!(fake.allocations() - allocations, 2);
+
+    // and it works once the server recovers
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn not_retried_without_policy() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Context::initialize(fs(&fake, 0), Config::new("retry"))
+        .await
+        .unwrap();
+
+    let allocations = fake.allocations();
+    fake.fail_allocations(0, 1);
+
+    assert!(object::create(
+        &ctx,
+        &"bucket".parse().unwrap(),
+        &"object".parse().unwrap(),
+        Patch::default()
+    )
+    .await
+    .is_err());
+    assert_eq!(fake.allocations() - allocations, 1);
+}
@@ -0,0 +1,80 @@
+//! Reading old revisions of objects.
+use std::sync::Arc;
+
+use futures_util::TryStreamExt;
+use jotta::range::ClosedByteRange;
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{self, meta::Patch, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+use rand::{rngs::OsRng, RngCore};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn stream_revision() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(fake.context(Config::new("revisions")).await);
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let mut versions = Vec::new();
+
+    for _ in 0..2 {
+        let mut data = vec![0; CHUNK_SIZE + 1000];
+        OsRng.fill_bytes(&mut data);
+
+        object::upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &data[..],
+            ConcurrencyStrategy::Fixed(2),
+            None,
+        )
+        .await
+        .unwrap();
+
+        versions.push(data);
+    }
+
+    let range = ClosedByteRange::new(500, CHUNK_SIZE as u64);
+
+    for (i, data) in versions.iter().enumerate() {
+        let chunks = object::stream_revision(
+            ctx.clone(),
+            bucket.clone(),
+            name.clone(),
+            i as u32 + 1,
+            range,
+            2,
+        )
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+        assert_eq!(chunks.concat(), &data[500..500 + CHUNK_SIZE]);
+    }
+
+    let err = object::stream_revision(ctx, bucket, name, 3, range, 2)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::NoSuchRevision {
+            chunk: 0,
+            revision: 3
+        }
+    ));
+}
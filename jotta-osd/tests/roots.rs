@@ -0,0 +1,29 @@
+//! Several roots sharing one `Fs`.
+use jotta_osd::{bucket, path::BucketName, Config};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn with_root() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("tenants/a")).await;
+    let tenant = ctx.with_root("tenants/b");
+    let name: BucketName = "bucket".parse().unwrap();
+
+    // nothing is created until the first write
+    assert_eq!(fake.folders("Jotta/Archive/tenants"), ["a"]);
+    assert!(bucket::list(&tenant).await.unwrap().is_empty());
+
+    bucket::create(&tenant, &name).await.unwrap();
+
+    assert_eq!(fake.folders("Jotta/Archive/tenants"), ["a", "b"]);
+    assert_eq!(fake.folders("Jotta/Archive/tenants/b"), ["bucket"]);
+
+    let names =
+        |buckets: Vec<bucket::Bucket>| buckets.into_iter().map(|b| b.name).collect::<Vec<_>>();
+
+    assert_eq!(names(bucket::list(&tenant).await.unwrap()), [name]);
+    assert!(bucket::list(&ctx).await.unwrap().is_empty());
+}
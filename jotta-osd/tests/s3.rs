@@ -0,0 +1,91 @@
+//! S3 compatible listings.
+use jotta_osd::{
+    object::{self, meta::Patch},
+    path::{BucketName, ObjectName},
+    s3::{self, CommonPrefix},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn list_objects_v2() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("s3")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+
+    for name in [
+        "a.txt",
+        "b.txt",
+        "photos/cat.jpeg",
+        "photos/dog.jpeg",
+        "photos/2022/bird.jpeg",
+    ] {
+        object::create(&ctx, &bucket, &name.parse().unwrap(), Patch::default())
+            .await
+            .unwrap();
+    }
+
+    let keys = |res: &s3::ListObjectsV2| {
+        res.contents
+            .iter()
+            .map(|o| o.key.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let res = s3::list_objects_v2(&ctx, &bucket, "", Some("/"), None, 1000)
+        .await
+        .unwrap();
+    assert_eq!(keys(&res), ["a.txt", "b.txt"]);
+    assert_eq!(
+        res.common_prefixes,
+        [CommonPrefix {
+            prefix: "photos/".into()
+        }]
+    );
+    assert_eq!(res.key_count, 3);
+    assert!(!res.is_truncated);
+    assert!(res
+        .contents
+        .iter()
+        .all(|o| o.size == 0 && o.etag.starts_with('"') && o.storage_class == "STANDARD"));
+
+    // paginate through everything below photos/
+    let mut token = None;
+    let mut pages = Vec::new();
+
+    loop {
+        let res = s3::list_objects_v2(&ctx, &bucket, "photos/", None, token.as_deref(), 2)
+            .await
+            .unwrap();
+        pages.push(keys(&res));
+
+        if !res.is_truncated {
+            break;
+        }
+
+        token = res.next_continuation_token;
+    }
+
+    assert_eq!(
+        pages,
+        [
+            vec!["photos/2022/bird.jpeg", "photos/cat.jpeg"],
+            vec!["photos/dog.jpeg"]
+        ]
+    );
+
+    assert!(
+        s3::list_objects_v2(&ctx, &bucket, "", None, Some("not hex"), 10)
+            .await
+            .is_err()
+    );
+
+    let name: ObjectName = "a.txt".parse().unwrap();
+    let res = s3::list_objects_v2(&ctx, &bucket, "a", None, None, 10)
+        .await
+        .unwrap();
+    assert_eq!(res.contents[0].key, name);
+}
@@ -0,0 +1,120 @@
+//! Content types of objects created without one are guessed from their
+//! first bytes if enabled, or else from the extension of their name.
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    object::{
+        self,
+        meta::{ContentType, Patch},
+        CHUNK_SIZE,
+    },
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+async fn upload(
+    ctx: &Context<impl jotta_osd::jotta::auth::TokenStore>,
+    name: &str,
+    patch: Patch,
+    offset: u64,
+    data: &[u8],
+) -> String {
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = name.parse().unwrap();
+
+    if offset == 0 {
+        object::create(ctx, &bucket, &name, patch).await.unwrap();
+    }
+
+    object::upload_range(
+        ctx,
+        &bucket,
+        &name,
+        offset,
+        data,
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap()
+    .content_type
+    .to_string()
+}
+
+#[tokio::test]
+async fn sniffing() {
+    let fake = FakeJottacloud::start().await;
+    let mut config = Config::new("sniff");
+    config.sniff_content_type = true;
+    let ctx = fake.context(config).await;
+
+    assert_eq!(
+        upload(&ctx, "a", Patch::default(), 0, PNG).await,
+        "image/png"
+    );
+    assert_eq!(
+        upload(&ctx, "b", Patch::default(), 0, b"just text").await,
+        "application/octet-stream"
+    );
+
+    // explicit content types are kept
+    let patch = Patch {
+        content_type: Some("text/plain".parse::<ContentType>().unwrap()),
+        ..Patch::default()
+    };
+    assert_eq!(upload(&ctx, "c", patch, 0, PNG).await, "text/plain");
+
+    // writes after the first chunk don't change anything
+    assert_eq!(
+        upload(&ctx, "a", Patch::default(), CHUNK_SIZE as u64, b"%PDF-").await,
+        "image/png"
+    );
+}
+
+#[tokio::test]
+async fn disabled_by_default() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("sniff")).await;
+
+    assert_eq!(
+        upload(&ctx, "a", Patch::default(), 0, PNG).await,
+        "application/octet-stream"
+    );
+}
+
+#[tokio::test]
+async fn extensions() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("sniff")).await;
+
+    assert_eq!(
+        upload(&ctx, "photos/cat.jpeg", Patch::default(), 0, PNG).await,
+        "image/jpeg"
+    );
+    assert_eq!(
+        upload(&ctx, "notes.unknown", Patch::default(), 0, b"text").await,
+        "application/octet-stream"
+    );
+
+    // explicit content types are kept
+    let patch = Patch {
+        content_type: Some("text/plain".parse::<ContentType>().unwrap()),
+        ..Patch::default()
+    };
+    assert_eq!(upload(&ctx, "c.png", patch, 0, PNG).await, "text/plain");
+
+    // sniffing takes precedence
+    let mut config = Config::new("sniff");
+    config.sniff_content_type = true;
+    let ctx = fake.context(config).await;
+
+    assert_eq!(
+        upload(&ctx, "d.pdf", Patch::default(), 0, PNG).await,
+        "image/png"
+    );
+}
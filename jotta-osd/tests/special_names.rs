@@ -0,0 +1,114 @@
+//! Names with characters that mean something in URLs, and long names.
+use std::sync::Arc;
+
+use futures_util::{future, TryStreamExt};
+use jotta::range::OpenByteRange;
+use jotta_osd::{
+    bucket,
+    errors::Error,
+    object::{self, meta::Patch},
+    path::{ObjectName, ParseObjectNameError},
+    Config, Context, DEFAULT_MAX_OBJECT_NAME_LEN,
+};
+
+mod fake;
+
+use fake::{object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn special_characters() {
+    let fake = FakeJottacloud::start().await;
+    let root = "my root #1 100%+";
+    let ctx = Arc::new(fake.context(Config::new(root)).await);
+
+    assert_eq!(fake.folders("Jotta/Archive"), [root]);
+
+    let bucket = "bucket".parse().unwrap();
+    bucket::create(&ctx, &bucket).await.unwrap();
+
+    for name in ["a b", "100%", "#hashtag", "1+1", "why?/really"] {
+        let name: ObjectName = name.parse().unwrap();
+        let body = name.to_string().into_bytes();
+
+        object_with(&ctx, &bucket, &name, &body).await;
+
+        let (_, stream) = object::stream_object(
+            ctx.clone(),
+            bucket.clone(),
+            name.clone(),
+            OpenByteRange::full(),
+            1,
+        )
+        .await
+        .unwrap();
+
+        let data = stream
+            .try_fold(Vec::new(), |mut data, bytes| {
+                data.extend_from_slice(&bytes);
+                future::ok(data)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(data, body);
+    }
+
+    let mut names = object::list(&ctx, &bucket).await.unwrap();
+    names.sort_by_key(ToString::to_string);
+
+    assert_eq!(
+        names.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        ["#hashtag", "1+1", "100%", "a b", "why?/really"]
+    );
+}
+
+#[tokio::test]
+async fn unrepresentable_root() {
+    let fake = FakeJottacloud::start().await;
+
+    let res = Context::initialize(fake.fs(), Config::new("../escape")).await;
+
+    assert!(matches!(
+        res,
+        Err(jotta_osd::errors::Error::Fs(jotta::Error::InvalidArgument))
+    ));
+}
+
+#[tokio::test]
+async fn long_names() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("long")).await;
+    let bucket = "bucket".parse().unwrap();
+    let longest: ObjectName = "a".repeat(DEFAULT_MAX_OBJECT_NAME_LEN).parse().unwrap();
+    let too_long: ObjectName = "a".repeat(DEFAULT_MAX_OBJECT_NAME_LEN + 1).parse().unwrap();
+
+    object::create(&ctx, &bucket, &longest, Patch::default())
+        .await
+        .unwrap();
+
+    let before = fake.uploads().len();
+
+    for res in [
+        object::create(&ctx, &bucket, &too_long, Patch::default()).await,
+        object::create_or_replace(&ctx, &bucket, &too_long, Patch::default(), true).await,
+    ] {
+        assert!(matches!(
+            res,
+            Err(Error::ParseObjectName(ParseObjectNameError::InvalidLength {
+                len,
+                max: DEFAULT_MAX_OBJECT_NAME_LEN,
+            })) if len == DEFAULT_MAX_OBJECT_NAME_LEN + 1
+        ));
+    }
+
+    assert_eq!(fake.uploads().len(), before);
+
+    // the limit is configurable
+    let mut config = Config::new("long");
+    config.max_object_name_len = 1000;
+    let ctx = fake.context(config).await;
+
+    object::create(&ctx, &bucket, &too_long, Patch::default())
+        .await
+        .unwrap();
+}
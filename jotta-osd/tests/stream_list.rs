@@ -0,0 +1,134 @@
+//! Listing objects page by page.
+use std::num::NonZeroU32;
+
+use futures_util::{StreamExt, TryStreamExt};
+use jotta_osd::{
+    bucket,
+    errors::Error,
+    jotta::path::UserScopedPath,
+    object::{self, meta::Patch},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn pages() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("paged")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let mut names = (0..7)
+        .map(|i| format!("object-{i}").parse().unwrap())
+        .collect::<Vec<ObjectName>>();
+
+    for name in &names {
+        object::create(&ctx, &bucket, name, Patch::default())
+            .await
+            .unwrap();
+    }
+
+    names.sort();
+
+    for page_size in [1, 3, 7, 100] {
+        let mut listed = object::stream_list(&ctx, &bucket, NonZeroU32::new(page_size).unwrap())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        listed.sort();
+
+        assert_eq!(listed, names, "page size {page_size}");
+    }
+}
+
+#[tokio::test]
+async fn error_mid_pagination() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("paged")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+
+    for name in ["a", "b", "c"] {
+        object::create(&ctx, &bucket, &name.parse().unwrap(), Patch::default())
+            .await
+            .unwrap();
+    }
+
+    let mut stream = Box::pin(object::stream_list(
+        &ctx,
+        &bucket,
+        NonZeroU32::new(2).unwrap(),
+    ));
+
+    assert!(stream.next().await.unwrap().is_ok());
+
+    bucket::delete(&ctx, &bucket).await.unwrap();
+
+    // the rest of the first page is already fetched
+    assert!(stream.next().await.unwrap().is_ok());
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn foreign_folders_are_skipped() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("paged")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    // not hex, not utf-8 and not a valid object name, respectively
+    for folder in ["helper", "ff", "0a"] {
+        fake.fs()
+            .create_folder(&UserScopedPath(format!(
+                "Jotta/Archive/paged/bucket/{folder}"
+            )))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        object::list(&ctx, &bucket).await.unwrap(),
+        std::slice::from_ref(&name)
+    );
+    assert_eq!(
+        object::stream_list(&ctx, &bucket, NonZeroU32::new(2).unwrap())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap(),
+        [name]
+    );
+}
+
+#[tokio::test]
+async fn junk_folder() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("junk")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        object::list_strict(&ctx, &bucket).await.unwrap(),
+        std::slice::from_ref(&name)
+    );
+
+    fake.fs()
+        .create_folder(&UserScopedPath("Jotta/Archive/junk/bucket/junk".into()))
+        .await
+        .unwrap();
+
+    assert_eq!(object::list(&ctx, &bucket).await.unwrap(), [name]);
+    assert!(matches!(
+        object::list_strict(&ctx, &bucket).await,
+        Err(Error::ParseObjectName(_))
+    ));
+}
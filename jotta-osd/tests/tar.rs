@@ -0,0 +1,61 @@
+//! Exporting objects as tar archives.
+use std::{io::Read, sync::Arc};
+
+use futures_util::TryStreamExt;
+use jotta_osd::{
+    object::{self, CHUNK_SIZE},
+    path::{BucketName, ObjectName},
+    Config,
+};
+
+mod fake;
+
+use fake::{data, object_with, FakeJottacloud};
+
+#[tokio::test]
+async fn stream_tar() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = Arc::new(fake.context(Config::new("tar")).await);
+    let bucket: BucketName = "bucket".parse().unwrap();
+
+    let objects = [
+        ("photos/cat.jpeg", data(CHUNK_SIZE * 2 + 10)),
+        ("../notes.txt", data(1000)),
+        ("empty", Vec::new()),
+    ];
+    let mut names = Vec::new();
+
+    for (name, body) in &objects {
+        let name: ObjectName = name.parse().unwrap();
+        object_with(&ctx, &bucket, &name, body).await;
+        names.push(name);
+    }
+
+    let archive = object::stream_tar(ctx, bucket, names, 2)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap()
+        .concat();
+
+    let mut archive = tar::Archive::new(&archive[..]);
+    let entries = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let mut body = Vec::new();
+            entry.read_to_end(&mut body).unwrap();
+
+            (entry.path().unwrap().to_str().unwrap().to_owned(), body)
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        entries,
+        [
+            ("photos/cat.jpeg".to_owned(), objects[0].1.clone()),
+            ("notes.txt".to_owned(), objects[1].1.clone()),
+            ("empty".to_owned(), Vec::new()),
+        ]
+    );
+}
@@ -0,0 +1,79 @@
+//! Custom creation and modification times, such as those of imported files.
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{self, meta::Patch, Timestamps, MAX_TIMESTAMP_SKEW},
+    path::{BucketName, ObjectName},
+    Config,
+};
+use time::{macros::datetime, OffsetDateTime};
+
+mod fake;
+
+use fake::FakeJottacloud;
+
+#[tokio::test]
+async fn upload_with_timestamps() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("timestamps")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let times = Timestamps {
+        created: Some(datetime!(2001-02-03 04:05:06 UTC)),
+        modified: Some(datetime!(2002-03-04 05:06:07 UTC)),
+    };
+
+    let upload = |times| {
+        let (ctx, bucket, name) = (&ctx, &bucket, &name);
+
+        async move {
+            object::upload_range_with_timestamps(
+                ctx,
+                bucket,
+                name,
+                0,
+                &b"hello"[..],
+                ConcurrencyStrategy::Fixed(1),
+                None,
+                times,
+            )
+            .await
+        }
+    };
+
+    let meta = upload(times).await.unwrap();
+    assert_eq!(Some(meta.created), times.created);
+    assert_eq!(Some(meta.updated), times.modified);
+
+    let stored = object::meta::get(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(
+        (stored.created, stored.updated),
+        (meta.created, meta.updated)
+    );
+
+    // unset times are left alone, apart from the modification time
+    let meta = upload(Timestamps::default()).await.unwrap();
+    assert_eq!(Some(meta.created), times.created);
+    assert!(meta.updated > times.modified.unwrap());
+
+    let future = OffsetDateTime::now_utc() + MAX_TIMESTAMP_SKEW * 2;
+    let err = upload(Timestamps {
+        created: None,
+        modified: Some(future),
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::InvalidInput(_)), "{err:?}");
+    assert_eq!(
+        object::meta::get(&ctx, &bucket, &name)
+            .await
+            .unwrap()
+            .updated,
+        meta.updated
+    );
+}
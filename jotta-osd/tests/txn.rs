@@ -0,0 +1,142 @@
+//! Upload transactions.
+use jotta::{auth::MockTokenStore, Fs, FsOptions, RetryPolicy};
+use jotta_osd::{
+    object::{
+        self, meta,
+        meta::{ContentType, Patch},
+        txn::UploadTxn,
+        CHUNK_SIZE,
+    },
+    path::{BucketName, ObjectName},
+    Config, Context,
+};
+
+mod fake;
+
+use fake::{object_folder, setup, FakeJottacloud, USERNAME};
+
+#[tokio::test]
+async fn commit() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "txn", &[1; 10]).await;
+
+    let mut txn = UploadTxn::begin(&ctx, &bucket, &name, 2).await.unwrap();
+    txn.write(10, &vec![2; CHUNK_SIZE][..]).await.unwrap();
+    txn.write(5, &[3; 5][..]).await.unwrap();
+
+    // the new size isn't visible until the transaction is committed
+    assert_eq!(meta::get(&ctx, &bucket, &name).await.unwrap().size, 10);
+
+    let meta = txn.commit().await.unwrap();
+
+    assert_eq!(meta.size, CHUNK_SIZE as u64 + 10);
+    assert_eq!(
+        meta::get(&ctx, &bucket, &name).await.unwrap().size,
+        meta.size
+    );
+
+    let mut files = fake.files(&object_folder("txn", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "1", "meta"]);
+}
+
+#[tokio::test]
+async fn abort() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "txn", &[1; 10]).await;
+
+    let mut txn = UploadTxn::begin(&ctx, &bucket, &name, 2).await.unwrap();
+    txn.write(10, &vec![2; CHUNK_SIZE * 2][..]).await.unwrap();
+
+    let mut files = fake.files(&object_folder("txn", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "1", "2", "meta"]);
+
+    txn.abort().await.unwrap();
+
+    // the first chunk existed before, so it's kept
+    let mut files = fake.files(&object_folder("txn", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "meta"]);
+    assert_eq!(meta::get(&ctx, &bucket, &name).await.unwrap().size, 10);
+}
+
+#[tokio::test]
+async fn dropped() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "txn", &[1; 10]).await;
+
+    let mut txn = UploadTxn::begin(&ctx, &bucket, &name, 2).await.unwrap();
+    txn.write(10, &vec![2; CHUNK_SIZE][..]).await.unwrap();
+    drop(txn);
+
+    // partial data is left behind
+    let mut files = fake.files(&object_folder("txn", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "1", "meta"]);
+    assert_eq!(meta::get(&ctx, &bucket, &name).await.unwrap().size, 10);
+}
+
+#[tokio::test]
+async fn failed_write() {
+    let fake = FakeJottacloud::start().await;
+    let options = FsOptions {
+        allocate_retry: RetryPolicy::NONE,
+        ..FsOptions::default()
+    };
+    let fs = Fs::with_options(
+        MockTokenStore::new(USERNAME).with_base_url(&fake.base_url()),
+        options,
+    );
+    let ctx = Context::initialize(fs, Config::new("txn")).await.unwrap();
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    object::create(&ctx, &bucket, &name, Patch::default())
+        .await
+        .unwrap();
+
+    let mut txn = UploadTxn::begin(&ctx, &bucket, &name, 1).await.unwrap();
+
+    // the first two chunks get through, the third doesn't
+    fake.fail_allocations(2, 1);
+    assert!(txn.write(0, &vec![2; CHUNK_SIZE * 3][..]).await.is_err());
+
+    let mut files = fake.files(&object_folder("txn", &bucket, &name));
+    files.sort();
+    assert_eq!(files, ["0", "1", "meta"]);
+
+    txn.abort().await.unwrap();
+
+    assert_eq!(fake.files(&object_folder("txn", &bucket, &name)), ["meta"]);
+    assert_eq!(meta::get(&ctx, &bucket, &name).await.unwrap().size, 0);
+}
+
+#[tokio::test]
+async fn replace() {
+    let fake = FakeJottacloud::start().await;
+    let (ctx, bucket, name) = setup(&fake, "txn", &vec![1; CHUNK_SIZE * 2 + 1]).await;
+
+    let patch = Patch {
+        content_type: Some(ContentType(mime::TEXT_PLAIN)),
+        ..Patch::default()
+    };
+    let mut txn = UploadTxn::replace(&ctx, &bucket, &name, patch, 2)
+        .await
+        .unwrap();
+    txn.write(0, &[2; 10][..]).await.unwrap();
+
+    // still the original until committed
+    let original = meta::get(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(original.size, CHUNK_SIZE as u64 * 2 + 1);
+
+    let meta = txn.commit().await.unwrap();
+
+    assert_eq!(meta.size, 10);
+    assert_eq!(meta.content_type.to_string(), "text/plain");
+    assert_eq!(meta.md5, Some(md5::compute([2; 10])));
+    assert_eq!(
+        fake.files(&object_folder("txn", &bucket, &name)),
+        ["0", "meta"]
+    );
+}
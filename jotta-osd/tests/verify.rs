@@ -0,0 +1,123 @@
+//! Detecting and repairing corrupt chunks.
+use bytes::Bytes;
+use futures_util::future;
+use jotta_osd::{
+    concurrency::ConcurrencyStrategy,
+    errors::Error,
+    object::{
+        self,
+        health::{BadChunk, ChunkProblem},
+        meta::Patch,
+        CHUNK_SIZE,
+    },
+    path::{BucketName, ObjectName},
+    ChunkLayout, Config,
+};
+
+mod fake;
+
+use fake::{data, object_with, FakeJottacloud};
+
+async fn verify_and_repair(chunk_layout: ChunkLayout) {
+    let fake = FakeJottacloud::start().await;
+    let mut config = Config::new("verify");
+    config.chunk_layout = chunk_layout;
+    let ctx = fake.context(config).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    let body = Bytes::from(data(CHUNK_SIZE * 2 + 10));
+
+    object_with(&ctx, &bucket, &name, &body).await;
+
+    let report = object::verify(&ctx, &bucket, &name).await.unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.chunks, 3);
+
+    let chunk = match chunk_layout {
+        ChunkLayout::Indexed => format!("Jotta/Archive/verify/bucket/{}/1", name.to_hex()),
+        ChunkLayout::ContentAddressed => {
+            let address = format!(
+                "{:x}-{CHUNK_SIZE}",
+                md5::compute(&body[CHUNK_SIZE..CHUNK_SIZE * 2])
+            );
+            format!("Jotta/Archive/verify/.chunks/{address}")
+        }
+    };
+
+    fake.corrupt(&chunk);
+
+    let report = object::verify(&ctx, &bucket, &name).await.unwrap();
+    assert_eq!(
+        report.bad_chunks,
+        [BadChunk {
+            index: 1,
+            problem: ChunkProblem::Corrupt
+        }]
+    );
+
+    // the wrong bytes are rejected
+    let res = object::repair(&ctx, &bucket, &name, |range| {
+        future::ok(Bytes::from(vec![0; range.len() as usize - 1]))
+    })
+    .await;
+    assert!(matches!(res, Err(Error::InvalidInput(_))));
+
+    let before = fake.uploads().len();
+    let repaired = object::repair(&ctx, &bucket, &name, |range| {
+        future::ok(body.slice(range.start() as usize..=range.end() as usize))
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(repaired, report);
+    assert_eq!(fake.uploads()[before..], [chunk]);
+    assert!(object::verify(&ctx, &bucket, &name).await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn indexed() {
+    verify_and_repair(ChunkLayout::Indexed).await;
+}
+
+#[tokio::test]
+async fn content_addressed() {
+    verify_and_repair(ChunkLayout::ContentAddressed).await;
+}
+
+#[tokio::test]
+async fn truncated_uploads() {
+    let fake = FakeJottacloud::start().await;
+    let ctx = fake.context(Config::new("verify")).await;
+    let bucket: BucketName = "bucket".parse().unwrap();
+    let name: ObjectName = "object".parse().unwrap();
+
+    // metadata
+    fake.truncate_uploads(1);
+    assert!(matches!(
+        object::create(&ctx, &bucket, &name, Patch::default()).await,
+        Err(Error::Fs(jotta_osd::jotta::Error::CorruptUpload))
+    ));
+
+    object::create_or_replace(&ctx, &bucket, &name, Patch::default(), false)
+        .await
+        .unwrap();
+
+    // a chunk
+    fake.truncate_uploads(1);
+    let err = object::upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        &b"hello"[..],
+        ConcurrencyStrategy::Fixed(1),
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(err, Error::Fs(jotta_osd::jotta::Error::CorruptUpload)),
+        "{err:?}"
+    );
+}
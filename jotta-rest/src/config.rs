@@ -1,25 +1,122 @@
-use std::{fmt::Debug, str::FromStr};
+use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
 
 mod auth;
 
 use auth::Auth;
-use jotta_osd::jotta::Fs;
+use jotta_osd::{
+    concurrency::DownloadMode,
+    jotta::{metrics::AtomicMetrics, Fs},
+    object::meta::CacheControl,
+};
 
-use crate::AppContext;
+use crate::{errors::AppError, idempotency::IdempotencyCache, AppContext, AppResult};
+
+/// Default value of [`AppConfig::max_connections_per_request`].
+pub const DEFAULT_MAX_CONNECTIONS_PER_REQUEST: usize = 32;
+
+/// Default value of [`AppConfig::shutdown_timeout`].
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     auth: Auth,
     pub root: String,
     pub connections_per_request: usize,
+    /// Upper bound of the `connections` query parameter, which lets
+    /// clients override [`AppConfig::connections_per_request`].
+    pub max_connections_per_request: usize,
+    /// How many chunks of an object may be downloaded ahead of a client
+    /// reading it, if fewer than the number of connections. This caps
+    /// the memory used by each download when clients read slowly.
+    pub max_prefetch_chunks: Option<usize>,
+    /// Expose operator-only debug information, such as object chunk health.
+    pub debug_routes: bool,
+    /// Don't send a `Cache-Control` header for objects that weren't
+    /// created with one.
+    pub omit_default_cache_control: bool,
+    /// Create the root folder on startup if it doesn't exist.
+    pub create_root: bool,
+    /// Guess the content type of objects uploaded without one. See
+    /// [`jotta_osd::Config::sniff_content_type`].
+    pub sniff_content_type: bool,
+    /// Refuse to start if the account is read or write locked. See
+    /// [`jotta_osd::Config::check_locks`].
+    pub check_locks: bool,
+    /// Maximum length of the names of new objects. See
+    /// [`jotta_osd::Config::max_object_name_len`].
+    pub max_object_name_len: usize,
+    /// Request metrics, served at `/metrics` if enabled.
+    pub metrics: Option<Arc<AtomicMetrics>>,
+    /// Responses of requests with idempotency keys, shared by all workers.
+    pub idempotency: Arc<IdempotencyCache>,
+    /// How long in-flight requests, such as uploads whose metadata hasn't
+    /// been written yet, may keep running after a shutdown signal.
+    pub shutdown_timeout: Duration,
+    /// Maximum size of an upload in bytes, if any. Uploads are also
+    /// limited by the storage left on the account.
+    pub upload_limit: Option<u64>,
+    /// Renew the access token this often in the background, instead of
+    /// when a request finds it about to expire.
+    pub token_refresh_interval: Option<Duration>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
+        let connections_per_request = env_opt("CONNECTIONS_PER_REQUEST").unwrap_or(10);
+
+        assert!(
+            connections_per_request > 0,
+            "`CONNECTIONS_PER_REQUEST` must be at least 1"
+        );
+
+        let max_connections_per_request = env_opt("MAX_CONNECTIONS_PER_REQUEST")
+            .unwrap_or_else(|| connections_per_request.max(DEFAULT_MAX_CONNECTIONS_PER_REQUEST));
+
+        assert!(
+            max_connections_per_request >= connections_per_request,
+            "`MAX_CONNECTIONS_PER_REQUEST` must be at least `CONNECTIONS_PER_REQUEST`"
+        );
+
+        let max_prefetch_chunks = env_opt("MAX_PREFETCH_CHUNKS");
+
+        assert!(
+            max_prefetch_chunks != Some(0),
+            "`MAX_PREFETCH_CHUNKS` must be at least 1"
+        );
+
+        let token_refresh_interval = env_opt("TOKEN_REFRESH_INTERVAL").map(Duration::from_secs);
+
+        assert!(
+            token_refresh_interval != Some(Duration::ZERO),
+            "`TOKEN_REFRESH_INTERVAL` must be at least 1"
+        );
+
         Self {
             auth: Auth::default(),
             root: env("ROOT"),
-            connections_per_request: env_opt("CONNECTIONS_PER_REQUEST").unwrap_or(10),
+            connections_per_request,
+            max_connections_per_request,
+            max_prefetch_chunks,
+            debug_routes: env_opt("DEBUG_ROUTES").unwrap_or(false),
+            omit_default_cache_control: env_opt("OMIT_DEFAULT_CACHE_CONTROL").unwrap_or(false),
+            create_root: env_opt("CREATE_ROOT").unwrap_or(true),
+            sniff_content_type: env_opt("SNIFF_CONTENT_TYPE").unwrap_or(false),
+            check_locks: env_opt("CHECK_LOCKS").unwrap_or(false),
+            max_object_name_len: env_opt("MAX_OBJECT_NAME_LEN")
+                .unwrap_or(jotta_osd::DEFAULT_MAX_OBJECT_NAME_LEN),
+            metrics: env_opt("METRICS")
+                .unwrap_or(false)
+                .then(|| Arc::new(AtomicMetrics::new())),
+            idempotency: Arc::new(
+                env_opt("IDEMPOTENCY_TTL")
+                    .map(Duration::from_secs)
+                    .map(IdempotencyCache::new)
+                    .unwrap_or_default(),
+            ),
+            shutdown_timeout: env_opt("SHUTDOWN_TIMEOUT")
+                .map_or(DEFAULT_SHUTDOWN_TIMEOUT, Duration::from_secs),
+            upload_limit: env_opt("UPLOAD_LIMIT"),
+            token_refresh_interval,
         }
     }
 }
@@ -30,19 +127,114 @@ impl AppConfig {
             auth: Auth::default(),
             root: "jotta-test".into(),
             connections_per_request: 10,
+            max_connections_per_request: DEFAULT_MAX_CONNECTIONS_PER_REQUEST,
+            max_prefetch_chunks: None,
+            debug_routes: true,
+            omit_default_cache_control: false,
+            create_root: true,
+            sniff_content_type: false,
+            check_locks: false,
+            max_object_name_len: jotta_osd::DEFAULT_MAX_OBJECT_NAME_LEN,
+            metrics: None,
+            idempotency: Arc::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            upload_limit: None,
+            token_refresh_interval: None,
+        }
+    }
+
+    /// Number of concurrent connections to use for a request, which
+    /// may ask for anything from 1 up to and including
+    /// [`AppConfig::max_connections_per_request`].
+    ///
+    /// # Errors
+    ///
+    /// [`AppError::InvalidInput`] if `requested` is out of bounds.
+    pub fn connections(&self, requested: Option<usize>) -> AppResult<usize> {
+        match requested {
+            None => Ok(self.connections_per_request),
+            Some(n) if (1..=self.max_connections_per_request).contains(&n) => Ok(n),
+            Some(n) => Err(AppError::InvalidInput {
+                message: format!(
+                    "`connections` must be between 1 and {}, got {n}",
+                    self.max_connections_per_request
+                ),
+            }),
+        }
+    }
+
+    /// How to download objects with `num_connections` connections,
+    /// honoring [`AppConfig::max_prefetch_chunks`].
+    #[must_use]
+    pub fn download_mode(&self, num_connections: usize) -> DownloadMode {
+        match self.max_prefetch_chunks {
+            Some(max) if max < num_connections => DownloadMode::Windowed { max },
+            _ => DownloadMode::Ordered,
         }
     }
 
     pub fn osd_config(&self) -> jotta_osd::Config {
-        jotta_osd::Config {
-            root: self.root.clone(),
+        let mut config = jotta_osd::Config::new(self.root.clone());
+        config.create_root = self.create_root;
+        config.sniff_content_type = self.sniff_content_type;
+        config.check_locks = self.check_locks;
+        config.max_object_name_len = self.max_object_name_len;
+
+        if self.omit_default_cache_control {
+            config.default_cache_control = CacheControl::none();
         }
+
+        config
+    }
+
+    /// A config for unit tests that doesn't read any credentials from
+    /// the environment. Use it with [`AppConfig::create_mock_context`].
+    #[cfg(test)]
+    pub(crate) fn mock() -> Self {
+        Self {
+            auth: Auth::Legacy {
+                username: "alice".into(),
+                password: String::new(),
+            },
+            root: "jotta-test".into(),
+            connections_per_request: 10,
+            max_connections_per_request: DEFAULT_MAX_CONNECTIONS_PER_REQUEST,
+            max_prefetch_chunks: None,
+            debug_routes: true,
+            omit_default_cache_control: false,
+            create_root: true,
+            sniff_content_type: false,
+            check_locks: false,
+            max_object_name_len: jotta_osd::DEFAULT_MAX_OBJECT_NAME_LEN,
+            metrics: None,
+            idempotency: Arc::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            upload_limit: None,
+            token_refresh_interval: None,
+        }
+    }
+
+    /// Create a context that talks to a fake Jottacloud at `base_url`.
+    #[cfg(test)]
+    pub(crate) async fn create_mock_context(&self, base_url: &str) -> AppContext {
+        use jotta_osd::jotta::auth::MockTokenStore;
+
+        let token_store: Box<dyn jotta_osd::jotta::auth::TokenStore> =
+            Box::new(MockTokenStore::new("alice").with_base_url(base_url));
+
+        AppContext::initialize(Fs::new(token_store), self.osd_config())
+            .await
+            .unwrap()
     }
 
     pub async fn create_context(&self) -> AppContext {
         let token_store = self.auth.build_token_store().await;
 
-        let fs = Fs::new(token_store);
+        let mut fs = Fs::new(token_store);
+
+        if let Some(metrics) = &self.metrics {
+            fs = fs.with_metrics(metrics.clone());
+        }
 
         AppContext::initialize(fs, self.osd_config()).await.unwrap()
     }
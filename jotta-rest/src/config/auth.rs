@@ -1,4 +1,7 @@
-use jotta_osd::jotta::auth::{LegacyAuth, OAuth2, TokenStore, TELE2_TOKEN_URL};
+use jotta_osd::jotta::{
+    auth::{LegacyAuth, OAuth2, TokenStore},
+    provider::TELE2,
+};
 
 use super::env;
 
@@ -22,7 +25,7 @@ impl Auth {
                 Box::new(LegacyAuth::init(username, password).await.unwrap())
             }
             Auth::Tele2 { refresh_token } => {
-                Box::new(OAuth2::init(TELE2_TOKEN_URL, refresh_token).unwrap())
+                Box::new(OAuth2::init(TELE2.clone(), refresh_token).unwrap())
             }
         }
     }
@@ -1,6 +1,14 @@
-use actix_web::{http::StatusCode, ResponseError};
-use http_range::HttpRangeParseError;
-use jotta_osd::jotta;
+use actix_web::{
+    http::{
+        header::{self, ContentType},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use jotta_osd::{
+    jotta,
+    path::{BucketName, ObjectName},
+};
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
     #[error("internal server error")]
@@ -9,10 +17,31 @@ pub enum AppError {
     BadRequest,
     #[error("file conflict")]
     Conflict,
+    #[error("object {object} already exists in bucket {bucket}")]
+    ObjectExists {
+        bucket: BucketName,
+        object: ObjectName,
+    },
     #[error("not found")]
     NotFound,
+    #[error("method not allowed")]
+    MethodNotAllowed,
+    /// None of the requested ranges overlap the object, which is `size`
+    /// bytes long.
     #[error("range not satisfiable")]
-    RangeNotSatisfiable,
+    RangeNotSatisfiable { size: u64 },
+    #[error("precondition failed")]
+    PreconditionFailed,
+    #[error("upstream timed out")]
+    GatewayTimeout,
+    #[error("upstream unreachable")]
+    BadGateway,
+    #[error("insufficient storage")]
+    InsufficientStorage,
+    #[error("payload too large")]
+    PayloadTooLarge,
+    #[error("{feature} is not implemented")]
+    NotImplemented { feature: &'static str },
     #[error("invalid input: {message}")]
     InvalidInput { message: String },
     #[error("{0}")]
@@ -26,6 +55,8 @@ impl From<jotta_osd::errors::Error> for AppError {
         match e {
             jotta_osd::errors::Error::Fs(e) => match e {
                 jotta::Error::Http(_) => Self::InternalError,
+                jotta::Error::Timeout(_) => Self::GatewayTimeout,
+                jotta::Error::Connect(_) => Self::BadGateway,
                 jotta::Error::Url(_) => Self::BadRequest,
                 jotta::Error::Jotta(_) => Self::InternalError,
                 jotta::Error::Xml(_) => Self::InternalError,
@@ -33,10 +64,12 @@ impl From<jotta_osd::errors::Error> for AppError {
                 jotta::Error::BadCredentials => Self::InternalError,
                 jotta::Error::NoSuchFileOrFolder => Self::NotFound,
                 jotta::Error::IncompleteUpload => Self::InternalError,
+                jotta::Error::IncompleteDownload { .. } => Self::InternalError,
                 jotta::Error::InvalidArgument => Self::BadRequest,
                 jotta::Error::CorruptUpload => Self::InternalError,
                 jotta::Error::TokenRenewalFailed => Self::InternalError,
                 jotta::Error::RangeNotSatisfiable => Self::InternalError,
+                jotta::Error::QuotaExceeded => Self::InsufficientStorage,
                 jotta::Error::EventError(_) => Self::InternalError,
             },
             jotta_osd::errors::Error::ParseObjectName(e) => Self::InvalidInput {
@@ -44,10 +77,27 @@ impl From<jotta_osd::errors::Error> for AppError {
             },
             jotta_osd::errors::Error::MsgpackEncode(_) => Self::InternalError,
             jotta_osd::errors::Error::MsgpackDecode(_) => Self::InternalError,
-            jotta_osd::errors::Error::IoError(_) => Self::InternalError,
+            jotta_osd::errors::Error::IoError(e) => {
+                crate::quota::exceeded(&e).map_or(Self::InternalError, Into::into)
+            }
             jotta_osd::errors::Error::ParseBucketName(e) => Self::InvalidInput {
                 message: e.to_string(),
             },
+            e @ jotta_osd::errors::Error::MetaTooLarge { .. } => Self::InvalidInput {
+                message: e.to_string(),
+            },
+            jotta_osd::errors::Error::PreconditionFailed => Self::PreconditionFailed,
+            jotta_osd::errors::Error::Cancelled => Self::InternalError,
+            jotta_osd::errors::Error::Timeout => Self::GatewayTimeout,
+            jotta_osd::errors::Error::NoSuchMountPoint { .. }
+            | jotta_osd::errors::Error::NoSuchRoot { .. }
+            | jotta_osd::errors::Error::WriteLocked
+            | jotta_osd::errors::Error::ReadLocked => Self::InternalError,
+            jotta_osd::errors::Error::NoSuchRevision { .. } => Self::NotFound,
+            e @ jotta_osd::errors::Error::Fetch(_) => Self::InvalidInput {
+                message: e.to_string(),
+            },
+            jotta_osd::errors::Error::InvalidInput(message) => Self::InvalidInput { message },
         }
     }
 }
@@ -57,19 +107,32 @@ impl ResponseError for AppError {
         match self {
             AppError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::BadRequest => StatusCode::BAD_REQUEST,
-            AppError::Conflict => StatusCode::CONFLICT,
+            AppError::Conflict | AppError::ObjectExists { .. } => StatusCode::CONFLICT,
             AppError::NotFound => StatusCode::NOT_FOUND,
-            AppError::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::RangeNotSatisfiable { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            AppError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            AppError::GatewayTimeout => StatusCode::GATEWAY_TIMEOUT,
+            AppError::BadGateway => StatusCode::BAD_GATEWAY,
+            AppError::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::NotImplemented { .. } => StatusCode::NOT_IMPLEMENTED,
             AppError::InvalidInput { .. } => StatusCode::BAD_REQUEST,
             AppError::ActixError(e) => e.error_response().status(),
             AppError::ContentTypeError(e) => e.status_code(),
         }
     }
-}
 
-impl From<HttpRangeParseError> for AppError {
-    fn from(_: HttpRangeParseError) -> Self {
-        Self::RangeNotSatisfiable
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+
+        // RFC 7233, section 4.4
+        if let AppError::RangeNotSatisfiable { size } = self {
+            res.insert_header((header::CONTENT_RANGE, format!("bytes */{size}")));
+        }
+
+        res.insert_header(ContentType::plaintext())
+            .body(self.to_string())
     }
 }
 
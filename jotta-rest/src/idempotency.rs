@@ -0,0 +1,325 @@
+//! Idempotency keys, which make retried uploads safe.
+//!
+//! A client sends a unique `Idempotency-Key` header with a request. If
+//! the request is repeated with the same key (for the same object), the
+//! original response is returned instead of running the request again.
+//! Only successful responses are remembered, so failed requests can be
+//! retried.
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body,
+    http::{header::HeaderMap, StatusCode},
+    web::Bytes,
+    HttpRequest, HttpResponse,
+};
+use jotta_osd::path::{BucketName, ObjectName};
+
+use crate::{errors::AppError, AppResult};
+
+/// Name of the idempotency key header.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Maximum length of an idempotency key.
+const MAX_KEY_LEN: usize = 255;
+
+/// Default value of [`IdempotencyCache::ttl`].
+pub const DEFAULT_TTL: Duration = Duration::from_hours(24);
+
+/// Requests are identified by the object and the key.
+type Scope = (BucketName, ObjectName, String);
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    fn to_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status);
+
+        for (name, value) in &self.headers {
+            res.append_header((name.clone(), value.clone()));
+        }
+
+        res.body(self.body.clone())
+    }
+}
+
+#[derive(Debug)]
+enum Entry {
+    InProgress,
+    Done(CachedResponse),
+}
+
+/// Recently seen idempotency keys and their responses.
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Scope, (Instant, Entry)>>,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+/// Outcome of [`IdempotencyCache::claim`].
+#[derive(Debug)]
+pub enum Claim<'a> {
+    /// The key is new. Run the request and pass the response to
+    /// [`Reservation::complete`].
+    New(Reservation<'a>),
+    /// The key has been seen before. This is the original response.
+    Done(HttpResponse),
+}
+
+impl IdempotencyCache {
+    /// Remember keys for `ttl`.
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long keys are remembered.
+    #[must_use]
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Claim a key, unless it has already been used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Conflict`] if a request with the same key is
+    /// still in progress.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    pub fn claim(
+        &self,
+        bucket: &BucketName,
+        object: &ObjectName,
+        key: String,
+    ) -> AppResult<Claim<'_>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+
+        entries.retain(|_, (at, entry)| {
+            matches!(entry, Entry::InProgress) || now.duration_since(*at) < self.ttl
+        });
+
+        let scope = (bucket.clone(), object.clone(), key);
+
+        match entries.get(&scope) {
+            Some((_, Entry::Done(res))) => Ok(Claim::Done(res.to_response())),
+            Some((_, Entry::InProgress)) => Err(AppError::Conflict),
+            None => {
+                entries.insert(scope.clone(), (now, Entry::InProgress));
+
+                Ok(Claim::New(Reservation {
+                    cache: self,
+                    scope: Some(scope),
+                }))
+            }
+        }
+    }
+}
+
+/// A claimed key. The key is released if this is dropped without being
+/// completed, for example if the request failed.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    cache: &'a IdempotencyCache,
+    scope: Option<Scope>,
+}
+
+impl Reservation<'_> {
+    /// Remember `res` as the response of the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the response body can't be read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock is poisoned.
+    pub async fn complete(mut self, res: HttpResponse) -> AppResult<HttpResponse> {
+        let (res, body) = res.into_parts();
+        let body = body::to_bytes(body)
+            .await
+            .map_err(|_| AppError::InternalError)?;
+
+        let cached = CachedResponse {
+            status: res.status(),
+            headers: res.headers().clone(),
+            body,
+        };
+        let res = cached.to_response();
+
+        if let Some(scope) = self.scope.take() {
+            self.cache
+                .entries
+                .lock()
+                .unwrap()
+                .insert(scope, (Instant::now(), Entry::Done(cached)));
+        }
+
+        Ok(res)
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(scope) = self.scope.take() {
+            if let Ok(mut entries) = self.cache.entries.lock() {
+                entries.remove(&scope);
+            }
+        }
+    }
+}
+
+/// Get the idempotency key of a request, if any.
+///
+/// # Errors
+///
+/// Returns an error if the key is empty, too long or not printable ASCII.
+pub fn idempotency_key(req: &HttpRequest) -> AppResult<Option<String>> {
+    let Some(value) = req.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+
+    match value.to_str() {
+        Ok(key)
+            if !key.is_empty()
+                && key.len() <= MAX_KEY_LEN
+                && key.bytes().all(|b| b.is_ascii_graphic()) =>
+        {
+            Ok(Some(key.to_owned()))
+        }
+        _ => Err(AppError::InvalidInput {
+            message: format!("invalid `{IDEMPOTENCY_KEY}` header"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{body, http::StatusCode, test::TestRequest, HttpResponse};
+    use jotta_osd::path::{BucketName, ObjectName};
+
+    use super::{idempotency_key, Claim, IdempotencyCache};
+    use crate::errors::AppError;
+
+    fn names() -> (BucketName, ObjectName) {
+        ("bucket".parse().unwrap(), "object".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn repeated_key() {
+        let cache = IdempotencyCache::default();
+        let (bucket, object) = names();
+
+        let Claim::New(reservation) = cache.claim(&bucket, &object, "a".into()).unwrap() else {
+            panic!("key should be new");
+        };
+
+        // in progress
+        assert!(matches!(
+            cache.claim(&bucket, &object, "a".into()),
+            Err(AppError::Conflict)
+        ));
+        // other objects and keys are unaffected
+        assert!(matches!(
+            cache.claim(&bucket, &"other".parse().unwrap(), "a".into()),
+            Ok(Claim::New(_))
+        ));
+        assert!(matches!(
+            cache.claim(&bucket, &object, "b".into()),
+            Ok(Claim::New(_))
+        ));
+
+        let res = HttpResponse::Created()
+            .insert_header(("x-revision", "1"))
+            .body("hello");
+        let res = reservation.complete(res).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let Claim::Done(res) = cache.claim(&bucket, &object, "a".into()).unwrap() else {
+            panic!("key should be done");
+        };
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers().get("x-revision").unwrap(), "1");
+        assert_eq!(body::to_bytes(res.into_body()).await.unwrap(), "hello");
+    }
+
+    #[actix_web::test]
+    async fn released_on_drop() {
+        let cache = IdempotencyCache::default();
+        let (bucket, object) = names();
+
+        drop(cache.claim(&bucket, &object, "a".into()).unwrap());
+
+        assert!(matches!(
+            cache.claim(&bucket, &object, "a".into()),
+            Ok(Claim::New(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn expiry() {
+        let cache = IdempotencyCache::new(Duration::from_millis(10));
+        let (bucket, object) = names();
+
+        let Ok(Claim::New(reservation)) = cache.claim(&bucket, &object, "a".into()) else {
+            panic!("key should be new");
+        };
+        reservation
+            .complete(HttpResponse::Ok().finish())
+            .await
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(matches!(
+            cache.claim(&bucket, &object, "a".into()),
+            Ok(Claim::New(_))
+        ));
+    }
+
+    #[test]
+    fn key_header() {
+        assert_eq!(
+            idempotency_key(&TestRequest::post().to_http_request()).unwrap(),
+            None
+        );
+
+        let req = TestRequest::post()
+            .insert_header(("idempotency-key", "8e03978e-40d5-43e8-bc93-6894a57f9324"))
+            .to_http_request();
+        assert_eq!(
+            idempotency_key(&req).unwrap().as_deref(),
+            Some("8e03978e-40d5-43e8-bc93-6894a57f9324")
+        );
+
+        for invalid in ["", "has space", &"x".repeat(256)] {
+            let req = TestRequest::post()
+                .insert_header(("idempotency-key", invalid))
+                .to_http_request();
+            assert!(idempotency_key(&req).is_err(), "{invalid:?}");
+        }
+    }
+}
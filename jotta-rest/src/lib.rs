@@ -2,6 +2,8 @@ use jotta_osd::{jotta::auth::TokenStore, Context};
 
 pub mod config;
 pub mod errors;
+pub mod idempotency;
+pub mod quota;
 pub mod routes;
 
 pub type AppResult<T> = Result<T, errors::AppError>;
@@ -1,7 +1,71 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
-use actix_web::{web::Data, HttpServer};
-use jotta_rest::{config::env_opt, create_app};
+use actix_web::{dev::ServerHandle, web::Data, HttpServer};
+use futures_util::future::{self, Either};
+use jotta_rest::{config::env_opt, create_app, AppContext};
+
+/// Wait for `SIGINT` or `SIGTERM`.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_rt::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+
+        let ctrl_c = actix_rt::signal::ctrl_c();
+        let sigterm = sigterm.recv();
+
+        actix_rt::pin!(ctrl_c, sigterm);
+
+        future::select(ctrl_c, sigterm).await;
+    }
+
+    #[cfg(not(unix))]
+    actix_rt::signal::ctrl_c()
+        .await
+        .expect("failed to listen for ctrl-c");
+}
+
+/// Stop accepting new connections on the first signal and let in-flight
+/// requests finish. A second signal stops the server immediately.
+async fn handle_signals(server: ServerHandle, timeout: std::time::Duration) {
+    shutdown_signal().await;
+
+    eprintln!(
+        "shutting down, waiting up to {}s for in-flight requests",
+        timeout.as_secs()
+    );
+
+    let graceful = server.stop(true);
+    let forced = shutdown_signal();
+
+    actix_rt::pin!(graceful, forced);
+
+    if let Either::Right(_) = future::select(graceful, forced).await {
+        eprintln!("forcing shutdown");
+        server.stop(false).await;
+    }
+}
+
+/// Renew the access token every `period`, so that no request has to
+/// wait for it to be renewed.
+async fn refresh_tokens(ctx: Data<AppContext>, period: Duration) {
+    let mut interval = actix_rt::time::interval(period);
+
+    // the first tick completes immediately, and the token is fresh
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = ctx.refresh_token().await {
+            eprintln!("failed to refresh access token: {e}");
+        }
+    }
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -14,11 +78,21 @@ async fn main() -> std::io::Result<()> {
 
     let port = env_opt("PORT").unwrap_or(8000);
     let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
+    let shutdown_timeout = config.shutdown_timeout;
+
+    if let Some(period) = config.token_refresh_interval {
+        actix_rt::spawn(refresh_tokens(ctx.clone(), period));
+    }
 
     eprintln!("binding {}", addr);
 
-    HttpServer::new(move || create_app!(config, ctx))
+    let server = HttpServer::new(move || create_app!(config, ctx))
         .bind(addr)?
-        .run()
-        .await
+        .disable_signals()
+        .shutdown_timeout(shutdown_timeout.as_secs())
+        .run();
+
+    actix_rt::spawn(handle_signals(server.handle(), shutdown_timeout));
+
+    server.await
 }
@@ -0,0 +1,153 @@
+//! Refusing uploads that won't fit, before they are sent to Jottacloud.
+//!
+//! Jottacloud only reports that the quota is exceeded when a chunk is
+//! allocated, which may be long after the upload started. Uploads with
+//! a `Content-Length` are checked up front, and all other uploads fail
+//! as soon as they have sent more bytes than allowed.
+use std::io;
+
+use actix_web::web::Bytes;
+use futures_util::{Stream, StreamExt};
+
+use crate::{config::AppConfig, errors::AppError, AppContext, AppResult};
+
+/// Why an upload was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Exceeded {
+    /// The account doesn't have enough storage left.
+    #[error("not enough storage left on the account")]
+    Quota,
+    /// The upload is larger than [`AppConfig::upload_limit`].
+    #[error("upload is too large")]
+    UploadLimit,
+}
+
+impl From<Exceeded> for AppError {
+    fn from(e: Exceeded) -> Self {
+        match e {
+            Exceeded::Quota => Self::InsufficientStorage,
+            Exceeded::UploadLimit => Self::PayloadTooLarge,
+        }
+    }
+}
+
+/// How many bytes an upload may contain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// Storage left on the account, or `None` if it's unlimited.
+    pub remaining: Option<u64>,
+    /// See [`AppConfig::upload_limit`].
+    pub upload_limit: Option<u64>,
+}
+
+impl Budget {
+    /// Look up the storage left on the account.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the account information can't be fetched.
+    pub async fn fetch(config: &AppConfig, ctx: &AppContext) -> AppResult<Self> {
+        Ok(Self {
+            remaining: ctx.remaining_storage().await?,
+            upload_limit: config.upload_limit,
+        })
+    }
+
+    /// Check that an upload of `len` bytes fits.
+    ///
+    /// # Errors
+    ///
+    /// The limit that `len` exceeds. The upload limit is checked first.
+    pub fn check(&self, len: u64) -> Result<(), Exceeded> {
+        if self.upload_limit.is_some_and(|limit| len > limit) {
+            Err(Exceeded::UploadLimit)
+        } else if self.remaining.is_some_and(|remaining| len > remaining) {
+            Err(Exceeded::Quota)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fail `payload` as soon as it exceeds the budget, without passing
+    /// on the bytes that don't fit. The error is an [`io::Error`]
+    /// wrapping [`Exceeded`], which is turned into the right status by
+    /// [`AppError`].
+    pub fn limit<S, E>(self, payload: S) -> impl Stream<Item = io::Result<Bytes>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut total = 0;
+
+        payload.map(move |bytes| {
+            let bytes = bytes.map_err(io::Error::other)?;
+            total += bytes.len() as u64;
+
+            self.check(total).map_err(io::Error::other)?;
+
+            Ok(bytes)
+        })
+    }
+}
+
+/// The [`Exceeded`] wrapped by an error from [`Budget::limit`], if any.
+pub(crate) fn exceeded(e: &io::Error) -> Option<Exceeded> {
+    e.get_ref()?.downcast_ref().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, web::Bytes, ResponseError};
+    use futures_util::{stream, StreamExt, TryStreamExt};
+
+    use super::{exceeded, Budget, Exceeded};
+    use crate::errors::AppError;
+
+    #[test]
+    fn check() {
+        let unlimited = Budget::default();
+        assert_eq!(unlimited.check(u64::MAX), Ok(()));
+
+        let budget = Budget {
+            remaining: Some(100),
+            upload_limit: Some(50),
+        };
+        assert_eq!(budget.check(50), Ok(()));
+        assert_eq!(budget.check(51), Err(Exceeded::UploadLimit));
+
+        let budget = Budget {
+            remaining: Some(100),
+            upload_limit: None,
+        };
+        assert_eq!(budget.check(100), Ok(()));
+        assert_eq!(budget.check(101), Err(Exceeded::Quota));
+    }
+
+    #[actix_web::test]
+    async fn limit() {
+        let budget = Budget {
+            remaining: Some(10),
+            upload_limit: None,
+        };
+        let payload = || {
+            stream::iter(["hello", "world", "!"])
+                .map(|s| Ok::<_, std::io::Error>(Bytes::from_static(s.as_bytes())))
+        };
+
+        let mut limited = Box::pin(budget.limit(payload()));
+        assert_eq!(limited.try_next().await.unwrap().unwrap(), "hello");
+        assert_eq!(limited.try_next().await.unwrap().unwrap(), "world");
+        let err = limited.try_next().await.unwrap_err();
+        assert_eq!(exceeded(&err), Some(Exceeded::Quota));
+
+        let err = AppError::from(jotta_osd::errors::Error::from(err));
+        assert_eq!(err.status_code(), StatusCode::INSUFFICIENT_STORAGE);
+
+        let chunks = Budget::default()
+            .limit(payload())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"helloworld!");
+    }
+}
@@ -1,8 +1,8 @@
 use actix_web::{
-    web::{self, Data, Path, ServiceConfig},
+    web::{self, Data, Json, Path, ServiceConfig},
     HttpResponse,
 };
-use jotta_osd::path::BucketName;
+use jotta_osd::{bucket::BucketPatch, path::BucketName};
 
 use crate::{AppContext, AppResult};
 
@@ -26,6 +26,16 @@ pub async fn post(ctx: Data<AppContext>, bucket: Path<BucketName>) -> AppResult<
     Ok(HttpResponse::Created().json(bucket))
 }
 
+pub async fn patch(
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+    patch: Json<BucketPatch>,
+) -> AppResult<HttpResponse> {
+    let bucket = jotta_osd::bucket::patch(&ctx, &bucket, patch.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(bucket))
+}
+
 pub async fn delete(ctx: Data<AppContext>, bucket: Path<BucketName>) -> AppResult<HttpResponse> {
     jotta_osd::bucket::delete(&ctx, &bucket).await?;
 
@@ -38,7 +48,9 @@ pub fn config(cfg: &mut ServiceConfig) {
             web::resource("/{bucket}")
                 .route(web::get().to(get))
                 .route(web::post().to(post))
+                .route(web::patch().to(patch))
                 .route(web::delete().to(delete)),
         )
+        .service(web::resource("/{bucket}/o:export").route(web::get().to(object::export)))
         .service(web::scope("/{bucket}/o").configure(object::config));
 }
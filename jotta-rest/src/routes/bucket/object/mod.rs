@@ -1,31 +1,42 @@
 use actix_web::{
     dev,
     http::{
-        header::{self, ContentType},
+        header::{
+            self, CacheControl, CacheDirective, ContentDisposition, ContentType, DispositionParam,
+            DispositionType, ETag, EntityTag, HttpDate, IfMatch, IfRange,
+        },
         StatusCode,
     },
-    web::{self, Data, Json, Path, Payload, Query, ServiceConfig},
+    web::{self, Bytes, Data, Json, Path, Payload, Query, ServiceConfig},
     FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
 };
 
-use futures_util::{io::BufReader, TryStreamExt};
+use futures_util::{future, io::BufReader, stream, StreamExt, TryStreamExt};
 use http_range::HttpRange;
 use httpdate::fmt_http_date;
-use jotta_osd::jotta::range::ClosedByteRange;
+use jotta_osd::jotta::{self, range::ClosedByteRange};
 use jotta_osd::{
+    concurrency::DownloadMode,
     object::{
-        create,
-        meta::{Meta, Patch},
-        upload_range,
+        create, create_or_replace, etag,
+        meta::{ContentEncoding, Meta, ParseContentEncodingError, Patch},
+        upload_range_with_timestamps, Timestamps,
     },
     path::{BucketName, ObjectName},
 };
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
-use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::{sync::Arc, time::SystemTime};
+use time::OffsetDateTime;
 
-use crate::{config::AppConfig, errors::AppError, AppContext, AppResult};
+use crate::{
+    config::AppConfig,
+    errors::AppError,
+    idempotency::{idempotency_key, Claim},
+    quota::Budget,
+    AppContext, AppResult,
+};
 
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -34,18 +45,71 @@ pub struct ObjectPath {
     object: ObjectName,
 }
 
-pub async fn list(ctx: Data<AppContext>, bucket: Path<BucketName>) -> AppResult<HttpResponse> {
-    let objects = jotta_osd::object::list(&ctx, &bucket.into_inner()).await?;
+#[derive(Debug, Deserialize)]
+pub struct ListParameters {
+    #[serde(default)]
+    prefix: String,
+    delimiter: Option<String>,
+}
+
+pub async fn list(
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+    params: Query<ListParameters>,
+) -> AppResult<HttpResponse> {
+    let bucket = bucket.into_inner();
+
+    match params.delimiter {
+        Some(ref delimiter) => {
+            let listing =
+                jotta_osd::object::list_delimited(&ctx, &bucket, &params.prefix, delimiter).await?;
 
-    Ok(HttpResponse::Ok().json(objects))
+            Ok(HttpResponse::Ok().json(listing))
+        }
+        None => {
+            let objects = jotta_osd::object::list_prefixed(&ctx, &bucket, &params.prefix).await?;
+
+            Ok(HttpResponse::Ok().json(objects))
+        }
+    }
 }
 
-fn append_object_headers(res: &mut HttpResponseBuilder, meta: &Meta) {
+/// Headers describing the object data itself, including range support.
+fn append_media_headers(res: &mut HttpResponseBuilder, meta: &Meta) {
     res.append_header((header::CONTENT_TYPE, meta.content_type.to_string()))
-        .append_header((header::CONTENT_LENGTH, meta.size))
         .append_header((header::ACCEPT_RANGES, "bytes"))
         .append_header((header::LAST_MODIFIED, fmt_http_date(meta.updated.into())))
-        .append_header((header::CACHE_CONTROL, meta.cache_control.0.clone()));
+        .insert_header(ETag(entity_tag(meta)));
+
+    if !meta.cache_control.is_none() {
+        res.append_header((header::CACHE_CONTROL, meta.cache_control.0.clone()));
+    }
+
+    if meta.content_encoding != ContentEncoding::Identity {
+        res.append_header((header::CONTENT_ENCODING, meta.content_encoding.as_str()));
+    }
+}
+
+/// `Content-MD5` of the whole object, if known. Only send it along
+/// with the entire object, never with a part of it.
+fn append_content_md5(res: &mut HttpResponseBuilder, meta: &Meta) {
+    if let Some(md5) = meta.md5 {
+        res.append_header(("content-md5", base64::encode(md5.0)));
+    }
+}
+
+/// Headers for JSON metadata responses. The metadata can change at any
+/// time, so it must be revalidated.
+fn append_json_headers(res: &mut HttpResponseBuilder, meta: &Meta) {
+    res.append_header((header::LAST_MODIFIED, fmt_http_date(meta.updated.into())))
+        .insert_header(ETag(entity_tag(meta)))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]));
+}
+
+/// The validator sent as `ETag` and checked against `If-Match` and
+/// `If-Range`. See [`jotta_osd::object::etag`].
+fn entity_tag(meta: &Meta) -> EntityTag {
+    EntityTag::new_strong(etag(meta))
 }
 
 #[derive(Debug, Deserialize)]
@@ -60,8 +124,106 @@ pub enum UploadType {
 #[serde(rename_all = "camelCase")]
 pub struct PostParameters {
     upload_type: UploadType,
+    /// Override the number of concurrent connections.
+    connections: Option<usize>,
+    /// Original creation time (RFC 3339) of the uploaded data, such as
+    /// that of an imported file.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created: Option<OffsetDateTime>,
+    /// Original modification time (RFC 3339) of the uploaded data.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    modified: Option<OffsetDateTime>,
+}
+
+/// Parse the `Content-Type` header of an upload.
+pub(crate) fn content_type(
+    req: &HttpRequest,
+) -> AppResult<Option<jotta_osd::object::meta::ContentType>> {
+    let Some(value) = req.headers().get(header::CONTENT_TYPE) else {
+        return Ok(None);
+    };
+
+    let content_type = value
+        .to_str()
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| AppError::InvalidInput {
+            message: format!("invalid `Content-Type`: {value:?}"),
+        })?;
+
+    Ok(Some(content_type))
+}
+
+/// Parse the `Content-Encoding` header of an upload. The body is stored
+/// as is, so the encoding is only recorded.
+pub(crate) fn content_encoding(req: &HttpRequest) -> AppResult<Option<ContentEncoding>> {
+    req.headers()
+        .get(header::CONTENT_ENCODING)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|e| e.to_string())
+                .and_then(|s| {
+                    s.parse()
+                        .map_err(|e: ParseContentEncodingError| e.to_string())
+                })
+                .map_err(|message| AppError::InvalidInput { message })
+        })
+        .transpose()
+}
+
+/// The declared length of the request body, if any.
+pub(crate) fn content_length(req: &HttpRequest) -> Option<u64> {
+    req.headers()
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
 }
 
+/// Should an existing object be replaced? Set with `x-overwrite: true`.
+fn overwrite(req: &HttpRequest) -> AppResult<bool> {
+    match req.headers().get("x-overwrite") {
+        None => Ok(false),
+        Some(value) => match value.to_str() {
+            Ok("true") => Ok(true),
+            Ok("false") => Ok(false),
+            _ => Err(AppError::InvalidInput {
+                message: format!("invalid `x-overwrite`: {value:?}"),
+            }),
+        },
+    }
+}
+
+/// Create an object, replacing (and purging) any existing one if
+/// `overwrite` is set. Otherwise, an existing object is reported as
+/// [`AppError::ObjectExists`].
+async fn create_object(
+    ctx: &AppContext,
+    path: &ObjectPath,
+    meta: Patch,
+    overwrite: bool,
+) -> AppResult<Meta> {
+    let meta = if overwrite {
+        create_or_replace(ctx, &path.bucket, &path.object, meta, true).await?
+    } else {
+        match create(ctx, &path.bucket, &path.object, meta).await {
+            Err(jotta_osd::errors::Error::Fs(jotta::Error::AlreadyExists)) => {
+                return Err(AppError::ObjectExists {
+                    bucket: path.bucket.clone(),
+                    object: path.object.clone(),
+                })
+            }
+            res => res?,
+        }
+    };
+
+    Ok(meta)
+}
+
+/// Create an object. Requests with an `Idempotency-Key` are only run
+/// once; repeating them returns the original response.
 pub async fn post(
     config: Data<AppConfig>,
     ctx: Data<AppContext>,
@@ -70,44 +232,82 @@ pub async fn post(
     payload: Payload,
     req: HttpRequest,
 ) -> AppResult<HttpResponse> {
-    let content_type = req.mime_type()?.map(jotta_osd::object::meta::ContentType);
+    let Some(key) = idempotency_key(&req)? else {
+        return create_and_upload(&config, &ctx, &path, &params, payload, &req).await;
+    };
+
+    match config.idempotency.claim(&path.bucket, &path.object, key)? {
+        Claim::Done(res) => Ok(res),
+        Claim::New(reservation) => {
+            let res = create_and_upload(&config, &ctx, &path, &params, payload, &req).await?;
+
+            reservation.complete(res).await
+        }
+    }
+}
+
+async fn create_and_upload(
+    config: &AppConfig,
+    ctx: &AppContext,
+    path: &ObjectPath,
+    params: &PostParameters,
+    payload: Payload,
+    req: &HttpRequest,
+) -> AppResult<HttpResponse> {
+    let content_type = content_type(req)?;
+    let overwrite = overwrite(req)?;
+    let num_connections = config.connections(params.connections)?;
 
     match params.upload_type {
         UploadType::Media => {
             let meta = Patch {
                 content_type,
                 cache_control: None,
+                content_encoding: content_encoding(req)?,
+                user_metadata: None,
             };
 
-            create(&ctx, &path.bucket, &path.object, meta).await?;
+            // fail before anything is created or uploaded if possible
+            let budget = Budget::fetch(config, ctx).await?;
+
+            if let Some(len) = content_length(req) {
+                budget.check(len)?;
+            }
+
+            create_object(ctx, path, meta, overwrite).await?;
 
-            let reader = payload
-                .map_err(|r| IoError::new(IoErrorKind::Other, r))
-                .into_async_read();
+            let reader = budget.limit(payload).into_async_read();
 
             let reader = BufReader::new(reader);
 
-            let meta = upload_range(
-                &ctx,
+            let meta = upload_range_with_timestamps(
+                ctx,
                 &path.bucket,
                 &path.object,
                 0,
                 reader,
-                config.connections_per_request,
+                num_connections.into(),
+                None,
+                Timestamps {
+                    created: params.created,
+                    modified: params.modified,
+                },
             )
             .await?;
 
             let mut res = HttpResponse::Ok();
 
-            append_object_headers(&mut res, &meta); // TODO: should we really return a cache-control header here?
+            append_json_headers(&mut res, &meta);
 
             Ok(res.content_type(ContentType::json()).json(meta))
         }
-        UploadType::Multipart => todo!(),
+        UploadType::Multipart => Err(AppError::NotImplemented {
+            feature: "multipart upload",
+        }),
         UploadType::Resumable => {
             let meta = if content_type.is_some() {
                 Json::<Patch>::from_request(
-                    &req,
+                    req,
                     &mut dev::Payload::Stream {
                         payload: Box::pin(payload),
                     },
@@ -118,7 +318,7 @@ pub async fn post(
                 Default::default()
             };
 
-            create(&ctx, &path.bucket, &path.object, meta).await?;
+            create_object(ctx, path, meta, overwrite).await?;
 
             let mut res = HttpResponse::Created();
 
@@ -133,32 +333,37 @@ pub async fn post(
 }
 
 pub async fn head(ctx: Data<AppContext>, path: Path<ObjectPath>) -> AppResult<HttpResponse> {
-    let mut res = HttpResponse::Ok();
-
     let meta = jotta_osd::object::meta::get(&ctx, &path.bucket, &path.object).await?;
 
-    append_object_headers(&mut res, &meta);
+    Ok(head_response(&meta))
+}
+
+/// Response to a `HEAD` request for the object data.
+pub(crate) fn head_response(meta: &Meta) -> HttpResponse {
+    let mut res = HttpResponse::Ok();
+
+    append_media_headers(&mut res, meta);
+    append_content_md5(&mut res, meta);
 
-    Ok(res.no_chunking(meta.size).finish())
+    res.no_chunking(meta.size).finish()
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub enum AltType {
+    #[default]
     Json,
     Media,
 }
 
-impl Default for AltType {
-    fn default() -> Self {
-        Self::Json
-    }
-}
-
 #[derive(Debug, Deserialize)]
 pub struct GetParameters {
     #[serde(default)]
     alt: AltType,
+    /// Return a chunk health report instead of the object (`?debug`).
+    debug: Option<String>,
+    /// Override the number of concurrent connections.
+    connections: Option<usize>,
 }
 
 pub async fn get(
@@ -168,48 +373,203 @@ pub async fn get(
     path: Path<ObjectPath>,
     params: Query<GetParameters>,
 ) -> AppResult<HttpResponse> {
-    let meta = jotta_osd::object::meta::get(&ctx, &path.bucket, &path.object).await?;
-    let mut res = HttpResponse::Ok();
+    if params.debug.is_some() {
+        if !config.debug_routes {
+            return Err(AppError::NotFound);
+        }
 
-    append_object_headers(&mut res, &meta);
+        let health = jotta_osd::object::health::check(&ctx, &path.bucket, &path.object).await?;
+
+        return Ok(HttpResponse::Ok()
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+            .json(health));
+    }
+
+    let meta = jotta_osd::object::meta::get(&ctx, &path.bucket, &path.object).await?;
 
     match params.alt {
-        AltType::Json => Ok(res.content_type(ContentType::json()).json(meta)),
+        AltType::Json => {
+            let mut res = HttpResponse::Ok();
+
+            append_json_headers(&mut res, &meta);
+
+            Ok(res.content_type(ContentType::json()).json(meta))
+        }
         AltType::Media => {
-            let range = req.headers().get(header::RANGE).map_or(
-                Ok(ClosedByteRange::new_to_including(meta.size)),
-                |header| {
-                    HttpRange::parse_bytes(header.as_bytes(), meta.size)
-                        .map(|ranges| ClosedByteRange::new(ranges[0].start, ranges[0].length))
-                },
-            )?;
+            let num_connections = config.connections(params.connections)?;
+            let ObjectPath { bucket, object } = path.into_inner();
 
-            let stream = jotta_osd::object::stream_range(
+            media_response(
+                &req,
                 ctx.into_inner(),
-                path.bucket.clone(),
-                path.object.clone(),
-                range,
-                config.connections_per_request,
-            );
+                bucket,
+                object,
+                &meta,
+                num_connections,
+                config.download_mode(num_connections),
+            )
+        }
+    }
+}
+
+/// Stream the object data, honoring `Range` and `If-Range`.
+pub(crate) fn media_response(
+    req: &HttpRequest,
+    ctx: Arc<AppContext>,
+    bucket: BucketName,
+    object: ObjectName,
+    meta: &Meta,
+    num_connections: usize,
+    mode: DownloadMode,
+) -> AppResult<HttpResponse> {
+    let mut res = HttpResponse::Ok();
 
-            if range.len() < meta.size {
-                res.status(StatusCode::PARTIAL_CONTENT);
+    append_media_headers(&mut res, meta);
 
-                res.insert_header((
-                    header::CONTENT_RANGE,
-                    format!("bytes {}-{}/{}", range.start(), range.end(), meta.size),
-                ));
-            }
+    let part_meta = meta.clone();
+    let stream_part = move |range: &HttpRange| {
+        jotta_osd::object::stream_range_with_meta(
+            ctx.clone(),
+            bucket.clone(),
+            object.clone(),
+            &part_meta,
+            ClosedByteRange::new(range.start, range.length),
+            num_connections,
+            mode,
+        )
+    };
+
+    match requested_ranges(req, meta)?.as_deref() {
+        Some([range]) if range.length < meta.size => {
+            res.status(StatusCode::PARTIAL_CONTENT)
+                .insert_header((header::CONTENT_RANGE, content_range(range, meta.size)));
+
+            Ok(res
+                .no_chunking(range.length)
+                .streaming(Box::pin(stream_part(range))))
+        }
+        Some(ranges @ [_, _, ..]) => {
+            let boundary = etag(meta);
+            let content_type = meta.content_type.to_string();
+
+            let parts = ranges
+                .iter()
+                .map(|range| {
+                    let head = part_header(&boundary, &content_type, range, meta.size);
+                    (head, *range)
+                })
+                .collect::<Vec<_>>();
+
+            let closing = format!("--{boundary}--\r\n");
+            let len = parts
+                .iter()
+                .map(|(head, range)| head.len() as u64 + range.length + 2)
+                .sum::<u64>()
+                + closing.len() as u64;
+
+            let body = stream::iter(parts)
+                .flat_map(move |(head, range)| {
+                    stream::once(future::ok(Bytes::from(head)))
+                        .chain(stream_part(&range))
+                        .chain(stream::once(future::ok(Bytes::from_static(b"\r\n"))))
+                })
+                .chain(stream::once(future::ok(Bytes::from(closing))));
+
+            res.status(StatusCode::PARTIAL_CONTENT).insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/byteranges; boundary={boundary}"),
+            ));
+
+            Ok(res.no_chunking(len).streaming(Box::pin(body)))
+        }
+        _ if meta.size == 0 => {
+            append_content_md5(&mut res, meta);
+
+            Ok(res.no_chunking(0).finish())
+        }
+        _ => {
+            append_content_md5(&mut res, meta);
+
+            Ok(res
+                .no_chunking(meta.size)
+                .streaming(Box::pin(stream_part(&HttpRange {
+                    start: 0,
+                    length: meta.size,
+                }))))
+        }
+    }
+}
+
+/// Parse the `Range` header, unless the object has changed according to
+/// `If-Range`, in which case the whole object should be sent.
+///
+/// Ranges starting at or beyond the end of the object are dropped. If
+/// no range is left (or the header is malformed),
+/// [`AppError::RangeNotSatisfiable`] is returned.
+fn requested_ranges(req: &HttpRequest, meta: &Meta) -> AppResult<Option<Vec<HttpRange>>> {
+    let Some(range) = req.headers().get(header::RANGE) else {
+        return Ok(None);
+    };
 
-            Ok(res.streaming(Box::pin(stream)))
+    if let Some(if_range) = req.get_header::<IfRange>() {
+        if !if_range_matches(&if_range, meta) {
+            return Ok(None);
         }
     }
+
+    HttpRange::parse_bytes(range.as_bytes(), meta.size)
+        .map(Some)
+        .map_err(|_| AppError::RangeNotSatisfiable { size: meta.size })
+}
+
+/// Does the `If-Range` validator match the current object? Dates must
+/// match `Last-Modified` exactly and entity tags are compared strongly.
+fn if_range_matches(if_range: &IfRange, meta: &Meta) -> bool {
+    match if_range {
+        IfRange::Date(date) => *date == HttpDate::from(SystemTime::from(meta.updated)),
+        IfRange::EntityTag(tag) => tag.strong_eq(&entity_tag(meta)),
+    }
+}
+
+fn content_range(range: &HttpRange, size: u64) -> String {
+    format!(
+        "bytes {}-{}/{}",
+        range.start,
+        range.start + range.length - 1,
+        size
+    )
+}
+
+/// Headers preceding a part of a `multipart/byteranges` body.
+fn part_header(boundary: &str, content_type: &str, range: &HttpRange, size: u64) -> String {
+    format!(
+        "--{boundary}\r\n{}: {content_type}\r\n{}: {}\r\n\r\n",
+        header::CONTENT_TYPE,
+        header::CONTENT_RANGE,
+        content_range(range, size)
+    )
+}
+
+/// Parse the `If-Match` header as an expected entity tag. Weak tags
+/// never match, since `If-Match` uses strong comparison.
+fn expected_etag(req: &HttpRequest) -> AppResult<Option<String>> {
+    match req.get_header::<IfMatch>() {
+        None | Some(IfMatch::Any) => Ok(None),
+        Some(IfMatch::Items(tags)) => match tags.as_slice() {
+            [tag] if tag.weak => Err(AppError::PreconditionFailed),
+            [tag] => Ok(Some(tag.tag().to_owned())),
+            _ => Err(AppError::InvalidInput {
+                message: "expected exactly one entity tag in `If-Match`".into(),
+            }),
+        },
+    }
 }
 
 pub async fn patch(
     ctx: Data<AppContext>,
     path: Path<ObjectPath>,
     patch: Json<Patch>,
+    req: HttpRequest,
 ) -> AppResult<HttpResponse> {
     let patch = patch.into_inner();
 
@@ -217,17 +577,66 @@ pub async fn patch(
         return Err(AppError::BadRequest);
     }
 
-    let new = jotta_osd::object::meta::patch(&ctx, &path.bucket, &path.object, patch).await?;
+    let expected_etag = expected_etag(&req)?;
+
+    let new = jotta_osd::object::meta::patch(
+        &ctx,
+        &path.bucket,
+        &path.object,
+        patch,
+        expected_etag.as_deref(),
+    )
+    .await?;
 
     let mut res = HttpResponse::Ok();
 
-    append_object_headers(&mut res, &new);
+    append_json_headers(&mut res, &new);
 
     Ok(res.content_type(ContentType::json()).json(new))
 }
 
-pub async fn delete(ctx: Data<AppContext>, path: Path<ObjectPath>) -> AppResult<HttpResponse> {
-    jotta_osd::object::delete(&ctx, &path.bucket, &path.object).await?;
+/// Export all objects in a bucket as a tar archive.
+pub async fn export(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+) -> AppResult<HttpResponse> {
+    let bucket = bucket.into_inner();
+    let names = jotta_osd::object::list(&ctx, &bucket).await?;
+
+    let stream = jotta_osd::object::stream_tar(
+        ctx.into_inner(),
+        bucket.clone(),
+        names,
+        config.connections_per_request,
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-tar")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{bucket}.tar"))],
+        })
+        .streaming(Box::pin(stream)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteParameters {
+    /// Delete permanently instead of moving the object to the trash.
+    #[serde(default)]
+    permanent: bool,
+}
+
+pub async fn delete(
+    ctx: Data<AppContext>,
+    path: Path<ObjectPath>,
+    params: Query<DeleteParameters>,
+) -> AppResult<HttpResponse> {
+    if params.permanent {
+        jotta_osd::object::delete(&ctx, &path.bucket, &path.object).await?;
+    } else {
+        jotta_osd::object::soft_delete(&ctx, &path.bucket, &path.object).await?;
+    }
 
     Ok(HttpResponse::NoContent().finish())
 }
@@ -243,3 +652,556 @@ pub fn config(cfg: &mut ServiceConfig) {
                 .route(web::delete().to(delete)),
         );
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use actix_web::{
+        http::{
+            header::{self, EntityTag, HttpDate, IfRange},
+            StatusCode,
+        },
+        test::{self, TestRequest},
+        web::Data,
+        App, HttpResponse, ResponseError,
+    };
+    use http_range::HttpRange;
+    use jotta::mock::MockJottacloud;
+    use jotta_osd::concurrency::{ConcurrencyStrategy, DownloadMode};
+    use jotta_osd::object::{
+        create, etag,
+        meta::{CacheControl, ContentEncoding, ContentType, Meta, Patch},
+        upload_range,
+    };
+    use jotta_osd::path::{BucketName, ObjectName};
+    use time::Duration;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{
+        append_content_md5, append_json_headers, append_media_headers, content_encoding,
+        content_type, expected_etag, if_range_matches, overwrite, part_header, requested_ranges,
+        DeleteParameters, PostParameters,
+    };
+    use crate::{config::AppConfig, errors::AppError, routes};
+
+    fn meta() -> Meta {
+        Meta {
+            size: 1337,
+            content_type: ContentType(mime::IMAGE_JPEG),
+            ..Meta::default()
+        }
+    }
+
+    #[test]
+    fn json_headers() {
+        let mut res = HttpResponse::Ok();
+        append_json_headers(&mut res, &meta());
+        let res = res.finish();
+
+        assert!(res.headers().get(header::ACCEPT_RANGES).is_none());
+        assert_eq!(
+            res.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        assert_eq!(
+            res.headers().get(header::ETAG).unwrap(),
+            &format!("\"{}\"", etag(&meta()))
+        );
+    }
+
+    #[test]
+    fn media_headers() {
+        let mut res = HttpResponse::Ok();
+        append_media_headers(&mut res, &meta());
+        let res = res.finish();
+
+        assert_eq!(res.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/jpeg"
+        );
+        assert_eq!(
+            res.headers().get(header::ETAG).unwrap(),
+            &format!("\"{}\"", etag(&meta()))
+        );
+    }
+
+    #[test]
+    fn if_match() {
+        let if_match = |value: &str| {
+            let req = TestRequest::patch()
+                .insert_header((header::IF_MATCH, value))
+                .to_http_request();
+            expected_etag(&req)
+        };
+
+        assert_eq!(
+            expected_etag(&TestRequest::patch().to_http_request()).unwrap(),
+            None
+        );
+        assert_eq!(if_match("*").unwrap(), None);
+
+        // the validator sent as `ETag` is accepted back
+        let mut res = HttpResponse::Ok();
+        append_json_headers(&mut res, &meta());
+        let res = res.finish();
+        let sent = res.headers().get(header::ETAG).unwrap().to_str().unwrap();
+        assert_eq!(if_match(sent).unwrap(), Some(etag(&meta())));
+
+        assert!(matches!(
+            if_match("W/\"abc\""),
+            Err(AppError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            if_match("\"a\", \"b\""),
+            Err(AppError::InvalidInput { .. })
+        ));
+    }
+
+    #[test]
+    fn unsatisfiable_range() {
+        let meta = meta();
+
+        for range in ["bytes=1337-", "bytes=2000-3000", "bytes=1337-,5000-"] {
+            let req = TestRequest::get()
+                .insert_header((header::RANGE, range))
+                .to_http_request();
+            let res = requested_ranges(&req, &meta).unwrap_err().error_response();
+
+            assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{range}");
+            assert_eq!(
+                res.headers().get(header::CONTENT_RANGE).unwrap(),
+                "bytes */1337"
+            );
+        }
+
+        // ranges that overlap are truncated
+        let req = TestRequest::get()
+            .insert_header((header::RANGE, "bytes=1000-5000,2000-"))
+            .to_http_request();
+        let ranges = requested_ranges(&req, &meta).unwrap().unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].start, ranges[0].length), (1000, 337));
+    }
+
+    #[test]
+    fn content_md5() {
+        let mut res = HttpResponse::Ok();
+        append_content_md5(&mut res, &meta());
+        assert!(res.finish().headers().get("content-md5").is_none());
+
+        let mut res = HttpResponse::Ok();
+        append_content_md5(
+            &mut res,
+            &Meta {
+                md5: Some(md5::compute("hello")),
+                ..meta()
+            },
+        );
+        assert_eq!(
+            res.finish().headers().get("content-md5").unwrap(),
+            "XUFAKrxLKna5cZ2REBfFkg=="
+        );
+    }
+
+    #[test]
+    fn omitted_cache_control() {
+        let mut res = HttpResponse::Ok();
+        append_media_headers(&mut res, &meta());
+
+        assert_eq!(
+            res.finish().headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+
+        let mut meta = meta();
+        meta.cache_control = CacheControl::none();
+
+        let mut res = HttpResponse::Ok();
+        append_media_headers(&mut res, &meta);
+
+        assert!(res.finish().headers().get(header::CACHE_CONTROL).is_none());
+    }
+
+    #[test]
+    fn if_range() {
+        let meta = meta();
+        let last_modified = HttpDate::from(SystemTime::from(meta.updated));
+        let stale = HttpDate::from(SystemTime::from(meta.updated - Duration::hours(1)));
+
+        assert!(if_range_matches(&IfRange::Date(last_modified), &meta));
+        assert!(!if_range_matches(&IfRange::Date(stale), &meta));
+
+        let tag = EntityTag::new_strong(etag(&meta));
+
+        assert!(if_range_matches(&IfRange::EntityTag(tag), &meta));
+        assert!(!if_range_matches(
+            &IfRange::EntityTag(EntityTag::new_strong("nope".into())),
+            &meta
+        ));
+    }
+
+    #[test]
+    fn multipart_part_header() {
+        let range = HttpRange {
+            start: 100,
+            length: 50,
+        };
+
+        assert_eq!(
+            part_header("xyz", "image/jpeg", &range, 1337),
+            "--xyz\r\ncontent-type: image/jpeg\r\ncontent-range: bytes 100-149/1337\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn upload_content_type() {
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "Text/Plain; charset=utf-8"))
+            .to_http_request();
+
+        assert_eq!(
+            content_type(&req).unwrap().unwrap().to_string(),
+            "text/plain; charset=utf-8"
+        );
+
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "definitely not a mime"))
+            .to_http_request();
+
+        assert!(matches!(
+            content_type(&req),
+            Err(AppError::InvalidInput { .. })
+        ));
+
+        assert!(content_type(&TestRequest::post().to_http_request())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn overwrite_header() {
+        assert!(!overwrite(&TestRequest::post().to_http_request()).unwrap());
+
+        for (value, expected) in [("true", true), ("false", false)] {
+            let req = TestRequest::post()
+                .insert_header(("x-overwrite", value))
+                .to_http_request();
+            assert_eq!(overwrite(&req).unwrap(), expected);
+        }
+
+        let req = TestRequest::post()
+            .insert_header(("x-overwrite", "yes"))
+            .to_http_request();
+        assert!(matches!(
+            overwrite(&req),
+            Err(AppError::InvalidInput { .. })
+        ));
+    }
+
+    #[test]
+    fn stored_content_encoding() {
+        let mut meta = meta();
+
+        let mut res = HttpResponse::Ok();
+        append_media_headers(&mut res, &meta);
+        assert!(res
+            .finish()
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .is_none());
+
+        meta.content_encoding = ContentEncoding::Gzip;
+
+        let mut res = HttpResponse::Ok();
+        append_media_headers(&mut res, &meta);
+        assert_eq!(
+            res.finish()
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .unwrap(),
+            "gzip"
+        );
+
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_ENCODING, "br"))
+            .to_http_request();
+        assert_eq!(content_encoding(&req).unwrap(), Some(ContentEncoding::Br));
+
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_ENCODING, "zstd"))
+            .to_http_request();
+        assert!(content_encoding(&req).is_err());
+    }
+
+    #[test]
+    fn upload_timestamps() {
+        let params = |query: &str| {
+            actix_web::web::Query::<PostParameters>::from_query(query).map(|q| q.into_inner())
+        };
+
+        let p = params("uploadType=media").unwrap();
+        assert_eq!((p.created, p.modified), (None, None));
+
+        let p = params(
+            "uploadType=media&created=2020-05-16T10:46:05Z&modified=2021-01-01T00:00:00%2B01:00",
+        )
+        .unwrap();
+        assert_eq!(
+            p.created,
+            Some(time::macros::datetime!(2020-05-16 10:46:05 UTC))
+        );
+        assert_eq!(
+            p.modified,
+            Some(time::macros::datetime!(2020-12-31 23:00:00 UTC))
+        );
+
+        assert!(params("uploadType=media&created=yesterday").is_err());
+    }
+
+    #[test]
+    fn delete_parameters() {
+        let permanent = |query: &str| {
+            actix_web::web::Query::<DeleteParameters>::from_query(query)
+                .map(|q| q.into_inner().permanent)
+        };
+
+        assert!(!permanent("").unwrap());
+        assert!(!permanent("permanent=false").unwrap());
+        assert!(permanent("permanent=true").unwrap());
+        assert!(permanent("permanent=yes").is_err());
+    }
+
+    /// Start a fake Jottacloud where every folder can be created but no
+    /// files exist. Only the `Jotta` device is listed, with an `Archive`
+    /// mount point.
+    async fn empty_jottacloud() -> String {
+        fake_jottacloud(false).await
+    }
+
+    /// Like [`empty_jottacloud`], but allocating a file fails as if it
+    /// already existed if `conflicts` is set.
+    async fn fake_jottacloud(conflicts: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        actix_rt::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut req = vec![0; 4096];
+                let n = socket.read(&mut req).await.unwrap();
+                let req = String::from_utf8_lossy(&req[..n]);
+
+                let (status, body) = if conflicts && req.starts_with("POST /files/v1/allocate ") {
+                    (
+                        "409 Conflict",
+                        r#"{"code":409,"message":null,"cause":null,"error_id":"UniqueFileException","x-id":null}"#,
+                    )
+                } else if req.starts_with("GET /jfs/alice/Jotta ") {
+                    (
+                        "200 OK",
+                        "<device><name>Jotta</name><mountPoints><mountPoint><name>Archive</name>\
+                        <size>0</size><modified></modified></mountPoint></mountPoints></device>",
+                    )
+                } else if req.contains("mkDir=true") {
+                    (
+                        "201 Created",
+                        "<folder name=\"jotta-test\"><path>/alice/Jotta/Archive</path></folder>",
+                    )
+                } else {
+                    (
+                        "404 Not Found",
+                        "<error><code>404</code>\
+                        <message>no.jotta.backup.errors.NoSuchFileException: meta</message>\
+                        <reason>Not Found</reason><cause></cause><hostname></hostname><x-id></x-id></error>",
+                    )
+                };
+
+                let res = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/xml\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+
+                socket.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+
+        base_url
+    }
+
+    #[test]
+    fn prefetch_limit() {
+        let mut config = AppConfig::mock();
+        assert_eq!(config.download_mode(10), DownloadMode::Ordered);
+
+        config.max_prefetch_chunks = Some(4);
+        assert_eq!(config.download_mode(10), DownloadMode::Windowed { max: 4 });
+        assert_eq!(config.download_mode(4), DownloadMode::Ordered);
+        assert_eq!(config.download_mode(2), DownloadMode::Ordered);
+    }
+
+    #[test]
+    fn connections_override() {
+        let config = AppConfig::mock();
+        let max = config.max_connections_per_request;
+
+        assert_eq!(
+            config.connections(None).unwrap(),
+            config.connections_per_request
+        );
+        assert_eq!(config.connections(Some(1)).unwrap(), 1);
+        assert_eq!(config.connections(Some(max)).unwrap(), max);
+        assert!(matches!(
+            config.connections(Some(0)),
+            Err(AppError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            config.connections(Some(max + 1)),
+            Err(AppError::InvalidInput { .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn missing_object() {
+        let config = AppConfig::mock();
+        let ctx = Data::new(config.create_mock_context(&empty_jottacloud().await).await);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .app_data(ctx)
+                .configure(routes::config),
+        )
+        .await;
+
+        for uri in [
+            "/b/bucket/o/missing",
+            "/b/bucket/o/missing?alt=json",
+            "/b/bucket/o/missing?alt=media",
+        ] {
+            let res = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "GET {uri}");
+        }
+
+        let req = TestRequest::default()
+            .method(actix_web::http::Method::HEAD)
+            .uri("/b/bucket/o/missing")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn duplicate_object() {
+        let config = AppConfig::mock();
+        let ctx = Data::new(
+            config
+                .create_mock_context(&fake_jottacloud(true).await)
+                .await,
+        );
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .app_data(ctx)
+                .configure(routes::config),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/b/bucket/o/existing?uploadType=resumable")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let body = test::read_body(res).await;
+        assert_eq!(body, "object existing already exists in bucket bucket");
+    }
+
+    #[actix_web::test]
+    async fn multipart_upload() {
+        let config = AppConfig::mock();
+        let ctx = Data::new(config.create_mock_context(&empty_jottacloud().await).await);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .app_data(ctx)
+                .configure(routes::config),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/b/bucket/o/object?uploadType=multipart")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED);
+
+        let body = test::read_body(res).await;
+        assert_eq!(body, "multipart upload is not implemented");
+    }
+
+    #[actix_web::test]
+    async fn unsatisfiable_range_response() {
+        let server = MockJottacloud::start().await;
+        let config = AppConfig::mock();
+        let ctx = config.create_mock_context(&server.base_url()).await;
+
+        let bucket: BucketName = "bucket".parse().unwrap();
+        let name: ObjectName = "object".parse().unwrap();
+        let size = 1337;
+
+        jotta_osd::bucket::create(&ctx, &bucket).await.unwrap();
+        create(&ctx, &bucket, &name, Patch::default())
+            .await
+            .unwrap();
+        upload_range(
+            &ctx,
+            &bucket,
+            &name,
+            0,
+            &vec![1; size][..],
+            ConcurrencyStrategy::Fixed(1),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .app_data(Data::new(ctx))
+                .configure(routes::config),
+        )
+        .await;
+
+        let before = server.downloads().len();
+
+        for range in [
+            format!("bytes={size}-"),
+            format!("bytes={}-{}", size + 100, size + 200),
+        ] {
+            let req = TestRequest::get()
+                .uri("/b/bucket/o/object?alt=media")
+                .insert_header((header::RANGE, range.as_str()))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+
+            assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{range}");
+            assert_eq!(
+                res.headers().get(header::CONTENT_RANGE).unwrap(),
+                format!("bytes */{size}").as_str()
+            );
+        }
+
+        // only metadata was read, not the chunk
+        let chunk = format!("Jotta/Archive/jotta-test/bucket/{}/0", name.storage_id());
+        let downloads = &server.downloads()[before..];
+        assert!(!downloads.is_empty());
+        assert!(!downloads.contains(&chunk), "{downloads:?}");
+    }
+}
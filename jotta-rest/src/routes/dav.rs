@@ -0,0 +1,711 @@
+//! A small subset of WebDAV (RFC 4918), so that the store can be mounted
+//! by file managers.
+//!
+//! Buckets are collections at the top level. Inside a bucket, object
+//! names are split on `/` into folders, like
+//! [`list_delimited`](jotta_osd::object::list_delimited) does. Folders only
+//! exist as long as they contain objects, so `MKCOL` is only supported
+//! for buckets.
+use std::fmt::Write;
+
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        Method, StatusCode,
+    },
+    web::{self, Data, Path, Payload, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use futures_util::{future, io::BufReader, stream, StreamExt, TryStreamExt};
+use httpdate::fmt_http_date;
+use jotta_osd::{
+    jotta,
+    object::{
+        create,
+        meta::{Meta, Patch},
+        txn::UploadTxn,
+        Listing,
+    },
+    path::{BucketName, ObjectName},
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::bucket::object::{content_length, content_type, head_response, media_response};
+use crate::{config::AppConfig, errors::AppError, quota::Budget, AppContext, AppResult};
+
+/// Where the WebDAV frontend is mounted.
+const PREFIX: &str = "/dav";
+
+/// Methods supported by the frontend.
+const ALLOW: &str = "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE, MKCOL";
+
+/// Characters that don't need to be escaped in a path segment.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// How deep a `PROPFIND` should go. `infinity`, the default, is treated
+/// like `1` since listing everything at once would be expensive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Depth {
+    Zero,
+    One,
+}
+
+/// Parse the `Depth` header.
+fn depth(req: &HttpRequest) -> AppResult<Depth> {
+    match req.headers().get("depth").map(HeaderValue::as_bytes) {
+        Some(b"0") => Ok(Depth::Zero),
+        Some(b"1" | b"infinity") | None => Ok(Depth::One),
+        Some(_) => Err(AppError::InvalidInput {
+            message: "invalid `Depth` header".into(),
+        }),
+    }
+}
+
+/// Percent-encoded href of a resource. Collections end with a slash.
+fn href<'a>(segments: impl IntoIterator<Item = &'a str>, collection: bool) -> String {
+    let mut href = PREFIX.to_owned();
+
+    for segment in segments {
+        href.push('/');
+        href.extend(utf8_percent_encode(segment, SEGMENT));
+    }
+
+    if collection {
+        href.push('/');
+    }
+
+    href
+}
+
+/// A resource in a `PROPFIND` response.
+#[derive(Debug)]
+struct Entry {
+    href: String,
+    name: String,
+    /// Metadata of an object, or `None` for collections.
+    meta: Option<Meta>,
+}
+
+impl Entry {
+    fn root() -> Self {
+        Self {
+            href: href([], true),
+            name: String::new(),
+            meta: None,
+        }
+    }
+
+    /// A bucket, or a folder within it if `prefix` (ending with a slash)
+    /// isn't empty.
+    fn folder(bucket: &BucketName, prefix: &str) -> Self {
+        let bucket = bucket.to_string();
+        let segments = prefix.strip_suffix('/').map(|p| p.split('/'));
+        let name = segments
+            .clone()
+            .and_then(Iterator::last)
+            .unwrap_or(&bucket)
+            .to_owned();
+
+        Self {
+            href: href(
+                std::iter::once(bucket.as_str()).chain(segments.into_iter().flatten()),
+                true,
+            ),
+            name,
+            meta: None,
+        }
+    }
+
+    fn object(bucket: &BucketName, name: &ObjectName, meta: Meta) -> Self {
+        let bucket = bucket.to_string();
+
+        Self {
+            href: href(
+                std::iter::once(bucket.as_str()).chain(name.split('/')),
+                false,
+            ),
+            name: name.rsplit('/').next().unwrap_or_default().to_owned(),
+            meta: Some(meta),
+        }
+    }
+}
+
+/// Escape text for use in XML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_multistatus(out: &mut String, entries: &[Entry]) -> std::fmt::Result {
+    write!(
+        out,
+        r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#
+    )?;
+
+    for entry in entries {
+        write!(
+            out,
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname>",
+            escape(&entry.href),
+            escape(&entry.name)
+        )?;
+
+        match &entry.meta {
+            None => write!(out, "<D:resourcetype><D:collection/></D:resourcetype>")?,
+            Some(meta) => write!(
+                out,
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+                <D:getcontenttype>{}</D:getcontenttype>\
+                <D:getlastmodified>{}</D:getlastmodified>\
+                <D:getetag>\"{}\"</D:getetag>",
+                meta.size,
+                escape(&meta.content_type.to_string()),
+                fmt_http_date(meta.updated.into()),
+                jotta_osd::object::etag(meta)
+            )?,
+        }
+
+        write!(
+            out,
+            "</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>"
+        )?;
+    }
+
+    write!(out, "</D:multistatus>")
+}
+
+/// A `207 Multi-Status` response describing `entries`.
+fn multistatus(entries: &[Entry]) -> HttpResponse {
+    let mut body = String::new();
+
+    // writing to a string never fails
+    let _ = write_multistatus(&mut body, entries);
+
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+fn parse_path(path: Path<(BucketName, String)>) -> AppResult<(BucketName, ObjectName)> {
+    let (bucket, name) = path.into_inner();
+    let name = name.parse().map_err(jotta_osd::errors::Error::from)?;
+
+    Ok((bucket, name))
+}
+
+/// Names of the objects in a folder, or an error if there are none.
+async fn list_folder(
+    ctx: &AppContext,
+    bucket: &BucketName,
+    prefix: &str,
+) -> AppResult<Vec<ObjectName>> {
+    let names = jotta_osd::object::list_prefixed(ctx, bucket, prefix).await?;
+
+    if names.is_empty() {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(names)
+}
+
+/// Describe a bucket or a folder within it, and its contents if `depth`
+/// is [`Depth::One`].
+async fn folder_entries(
+    config: &AppConfig,
+    ctx: &AppContext,
+    bucket: &BucketName,
+    names: Vec<ObjectName>,
+    prefix: &str,
+    depth: Depth,
+) -> AppResult<Vec<Entry>> {
+    let mut entries = vec![Entry::folder(bucket, prefix)];
+
+    if depth == Depth::Zero {
+        return Ok(entries);
+    }
+
+    let Listing {
+        objects,
+        common_prefixes,
+    } = Listing::group(names, prefix, "/");
+
+    entries.extend(common_prefixes.iter().map(|p| Entry::folder(bucket, p)));
+
+    let objects: Vec<Entry> = stream::iter(objects)
+        .map(|name| async move {
+            match jotta_osd::object::meta::get(ctx, bucket, &name)
+                .await
+                .map_err(AppError::from)
+            {
+                Ok(meta) => Ok(Some(Entry::object(bucket, &name, meta))),
+                // deleted since it was listed
+                Err(AppError::NotFound) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+        .buffered(config.connections_per_request)
+        .try_filter_map(future::ok)
+        .try_collect()
+        .await?;
+
+    entries.extend(objects);
+
+    Ok(entries)
+}
+
+pub async fn options() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("dav", "1"))
+        .insert_header((header::ALLOW, ALLOW))
+        .finish()
+}
+
+pub async fn method_not_allowed() -> AppResult<HttpResponse> {
+    Err(AppError::MethodNotAllowed)
+}
+
+/// `PROPFIND` on the root, which contains the buckets.
+pub async fn propfind_root(ctx: Data<AppContext>, req: HttpRequest) -> AppResult<HttpResponse> {
+    let mut entries = vec![Entry::root()];
+
+    if depth(&req)? == Depth::One {
+        let buckets = jotta_osd::bucket::list(&ctx).await?;
+
+        entries.extend(buckets.iter().map(|b| Entry::folder(&b.name, "")));
+    }
+
+    Ok(multistatus(&entries))
+}
+
+pub async fn propfind_bucket(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let depth = depth(&req)?;
+
+    // fails if there is no such bucket
+    let names = jotta_osd::object::list(&ctx, &bucket).await?;
+    let entries = folder_entries(&config, &ctx, &bucket, names, "", depth).await?;
+
+    Ok(multistatus(&entries))
+}
+
+/// `PROPFIND` on an object, or on a folder if there is no such object.
+pub async fn propfind_path(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let depth = depth(&req)?;
+    let (bucket, name) = parse_path(path)?;
+
+    match jotta_osd::object::meta::get(&ctx, &bucket, &name)
+        .await
+        .map_err(AppError::from)
+    {
+        Ok(meta) => return Ok(multistatus(&[Entry::object(&bucket, &name, meta)])),
+        Err(AppError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+
+    let prefix = format!("{name}/");
+    let names = list_folder(&ctx, &bucket, &prefix).await?;
+    let entries = folder_entries(&config, &ctx, &bucket, names, &prefix, depth).await?;
+
+    Ok(multistatus(&entries))
+}
+
+pub async fn get(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let (bucket, name) = parse_path(path)?;
+    let meta = jotta_osd::object::meta::get(&ctx, &bucket, &name).await?;
+
+    media_response(
+        &req,
+        ctx.into_inner(),
+        bucket,
+        name,
+        &meta,
+        config.connections_per_request,
+        config.download_mode(config.connections_per_request),
+    )
+}
+
+pub async fn head(
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+) -> AppResult<HttpResponse> {
+    let (bucket, name) = parse_path(path)?;
+    let meta = jotta_osd::object::meta::get(&ctx, &bucket, &name).await?;
+
+    Ok(head_response(&meta))
+}
+
+/// Upload an object, replacing any existing one.
+///
+/// The upload is staged in an [`UploadTxn`], so a failed upload leaves
+/// an existing object as it was (except for overwritten indexed chunks),
+/// and a new object is deleted again.
+pub async fn put(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+    payload: Payload,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let (bucket, name) = parse_path(path)?;
+
+    let patch = Patch {
+        content_type: content_type(&req)?,
+        ..Default::default()
+    };
+
+    // fail before anything is created or uploaded if possible
+    let budget = Budget::fetch(&config, &ctx).await?;
+
+    if let Some(len) = content_length(&req) {
+        budget.check(len)?;
+    }
+
+    let created = match create(&ctx, &bucket, &name, patch.clone()).await {
+        Ok(_) => true,
+        Err(jotta_osd::errors::Error::Fs(jotta::Error::AlreadyExists)) => false,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut txn =
+        UploadTxn::replace(&ctx, &bucket, &name, patch, config.connections_per_request).await?;
+
+    let reader = BufReader::new(budget.limit(payload).into_async_read());
+
+    if let Err(e) = txn.write(0, reader).await {
+        // the upload error is the one worth reporting
+        let _ = txn.abort().await;
+
+        if created {
+            let _ = jotta_osd::object::delete(&ctx, &bucket, &name).await;
+        }
+
+        return Err(e.into());
+    }
+
+    txn.commit().await?;
+
+    Ok(HttpResponse::Created().finish())
+}
+
+/// Delete an object, or every object in a folder if there is no such
+/// object. Objects are moved to the trash, like a `DELETE` to the REST
+/// API without `permanent`.
+pub async fn delete_path(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+) -> AppResult<HttpResponse> {
+    let (bucket, name) = parse_path(path)?;
+
+    match jotta_osd::object::soft_delete(&ctx, &bucket, &name)
+        .await
+        .map_err(AppError::from)
+    {
+        Ok(()) => return Ok(HttpResponse::NoContent().finish()),
+        Err(AppError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+
+    let names = list_folder(&ctx, &bucket, &format!("{name}/")).await?;
+
+    stream::iter(names)
+        .map(|name| {
+            let (ctx, bucket) = (&ctx, &bucket);
+
+            async move { jotta_osd::object::soft_delete(ctx, bucket, &name).await }
+        })
+        .buffer_unordered(config.connections_per_request)
+        .try_collect::<()>()
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn delete_bucket(
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+) -> AppResult<HttpResponse> {
+    jotta_osd::bucket::delete(&ctx, &bucket).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// `MKCOL` creates a bucket. Existing buckets can't be created again.
+pub async fn mkcol_bucket(
+    ctx: Data<AppContext>,
+    bucket: Path<BucketName>,
+) -> AppResult<HttpResponse> {
+    match jotta_osd::bucket::create(&ctx, &bucket).await {
+        Ok(_) => Ok(HttpResponse::Created().finish()),
+        // RFC 4918, section 9.3.1
+        Err(jotta_osd::errors::Error::Fs(jotta_osd::jotta::Error::AlreadyExists)) => {
+            Err(AppError::MethodNotAllowed)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    let propfind = || web::method(Method::from_bytes(b"PROPFIND").unwrap());
+    let mkcol = || web::method(Method::from_bytes(b"MKCOL").unwrap());
+
+    cfg.service(
+        web::resource("")
+            .route(web::method(Method::OPTIONS).to(options))
+            .route(propfind().to(propfind_root))
+            .route(mkcol().to(method_not_allowed)),
+    )
+    .service(
+        web::resource("/{bucket}")
+            .route(web::method(Method::OPTIONS).to(options))
+            .route(propfind().to(propfind_bucket))
+            .route(mkcol().to(mkcol_bucket))
+            .route(web::delete().to(delete_bucket)),
+    )
+    .service(
+        web::resource("/{bucket}/{path:.+}")
+            .route(web::method(Method::OPTIONS).to(options))
+            .route(propfind().to(propfind_path))
+            .route(web::get().to(get))
+            .route(web::head().to(head))
+            .route(web::put().to(put))
+            .route(web::delete().to(delete_path))
+            .route(mkcol().to(method_not_allowed)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use actix_http::Request;
+    use actix_web::{
+        dev::{Service, ServiceResponse},
+        http::{Method, StatusCode},
+        test::{self, TestRequest},
+        web::Data,
+        App,
+    };
+    use jotta::mock::MockJottacloud;
+    use jotta_osd::{
+        object::{
+            meta::{ContentType, Meta},
+            CHUNK_SIZE,
+        },
+        path::ObjectName,
+    };
+
+    use super::{depth, href, multistatus, Depth, Entry};
+    use crate::{config::AppConfig, errors::AppError, routes};
+
+    #[test]
+    fn hrefs() {
+        let bucket = "bucket".parse().unwrap();
+
+        assert_eq!(Entry::root().href, "/dav/");
+        assert_eq!(href(["a b", "100%"], false), "/dav/a%20b/100%25");
+
+        let folder = Entry::folder(&bucket, "");
+        assert_eq!(folder.href, "/dav/bucket/");
+        assert_eq!(folder.name, "bucket");
+
+        let folder = Entry::folder(&bucket, "photos/2022/");
+        assert_eq!(folder.href, "/dav/bucket/photos/2022/");
+        assert_eq!(folder.name, "2022");
+    }
+
+    #[test]
+    fn depth_header() {
+        let with_depth = |value| {
+            depth(
+                &TestRequest::default()
+                    .insert_header(("depth", value))
+                    .to_http_request(),
+            )
+        };
+
+        assert_eq!(
+            depth(&TestRequest::default().to_http_request()).unwrap(),
+            Depth::One
+        );
+        assert_eq!(with_depth("0").unwrap(), Depth::Zero);
+        assert_eq!(with_depth("1").unwrap(), Depth::One);
+        assert_eq!(with_depth("infinity").unwrap(), Depth::One);
+        assert!(matches!(
+            with_depth("2"),
+            Err(AppError::InvalidInput { .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn propfind_response() {
+        let bucket = "bucket".parse().unwrap();
+        let meta = Meta {
+            size: 1337,
+            content_type: ContentType(mime::TEXT_PLAIN),
+            ..Meta::default()
+        };
+
+        let res = multistatus(&[
+            Entry::folder(&bucket, "<docs>/"),
+            Entry::object(&bucket, &"<docs>/a&b.txt".parse().unwrap(), meta),
+        ]);
+        assert_eq!(res.status(), StatusCode::MULTI_STATUS);
+
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+
+        for part in [
+            "<D:href>/dav/bucket/%3Cdocs%3E/</D:href>",
+            "<D:displayname>&lt;docs&gt;</D:displayname>\
+            <D:resourcetype><D:collection/></D:resourcetype>",
+            "<D:href>/dav/bucket/%3Cdocs%3E/a%26b.txt</D:href>",
+            "<D:displayname>a&amp;b.txt</D:displayname><D:resourcetype/>",
+            "<D:getcontentlength>1337</D:getcontentlength>",
+            "<D:getcontenttype>text/plain</D:getcontenttype>",
+            "<D:getlastmodified>Thu, 01 Jan 1970 00:00:00 GMT</D:getlastmodified>",
+        ] {
+            assert!(body.contains(part), "missing `{part}` in\n{body}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn options() {
+        let app = test::init_service(App::new().configure(routes::config)).await;
+
+        for uri in ["/dav", "/dav/bucket", "/dav/bucket/photos/cat.jpeg"] {
+            let req = TestRequest::default()
+                .method(Method::OPTIONS)
+                .uri(uri)
+                .to_request();
+            let res = test::call_service(&app, req).await;
+
+            assert_eq!(res.status(), StatusCode::OK, "{uri}");
+            assert_eq!(res.headers().get("dav").unwrap(), "1");
+        }
+
+        let req = TestRequest::default()
+            .method(Method::from_bytes(b"MKCOL").unwrap())
+            .uri("/dav/bucket/folder")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    async fn app_with(
+        server: &MockJottacloud,
+        config: AppConfig,
+    ) -> impl Service<Request, Response = ServiceResponse, Error = actix_web::Error> {
+        let ctx = config.create_mock_context(&server.base_url()).await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .app_data(Data::new(ctx))
+                .configure(routes::config),
+        )
+        .await;
+
+        let req = TestRequest::default()
+            .method(Method::from_bytes(b"MKCOL").unwrap())
+            .uri("/dav/bucket")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        app
+    }
+
+    fn put(body: Vec<u8>) -> Request {
+        TestRequest::put()
+            .uri("/dav/bucket/object")
+            .set_payload(body)
+            .to_request()
+    }
+
+    fn object_folder() -> String {
+        let name: ObjectName = "object".parse().unwrap();
+
+        format!("Jotta/Archive/jotta-test/bucket/{}", name.storage_id())
+    }
+
+    #[actix_web::test]
+    async fn put_replaces_chunks() {
+        let server = MockJottacloud::start().await;
+        let app = app_with(&server, AppConfig::mock()).await;
+
+        let res = test::call_service(&app, put(vec![1; CHUNK_SIZE * 2 + 1])).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(server.files(&object_folder()), ["0", "1", "2", "meta"]);
+
+        let res = test::call_service(&app, put(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(server.files(&object_folder()), ["0", "meta"]);
+
+        let req = TestRequest::get().uri("/dav/bucket/object").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "hello");
+    }
+
+    #[actix_web::test]
+    async fn failed_put_keeps_object() {
+        let server = MockJottacloud::start().await;
+        let mut config = AppConfig::mock();
+        config.upload_limit = Some(5);
+        let app = app_with(&server, config).await;
+
+        let res = test::call_service(&app, put(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = test::call_service(&app, put(b"goodbye".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = TestRequest::get().uri("/dav/bucket/object").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "hello");
+
+        let req = TestRequest::put()
+            .uri("/dav/bucket/new")
+            .set_payload("goodbye")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = TestRequest::get().uri("/dav/bucket/new").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn delete_moves_to_trash() {
+        let server = MockJottacloud::start().await;
+        let app = app_with(&server, AppConfig::mock()).await;
+
+        let res = test::call_service(&app, put(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = TestRequest::delete().uri("/dav/bucket/object").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        assert!(server.trash().contains(&object_folder()));
+        assert!(server.files(&object_folder()).is_empty());
+    }
+}
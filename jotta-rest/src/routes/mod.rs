@@ -1,11 +1,15 @@
 use actix_web::{
-    http::header::{CacheControl, CacheDirective},
-    web::{self, ServiceConfig},
+    http::header::{CacheControl, CacheDirective, ContentType},
+    web::{self, Data, ServiceConfig},
     HttpResponse,
 };
 use serde::Serialize;
 
+use crate::{config::AppConfig, errors::AppError, AppResult};
+
 pub mod bucket;
+pub mod dav;
+pub mod s3;
 
 pub async fn health() -> HttpResponse {
     #[derive(Debug, Serialize)]
@@ -20,7 +24,65 @@ pub async fn health() -> HttpResponse {
         })
 }
 
+/// Request metrics in the Prometheus text format, if enabled.
+pub async fn metrics(config: Data<AppConfig>) -> AppResult<HttpResponse> {
+    let metrics = config.metrics.as_ref().ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .content_type(ContentType::plaintext())
+        .body(metrics.render()))
+}
+
 pub fn config(cfg: &mut ServiceConfig) {
     cfg.service(web::resource("/health").route(web::get().to(health)))
-        .service(web::scope("/b").configure(bucket::config));
+        .service(web::resource("/metrics").route(web::get().to(metrics)))
+        .service(web::scope("/b").configure(bucket::config))
+        .service(web::scope("/dav").configure(dav::config))
+        .service(web::scope("/s3").configure(s3::config));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{
+        http::StatusCode,
+        test::{self, TestRequest},
+        web::Data,
+        App,
+    };
+    use jotta_osd::jotta::metrics::AtomicMetrics;
+
+    use crate::config::AppConfig;
+
+    #[actix_web::test]
+    async fn metrics() {
+        let mut config = AppConfig::mock();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config.clone()))
+                .configure(super::config),
+        )
+        .await;
+        let res = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        config.metrics = Some(Arc::new(AtomicMetrics::new()));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(config))
+                .configure(super::config),
+        )
+        .await;
+        let res = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = test::read_body(res).await;
+        assert!(std::str::from_utf8(&body)
+            .unwrap()
+            .contains("# TYPE jotta_requests_total counter"));
+    }
 }
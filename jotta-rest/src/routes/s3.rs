@@ -0,0 +1,150 @@
+//! A small subset of the S3 API, for clients that only speak S3.
+use std::{collections::BTreeMap, io::Error as IoError};
+
+use actix_web::{
+    http::header,
+    web::{self, Data, Path, Payload, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use futures_util::{io::BufReader, TryStreamExt};
+use jotta_osd::{
+    object::{
+        create_or_replace,
+        meta::{CacheControl, ParseCacheControlError, Patch},
+        upload_range, ObjectStat,
+    },
+    path::{BucketName, ObjectName},
+};
+
+use super::bucket::object::{content_encoding, content_type};
+use crate::{config::AppConfig, errors::AppError, AppContext, AppResult};
+
+/// Prefix of headers containing user metadata.
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+
+/// Collect `x-amz-meta-*` headers into user metadata, keyed by the rest
+/// of the (lowercase) header name.
+fn user_metadata(req: &HttpRequest) -> AppResult<BTreeMap<String, String>> {
+    req.headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            let key = name.as_str().strip_prefix(USER_METADATA_PREFIX)?;
+
+            Some(
+                value
+                    .to_str()
+                    .map(|value| (key.to_owned(), value.to_owned()))
+                    .map_err(|_| AppError::InvalidInput {
+                        message: format!("invalid `{name}` header"),
+                    }),
+            )
+        })
+        .collect()
+}
+
+/// Parse the `Cache-Control` header of an upload.
+fn cache_control(req: &HttpRequest) -> AppResult<Option<CacheControl>> {
+    req.headers()
+        .get(header::CACHE_CONTROL)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|e| e.to_string())
+                .and_then(|s| s.parse().map_err(|e: ParseCacheControlError| e.to_string()))
+                .map_err(|message| AppError::InvalidInput { message })
+        })
+        .transpose()
+}
+
+/// `PutObject`. Existing objects are replaced.
+pub async fn put_object(
+    config: Data<AppConfig>,
+    ctx: Data<AppContext>,
+    path: Path<(BucketName, String)>,
+    payload: Payload,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let (bucket, key) = path.into_inner();
+    let name: ObjectName = key.parse().map_err(jotta_osd::errors::Error::from)?;
+
+    let patch = Patch {
+        content_type: content_type(&req)?,
+        cache_control: cache_control(&req)?,
+        content_encoding: content_encoding(&req)?,
+        user_metadata: Some(user_metadata(&req)?),
+    };
+
+    create_or_replace(&ctx, &bucket, &name, patch, true).await?;
+
+    let reader = BufReader::new(payload.map_err(IoError::other).into_async_read());
+
+    let meta = upload_range(
+        &ctx,
+        &bucket,
+        &name,
+        0,
+        reader,
+        config.connections_per_request.into(),
+        None,
+    )
+    .await?;
+
+    // S3 clients expect the MD5 of the body for single-chunk objects and
+    // skip verification of multipart ETags, which larger objects get
+    let etag = match (meta.etag(), meta.md5) {
+        (Some(etag), _) => etag,
+        (None, Some(md5)) => format!("{md5:x}"),
+        (None, None) => ObjectStat::from(meta).etag,
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, format!("\"{etag}\"")))
+        .finish())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(web::resource("/{bucket}/{key:.+}").route(web::put().to(put_object)));
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::{cache_control, user_metadata};
+    use crate::errors::AppError;
+
+    #[test]
+    fn amz_meta_headers() {
+        let req = TestRequest::put()
+            .insert_header(("x-amz-meta-Author", "alice"))
+            .insert_header(("x-amz-meta-reviewed", "yes"))
+            .insert_header(("x-amz-date", "20220401T120000Z"))
+            .to_http_request();
+
+        let metadata = user_metadata(&req).unwrap();
+
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["author"], "alice");
+        assert_eq!(metadata["reviewed"], "yes");
+    }
+
+    #[test]
+    fn cache_control_header() {
+        assert!(cache_control(&TestRequest::put().to_http_request())
+            .unwrap()
+            .is_none());
+
+        let req = TestRequest::put()
+            .insert_header(("cache-control", "no-store"))
+            .to_http_request();
+        assert_eq!(cache_control(&req).unwrap().unwrap().0, "no-store");
+
+        let req = TestRequest::put()
+            .insert_header(("cache-control", "max age"))
+            .to_http_request();
+        assert!(matches!(
+            cache_control(&req),
+            Err(AppError::InvalidInput { .. })
+        ));
+    }
+}
@@ -4,6 +4,7 @@ use futures::StreamExt;
 use jotta::{auth::LegacyAuth, events, Fs};
 
 #[tokio::main]
+#[allow(clippy::result_large_err)]
 async fn main() -> Result<(), jotta::Error> {
     tracing_subscriber::fmt::init();
 
@@ -28,6 +28,8 @@ pub enum Exception {
     IncompleteUploadOpenApiException,
     /// Range not satisfiable.
     RequestedRangeNotSatisfiedException,
+    /// The account is out of storage space.
+    QuotaExceededException,
 }
 
 /// A JSON error body returned by the JSON API on errors.
@@ -57,6 +59,7 @@ impl JavaErrorMessage {
     ///
     /// let exceptions = &[
     ///     ("no.jotta.backup.errors.NoSuchPathException: Directory /user69420/Jotta/Archive/s3-test", Some(Exception::NoSuchPathException)),
+    ///     ("no.jotta.backup.errors.QuotaExceededException: Quota exceeded", Some(Exception::QuotaExceededException)),
     ///     ("OH NO AN INTERNAL ERROR", None),
     ///     ("ArrayIndexOutOfBoundsException", None),
     /// ];
@@ -1,17 +1,32 @@
 //! Authentication and authorization for Jottacloud itself and whitelabel providers.
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::Arc,
+    time::{Duration as StdDuration, Instant},
+};
 
-use async_rwlock::{RwLock, RwLockWriteGuard};
+use async_rwlock::RwLock;
 use async_trait::async_trait;
 
 use reqwest::Client;
+use tracing::{debug, warn};
+
+use crate::{
+    provider::{Provider, JOTTACLOUD},
+    Error,
+};
 
 use time::{Duration, OffsetDateTime};
 
 mod legacy;
+#[cfg(feature = "mock")]
+mod mock;
 mod oauth2;
 
 pub use legacy::*;
+#[cfg(feature = "mock")]
+pub use mock::*;
 pub use oauth2::*;
 
 /// A [`TokenStore`] manages authentication tokens.
@@ -20,8 +35,23 @@ pub trait TokenStore: Debug + Send + Sync {
     /// Get the cached access token or renew it if it needs to be renewed.
     async fn get_access_token(&self, client: &Client) -> crate::Result<AccessToken>;
 
+    /// Renew the access token now, even if the cached one is still fresh,
+    /// and cache the new one. Long-running programs can call this
+    /// periodically to avoid renewing tokens on the request path.
+    ///
+    /// Defaults to [`TokenStore::get_access_token`], for stores whose
+    /// tokens can't be renewed.
+    async fn refresh_now(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.get_access_token(client).await
+    }
+
     /// Get the name of the currently authenticated user.
     fn username(&self) -> &str;
+
+    /// Get the provider that issued the tokens. Defaults to [`JOTTACLOUD`].
+    fn provider(&self) -> &Provider {
+        &JOTTACLOUD
+    }
 }
 
 #[async_trait]
@@ -30,9 +60,17 @@ impl TokenStore for Box<dyn TokenStore> {
         self.as_ref().get_access_token(client).await
     }
 
+    async fn refresh_now(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.as_ref().refresh_now(client).await
+    }
+
     fn username(&self) -> &str {
         self.as_ref().username()
     }
+
+    fn provider(&self) -> &Provider {
+        self.as_ref().provider()
+    }
 }
 
 /// An access token used to authenticate with all Jottacloud services.
@@ -62,16 +100,52 @@ impl std::fmt::Display for AccessToken {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-struct AccessTokenCache(Arc<RwLock<Option<AccessToken>>>);
+/// Default initial value of the refresh failure cooldown. See
+/// [`LegacyAuth::with_failure_cooldown`] and [`OAuth2::with_failure_cooldown`].
+pub const DEFAULT_FAILURE_COOLDOWN: StdDuration = StdDuration::from_secs(5);
+
+/// The cooldown is doubled after every consecutive failure, but at most
+/// this many times.
+const MAX_COOLDOWN_DOUBLINGS: u32 = 6;
+
+#[derive(Debug, Default)]
+struct CacheState {
+    access_token: Option<AccessToken>,
+    /// Number of consecutive refresh failures and when the last one happened.
+    failures: Option<(u32, Instant)>,
+}
+
+/// Caches access tokens as well as refresh failures, so that a failing
+/// refresh isn't retried by every request.
+#[derive(Debug, Clone)]
+struct AccessTokenCache {
+    state: Arc<RwLock<CacheState>>,
+    failure_cooldown: StdDuration,
+}
+
+impl Default for AccessTokenCache {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
 
 impl AccessTokenCache {
     pub(crate) fn new(access_token: Option<AccessToken>) -> Self {
-        Self(Arc::new(RwLock::new(access_token)))
+        Self {
+            state: Arc::new(RwLock::new(CacheState {
+                access_token,
+                failures: None,
+            })),
+            failure_cooldown: DEFAULT_FAILURE_COOLDOWN,
+        }
+    }
+
+    pub(crate) fn set_failure_cooldown(&mut self, cooldown: StdDuration) {
+        self.failure_cooldown = cooldown;
     }
 
-    pub(crate) async fn get_fresh(&self) -> Option<AccessToken> {
-        match *self.0.read().await {
+    fn fresh(state: &CacheState) -> Option<AccessToken> {
+        match state.access_token {
             Some(ref access_token)
                 if access_token.exp() >= OffsetDateTime::now_utc() + Duration::minutes(5) =>
             {
@@ -81,7 +155,181 @@ impl AccessTokenCache {
         }
     }
 
-    pub(crate) async fn write(&self) -> RwLockWriteGuard<'_, Option<AccessToken>> {
-        self.0.write().await
+    /// How long to wait after `failures` consecutive failures. It doubles
+    /// with every failure.
+    fn cooldown(&self, failures: u32) -> StdDuration {
+        self.failure_cooldown
+            .saturating_mul(1 << failures.saturating_sub(1).min(MAX_COOLDOWN_DOUBLINGS))
+    }
+
+    /// Get a fresh access token, or call `refresh` to get a new one.
+    ///
+    /// If a refresh recently failed with [`Error::TokenRenewalFailed`],
+    /// that error is returned without calling `refresh` until the cooldown
+    /// has passed. Other errors, such as network errors, aren't cached.
+    pub(crate) async fn get_or_refresh<F, Fut>(&self, refresh: F) -> crate::Result<AccessToken>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = crate::Result<AccessToken>>,
+    {
+        if let Some(access_token) = Self::fresh(&*self.state.read().await) {
+            return Ok(access_token);
+        }
+
+        let mut state = self.state.write().await;
+
+        // someone else might have refreshed while we were waiting
+        if let Some(access_token) = Self::fresh(&state) {
+            return Ok(access_token);
+        }
+
+        self.refresh_locked(&mut state, refresh).await
+    }
+
+    /// Call `refresh` to get a new access token, even if the cached one
+    /// is still fresh. Failures are cached like in
+    /// [`AccessTokenCache::get_or_refresh`].
+    pub(crate) async fn force_refresh<F, Fut>(&self, refresh: F) -> crate::Result<AccessToken>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = crate::Result<AccessToken>>,
+    {
+        let mut state = self.state.write().await;
+
+        self.refresh_locked(&mut state, refresh).await
+    }
+
+    async fn refresh_locked<F, Fut>(
+        &self,
+        state: &mut CacheState,
+        refresh: F,
+    ) -> crate::Result<AccessToken>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = crate::Result<AccessToken>>,
+    {
+        if let Some((failures, at)) = state.failures {
+            if at.elapsed() < self.cooldown(failures) {
+                debug!(failures, "token refresh failed recently; not retrying yet");
+                return Err(Error::TokenRenewalFailed);
+            }
+        }
+
+        match refresh().await {
+            Ok(access_token) => {
+                state.access_token = Some(access_token.clone());
+                state.failures = None;
+                Ok(access_token)
+            }
+            Err(Error::TokenRenewalFailed) => {
+                let failures = state.failures.map_or(1, |(n, _)| n.saturating_add(1));
+                warn!(failures, "token refresh failed");
+                state.failures = Some((failures, Instant::now()));
+                Err(Error::TokenRenewalFailed)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration as StdDuration,
+    };
+
+    use time::{Duration, OffsetDateTime};
+
+    use super::{AccessToken, AccessTokenCache};
+    use crate::Error;
+
+    fn token() -> AccessToken {
+        AccessToken::new(
+            "token".into(),
+            OffsetDateTime::now_utc() + Duration::hours(1),
+        )
+    }
+
+    #[tokio::test]
+    async fn refresh_failures_are_cached() {
+        let mut cache = AccessTokenCache::default();
+        cache.set_failure_cooldown(StdDuration::from_millis(50));
+        let calls = AtomicUsize::new(0);
+
+        let fail = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::TokenRenewalFailed)
+        };
+
+        for _ in 0..3 {
+            assert!(matches!(
+                cache.get_or_refresh(fail).await,
+                Err(Error::TokenRenewalFailed)
+            ));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // the cooldown has passed, but the second failure doubles it
+        tokio::time::sleep(StdDuration::from_millis(60)).await;
+        assert!(cache.get_or_refresh(fail).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        tokio::time::sleep(StdDuration::from_millis(60)).await;
+        assert!(cache.get_or_refresh(fail).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // a successful refresh clears the failures
+        tokio::time::sleep(StdDuration::from_millis(60)).await;
+        assert!(cache.get_or_refresh(|| async { Ok(token()) }).await.is_ok());
+        assert!(cache.state.read().await.failures.is_none());
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_cached() {
+        let cache = AccessTokenCache::default();
+        let calls = AtomicUsize::new(0);
+
+        for _ in 0..3 {
+            let res = cache
+                .get_or_refresh(|| async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(Error::IncompleteUpload)
+                })
+                .await;
+            assert!(res.is_err());
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn fresh_tokens_are_reused() {
+        let cache = AccessTokenCache::new(Some(token()));
+
+        let access_token = cache
+            .get_or_refresh(|| async { unreachable!() })
+            .await
+            .unwrap();
+
+        assert_eq!(access_token.to_string(), "token");
+    }
+
+    #[tokio::test]
+    async fn forced_refresh() {
+        let cache = AccessTokenCache::new(Some(token()));
+
+        let renewed = AccessToken::new(
+            "renewed".into(),
+            OffsetDateTime::now_utc() + Duration::hours(1),
+        );
+
+        let access_token = cache.force_refresh(|| async { Ok(renewed) }).await.unwrap();
+        assert_eq!(access_token.to_string(), "renewed");
+
+        let access_token = cache
+            .get_or_refresh(|| async { unreachable!() })
+            .await
+            .unwrap();
+        assert_eq!(access_token.to_string(), "renewed");
     }
 }
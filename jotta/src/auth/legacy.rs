@@ -6,7 +6,10 @@ use time::{Duration, OffsetDateTime};
 use tracing::{debug, instrument};
 use uuid::Uuid;
 
-use crate::Error;
+use crate::{
+    provider::{Provider, JOTTACLOUD},
+    Error,
+};
 
 use super::{AccessToken, AccessTokenCache, TokenStore};
 
@@ -20,6 +23,7 @@ pub struct LegacyAuth {
     client_id: String,
     client_secret: String,
     username: String,
+    provider: Provider,
 }
 
 #[derive(Debug, Serialize, Clone, Copy)]
@@ -71,10 +75,11 @@ impl LegacyAuth {
     #[instrument(skip_all)]
     async fn register_device(
         client: &Client,
+        provider: &Provider,
         device_id: impl Serialize,
     ) -> crate::Result<DeviceRegistration> {
         let res = client
-            .post("https://api.jottacloud.com/auth/v1/register")
+            .post(format!("{}register", provider.legacy_auth_base))
             .bearer_auth("c2xrZmpoYWRsZmFramhkc2xma2phaHNkbGZramhhc2xkZmtqaGFzZGxrZmpobGtq")
             .form(&[("device_id", device_id)])
             .send()
@@ -83,9 +88,13 @@ impl LegacyAuth {
         res.json().await.map_err(Into::into)
     }
 
-    async fn manage_token(client: &Client, req: &TokenRequest<'_>) -> crate::Result<TokenResponse> {
+    async fn manage_token(
+        client: &Client,
+        provider: &Provider,
+        req: &TokenRequest<'_>,
+    ) -> crate::Result<TokenResponse> {
         let resp = client
-            .post("https://api.jottacloud.com/auth/v1/token")
+            .post(format!("{}token", provider.legacy_auth_base))
             .form(req)
             .send()
             .await?;
@@ -102,8 +111,22 @@ impl LegacyAuth {
     /// # Errors
     ///
     /// - incorrect username and/or password
-    #[instrument(skip(password))]
     pub async fn init(username: impl Into<String> + Debug, password: &str) -> crate::Result<Self> {
+        Self::init_with_provider(JOTTACLOUD.clone(), username, password).await
+    }
+
+    /// Login with username and password at a [`Provider`] other than
+    /// vanilla Jottacloud.
+    ///
+    /// # Errors
+    ///
+    /// - incorrect username and/or password
+    #[instrument(skip(provider, password), fields(provider = %provider.name))]
+    pub async fn init_with_provider(
+        provider: Provider,
+        username: impl Into<String> + Debug,
+        password: &str,
+    ) -> crate::Result<Self> {
         let client = Client::new();
 
         let username = username.into();
@@ -113,10 +136,11 @@ impl LegacyAuth {
         let DeviceRegistration {
             client_id,
             client_secret,
-        } = Self::register_device(&client, Uuid::new_v4()).await?;
+        } = Self::register_device(&client, &provider, Uuid::new_v4()).await?;
 
         let resp = Self::manage_token(
             &client,
+            &provider,
             &TokenRequest {
                 grant_type: GrantType::Password,
                 password: Some(password),
@@ -136,21 +160,25 @@ impl LegacyAuth {
             client_id,
             client_secret,
             username,
+            provider,
         })
     }
-}
 
-#[async_trait]
-impl TokenStore for LegacyAuth {
-    #[instrument(skip_all)]
-    async fn get_access_token(&self, client: &Client) -> crate::Result<AccessToken> {
-        if let Some(access_token) = self.access_token.get_fresh().await {
-            return Ok(access_token);
-        }
+    /// Wait at least `cooldown` before retrying a failed token refresh,
+    /// instead of retrying on every request. The cooldown doubles with
+    /// every consecutive failure and is reset by a successful refresh.
+    /// Defaults to [`DEFAULT_FAILURE_COOLDOWN`](super::DEFAULT_FAILURE_COOLDOWN).
+    #[must_use]
+    pub fn with_failure_cooldown(mut self, cooldown: std::time::Duration) -> Self {
+        self.access_token.set_failure_cooldown(cooldown);
+        self
+    }
 
-        let mut w = self.access_token.write().await;
+    /// Exchange the refresh token for a new access token.
+    async fn renew(&self, client: &Client) -> crate::Result<AccessToken> {
         let res = Self::manage_token(
             client,
+            &self.provider,
             &TokenRequest {
                 grant_type: GrantType::RefreshToken,
                 password: None,
@@ -162,12 +190,57 @@ impl TokenStore for LegacyAuth {
         )
         .await?;
 
-        let access_token = res.to_access_token();
-        *w = Some(access_token.clone());
-        Ok(access_token)
+        Ok(res.to_access_token())
+    }
+}
+
+#[async_trait]
+impl TokenStore for LegacyAuth {
+    #[instrument(skip_all)]
+    async fn get_access_token(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.access_token
+            .get_or_refresh(|| self.renew(client))
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn refresh_now(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.access_token.force_refresh(|| self.renew(client)).await
     }
 
     fn username(&self) -> &str {
         &self.username
     }
+
+    fn provider(&self) -> &Provider {
+        &self.provider
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use reqwest::Client;
+
+    use super::LegacyAuth;
+    use crate::{
+        auth::{MockTokenStore, TokenStore, MOCK_ACCESS_TOKEN},
+        mock::{MockJottacloud, MOCK_USERNAME},
+    };
+
+    #[tokio::test]
+    async fn provider_endpoints() {
+        let server = MockJottacloud::start().await;
+        let provider = MockTokenStore::new(MOCK_USERNAME)
+            .with_base_url(&server.base_url())
+            .provider()
+            .clone();
+
+        let auth = LegacyAuth::init_with_provider(provider.clone(), MOCK_USERNAME, "password")
+            .await
+            .unwrap();
+        assert_eq!(auth.provider(), &provider);
+
+        let token = auth.refresh_now(&Client::new()).await.unwrap();
+        assert_eq!(token.to_string(), MOCK_ACCESS_TOKEN);
+    }
 }
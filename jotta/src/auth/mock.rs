@@ -0,0 +1,126 @@
+//! A fake token store for testing without Jottacloud credentials.
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use time::{Duration, OffsetDateTime};
+
+use crate::provider::Provider;
+
+use super::{AccessToken, TokenStore};
+
+/// Access token handed out by [`MockTokenStore`].
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+
+/// A token store that never talks to any server and always returns
+/// [`MOCK_ACCESS_TOKEN`].
+///
+/// Combine it with [`MockTokenStore::with_base_url`] to point an
+/// [`Fs`](crate::Fs) at a local fake server, such as
+/// [`MockJottacloud`](crate::mock::MockJottacloud):
+///
+/// ```
+/// use jotta::{auth::MockTokenStore, Fs};
+///
+/// let fs = Fs::new(MockTokenStore::new("alice").with_base_url("http://127.0.0.1:1234"));
+/// ```
+#[derive(Debug, Clone)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MockTokenStore {
+    username: String,
+    provider: Provider,
+}
+
+impl MockTokenStore {
+    /// Create a mock token store for a user. Requests are sent to
+    /// Jottacloud unless a base url is set.
+    pub fn new(username: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            provider: Provider::default(),
+        }
+    }
+
+    /// Send all requests to `base_url` instead. The JFS is expected at
+    /// `{base_url}/jfs/`, the files API at `{base_url}/files/v1/` and the
+    /// legacy authentication API at `{base_url}/auth/v1/`.
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        self.provider = Provider {
+            name: Cow::Borrowed("Mock"),
+            token_url: Cow::Owned(format!("{base_url}/token")),
+            client_id: Cow::Borrowed("mock"),
+            jfs_base: Cow::Owned(format!("{base_url}/jfs/")),
+            files_base: Cow::Owned(format!("{base_url}/files/v1/")),
+            legacy_auth_base: Cow::Owned(format!("{base_url}/auth/v1/")),
+        };
+
+        self
+    }
+}
+
+#[async_trait]
+impl TokenStore for MockTokenStore {
+    async fn get_access_token(&self, _client: &Client) -> crate::Result<AccessToken> {
+        Ok(AccessToken::new(
+            MOCK_ACCESS_TOKEN.into(),
+            OffsetDateTime::now_utc() + Duration::days(1),
+        ))
+    }
+
+    fn username(&self) -> &str {
+        &self.username
+    }
+
+    fn provider(&self) -> &Provider {
+        &self.provider
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{MockTokenStore, MOCK_ACCESS_TOKEN};
+    use crate::{path::UserScopedPath, Error, Fs};
+
+    #[tokio::test]
+    async fn local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut req = vec![0; 4096];
+            let n = socket.read(&mut req).await.unwrap();
+
+            let body = "<error><code>404</code>\
+                <message>no.jotta.backup.errors.NoSuchPathException: Directory /alice/Jotta/Archive/nope</message>\
+                <reason>Not Found</reason><cause></cause><hostname></hostname><x-id></x-id></error>";
+
+            let res = format!(
+                "HTTP/1.1 404 Not Found\r\ncontent-type: application/xml\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+
+            socket.write_all(res.as_bytes()).await.unwrap();
+
+            String::from_utf8_lossy(&req[..n]).into_owned()
+        });
+
+        let fs = Fs::new(MockTokenStore::new("alice").with_base_url(&base_url));
+        let res = fs.index(&UserScopedPath("Jotta/Archive/nope".into())).await;
+
+        assert!(matches!(res, Err(Error::NoSuchFileOrFolder)));
+
+        let req = server.await.unwrap();
+
+        assert!(req.starts_with("GET /jfs/alice/Jotta/Archive/nope "));
+        assert!(req.contains(&format!("authorization: Bearer {MOCK_ACCESS_TOKEN}")));
+    }
+}
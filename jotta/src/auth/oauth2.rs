@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 use tracing::instrument;
 
+use crate::provider::Provider;
+
 use super::{AccessToken, AccessTokenCache, TokenStore};
 
 /// Tele2 Cloud (formerly ComHem Cloud) OAuth2 token url.
@@ -19,7 +21,7 @@ pub struct OAuth2 {
     access_token: AccessTokenCache,
     refresh_token: String,
     username: String,
-    token_url: &'static str,
+    provider: Provider,
 }
 
 fn extract_username(refresh_token: &str) -> Option<String> {
@@ -35,37 +37,42 @@ fn extract_username(refresh_token: &str) -> Option<String> {
         jsonwebtoken::decode::<Payload>(refresh_token, &DecodingKey::from_secret(&[]), &validation)
             .ok()?;
 
-    jwt.claims.sub.split(':').last().map(Into::into)
+    jwt.claims.sub.split(':').next_back().map(Into::into)
 }
 
 impl OAuth2 {
-    /// Initialize an OAuth2 client.
+    /// Initialize an OAuth2 client for a provider, e.g. [`TELE2`](crate::provider::TELE2).
     ///
     /// # Errors
     ///
     /// If the username cannot be extracted from the refresh token, this function will
     /// return an error.
-    pub fn init(token_url: &'static str, refresh_token: impl Into<String>) -> crate::Result<Self> {
+    pub fn init(provider: Provider, refresh_token: impl Into<String>) -> crate::Result<Self> {
         let refresh_token = refresh_token.into();
 
         Ok(Self {
             access_token: AccessTokenCache::default(),
             username: extract_username(&refresh_token).ok_or(crate::Error::TokenRenewalFailed)?,
             refresh_token,
-            token_url,
+            provider,
         })
     }
-}
 
-#[async_trait]
-impl TokenStore for OAuth2 {
-    #[instrument(skip_all)]
-    async fn get_access_token(&self, client: &Client) -> crate::Result<AccessToken> {
+    /// Wait at least `cooldown` before retrying a failed token refresh.
+    /// See [`LegacyAuth::with_failure_cooldown`](super::LegacyAuth::with_failure_cooldown).
+    #[must_use]
+    pub fn with_failure_cooldown(mut self, cooldown: std::time::Duration) -> Self {
+        self.access_token.set_failure_cooldown(cooldown);
+        self
+    }
+
+    /// Exchange the refresh token for a new access token.
+    async fn renew(&self, client: &Client) -> crate::Result<AccessToken> {
         #[derive(Serialize)]
         struct Params<'a> {
             grant_type: &'static str,
             refresh_token: &'a str,
-            client_id: &'static str,
+            client_id: &'a str,
         }
 
         #[derive(Deserialize)]
@@ -74,34 +81,48 @@ impl TokenStore for OAuth2 {
             expires_in: i64,
         }
 
-        if let Some(access_token) = self.access_token.get_fresh().await {
-            return Ok(access_token);
-        }
-
-        let mut w = self.access_token.write().await;
-
-        let res: Response = client
-            .post(self.token_url)
+        let res = client
+            .post(self.provider.token_url.as_ref())
             .form(&Params {
                 grant_type: "refresh_token",
                 refresh_token: &self.refresh_token,
-                client_id: "desktop",
+                client_id: &self.provider.client_id,
             })
             .send()
-            .await?
-            .json()
             .await?;
 
-        let access_token = AccessToken::new(
+        if !res.status().is_success() {
+            return Err(crate::Error::TokenRenewalFailed);
+        }
+
+        let res: Response = res.json().await?;
+
+        Ok(AccessToken::new(
             res.access_token,
             OffsetDateTime::now_utc() + Duration::seconds(res.expires_in),
-        );
+        ))
+    }
+}
+
+#[async_trait]
+impl TokenStore for OAuth2 {
+    #[instrument(skip_all)]
+    async fn get_access_token(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.access_token
+            .get_or_refresh(|| self.renew(client))
+            .await
+    }
 
-        *w = Some(access_token.clone());
-        Ok(access_token)
+    #[instrument(skip_all)]
+    async fn refresh_now(&self, client: &Client) -> crate::Result<AccessToken> {
+        self.access_token.force_refresh(|| self.renew(client)).await
     }
 
     fn username(&self) -> &str {
         &self.username
     }
+
+    fn provider(&self) -> &Provider {
+        &self.provider
+    }
 }
@@ -0,0 +1,170 @@
+//! A synchronous wrapper around [`Fs`] for consumers without an async
+//! runtime, such as scripts and command line tools.
+//!
+//! Every call blocks the current thread until it's done. The calls run on
+//! a current-thread Tokio runtime owned by the wrapper, so they must not
+//! be made from within another async runtime, and the wrapper must not be
+//! dropped there either. Both panic.
+//!
+//! Requires the `blocking` feature.
+use std::future::Future;
+
+use bytes::Bytes;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    auth::TokenStore,
+    files::ConflictHandler,
+    jfs::FolderDetail,
+    path::{PathOnDevice, UserScopedPath},
+    range::OpenByteRange,
+    Fs,
+};
+
+/// A blocking [`Fs`]. Each method blocks the current thread.
+#[derive(Debug)]
+pub struct BlockingFs<S: TokenStore> {
+    fs: Fs<S>,
+    rt: Runtime,
+}
+
+impl<S: TokenStore> BlockingFs<S> {
+    /// Wrap a filesystem.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime fails to initialize.
+    #[must_use]
+    pub fn new(fs: Fs<S>) -> Self {
+        let rt = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build runtime");
+
+        Self { fs, rt }
+    }
+
+    /// The wrapped filesystem.
+    #[must_use]
+    pub fn fs(&self) -> &Fs<S> {
+        &self.fs
+    }
+
+    /// Run any future on the runtime of this wrapper, for the operations
+    /// that have no blocking counterpart.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.rt.block_on(future)
+    }
+
+    /// Upload a whole file, replacing it with a new revision if it
+    /// already exists and `conflict_handler` allows it. See
+    /// [`Fs::upload_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::upload_bytes`].
+    pub fn upload(
+        &self,
+        path: &PathOnDevice,
+        body: Bytes,
+        conflict_handler: ConflictHandler,
+    ) -> crate::Result<()> {
+        self.block_on(self.fs.upload_bytes(path, body, conflict_handler))
+            .map(drop)
+    }
+
+    /// Download a whole file. See [`Fs::file_to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::file_to_bytes`].
+    pub fn download(&self, path: &UserScopedPath) -> crate::Result<Bytes> {
+        self.block_on(self.fs.file_to_bytes(path, OpenByteRange::full()))
+    }
+
+    /// List a folder. See [`Fs::index`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::index`].
+    pub fn list(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
+        self.block_on(self.fs.index(path))
+    }
+
+    /// Create a folder. See [`Fs::create_folder`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::create_folder`].
+    pub fn create(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
+        self.block_on(self.fs.create_folder(path))
+    }
+
+    /// Remove a file. See [`Fs::remove_file`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::remove_file`].
+    pub fn delete(&self, path: &UserScopedPath) -> crate::Result<()> {
+        self.block_on(self.fs.remove_file(path))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{net::SocketAddr, thread};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{auth::MockTokenStore, path::UserScopedPath, Fs};
+
+    use super::BlockingFs;
+
+    /// Serve a file with the contents `hello` to every request, on a
+    /// runtime of its own.
+    fn serve_hello() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            rt.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+
+                loop {
+                    let (mut socket, _) = listener.accept().await.unwrap();
+                    let mut req = vec![0; 4096];
+                    let _ = socket.read(&mut req).await.unwrap();
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                        .await
+                        .unwrap();
+                }
+            });
+        });
+
+        addr
+    }
+
+    #[test]
+    fn download() {
+        let addr = serve_hello();
+        let fs = BlockingFs::new(Fs::new(
+            MockTokenStore::new("alice").with_base_url(&format!("http://{addr}")),
+        ));
+
+        let body = fs
+            .download(&UserScopedPath("Jotta/Archive/hello.txt".into()))
+            .unwrap();
+
+        assert_eq!(&body[..], b"hello");
+    }
+}
@@ -8,7 +8,17 @@ use crate::api::{Exception, JsonErrorBody, MaybeUnknown, XmlErrorBody};
 pub enum Error {
     /// HTTP error.
     #[error("{0}")]
-    Http(#[from] reqwest::Error),
+    Http(reqwest::Error),
+
+    /// The request timed out, either while connecting or while waiting
+    /// for a response.
+    #[error("request timed out: {0}")]
+    Timeout(reqwest::Error),
+
+    /// No connection could be made, for example because it was refused
+    /// or the host couldn't be resolved.
+    #[error("connection failed: {0}")]
+    Connect(reqwest::Error),
 
     /// Url error.
     #[error("invalid url")]
@@ -39,6 +49,16 @@ pub enum Error {
     #[error("incomplete upload; maybe too short body?")]
     IncompleteUpload,
 
+    /// A download ended before all bytes were received (or more bytes
+    /// than expected were received).
+    #[error("incomplete download; expected {expected} bytes but received {received}")]
+    IncompleteDownload {
+        /// Number of bytes that should have been received.
+        expected: u64,
+        /// Number of bytes actually received.
+        received: u64,
+    },
+
     /// Invalid argument.
     #[error("invalid argument")]
     InvalidArgument,
@@ -55,6 +75,10 @@ pub enum Error {
     #[error("range not satisfiable")]
     RangeNotSatisfiable,
 
+    /// The account is out of storage space.
+    #[error("quota exceeded")]
+    QuotaExceeded,
+
     /// Events error.
     #[error("{0}")]
     EventError(#[from] crate::events::Error),
@@ -69,10 +93,65 @@ pub enum ApiResError {
     Xml(XmlErrorBody),
 }
 
+/// HTTP status code used by Jottacloud when the account is full.
+const INSUFFICIENT_STORAGE: u16 = 507;
+
+/// HTTP status code used by Jottacloud when a file already exists. The
+/// error id is usually `UniqueFileException`, but not always.
+const CONFLICT: u16 = 409;
+
+impl Error {
+    /// Whether the request may succeed if it's simply sent again, such
+    /// as after a timeout, a dropped connection or a server error.
+    /// Errors caused by the request itself, like a missing file or an
+    /// exceeded quota, are never retryable.
+    ///
+    /// ```
+    /// use jotta::Error;
+    ///
+    /// assert!(!Error::NoSuchFileOrFolder.is_retryable());
+    /// assert!(!Error::QuotaExceeded.is_retryable());
+    /// ```
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::Connect(_) => true,
+            Self::Http(err) => {
+                err.is_request()
+                    || err
+                        .status()
+                        .is_some_and(|s| is_retryable_status(s.as_u16()))
+            }
+            Self::Jotta(ApiResError::Json(body)) => body.code.is_some_and(is_retryable_status),
+            Self::Jotta(ApiResError::Xml(body)) => is_retryable_status(body.code),
+            _ => false,
+        }
+    }
+}
+
+/// Too many requests, or a server error other than a full account.
+fn is_retryable_status(code: u16) -> bool {
+    code == 429 || ((500..600).contains(&code) && code != INSUFFICIENT_STORAGE)
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout(err)
+        } else if err.is_connect() {
+            Self::Connect(err)
+        } else {
+            Self::Http(err)
+        }
+    }
+}
+
 impl From<JsonErrorBody> for Error {
     fn from(err: JsonErrorBody) -> Self {
         match err.error_id {
             Some(MaybeUnknown::Known(exception)) => Error::from(exception),
+            _ if err.code == Some(INSUFFICIENT_STORAGE) => Self::QuotaExceeded,
+            _ if err.code == Some(CONFLICT) => Self::AlreadyExists,
             _ => Self::Jotta(ApiResError::Json(err)),
         }
     }
@@ -82,6 +161,10 @@ impl From<XmlErrorBody> for Error {
     fn from(err: XmlErrorBody) -> Self {
         if let Some(exception) = err.exception_opt() {
             Error::from(exception)
+        } else if err.code == INSUFFICIENT_STORAGE {
+            Self::QuotaExceeded
+        } else if err.code == CONFLICT {
+            Self::AlreadyExists
         } else {
             Self::Jotta(ApiResError::Xml(err))
         }
@@ -100,6 +183,116 @@ impl From<Exception> for Error {
             Exception::InvalidArgumentException => Error::InvalidArgument,
             Exception::IncompleteUploadOpenApiException => Error::IncompleteUpload,
             Exception::RequestedRangeNotSatisfiedException => Error::RangeNotSatisfiable,
+            Exception::QuotaExceededException => Error::QuotaExceeded,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use super::Error;
+    use crate::api::{JsonErrorBody, XmlErrorBody};
+
+    #[test]
+    fn quota_exceeded() {
+        let body: JsonErrorBody = serde_json::from_str(
+            r#"{"code":507,"message":"Insufficient storage","cause":null,"error_id":null,"x-id":null}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(Error::from(body), Error::QuotaExceeded));
+
+        let body: JsonErrorBody = serde_json::from_str(
+            r#"{"code":400,"message":null,"cause":null,"error_id":"QuotaExceededException","x-id":null}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(Error::from(body), Error::QuotaExceeded));
+    }
+
+    #[test]
+    fn conflict() {
+        for json in [
+            r#"{"code":409,"message":null,"cause":null,"error_id":"UniqueFileException","x-id":null}"#,
+            r#"{"code":409,"message":"Conflict","cause":null,"error_id":null,"x-id":null}"#,
+        ] {
+            let body: JsonErrorBody = serde_json::from_str(json).unwrap();
+
+            assert!(matches!(Error::from(body), Error::AlreadyExists), "{json}");
+        }
+
+        let body: XmlErrorBody = serde_xml_rs::from_str(
+            "<error><code>409</code><message>Conflict</message><reason>Conflict</reason>\
+            <cause></cause><hostname></hostname><x-id></x-id></error>",
+        )
+        .unwrap();
+
+        assert!(matches!(Error::from(body), Error::AlreadyExists));
+    }
+
+    #[test]
+    fn retryable() {
+        let json = |code: u16| {
+            let body: JsonErrorBody = serde_json::from_value(serde_json::json!({
+                "code": code,
+                "message": null,
+                "cause": null,
+                "error_id": null,
+                "x-id": null,
+            }))
+            .unwrap();
+
+            Error::from(body)
+        };
+
+        assert!(json(500).is_retryable());
+        assert!(json(503).is_retryable());
+        assert!(json(429).is_retryable());
+        assert!(!json(400).is_retryable());
+        assert!(!json(404).is_retryable());
+        assert!(!json(507).is_retryable());
+        assert!(!Error::IncompleteUpload.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn timeout_error() {
+        // accepts connections but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let err = reqwest::Client::new()
+            .get(format!("http://{addr}"))
+            .timeout(Duration::from_millis(50))
+            .send()
+            .await
+            .unwrap_err();
+
+        let err = Error::from(err);
+        assert!(matches!(err, Error::Timeout(_)));
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn connect_error() {
+        // nothing listens on the port once the listener is dropped
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let err = reqwest::Client::new()
+            .get(format!("http://{addr}"))
+            .send()
+            .await
+            .unwrap_err();
+
+        let err = Error::from(err);
+        assert!(matches!(err, Error::Connect(_)));
+        assert!(err.is_retryable());
+    }
+}
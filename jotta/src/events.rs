@@ -341,7 +341,7 @@ mod tests {
                     Uuid::parse_str("40660078-abab-11ec-881d-90e2bae6bf68").unwrap()
                 );
             }
-            _ => panic!("wrong type"),
+            ServerMessage::Event { .. } => panic!("wrong type"),
         }
     }
 }
@@ -1,7 +1,8 @@
 //! Utilities for the API at `api.jottacloud.com/files/v1`.
-use std::ops::RangeInclusive;
+use std::{fmt::Display, ops::RangeInclusive};
 
 use md5::Digest;
+use reqwest::header::HeaderMap;
 
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -94,6 +95,27 @@ pub struct CompleteUploadRes {
     /// Modification date.
     #[serde_as(as = "crate::serde::UnixMillis")]
     pub modified: OffsetDateTime,
+
+    /// Storage pool that received the upload, if reported.
+    #[serde(skip)]
+    pub pool: Option<Pool>,
+}
+
+impl CompleteUploadRes {
+    /// Check that Jottacloud received what was uploaded, rather than
+    /// trusting it blindly.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::CorruptUpload`](crate::Error::CorruptUpload) if
+    /// the reported size or MD5 sum differs from `bytes` and `md5`.
+    pub fn verify(&self, bytes: u64, md5: Digest) -> crate::Result<()> {
+        if self.bytes == bytes && self.md5 == md5 {
+            Ok(())
+        } else {
+            Err(crate::Error::CorruptUpload)
+        }
+    }
 }
 
 /// Pretty-print of the Jottacloud exception returned when performing a
@@ -102,6 +124,9 @@ pub struct CompleteUploadRes {
 pub struct IncompleteUploadRes {
     /// Range of the bytes uploaded now -- NOT the total bytes uploaded (for all chunks).
     pub range: RangeInclusive<u64>,
+
+    /// Storage pool that received the upload, if reported.
+    pub pool: Option<Pool>,
 }
 
 /// Upload response.
@@ -112,3 +137,47 @@ pub enum UploadRes {
     /// Incomplete upload.
     Incomplete(IncompleteUploadRes),
 }
+
+impl UploadRes {
+    /// Storage pool that received the upload, if reported.
+    #[must_use]
+    pub fn pool(&self) -> Option<&Pool> {
+        match self {
+            UploadRes::Complete(res) => res.pool.as_ref(),
+            UploadRes::Incomplete(res) => res.pool.as_ref(),
+        }
+    }
+}
+
+/// Identifier of the storage pool (or node) that handled an upload,
+/// returned by Jottacloud in the `pool` response header. It can't be
+/// used to route uploads, but it's useful when debugging throughput.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pool(pub String);
+
+impl Pool {
+    /// Read the pool from response headers.
+    ///
+    /// ```
+    /// use jotta::files::Pool;
+    /// use reqwest::header::{HeaderMap, HeaderValue};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(Pool::from_headers(&headers), None);
+    ///
+    /// headers.insert("pool", HeaderValue::from_static("upload-7"));
+    /// assert_eq!(Pool::from_headers(&headers), Some(Pool("upload-7".into())));
+    /// ```
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let pool = headers.get("pool")?.to_str().ok()?.trim();
+
+        (!pool.is_empty()).then(|| Self(pool.to_owned()))
+    }
+}
+
+impl Display for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -1,24 +1,36 @@
 //! A higher-level but still pretty low-level Jottacloud client with
 //! basic filesystem capabilities.
-use std::{fmt::Debug, ops::RangeInclusive};
+use std::{
+    fmt::Debug,
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
-use futures::{Stream, TryStreamExt};
-
-use once_cell::sync::Lazy;
+use futures::{stream, Stream, TryStreamExt};
 
 use reqwest::{
     header::{self},
-    Body, Client, IntoUrl, Method, RequestBuilder, Response, Url,
+    Body, Client, IntoUrl, Method, Request, RequestBuilder, Response, StatusCode, Url,
 };
-use tracing::{debug, instrument};
+use time::OffsetDateTime;
+use tracing::{debug, instrument, trace, warn};
+use uuid::Uuid;
 
 use crate::{
     api::{read_json, read_xml, Exception, MaybeUnknown, XmlErrorBody},
     auth::TokenStore,
-    files::{AllocReq, AllocRes, CompleteUploadRes, IncompleteUploadRes, UploadRes},
-    jfs::{FileDetail, FolderDetail},
-    path::UserScopedPath,
+    files::{
+        AllocReq, AllocRes, CompleteUploadRes, ConflictHandler, IncompleteUploadRes, Pool,
+        UploadRes,
+    },
+    jfs::{
+        AccountInfo, Device, DeviceDetail, FileDetail, FolderDetail, MountPoint, PathKind,
+        RevisionState,
+    },
+    metrics::{Metrics, Operation},
+    path::{push_path, PathOnDevice, UserScopedPath},
     range::{ByteRange, OpenByteRange},
 };
 
@@ -31,40 +43,278 @@ pub static USER_AGENT: &str = concat!(
     env!("CARGO_PKG_REPOSITORY")
 );
 
+/// How many times in a row an upload may be resumed without Jottacloud
+/// receiving any more bytes before giving up.
+pub const MAX_STALLED_RESUMES: usize = 3;
+
+/// Header containing a random id of each request, which can be used
+/// to correlate requests with logs when reporting issues upstream.
+pub static REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Timeouts and other options for the HTTP client used by [`Fs`].
+#[derive(Debug, Clone)]
+pub struct FsOptions {
+    /// Timeout for establishing a connection.
+    pub connect_timeout: Option<Duration>,
+    /// Timeout for regular (non-transfer) requests, from start to finish.
+    pub request_timeout: Option<Duration>,
+    /// Timeout for requests that stream file data, i.e. uploads and
+    /// downloads. Large transfers can take a long time, so this is
+    /// disabled by default.
+    pub transfer_timeout: Option<Duration>,
+    /// How long idle connections are kept in the pool.
+    pub pool_idle_timeout: Option<Duration>,
+    /// Appended to [`USER_AGENT`] to identify the application.
+    pub user_agent_suffix: Option<String>,
+    /// How [`Fs::allocate`] is retried after errors that are likely
+    /// transient (see [`Error::is_retryable`](crate::Error::is_retryable)).
+    pub allocate_retry: RetryPolicy,
+}
+
+impl FsOptions {
+    /// The `User-Agent` to send.
+    ///
+    /// ```
+    /// use jotta::{FsOptions, USER_AGENT};
+    ///
+    /// let mut options = FsOptions::default();
+    /// assert_eq!(options.user_agent(), USER_AGENT);
+    ///
+    /// options.user_agent_suffix = Some("my-app/1.0".into());
+    /// assert_eq!(options.user_agent(), format!("{USER_AGENT} my-app/1.0"));
+    /// ```
+    #[must_use]
+    pub fn user_agent(&self) -> String {
+        match self.user_agent_suffix {
+            Some(ref suffix) => format!("{USER_AGENT} {suffix}"),
+            None => USER_AGENT.into(),
+        }
+    }
+}
+
+impl Default for FsOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_secs(30)),
+            transfer_timeout: None,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            user_agent_suffix: None,
+            allocate_retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// How many times, and how patiently, a failed request is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. The wait is doubled for
+    /// every subsequent retry.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    /// How long to wait before retry number `retry`, counting from zero.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use jotta::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy { max_retries: 3, backoff: Duration::from_millis(100) };
+    ///
+    /// assert_eq!(policy.backoff(0), Duration::from_millis(100));
+    /// assert_eq!(policy.backoff(2), Duration::from_millis(400));
+    /// ```
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2_u32.saturating_pow(retry))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(250),
+        }
+    }
+}
+
 /// A Jottacloud "filesystem".
 pub struct Fs<S> {
     client: Client,
     token_store: S,
+    options: FsOptions,
+    jfs_base: Url,
+    files_base: Url,
+    metrics: Option<Arc<dyn Metrics>>,
 }
 
 impl<S: TokenStore> Fs<S> {
-    /// Create a new filesystem.
+    /// Create a new filesystem with the default [`FsOptions`].
     ///
     /// # Panics
     ///
     /// Panics if the HTTP client fails to initialize.
     #[must_use]
     pub fn new(token_store: S) -> Self {
+        Self::with_options(token_store, FsOptions::default())
+    }
+
+    /// Create a new filesystem with custom options. The base urls are
+    /// taken from the [`Provider`](crate::provider::Provider) of the token store.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client fails to initialize or if the provider's
+    /// base urls are invalid.
+    #[must_use]
+    pub fn with_options(token_store: S, options: FsOptions) -> Self {
+        let provider = token_store.provider();
+        let jfs_base = Url::parse(&provider.jfs_base).expect("invalid jfs base url");
+        let files_base = Url::parse(&provider.files_base).expect("invalid files base url");
+
+        let mut builder = Client::builder()
+            .user_agent(options.user_agent())
+            .pool_idle_timeout(options.pool_idle_timeout);
+
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
         Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            client: builder.build().unwrap(),
             token_store,
+            options,
+            jfs_base,
+            files_base,
+            metrics: None,
         }
     }
 
+    /// Send requests to other base urls than those of the token store's
+    /// [`Provider`](crate::provider::Provider), such as a proxy or a
+    /// local stub server. Authentication is unaffected.
+    ///
+    /// A trailing slash is added to the urls if they lack one.
+    #[must_use]
+    pub fn with_base_urls(mut self, jfs_base: Url, files_base: Url) -> Self {
+        self.jfs_base = with_trailing_slash(jfs_base);
+        self.files_base = with_trailing_slash(files_base);
+        self
+    }
+
+    /// Record all requests in `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Get the options used by this filesystem.
+    #[must_use]
+    pub fn options(&self) -> &FsOptions {
+        &self.options
+    }
+
     /// Get the username of the currently authenticated user.
     #[must_use]
     pub fn username(&self) -> &str {
         self.token_store.username()
     }
 
+    /// Renew the access token now instead of when it's about to expire.
+    /// See [`TokenStore::refresh_now`].
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - the token couldn't be renewed
+    pub async fn refresh_token(&self) -> crate::Result<()> {
+        self.token_store.refresh_now(&self.client).await?;
+
+        Ok(())
+    }
+
     pub(crate) async fn authed_req(
         &self,
         method: Method,
         url: impl IntoUrl,
     ) -> crate::Result<RequestBuilder> {
         let access_token = self.token_store.get_access_token(&self.client).await?;
+        let request_id = Uuid::new_v4();
+
+        debug!(%request_id, %method, "sending request");
+
+        let mut req = self
+            .client
+            .request(method, url)
+            .bearer_auth(access_token)
+            .header(REQUEST_ID_HEADER, request_id.to_string());
 
-        Ok(self.client.request(method, url).bearer_auth(access_token))
+        if let Some(timeout) = self.options.request_timeout {
+            req = req.timeout(timeout);
+        }
+
+        Ok(req)
+    }
+
+    /// Send a request.
+    async fn send(&self, op: Operation, req: RequestBuilder) -> crate::Result<Response> {
+        self.execute(op, req.build()?).await
+    }
+
+    /// Send a request that transfers file data, replacing the regular
+    /// request timeout with the transfer timeout.
+    async fn send_transfer(&self, op: Operation, req: RequestBuilder) -> crate::Result<Response> {
+        let mut req = req.build()?;
+        *req.timeout_mut() = self.options.transfer_timeout;
+
+        self.execute(op, req).await
+    }
+
+    /// Execute a request, recording it if there is a metrics sink.
+    async fn execute(&self, op: Operation, req: Request) -> crate::Result<Response> {
+        let Some(metrics) = &self.metrics else {
+            return Ok(self.client.execute(req).await?);
+        };
+
+        let sent = content_length(req.headers());
+        let start = Instant::now();
+        let res = self.client.execute(req).await;
+        let duration = start.elapsed();
+
+        match res {
+            Ok(res) => {
+                metrics.record_request(op, Some(res.status()), duration);
+
+                if res.status().is_success() {
+                    match op {
+                        Operation::Upload => metrics.record_bytes(op, sent.unwrap_or(0)),
+                        Operation::Download => {
+                            metrics.record_bytes(op, res.content_length().unwrap_or(0));
+                        }
+                        _ => {}
+                    }
+                }
+
+                Ok(res)
+            }
+            Err(e) => {
+                let e = e.into();
+                metrics.record_request(op, None, duration);
+                metrics.record_error(op, &e);
+                Err(e)
+            }
+        }
     }
 
     async fn jfs_req(
@@ -72,12 +322,9 @@ impl<S: TokenStore> Fs<S> {
         method: Method,
         path: &UserScopedPath,
     ) -> crate::Result<RequestBuilder> {
-        static JFS_BASE: Lazy<Url> =
-            Lazy::new(|| Url::parse("https://jfs.jottacloud.com/jfs/").unwrap());
-
-        let url = JFS_BASE
-            .join(&format!("{}/", self.token_store.username()))?
-            .join(path)?;
+        let mut url = self.jfs_base.clone();
+        push_path(&mut url, self.token_store.username())?;
+        push_path(&mut url, path)?;
 
         self.authed_req(method, url).await
     }
@@ -87,29 +334,46 @@ impl<S: TokenStore> Fs<S> {
         method: Method,
         path: &str,
     ) -> crate::Result<RequestBuilder> {
-        static FILES_V1_BASE: Lazy<Url> =
-            Lazy::new(|| Url::parse("https://api.jottacloud.com/files/v1/").unwrap());
-
-        let url = FILES_V1_BASE.join(path)?;
+        let url = self.files_base.join(path)?;
 
         self.authed_req(method, url).await
     }
 
     /// Allocate for uploading a new file or a new file revision.
     ///
+    /// Allocating is idempotent, so it's retried according to
+    /// [`FsOptions::allocate_retry`] if it fails for reasons that are
+    /// likely transient, such as timeouts and server errors.
+    ///
     /// # Errors
     ///
-    /// - network errors
+    /// - network errors, once the retries are exhausted
     /// - authentication errors (invalid token)
     /// - jottacloud errors
     /// - too little space left? (not verified)
     pub async fn allocate(&self, req: &AllocReq<'_>) -> crate::Result<AllocRes> {
-        let response = self
+        let policy = self.options.allocate_retry;
+        let mut retry = 0;
+
+        loop {
+            match self.allocate_once(req).await {
+                Err(e) if e.is_retryable() && retry < policy.max_retries => {
+                    let backoff = policy.backoff(retry);
+                    warn!(retry, ?backoff, "allocation failed, retrying: {e}");
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    async fn allocate_once(&self, req: &AllocReq<'_>) -> crate::Result<AllocRes> {
+        let req = self
             .files_v1_req_builder(Method::POST, "allocate")
             .await?
-            .json(req)
-            .send()
-            .await?;
+            .json(req);
+        let response = self.send(Operation::Allocate, req).await?;
 
         Ok(read_json(response).await??)
     }
@@ -128,7 +392,7 @@ impl<S: TokenStore> Fs<S> {
         body: impl Into<Body>,
         range: RangeInclusive<u64>,
     ) -> crate::Result<UploadRes> {
-        let res = self
+        let req = self
             .authed_req(Method::POST, upload_url)
             .await?
             .body(body)
@@ -137,21 +401,133 @@ impl<S: TokenStore> Fs<S> {
             .header(
                 header::RANGE,
                 format!("bytes={}-{}", range.start(), range.end()),
-            )
-            .send()
-            .await?;
+            );
+
+        let res = self.send_transfer(Operation::Upload, req).await?;
+        let pool = Pool::from_headers(res.headers());
 
         match read_json::<CompleteUploadRes>(res).await? {
-            Ok(complete) => Ok(UploadRes::Complete(complete)),
+            Ok(complete) => Ok(UploadRes::Complete(CompleteUploadRes { pool, ..complete })),
             Err(err) => match err.error_id {
                 Some(MaybeUnknown::Known(Exception::IncompleteUploadOpenApiException)) => {
-                    Ok(UploadRes::Incomplete(IncompleteUploadRes { range }))
+                    Ok(UploadRes::Incomplete(IncompleteUploadRes { range, pool }))
                 }
                 _ => Err(err.into()),
             },
         }
     }
 
+    /// Upload a whole file in one call: allocate it, upload `body`, and
+    /// resume the upload for as long as Jottacloud reports it as
+    /// incomplete. See [`Fs::upload_bytes_with`] to set timestamps or
+    /// reuse an already computed digest.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::upload_bytes_with`].
+    pub async fn upload_bytes(
+        &self,
+        path: &PathOnDevice,
+        body: Bytes,
+        conflict_handler: ConflictHandler,
+    ) -> crate::Result<CompleteUploadRes> {
+        let req = AllocReq {
+            path,
+            bytes: body.len() as u64,
+            md5: md5::compute(&body),
+            conflict_handler,
+            created: None,
+            modified: None,
+        };
+
+        self.upload_bytes_with(&req, body).await
+    }
+
+    /// Like [`Fs::upload_bytes`], but with a prepared allocation request.
+    /// `req.bytes` and `req.md5` must describe `body`.
+    ///
+    /// If Jottacloud already has the file, nothing is uploaded and the
+    /// response is made up from the allocation. It has no content id
+    /// then.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors
+    /// - [`Error::CorruptUpload`](crate::Error::CorruptUpload) if the
+    ///   size or MD5 sum reported by Jottacloud doesn't match `req`
+    /// - [`Error::IncompleteUpload`](crate::Error::IncompleteUpload) if
+    ///   the upload is resumed more than [`MAX_STALLED_RESUMES`] times in
+    ///   a row without Jottacloud receiving any more bytes
+    #[instrument(skip(self, req, body), fields(path = %req.path, bytes = req.bytes))]
+    pub async fn upload_bytes_with(
+        &self,
+        req: &AllocReq<'_>,
+        body: Bytes,
+    ) -> crate::Result<CompleteUploadRes> {
+        let mut stalled = 0;
+        let mut last_pos = None;
+
+        loop {
+            let alloc = self.allocate(req).await?;
+
+            if alloc.state == RevisionState::Completed || alloc.resume_pos >= req.bytes {
+                trace!("file already exists");
+
+                return Ok(CompleteUploadRes {
+                    md5: req.md5,
+                    bytes: req.bytes,
+                    content_id: String::new(),
+                    path: alloc.path,
+                    modified: req.modified.unwrap_or_else(OffsetDateTime::now_utc),
+                    pool: None,
+                });
+            }
+
+            let resume_pos = alloc.resume_pos;
+
+            if last_pos.is_some_and(|last| resume_pos <= last) {
+                stalled += 1;
+
+                if stalled > MAX_STALLED_RESUMES {
+                    return Err(crate::Error::IncompleteUpload);
+                }
+            } else {
+                stalled = 0;
+            }
+
+            last_pos = Some(resume_pos);
+
+            #[allow(clippy::cast_possible_truncation)] // the body is in memory
+            let rest = body.slice(resume_pos as usize..);
+
+            match self
+                .upload_range(&alloc.upload_url, rest, resume_pos..=req.bytes)
+                .await?
+            {
+                UploadRes::Complete(res) => {
+                    if let Err(e) = res.verify(req.bytes, req.md5) {
+                        warn!(
+                            bytes = res.bytes,
+                            md5 = %format!("{:x}", res.md5),
+                            "upload doesn't match what was sent"
+                        );
+                        return Err(e);
+                    }
+
+                    return Ok(res);
+                }
+                UploadRes::Incomplete(res) => {
+                    debug!(
+                        resume_pos,
+                        pool = res.pool.as_ref().map(|pool| pool.0.as_str()),
+                        "upload incomplete, resuming"
+                    );
+                }
+            }
+        }
+    }
+
     /// List all files and folders at a path. Similar to the UNIX `fs` command.
     ///
     /// # Errors
@@ -160,11 +536,109 @@ impl<S: TokenStore> Fs<S> {
     /// - jottacloud errors (including auth)
     /// - path doesn't exist
     pub async fn index(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
-        let res = self.jfs_req(Method::GET, path).await?.send().await?;
+        let req = self.jfs_req(Method::GET, path).await?;
+        let res = self.send(Operation::Index, req).await?;
 
         read_xml(res).await
     }
 
+    /// List a page of the files and folders at a path, skipping the first
+    /// `first` entries and returning at most `max`. Folders come before
+    /// files. The total number of entries is found in
+    /// [`FolderDetail::metadata`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Fs::index`].
+    pub async fn index_page(
+        &self,
+        path: &UserScopedPath,
+        first: u32,
+        max: u32,
+    ) -> crate::Result<FolderDetail> {
+        let req = self
+            .jfs_req(Method::GET, path)
+            .await?
+            .query(&[("first", first), ("max", max)]);
+        let res = self.send(Operation::Index, req).await?;
+
+        read_xml(res).await
+    }
+
+    /// Get information about the account, such as its storage quota.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    pub async fn account_info(&self) -> crate::Result<AccountInfo> {
+        let req = self
+            .jfs_req(Method::GET, &UserScopedPath(String::new()))
+            .await?;
+        let res = self.send(Operation::Index, req).await?;
+
+        read_xml(res).await
+    }
+
+    /// List the devices of the account, such as the `Jotta` device
+    /// containing the `Archive` mount point.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    pub async fn list_devices(&self) -> crate::Result<Vec<Device>> {
+        let account = self.account_info().await?;
+
+        Ok(account.devices.devices)
+    }
+
+    /// List the mount points of a device. The device name is case-insensitive.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    /// - no device found with that name
+    pub async fn list_mountpoints(&self, device: &str) -> crate::Result<Vec<MountPoint>> {
+        let req = self
+            .jfs_req(Method::GET, &UserScopedPath(device.into()))
+            .await?;
+        let res = self.send(Operation::Index, req).await?;
+
+        let device: DeviceDetail = read_xml(res).await?;
+
+        Ok(device.mount_points.inner)
+    }
+
+    /// Path to a mount point of a device, such as [`SYNC`](crate::path::SYNC),
+    /// after checking that it exists. Pass it to [`Fs::index`] or append
+    /// to it to read files synced by other clients.
+    ///
+    /// Like devices, mount points are matched case-insensitively. The
+    /// path uses the name reported by Jottacloud.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    /// - [`Error::NoSuchFileOrFolder`](crate::Error::NoSuchFileOrFolder)
+    ///   if there is no such device or mount point
+    pub async fn mount_point_path(
+        &self,
+        device: &str,
+        mount_point: &str,
+    ) -> crate::Result<UserScopedPath> {
+        let mount_point = self
+            .list_mountpoints(device)
+            .await?
+            .into_iter()
+            .find(|m| m.name.eq_ignore_ascii_case(mount_point))
+            .ok_or(crate::Error::NoSuchFileOrFolder)?;
+
+        Ok(PathOnDevice::new(&mount_point.name, "").on_device(device))
+    }
+
     /// Get metadata associated with a file.
     ///
     /// # Errors
@@ -173,71 +647,217 @@ impl<S: TokenStore> Fs<S> {
     /// - jottacloud errors
     /// - no such file
     pub async fn file_detail(&self, path: &UserScopedPath) -> crate::Result<FileDetail> {
-        let res = self.jfs_req(Method::GET, path).await?.send().await?;
+        let req = self.jfs_req(Method::GET, path).await?;
+        let res = self.send(Operation::FileDetail, req).await?;
 
         read_xml(res).await
     }
 
+    /// Check whether a path is a file or a folder, or `None` if nothing
+    /// exists there.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - jottacloud errors (including auth)
+    pub async fn path_kind(&self, path: &UserScopedPath) -> crate::Result<Option<PathKind>> {
+        let req = self.jfs_req(Method::GET, path).await?;
+        let res = self.send(Operation::Index, req).await?;
+        let status = res.status();
+        let xml = res.text().await?;
+
+        if status.is_success() {
+            return Ok(Some(PathKind::from_xml(&xml)));
+        }
+
+        let err: XmlErrorBody = serde_xml_rs::from_str(&xml)?;
+
+        match err.into() {
+            crate::Error::NoSuchFileOrFolder => Ok(None),
+            e => Err(e),
+        }
+    }
+
     /// **Permanently** removes a folder. It must be a folder. It fails if you try to
-    /// delete a single file.
+    /// delete a single file. See [`Fs::trash_folder`] for a recoverable
+    /// alternative.
     ///
     /// # Errors
     ///
     /// - your usual Jottacloud errors
     /// - trying to remove a file instead of a folder
     pub async fn remove_folder(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
-        let res = self
+        let req = self
             .jfs_req(Method::POST, path)
             .await?
-            // switching this to ?dlDir=true will move the folder to trash instead of irreversibly deleting
-            .query(&[("rmDir", "true")])
-            .send()
-            .await?;
+            .query(&[("rmDir", "true")]);
+        let res = self.send(Operation::Remove, req).await?;
+
+        read_xml(res).await
+    }
+
+    /// Move a folder to the trash, from where it can be restored until
+    /// Jottacloud empties it. Like [`Fs::remove_folder`], it must be a
+    /// folder.
+    ///
+    /// # Errors
+    ///
+    /// - your usual Jottacloud errors
+    /// - trying to trash a file instead of a folder
+    pub async fn trash_folder(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
+        let req = self
+            .jfs_req(Method::POST, path)
+            .await?
+            .query(&[("dlDir", "true")]);
+        let res = self.send(Operation::Remove, req).await?;
 
         read_xml(res).await
     }
 
+    /// **Permanently** removes a file.
+    ///
+    /// # Errors
+    ///
+    /// - your usual Jottacloud errors
+    /// - no such file
+    pub async fn remove_file(&self, path: &UserScopedPath) -> crate::Result<()> {
+        let req = self
+            .jfs_req(Method::POST, path)
+            .await?
+            .query(&[("rm", "true")]);
+        let res = self.send(Operation::Remove, req).await?;
+
+        if !res.status().is_success() {
+            let err: XmlErrorBody = serde_xml_rs::from_str(&res.text().await?)?;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    /// Alias of [`Fs::remove_folder`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Fs::remove_folder`].
+    pub async fn delete_folder(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
+        self.remove_folder(path).await
+    }
+
     /// Create a new folder.
     ///
     /// # Errors
     ///
     /// This does NOT return an error if a folder already exists.
     /// Therefore, it's more similar `mkdir -p`. It can, however,
-    /// fail due to your usual Jottacloud errors.
+    /// fail due to your usual Jottacloud errors. See
+    /// [`Fs::create_new_folder`] for a version that fails instead.
     #[instrument(skip(self))]
     pub async fn create_folder(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
+        match self.create_new_folder(path).await {
+            Err(crate::Error::AlreadyExists) => {
+                debug!("folder already exists");
+                self.index(path).await
+            }
+            res => res,
+        }
+    }
+
+    /// Create a new folder, failing if something already exists at the
+    /// path.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::AlreadyExists`](crate::Error::AlreadyExists) if the
+    ///   folder (or a file) already exists
+    /// - your usual Jottacloud errors
+    #[instrument(skip(self))]
+    pub async fn create_new_folder(&self, path: &UserScopedPath) -> crate::Result<FolderDetail> {
         debug!("creating folder `{}`", path);
 
-        let res = self
+        let req = self
             .jfs_req(Method::POST, path)
             .await?
-            .query(&[("mkDir", "true")])
-            .send()
-            .await?;
+            .query(&[("mkDir", "true")]);
+        let res = self.send(Operation::CreateFolder, req).await?;
 
         read_xml(res).await
     }
 
+    /// Send a raw `GET` request to an arbitrary JFS path, for features that
+    /// this crate doesn't model yet (thumbnails, shares, `?mode=list` and
+    /// so on).
+    ///
+    /// This is a low-level escape hatch. The response is returned as is,
+    /// so it's up to the caller to check the status code and parse the
+    /// (usually XML) body.
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - authentication errors
+    pub async fn raw_jfs_get(
+        &self,
+        path: &UserScopedPath,
+        query: &[(&str, &str)],
+    ) -> crate::Result<Response> {
+        let req = self.jfs_req(Method::GET, path).await?.query(query);
+        let res = self.send(Operation::Raw, req).await?;
+
+        Ok(res)
+    }
+
+    /// Send a raw `POST` request to an arbitrary JFS path. See
+    /// [`Fs::raw_jfs_get`].
+    ///
+    /// # Errors
+    ///
+    /// - network errors
+    /// - authentication errors
+    pub async fn raw_jfs_post(
+        &self,
+        path: &UserScopedPath,
+        query: &[(&str, &str)],
+    ) -> crate::Result<Response> {
+        let req = self.jfs_req(Method::POST, path).await?.query(query);
+        let res = self.send(Operation::Raw, req).await?;
+
+        Ok(res)
+    }
+
+    /// Request the contents of a file, or of a specific revision of it.
     #[instrument(skip(self))]
     async fn file_bin(
         &self,
         path: &UserScopedPath,
         range: impl ByteRange,
+        revision: Option<u32>,
     ) -> crate::Result<Response> {
         debug!("requesting file");
 
-        let res = self
+        let mut req = self
             .jfs_req(Method::GET, path)
             .await?
             .query(&[("mode", "bin")])
-            .header(header::RANGE, range.to_http())
-            .send()
-            .await?;
+            .header(header::RANGE, range.to_http());
 
-        if !res.status().is_success() {
+        if let Some(revision) = revision {
+            req = req.query(&[("revision", revision)]);
+        }
+
+        let res = self.send_transfer(Operation::Download, req).await?;
+
+        let status = res.status();
+
+        if !status.is_success() {
             let err_xml = res.text().await?;
-            let err: XmlErrorBody = serde_xml_rs::from_str(&err_xml)?;
-            return Err(err.into());
+
+            return match serde_xml_rs::from_str::<XmlErrorBody>(&err_xml) {
+                Ok(err) => Err(err.into()),
+                // some 404s come without a proper error body
+                Err(_) if status == StatusCode::NOT_FOUND => Err(crate::Error::NoSuchFileOrFolder),
+                Err(e) => Err(e.into()),
+            };
         }
 
         Ok(res)
@@ -251,14 +871,33 @@ impl<S: TokenStore> Fs<S> {
     /// - range is larger than the file itself
     /// - network errors
     /// - jottacloud errors
+    ///
+    /// If the range is closed, the stream ends with
+    /// [`Error::IncompleteDownload`](crate::Error::IncompleteDownload) if
+    /// the connection is closed before every byte was received.
     pub async fn file_to_stream(
         &self,
         path: &UserScopedPath,
         range: impl ByteRange,
     ) -> crate::Result<impl Stream<Item = crate::Result<Bytes>>> {
-        let res = self.file_bin(path, range).await?;
+        let requested = range.len();
+        let res = self.file_bin(path, range, None).await?;
+        let expected = expected_len(&res, requested);
+
+        let stream = stream::try_unfold(
+            (Box::pin(res.bytes_stream()), 0),
+            move |(mut inner, received)| async move {
+                if let Some(bytes) = inner.try_next().await? {
+                    let received = received + bytes.len() as u64;
+                    Ok(Some((bytes, (inner, received))))
+                } else {
+                    check_len(expected, received)?;
+                    Ok(None)
+                }
+            },
+        );
 
-        Ok(res.bytes_stream().map_err(Into::into))
+        Ok(stream)
     }
 
     /// Read a file as a string.
@@ -271,7 +910,7 @@ impl<S: TokenStore> Fs<S> {
     /// - jottacloud errors
     pub async fn file_to_string(&self, path: &UserScopedPath) -> crate::Result<String> {
         let text = self
-            .file_bin(path, OpenByteRange::full())
+            .file_bin(path, OpenByteRange::full(), None)
             .await?
             .text()
             .await?;
@@ -287,19 +926,317 @@ impl<S: TokenStore> Fs<S> {
     /// - range is larger than the file itself
     /// - network errors
     /// - jottacloud errors
+    /// - incomplete download (see [`Fs::file_to_stream`])
     pub async fn file_to_bytes(
         &self,
         path: &UserScopedPath,
         range: impl ByteRange,
     ) -> crate::Result<Bytes> {
-        let res = self.file_bin(path, range).await?;
+        self.bytes(path, range, None).await
+    }
+
+    /// Read a specific revision of a file as bytes. Revisions are
+    /// numbered from 1, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Fs::file_to_bytes`]. A missing revision is reported as
+    /// [`Error::NoSuchFileOrFolder`](crate::Error::NoSuchFileOrFolder).
+    pub async fn file_revision_to_bytes(
+        &self,
+        path: &UserScopedPath,
+        revision: u32,
+        range: impl ByteRange,
+    ) -> crate::Result<Bytes> {
+        self.bytes(path, range, Some(revision)).await
+    }
+
+    async fn bytes(
+        &self,
+        path: &UserScopedPath,
+        range: impl ByteRange,
+        revision: Option<u32>,
+    ) -> crate::Result<Bytes> {
+        let requested = range.len();
+        let res = self.file_bin(path, range, revision).await?;
+        let expected = expected_len(&res, requested);
 
-        Ok(res.bytes().await?)
+        let bytes = res.bytes().await?;
+
+        check_len(expected, bytes.len() as u64)?;
+
+        Ok(bytes)
     }
 }
 
+fn content_length(headers: &header::HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Number of bytes that a response to a request for `requested` bytes
+/// should contain, or `None` for open-ended ranges. Ranges extending
+/// beyond the end of the file are truncated by Jottacloud, which is
+/// reflected in the `Content-Length` or `Content-Range` headers.
+fn expected_len(res: &Response, requested: Option<u64>) -> Option<u64> {
+    let requested = requested?;
+
+    if let Some(len) = res.content_length() {
+        return Some(len);
+    }
+
+    let served = res
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("bytes "))
+        .and_then(|v| v.split_once('/'))
+        .and_then(|(range, _)| range.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()?)))
+        .map(|(start, end)| end + 1 - start);
+
+    Some(served.unwrap_or(requested))
+}
+
+fn check_len(expected: Option<u64>, received: u64) -> crate::Result<()> {
+    match expected {
+        Some(expected) if expected != received => {
+            Err(crate::Error::IncompleteDownload { expected, received })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Base urls are joined with relative paths, which replaces the last
+/// segment unless the url ends with a slash.
+fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    url
+}
+
 impl<P> Debug for Fs<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Fs").finish()
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::TryStreamExt;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{
+        auth::MockTokenStore,
+        metrics::{AtomicMetrics, Operation},
+        path::UserScopedPath,
+        range::{ClosedByteRange, OpenByteRange},
+        Error, Fs, FsOptions, REQUEST_ID_HEADER, USER_AGENT,
+    };
+
+    use super::with_trailing_slash;
+    use uuid::Uuid;
+
+    /// Serve `body` without a `Content-Length`, like a connection that is
+    /// closed prematurely.
+    async fn truncating_server(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut req = vec![0; 4096];
+                let _ = socket.read(&mut req).await.unwrap();
+
+                let res = format!(
+                    "HTTP/1.1 206 Partial Content\r\ncontent-type: application/octet-stream\r\nconnection: close\r\n\r\n{body}"
+                );
+
+                socket.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+
+        base_url
+    }
+
+    #[tokio::test]
+    async fn request_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut req = vec![0; 4096];
+                let n = socket.read(&mut req).await.unwrap();
+                requests.push(String::from_utf8_lossy(&req[..n]).into_owned());
+
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+
+            requests
+        });
+
+        let options = FsOptions {
+            user_agent_suffix: Some("test-suite/1.0".into()),
+            ..FsOptions::default()
+        };
+        let fs = Fs::with_options(
+            MockTokenStore::new("alice").with_base_url(&base_url),
+            options,
+        );
+        let path = UserScopedPath("Jotta/Archive/file".into());
+
+        for _ in 0..2 {
+            fs.raw_jfs_get(&path, &[]).await.unwrap();
+        }
+
+        let ids = server
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|req| {
+                assert!(req.contains(&format!("user-agent: {USER_AGENT} test-suite/1.0\r\n")));
+
+                req.lines()
+                    .find_map(|line| line.strip_prefix(&format!("{REQUEST_ID_HEADER}: ")))
+                    .unwrap()
+                    .parse::<Uuid>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn truncated_download() {
+        let base_url = truncating_server("hello").await;
+        let fs = Fs::new(MockTokenStore::new("alice").with_base_url(&base_url));
+        let path = UserScopedPath("Jotta/Archive/file".into());
+
+        let res = fs
+            .file_to_bytes(&path, ClosedByteRange::new_to_including(9))
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::IncompleteDownload {
+                expected: 10,
+                received: 5
+            })
+        ));
+
+        let res = fs
+            .file_to_stream(&path, ClosedByteRange::new_to_including(9))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await;
+        assert!(matches!(res, Err(Error::IncompleteDownload { .. })));
+
+        let bytes = fs
+            .file_to_bytes(&path, ClosedByteRange::new_to_including(4))
+            .await
+            .unwrap();
+        assert_eq!(bytes, "hello");
+
+        // the length of open ranges is unknown
+        let bytes = fs
+            .file_to_bytes(&path, OpenByteRange::new(2))
+            .await
+            .unwrap();
+        assert_eq!(bytes, "hello");
+    }
+
+    #[tokio::test]
+    async fn custom_base_urls() {
+        let base_url = truncating_server("hello").await;
+        let fs = Fs::new(MockTokenStore::new("alice")).with_base_urls(
+            format!("{base_url}/jfs").parse().unwrap(),
+            format!("{base_url}/files/v1/").parse().unwrap(),
+        );
+        let path = UserScopedPath("Jotta/Archive/file".into());
+
+        let bytes = fs
+            .file_to_bytes(&path, ClosedByteRange::new_to_including(4))
+            .await
+            .unwrap();
+        assert_eq!(bytes, "hello");
+
+        for url in ["http://localhost/jfs", "http://localhost/jfs/"] {
+            assert_eq!(
+                with_trailing_slash(url.parse().unwrap()).as_str(),
+                "http://localhost/jfs/"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut req = vec![0; 4096];
+                let _ = socket.read(&mut req).await.unwrap();
+
+                socket
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let metrics = Arc::new(AtomicMetrics::new());
+        let fs = Fs::new(MockTokenStore::new("alice").with_base_url(&base_url))
+            .with_metrics(metrics.clone());
+        let path = UserScopedPath("Jotta/Archive/file".into());
+
+        fs.file_to_bytes(&path, OpenByteRange::full())
+            .await
+            .unwrap();
+        fs.raw_jfs_get(&path, &[]).await.unwrap();
+
+        assert_eq!(metrics.requests(Operation::Download), 1);
+        assert_eq!(metrics.bytes(Operation::Download), 5);
+        assert_eq!(metrics.requests(Operation::Raw), 1);
+        assert_eq!(metrics.bytes(Operation::Raw), 0);
+
+        // nothing is listening here
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+
+        let fs = Fs::new(MockTokenStore::new("alice").with_base_url(&base_url))
+            .with_metrics(metrics.clone());
+        assert!(fs.index(&path).await.is_err());
+
+        assert_eq!(metrics.requests(Operation::Index), 1);
+        assert_eq!(metrics.errors(Operation::Index), 1);
+        assert!(metrics
+            .render()
+            .contains("jotta_requests_total{op=\"index\",status=\"none\"} 1"));
+    }
+}
@@ -1,8 +1,8 @@
 //! XML and Serde don't work well together. Pain.
 use md5::Digest;
 use num::{Integer, Signed};
-use reqwest::{header, Client};
-use serde::Deserialize;
+use reqwest::{header, Client, Url};
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
 use serde_with::serde_as;
@@ -10,7 +10,8 @@ use uuid::Uuid;
 
 use crate::api::read_xml;
 use crate::auth::AccessToken;
-use crate::path::AbsolutePath;
+use crate::path::{push_path, AbsolutePath};
+use crate::provider::Provider;
 use crate::serde::OptTypoDateTime;
 
 /// A Jottacloud device is used for sync and backup of files. The special `"Jotta"`
@@ -136,7 +137,20 @@ pub struct AccountInfo {
     pub devices: Devices,
 }
 
-/// Get information about the current account.
+impl AccountInfo {
+    /// Bytes left before the storage quota is reached, or `None` if the
+    /// account is unlimited.
+    #[must_use]
+    pub fn remaining(&self) -> Option<u64> {
+        self.capacity.limit().map(|capacity| {
+            u64::try_from(capacity)
+                .unwrap_or(0)
+                .saturating_sub(self.usage)
+        })
+    }
+}
+
+/// Get information about the current account from the JFS of `provider`.
 ///
 /// # Errors
 ///
@@ -144,12 +158,16 @@ pub struct AccountInfo {
 /// - jottacloud error
 pub async fn get_account(
     client: &Client,
+    provider: &Provider,
     username: &str,
     token: &AccessToken,
 ) -> crate::Result<AccountInfo> {
+    let mut url = Url::parse(&provider.jfs_base)?;
+    push_path(&mut url, username)?;
+
     let res = client
-        .get(format!("https://jfs.jottacloud.com/jfs/{}", username))
-        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .get(url)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
         .send()
         .await?;
 
@@ -175,7 +193,21 @@ pub struct MountPoint {
     pub modified: Option<OffsetDateTime>,
 }
 
-/// List mount points of a device. The device name is case-insensitive.
+#[derive(Debug, Deserialize)]
+pub(crate) struct MountPoints {
+    #[serde(rename = "$value")]
+    pub(crate) inner: Vec<MountPoint>,
+}
+
+/// Data returned when indexing a device.
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeviceDetail {
+    #[serde(rename(deserialize = "mountPoints"))]
+    pub(crate) mount_points: MountPoints,
+}
+
+/// List mount points of a device, using the JFS of `provider`. The device
+/// name is case-insensitive.
 ///
 /// # Errors
 ///
@@ -184,38 +216,54 @@ pub struct MountPoint {
 /// - no device found with that name
 pub async fn list_mountpoints(
     client: &Client,
+    provider: &Provider,
     username: &str,
     token: &AccessToken,
     device_name: &str,
 ) -> crate::Result<Vec<MountPoint>> {
-    #[derive(Debug, Deserialize)]
-    struct MountPoints {
-        #[serde(rename = "$value")]
-        inner: Vec<MountPoint>,
-    }
-
-    #[derive(Debug, Deserialize)]
-    struct Res {
-        #[serde(rename(deserialize = "mountPoints"))]
-        mount_points: MountPoints,
-    }
+    let mut url = Url::parse(&provider.jfs_base)?;
+    push_path(&mut url, username)?;
+    push_path(&mut url, device_name)?;
 
     let res = client
-        .get(format!(
-            "https://jfs.jottacloud.com/jfs/{}/{}",
-            username, device_name,
-        ))
-        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .get(url)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
         .send()
         .await?;
 
-    let data: Res = read_xml(res).await?;
+    let data: DeviceDetail = read_xml(res).await?;
 
     Ok(data.mount_points.inner)
 }
 
+/// What kind of node a path points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    /// A file, described by a [`FileDetail`].
+    File,
+    /// A folder (or mount point), described by a [`FolderDetail`].
+    Folder,
+}
+
+impl PathKind {
+    /// Tell files and folders apart by the root element of a JFS
+    /// response, which is `<file>` for files.
+    pub(crate) fn from_xml(xml: &str) -> Self {
+        let root = xml
+            .split('<')
+            .skip(1)
+            .find(|tag| !tag.starts_with(['?', '!']))
+            .and_then(|tag| tag.split([' ', '\t', '\r', '\n', '/', '>']).next());
+
+        match root {
+            Some("file") => Self::File,
+            _ => Self::Folder,
+        }
+    }
+}
+
 /// State of a revision.
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RevisionState {
     /// The revision is correctly uploaded.
@@ -422,6 +470,132 @@ impl FileDetail {
             && self
                 .current_revision
                 .as_ref()
-                .map_or(false, Revision::is_complete)
+                .is_some_and(Revision::is_complete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountInfo, DeviceDetail, PathKind};
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn provider_jfs_base() {
+        use reqwest::Client;
+
+        use super::{get_account, list_mountpoints};
+        use crate::{
+            auth::{MockTokenStore, TokenStore},
+            mock::{MockJottacloud, MOCK_USERNAME},
+        };
+
+        let server = MockJottacloud::start().await;
+        let store = MockTokenStore::new(MOCK_USERNAME).with_base_url(&server.base_url());
+        let client = Client::new();
+        let token = store.get_access_token(&client).await.unwrap();
+
+        let account = get_account(&client, store.provider(), MOCK_USERNAME, &token)
+            .await
+            .unwrap();
+        assert_eq!(account.username, MOCK_USERNAME);
+
+        let mount_points =
+            list_mountpoints(&client, store.provider(), MOCK_USERNAME, &token, "Jotta")
+                .await
+                .unwrap();
+        assert!(mount_points.iter().any(|m| m.name == "Archive"));
+    }
+
+    #[test]
+    fn devices() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<user time="2022-04-01-T12:00:00Z" host="dn-000.jotta.cloud">
+  <username>alice</username>
+  <account-type>unlimited</account-type>
+  <locked>false</locked>
+  <capacity>-1</capacity>
+  <max-devices>-1</max-devices>
+  <max-mobile-devices>-1</max-mobile-devices>
+  <usage>1337</usage>
+  <read-locked>false</read-locked>
+  <write-locked>false</write-locked>
+  <quota-write-locked>false</quota-write-locked>
+  <enable-sync>true</enable-sync>
+  <enable-foldershare>true</enable-foldershare>
+  <devices>
+    <device>
+      <name xml:space="preserve">Jotta</name>
+      <display_name xml:space="preserve">Jotta</display_name>
+      <type>JOTTA</type>
+      <sid>d2d7a1e5-4b8e-4a4c-9d5f-5f3b7c2a1e00</sid>
+      <size>1337</size>
+      <modified>2022-04-01-T12:00:00Z</modified>
+    </device>
+  </devices>
+</user>"#;
+
+        let account: AccountInfo = serde_xml_rs::from_str(xml).unwrap();
+        let devices = &account.devices.devices;
+
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "Jotta");
+        assert_eq!(devices[0].typ, "JOTTA");
+        assert!(account.capacity.is_unlimited());
+        assert_eq!(account.remaining(), None);
+
+        let limited = xml.replace("<capacity>-1</capacity>", "<capacity>5000</capacity>");
+        let account: AccountInfo = serde_xml_rs::from_str(&limited).unwrap();
+        assert_eq!(account.remaining(), Some(5000 - 1337));
+
+        let full = xml.replace("<capacity>-1</capacity>", "<capacity>1000</capacity>");
+        let account: AccountInfo = serde_xml_rs::from_str(&full).unwrap();
+        assert_eq!(account.remaining(), Some(0));
+    }
+
+    #[test]
+    fn path_kind() {
+        let file = r#"<?xml version="1.0" encoding="UTF-8"?>
+<file name="a.txt" uuid="f6c2f6a8-4d4b-4b4e-9c1c-2d5b5b1f3a2e" time="2022-04-01-T12:00:00Z" host="dn-1">"#;
+        let folder = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- comment -->
+<folder name="files" time="2022-04-01-T12:00:00Z" host="dn-1">"#;
+
+        assert_eq!(PathKind::from_xml(file), PathKind::File);
+        assert_eq!(PathKind::from_xml("<file/>"), PathKind::File);
+        assert_eq!(PathKind::from_xml(folder), PathKind::Folder);
+        assert_eq!(PathKind::from_xml("<mountPoint>"), PathKind::Folder);
+    }
+
+    #[test]
+    fn mountpoints() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<device time="2022-04-01-T12:00:00Z" host="dn-000.jotta.cloud">
+  <name xml:space="preserve">Jotta</name>
+  <type>JOTTA</type>
+  <size>1337</size>
+  <mountPoints>
+    <mountPoint>
+      <name xml:space="preserve">Archive</name>
+      <size>1337</size>
+      <modified>2022-04-01-T12:00:00Z</modified>
+    </mountPoint>
+    <mountPoint>
+      <name xml:space="preserve">Sync</name>
+      <size>0</size>
+      <modified></modified>
+    </mountPoint>
+  </mountPoints>
+</device>"#;
+
+        let device: DeviceDetail = serde_xml_rs::from_str(xml).unwrap();
+        let names = device
+            .mount_points
+            .inner
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["Archive", "Sync"]);
+        assert!(device.mount_points.inner[1].modified.is_none());
     }
 }
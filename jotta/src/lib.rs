@@ -5,15 +5,22 @@
     missing_docs,
     clippy::pedantic
 )]
+#![allow(clippy::result_large_err, clippy::non_std_lazy_statics)]
 
 pub mod api;
 pub mod auth;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod errors;
 pub mod events;
 pub mod files;
 mod fs;
 pub mod jfs;
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod path;
+pub mod provider;
 pub mod range;
 pub(crate) mod serde;
 
@@ -0,0 +1,332 @@
+//! Hooks for monitoring the requests made by [`Fs`](crate::Fs).
+//!
+//! Attach a [`Metrics`] sink with [`Fs::with_metrics`](crate::Fs::with_metrics).
+//! Nothing is measured if no sink is attached.
+use std::{
+    fmt::{Debug, Write},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use reqwest::StatusCode;
+
+use crate::Error;
+
+/// Kind of request made by [`Fs`](crate::Fs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// [`Fs::allocate`](crate::Fs::allocate).
+    Allocate,
+    /// [`Fs::upload_range`](crate::Fs::upload_range).
+    Upload,
+    /// Reading file contents, e.g. [`Fs::file_to_bytes`](crate::Fs::file_to_bytes).
+    Download,
+    /// Listing folders, devices and mount points.
+    Index,
+    /// [`Fs::file_detail`](crate::Fs::file_detail).
+    FileDetail,
+    /// [`Fs::create_folder`](crate::Fs::create_folder).
+    CreateFolder,
+    /// Removing files and folders.
+    Remove,
+    /// [`Fs::raw_jfs_get`](crate::Fs::raw_jfs_get) and
+    /// [`Fs::raw_jfs_post`](crate::Fs::raw_jfs_post).
+    Raw,
+}
+
+impl Operation {
+    /// All operations.
+    pub const ALL: [Operation; 8] = [
+        Self::Allocate,
+        Self::Upload,
+        Self::Download,
+        Self::Index,
+        Self::FileDetail,
+        Self::CreateFolder,
+        Self::Remove,
+        Self::Raw,
+    ];
+
+    /// Name of the operation, suitable as a metric label.
+    ///
+    /// ```
+    /// use jotta::metrics::Operation;
+    ///
+    /// assert_eq!(Operation::FileDetail.as_str(), "file_detail");
+    /// ```
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Allocate => "allocate",
+            Self::Upload => "upload",
+            Self::Download => "download",
+            Self::Index => "index",
+            Self::FileDetail => "file_detail",
+            Self::CreateFolder => "create_folder",
+            Self::Remove => "remove",
+            Self::Raw => "raw",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A sink for request metrics. All methods do nothing by default.
+///
+/// The methods are called on the hot path, so they should be cheap and
+/// must not block.
+pub trait Metrics: Debug + Send + Sync {
+    /// A request finished. `status` is `None` if no response was received.
+    fn record_request(&self, op: Operation, status: Option<StatusCode>, duration: Duration) {
+        let _ = (op, status, duration);
+    }
+
+    /// `bytes` bytes of file data were sent or received.
+    fn record_bytes(&self, op: Operation, bytes: u64) {
+        let _ = (op, bytes);
+    }
+
+    /// A request failed without a response.
+    fn record_error(&self, op: Operation, error: &Error) {
+        let _ = (op, error);
+    }
+}
+
+/// Metrics that are thrown away.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Debug, Default)]
+struct OperationMetrics {
+    /// Requests by status class. Index 0 counts requests without a
+    /// response, 1 through 5 count `1xx` through `5xx`.
+    statuses: [AtomicU64; 6],
+    /// Requests that timed out and requests that failed in another way.
+    errors: [AtomicU64; 2],
+    bytes: AtomicU64,
+    latency_micros: AtomicU64,
+    /// Non-cumulative counts per [`LATENCY_BUCKETS`], plus one for `+Inf`.
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// Lock-free in-memory metrics that can be rendered in the Prometheus
+/// text format with [`AtomicMetrics::render`].
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    operations: [OperationMetrics; Operation::ALL.len()],
+}
+
+impl AtomicMetrics {
+    /// Create empty metrics.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of finished requests, with or without a response.
+    #[must_use]
+    pub fn requests(&self, op: Operation) -> u64 {
+        self.operations[op.index()]
+            .statuses
+            .iter()
+            .map(|n| n.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Number of requests that failed without a response.
+    #[must_use]
+    pub fn errors(&self, op: Operation) -> u64 {
+        self.operations[op.index()]
+            .errors
+            .iter()
+            .map(|n| n.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Number of bytes of file data transferred.
+    #[must_use]
+    pub fn bytes(&self, op: Operation) -> u64 {
+        self.operations[op.index()].bytes.load(Ordering::Relaxed)
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        // writing to a string never fails
+        let _ = self.write_metrics(&mut out);
+
+        out
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn write_metrics(&self, out: &mut String) -> std::fmt::Result {
+        const STATUSES: [&str; 6] = ["none", "1xx", "2xx", "3xx", "4xx", "5xx"];
+        const ERRORS: [&str; 2] = ["timeout", "network"];
+
+        writeln!(out, "# HELP jotta_requests_total Requests to Jottacloud.")?;
+        writeln!(out, "# TYPE jotta_requests_total counter")?;
+        for op in Operation::ALL {
+            for (status, n) in STATUSES.iter().zip(&self.operations[op.index()].statuses) {
+                let n = n.load(Ordering::Relaxed);
+                let op = op.as_str();
+                writeln!(
+                    out,
+                    "jotta_requests_total{{op=\"{op}\",status=\"{status}\"}} {n}"
+                )?;
+            }
+        }
+
+        writeln!(
+            out,
+            "# HELP jotta_errors_total Requests that got no response."
+        )?;
+        writeln!(out, "# TYPE jotta_errors_total counter")?;
+        for op in Operation::ALL {
+            for (kind, n) in ERRORS.iter().zip(&self.operations[op.index()].errors) {
+                let n = n.load(Ordering::Relaxed);
+                let op = op.as_str();
+                writeln!(out, "jotta_errors_total{{op=\"{op}\",kind=\"{kind}\"}} {n}")?;
+            }
+        }
+
+        writeln!(
+            out,
+            "# HELP jotta_transferred_bytes_total File data transferred."
+        )?;
+        writeln!(out, "# TYPE jotta_transferred_bytes_total counter")?;
+        for op in Operation::ALL {
+            let n = self.bytes(op);
+            let op = op.as_str();
+            writeln!(out, "jotta_transferred_bytes_total{{op=\"{op}\"}} {n}")?;
+        }
+
+        writeln!(
+            out,
+            "# HELP jotta_request_duration_seconds Request latency."
+        )?;
+        writeln!(out, "# TYPE jotta_request_duration_seconds histogram")?;
+        for op in Operation::ALL {
+            let metrics = &self.operations[op.index()];
+            let op = op.as_str();
+            let mut cumulative = 0;
+
+            for (i, n) in metrics.latency_buckets.iter().enumerate() {
+                cumulative += n.load(Ordering::Relaxed);
+                let le = LATENCY_BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_owned(), ToString::to_string);
+                writeln!(
+                    out,
+                    "jotta_request_duration_seconds_bucket{{op=\"{op}\",le=\"{le}\"}} {cumulative}"
+                )?;
+            }
+
+            let sum = metrics.latency_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            writeln!(
+                out,
+                "jotta_request_duration_seconds_sum{{op=\"{op}\"}} {sum}"
+            )?;
+            writeln!(
+                out,
+                "jotta_request_duration_seconds_count{{op=\"{op}\"}} {cumulative}"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Metrics for AtomicMetrics {
+    fn record_request(&self, op: Operation, status: Option<StatusCode>, duration: Duration) {
+        let metrics = &self.operations[op.index()];
+        let class = status.map_or(0, |s| usize::from(s.as_u16() / 100).clamp(1, 5));
+        let secs = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&le| secs <= le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        metrics.statuses[class].fetch_add(1, Ordering::Relaxed);
+        metrics.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        metrics.latency_micros.fetch_add(
+            duration.as_micros().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn record_bytes(&self, op: Operation, bytes: u64) {
+        self.operations[op.index()]
+            .bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn record_error(&self, op: Operation, error: &Error) {
+        let kind = usize::from(!matches!(error, Error::Timeout(_)));
+
+        self.operations[op.index()].errors[kind].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::{AtomicMetrics, Metrics, Operation};
+
+    #[test]
+    fn atomic_metrics() {
+        let metrics = AtomicMetrics::new();
+
+        metrics.record_request(
+            Operation::Download,
+            Some(StatusCode::OK),
+            Duration::from_millis(20),
+        );
+        metrics.record_request(
+            Operation::Download,
+            Some(StatusCode::NOT_FOUND),
+            Duration::from_secs(3),
+        );
+        metrics.record_request(Operation::Upload, None, Duration::from_secs(45));
+        metrics.record_error(Operation::Upload, &crate::Error::IncompleteUpload);
+        metrics.record_bytes(Operation::Download, 1234);
+
+        assert_eq!(metrics.requests(Operation::Download), 2);
+        assert_eq!(metrics.requests(Operation::Upload), 1);
+        assert_eq!(metrics.errors(Operation::Upload), 1);
+        assert_eq!(metrics.bytes(Operation::Download), 1234);
+        assert_eq!(metrics.requests(Operation::Index), 0);
+
+        let text = metrics.render();
+
+        for line in [
+            "jotta_requests_total{op=\"download\",status=\"2xx\"} 1",
+            "jotta_requests_total{op=\"download\",status=\"4xx\"} 1",
+            "jotta_requests_total{op=\"upload\",status=\"none\"} 1",
+            "jotta_errors_total{op=\"upload\",kind=\"network\"} 1",
+            "jotta_transferred_bytes_total{op=\"download\"} 1234",
+            "jotta_request_duration_seconds_bucket{op=\"download\",le=\"0.05\"} 1",
+            "jotta_request_duration_seconds_bucket{op=\"download\",le=\"5\"} 2",
+            "jotta_request_duration_seconds_bucket{op=\"upload\",le=\"30\"} 0",
+            "jotta_request_duration_seconds_bucket{op=\"upload\",le=\"+Inf\"} 1",
+            "jotta_request_duration_seconds_count{op=\"download\"} 2",
+            "jotta_request_duration_seconds_sum{op=\"download\"} 3.02",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing `{line}` in\n{text}"
+            );
+        }
+    }
+}
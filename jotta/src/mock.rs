@@ -0,0 +1,814 @@
+//! An in-memory Jottacloud for testing without credentials or network
+//! access.
+//!
+//! [`MockJottacloud`] implements just enough of the JFS and the files API
+//! for an [`Fs`] to run against it: folders, uploads (including resumed
+//! ones), ranged downloads, file revisions, trash, account info and
+//! [`LegacyAuth`](crate::auth::LegacyAuth) logins accepting any password. Its
+//! state can be inspected and faults can be injected, such as failing
+//! allocations or truncated uploads.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), jotta::Error> {
+//! use bytes::Bytes;
+//! use jotta::{files::ConflictHandler, mock::MockJottacloud, path::PathOnDevice};
+//!
+//! let server = MockJottacloud::start().await;
+//! let fs = server.fs();
+//! let path = PathOnDevice::new("Archive", "hello.txt");
+//!
+//! fs.upload_bytes(&path, Bytes::from_static(b"hello"), ConflictHandler::RejectConflicts)
+//!     .await?;
+//!
+//! assert_eq!(server.file("Jotta/Archive/hello.txt").unwrap(), "hello");
+//! assert_eq!(server.files("Jotta/Archive"), ["hello.txt"]);
+//! # Ok(())
+//! # }
+//! ```
+// the state is only poisoned if a request handler panicked
+#![allow(clippy::missing_panics_doc)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use hyper::{
+    body::to_bytes,
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+
+use crate::{auth::MockTokenStore, Fs};
+
+/// Username of the only account on a [`MockJottacloud`].
+pub const MOCK_USERNAME: &str = "alice";
+
+#[derive(Debug)]
+struct File {
+    /// Completed revisions, oldest first.
+    revisions: Vec<Bytes>,
+    /// Is the latest revision corrupt? It's then reported as the latest
+    /// rather than the current revision.
+    corrupt: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Folder,
+    File(File),
+}
+
+#[derive(Debug)]
+struct Allocation {
+    path: String,
+    md5: md5::Digest,
+    bytes: u64,
+    /// Bytes received so far.
+    received: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Nodes by user scoped path, such as `Jotta/Archive/foo`.
+    nodes: BTreeMap<String, Node>,
+    /// Trashed nodes, by their original path.
+    trash: BTreeMap<String, Node>,
+    allocations: HashMap<u64, Allocation>,
+    next_id: u64,
+    /// Paths of all uploaded files, in order.
+    uploads: Vec<String>,
+    /// Start position and body length of every upload request, in order.
+    upload_requests: Vec<(u64, usize)>,
+    /// Paths of all files that have been requested for download, in order.
+    downloads: Vec<String>,
+    /// Accept at most this many bytes per upload request, leaving the
+    /// rest of the upload incomplete.
+    max_upload_request: Option<usize>,
+    /// Number of allocation requests so far.
+    allocations_seen: usize,
+    /// Allocation requests, numbered from zero, that fail with a
+    /// server error.
+    failing_allocations: Range<usize>,
+    /// Number of upcoming uploads that silently lose their last byte.
+    truncated_uploads: usize,
+    /// Reported by the account info, but not enforced.
+    read_locked: bool,
+    write_locked: bool,
+}
+
+impl State {
+    fn create_parents(&mut self, path: &str) {
+        for (i, _) in path.match_indices('/') {
+            self.nodes
+                .entry(path[..i].to_owned())
+                .or_insert(Node::Folder);
+        }
+    }
+
+    fn children<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a str, &'a Node)> + 'a {
+        let prefix = format!("{path}/");
+
+        self.nodes
+            .range(prefix.clone()..)
+            .take_while(move |(p, _)| p.starts_with(&prefix))
+            .filter_map(move |(p, node)| {
+                let name = &p[path.len() + 1..];
+                (!name.contains('/')).then_some((name, node))
+            })
+    }
+}
+
+/// An in-memory Jottacloud server, listening on a random local port.
+#[derive(Debug, Clone)]
+pub struct MockJottacloud {
+    state: Arc<Mutex<State>>,
+    addr: SocketAddr,
+}
+
+impl MockJottacloud {
+    /// Start a server on a random port. It runs on the current Tokio
+    /// runtime until the runtime shuts down.
+    #[allow(clippy::unused_async)] // must be called within a runtime
+    pub async fn start() -> Self {
+        let mut state = State::default();
+        state.create_parents("Jotta/Archive/");
+        let state = Arc::new(Mutex::new(state));
+        let service_state = state.clone();
+
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(&state, req).await) }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+
+        Self { state, addr }
+    }
+
+    /// Accept at most `max` bytes per upload request, like a flaky
+    /// connection would.
+    pub fn limit_upload_requests(&self, max: usize) {
+        self.state.lock().unwrap().max_upload_request = Some(max);
+    }
+
+    /// Fail `count` allocation requests with a server error, after
+    /// letting the next `skip` through.
+    pub fn fail_allocations(&self, skip: usize, count: usize) {
+        let mut state = self.state.lock().unwrap();
+        let start = state.allocations_seen + skip;
+        state.failing_allocations = start..start + count;
+    }
+
+    /// Silently drop the last byte of the next `count` completed uploads,
+    /// as if the storage layer truncated them. The response reports what
+    /// was stored.
+    pub fn truncate_uploads(&self, count: usize) {
+        self.state.lock().unwrap().truncated_uploads = count;
+    }
+
+    /// Number of allocation requests so far, including failed ones.
+    #[must_use]
+    pub fn allocations(&self) -> usize {
+        self.state.lock().unwrap().allocations_seen
+    }
+
+    /// Report the account as read or write locked. Requests are still
+    /// served as usual.
+    pub fn lock(&self, read: bool, write: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.read_locked = read;
+        state.write_locked = write;
+    }
+
+    /// Base url of the server, to be passed to
+    /// [`MockTokenStore::with_base_url`].
+    #[must_use]
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A filesystem client talking to this server.
+    #[must_use]
+    pub fn fs(&self) -> Fs<MockTokenStore> {
+        Fs::new(MockTokenStore::new(MOCK_USERNAME).with_base_url(&self.base_url()))
+    }
+
+    /// Latest revision of a file. Paths are relative to the user, such
+    /// as `Jotta/Archive/foo`.
+    #[must_use]
+    pub fn file(&self, path: &str) -> Option<Bytes> {
+        match self.state.lock().unwrap().nodes.get(path)? {
+            Node::File(file) => file.revisions.last().cloned(),
+            Node::Folder => None,
+        }
+    }
+
+    /// Start position and body length of every upload request, in order.
+    #[must_use]
+    pub fn upload_requests(&self) -> Vec<(u64, usize)> {
+        self.state.lock().unwrap().upload_requests.clone()
+    }
+
+    /// Mark the latest revision of a file as corrupt, as if Jottacloud
+    /// had received the wrong bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such file.
+    pub fn corrupt(&self, path: &str) {
+        match self.state.lock().unwrap().nodes.get_mut(path) {
+            Some(Node::File(file)) => file.corrupt = true,
+            _ => panic!("no such file: {path}"),
+        }
+    }
+
+    /// Original paths of everything in the trash.
+    #[must_use]
+    pub fn trash(&self) -> Vec<String> {
+        self.state.lock().unwrap().trash.keys().cloned().collect()
+    }
+
+    /// Paths of all files that have been requested for download, in order.
+    #[must_use]
+    pub fn downloads(&self) -> Vec<String> {
+        self.state.lock().unwrap().downloads.clone()
+    }
+
+    /// Paths of all files that data has been uploaded to, in order.
+    #[must_use]
+    pub fn uploads(&self) -> Vec<String> {
+        self.state.lock().unwrap().uploads.clone()
+    }
+
+    /// Names of the files in a folder.
+    #[must_use]
+    pub fn files(&self, path: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .children(path)
+            .filter(|(_, node)| matches!(node, Node::File(_)))
+            .map(|(name, _)| name.to_owned())
+            .collect()
+    }
+
+    /// Names of the subfolders of a folder.
+    #[must_use]
+    pub fn folders(&self, path: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .children(path)
+            .filter(|(_, node)| matches!(node, Node::Folder))
+            .map(|(name, _)| name.to_owned())
+            .collect()
+    }
+}
+
+fn xml(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(body.into())
+        .unwrap()
+}
+
+fn json(status: StatusCode, body: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.to_string().into())
+        .unwrap()
+}
+
+fn xml_error(status: StatusCode, exception: &str) -> Response<Body> {
+    xml(
+        status,
+        format!(
+            "<error><code>{}</code><message>no.jotta.backup.errors.{exception}: fake</message>\
+            <reason>{}</reason><cause></cause><hostname></hostname><x-id></x-id></error>",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default(),
+        ),
+    )
+}
+
+fn json_error(status: StatusCode, exception: &str) -> Response<Body> {
+    json(
+        status,
+        &serde_json::json!({
+            "code": status.as_u16(),
+            "message": null,
+            "cause": null,
+            "error_id": exception,
+            "x-id": null,
+        }),
+    )
+}
+
+fn folder_xml(state: &State, path: &str) -> String {
+    folder_page_xml(state, path, None)
+}
+
+/// A folder listing, optionally paged by `(first, max)` like the JFS
+/// `first` and `max` query parameters. Folders are listed before files.
+fn folder_page_xml(state: &State, path: &str, page: Option<(usize, usize)>) -> String {
+    let name = path.rsplit('/').next().unwrap();
+    let mut folders = String::new();
+    let mut files = String::new();
+
+    let mut children = state.children(path).collect::<Vec<_>>();
+    children.sort_by_key(|(_, node)| matches!(node, Node::File(_)));
+    let total = children.len();
+    let num_folders = children
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::Folder))
+        .count();
+
+    let (first, max) = page.unwrap_or((0, total));
+
+    for (child, node) in children.into_iter().skip(first).take(max) {
+        match node {
+            Node::Folder => write!(folders, "<folder name=\"{child}\"></folder>").unwrap(),
+            Node::File(file) => write!(
+                files,
+                "<file name=\"{child}\" uuid=\"{}\">{}</file>",
+                uuid(0),
+                revisions_xml(file),
+            )
+            .unwrap(),
+        }
+    }
+
+    // empty wrappers can't be deserialized, so leave them out
+    let wrap = |tag: &str, inner: String| {
+        if inner.is_empty() {
+            inner
+        } else {
+            format!("<{tag}>{inner}</{tag}>")
+        }
+    };
+
+    let metadata = if page.is_some() {
+        format!(
+            "<metadata first=\"{first}\" max=\"{max}\" total=\"{total}\" \
+            num_folders=\"{num_folders}\" num_files=\"{}\"/>",
+            total - num_folders
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        "<folder name=\"{name}\"><path>/{MOCK_USERNAME}/{path}</path>{}{}{metadata}</folder>",
+        wrap("folders", folders),
+        wrap("files", files),
+    )
+}
+
+/// Parse `first=<n>&max=<n>`.
+fn parse_page(query: &str) -> Option<(usize, usize)> {
+    let mut first = None;
+    let mut max = None;
+
+    for pair in query.split('&') {
+        match pair.split_once('=')? {
+            ("first", n) => first = Some(n.parse().ok()?),
+            ("max", n) => max = Some(n.parse().ok()?),
+            _ => return None,
+        }
+    }
+
+    Some((first?, max?))
+}
+
+fn account_xml(state: &State) -> String {
+    let mut devices = String::new();
+
+    for (name, _) in state
+        .nodes
+        .iter()
+        .filter(|(path, node)| matches!(node, Node::Folder) && !path.contains('/'))
+    {
+        write!(
+            devices,
+            "<device><name>{name}</name><display_name>{name}</display_name>\
+            <type>JOTTA</type><sid>{}</sid><size>0</size><modified></modified></device>",
+            uuid(0),
+        )
+        .unwrap();
+    }
+
+    format!(
+        "<user><username>{MOCK_USERNAME}</username><account-type>unlimited</account-type>\
+        <locked>false</locked><capacity>-1</capacity><max-devices>-1</max-devices>\
+        <max-mobile-devices>-1</max-mobile-devices><usage>0</usage>\
+        <read-locked>{}</read-locked><write-locked>{}</write-locked>\
+        <quota-write-locked>false</quota-write-locked><enable-sync>true</enable-sync>\
+        <enable-foldershare>true</enable-foldershare><devices>{devices}</devices></user>",
+        state.read_locked, state.write_locked,
+    )
+}
+
+fn device_xml(state: &State, device: &str) -> String {
+    let mut mount_points = String::new();
+
+    for (name, _) in state.children(device) {
+        write!(
+            mount_points,
+            "<mountPoint><name>{name}</name><size>0</size><modified></modified></mountPoint>"
+        )
+        .unwrap();
+    }
+
+    format!("<device><name>{device}</name><mountPoints>{mount_points}</mountPoints></device>")
+}
+
+fn file_xml(path: &str, file: &File) -> String {
+    let (parent, name) = path.rsplit_once('/').unwrap();
+
+    format!(
+        "<file name=\"{name}\" uuid=\"{}\"><path>/{MOCK_USERNAME}/{parent}</path>\
+        <abspath>/{MOCK_USERNAME}/{parent}</abspath>{}</file>",
+        uuid(0),
+        revisions_xml(file),
+    )
+}
+
+/// The current revision of a file, or the previous one along with the
+/// corrupt latest revision.
+fn revisions_xml(file: &File) -> String {
+    let number = file.revisions.len();
+    let latest = file.revisions.last().unwrap();
+
+    if !file.corrupt {
+        return revision_xml("currentRevision", number, latest, "COMPLETED");
+    }
+
+    let mut xml = revision_xml("latestRevision", number, latest, "CORRUPT");
+
+    if let Some(current) = number.checked_sub(2).map(|i| &file.revisions[i]) {
+        xml += &revision_xml("currentRevision", number - 1, current, "COMPLETED");
+    }
+
+    xml
+}
+
+fn revision_xml(tag: &str, number: usize, data: &Bytes, state: &str) -> String {
+    format!(
+        "<{tag}><number>{number}</number><state>{state}</state>\
+        <created>2022-04-01-T12:00:00Z</created><modified>2022-04-01-T12:00:00Z</modified>\
+        <mime>application/octet-stream</mime><size>{}</size><md5>{:x}</md5>\
+        <updated>2022-04-01-T12:00:00Z</updated></{tag}>",
+        data.len(),
+        md5::compute(data),
+    )
+}
+
+fn uuid(n: u64) -> String {
+    format!("00000000-0000-0000-0000-{n:012}")
+}
+
+/// Parse `bytes=<start>-[end]` or `bytes=-<suffix>` into the half-open
+/// range of a file of `len` bytes. `None` if it can't be satisfied.
+fn parse_range(req: &Request<Body>, len: u64) -> Option<(u64, u64)> {
+    let Some(range) = req.headers().get(header::RANGE) else {
+        return Some((0, len));
+    };
+
+    let (start, end) = range
+        .to_str()
+        .unwrap()
+        .strip_prefix("bytes=")
+        .unwrap()
+        .split_once('-')
+        .unwrap();
+
+    if start.is_empty() {
+        let suffix: u64 = end.parse().unwrap();
+
+        return (suffix > 0).then(|| (len.saturating_sub(suffix), len));
+    }
+
+    let start: u64 = start.parse().unwrap();
+
+    if start >= len && len > 0 {
+        return None;
+    }
+
+    let end = end.parse().ok().map_or(len, |end: u64| (end + 1).min(len));
+
+    Some((start, end))
+}
+
+async fn handle(state: &Mutex<State>, req: Request<Body>) -> Response<Body> {
+    let path = percent_decode_str(req.uri().path().trim_end_matches('/'))
+        .decode_utf8()
+        .unwrap()
+        .into_owned();
+    let query = req.uri().query().unwrap_or_default().to_owned();
+
+    if let Some(path) = path.strip_prefix(&format!("/jfs/{MOCK_USERNAME}/")) {
+        return jfs(state, &req, path, &query);
+    }
+
+    match (req.method(), path.as_str()) {
+        (&Method::GET, account) if account == format!("/jfs/{MOCK_USERNAME}") => {
+            xml(StatusCode::OK, account_xml(&state.lock().unwrap()))
+        }
+        (&Method::POST, "/auth/v1/register") => json(
+            StatusCode::OK,
+            &serde_json::json!({ "client_id": "mock", "client_secret": "mock" }),
+        ),
+        (&Method::POST, "/auth/v1/token") => json(
+            StatusCode::OK,
+            &serde_json::json!({
+                "access_token": crate::auth::MOCK_ACCESS_TOKEN,
+                "refresh_token": "mock",
+                "expires_in": 3600,
+            }),
+        ),
+        (&Method::POST, "/files/v1/allocate") => allocate(state, req).await,
+        (&Method::POST, upload) if upload.starts_with("/upload/") => {
+            let id = upload["/upload/".len()..].parse().unwrap();
+            self::upload(state, req, id).await
+        }
+        _ => xml_error(StatusCode::NOT_FOUND, "NoSuchPathException"),
+    }
+}
+
+/// Parse `mode=bin` queries, returning the requested revision or 0 for
+/// the latest one.
+fn parse_revision(query: &str) -> Option<usize> {
+    match query.strip_prefix("mode=bin")? {
+        "" => Some(0),
+        rest => rest.strip_prefix("&revision=")?.parse().ok(),
+    }
+}
+
+fn jfs(state: &Mutex<State>, req: &Request<Body>, path: &str, query: &str) -> Response<Body> {
+    let mut state = state.lock().unwrap();
+
+    match (req.method(), query) {
+        (&Method::POST, "mkDir=true") => {
+            if state.nodes.contains_key(path) {
+                return xml_error(StatusCode::CONFLICT, "UniqueFileException");
+            }
+
+            state.create_parents(path);
+            state.nodes.insert(path.to_owned(), Node::Folder);
+            xml(StatusCode::CREATED, folder_xml(&state, path))
+        }
+        (&Method::POST, "rmDir=true") => match state.nodes.get(path) {
+            Some(Node::Folder) => {
+                let body = folder_xml(&state, path);
+                let prefix = format!("{path}/");
+                state
+                    .nodes
+                    .retain(|p, _| p != path && !p.starts_with(&prefix));
+                xml(StatusCode::OK, body)
+            }
+            _ => xml_error(StatusCode::NOT_FOUND, "NoSuchPathException"),
+        },
+        (&Method::POST, "dlDir=true") => match state.nodes.get(path) {
+            Some(Node::Folder) => {
+                let body = folder_xml(&state, path);
+                let prefix = format!("{path}/");
+                let trashed = state
+                    .nodes
+                    .extract_if(.., |p, _| p == path || p.starts_with(&prefix))
+                    .collect::<Vec<_>>();
+                state.trash.extend(trashed);
+                xml(StatusCode::OK, body)
+            }
+            _ => xml_error(StatusCode::NOT_FOUND, "NoSuchPathException"),
+        },
+        (&Method::POST, "rm=true") => match state.nodes.get(path) {
+            Some(Node::File(_)) => {
+                state.nodes.remove(path);
+                Response::new(Body::empty())
+            }
+            _ => xml_error(StatusCode::NOT_FOUND, "NoSuchFileException"),
+        },
+        (&Method::GET, query) if parse_revision(query).is_some() => {
+            state.downloads.push(path.to_owned());
+
+            match state.nodes.get(path) {
+                Some(Node::File(file)) => {
+                    // revisions are numbered from 1, and 0 means the latest
+                    let data = match parse_revision(query).unwrap() {
+                        0 => file.revisions.last(),
+                        n => file.revisions.get(n - 1),
+                    };
+                    let Some(data) = data else {
+                        return xml_error(StatusCode::NOT_FOUND, "NoSuchFileException");
+                    };
+
+                    let Some((start, end)) = parse_range(req, data.len() as u64) else {
+                        return xml_error(
+                            StatusCode::RANGE_NOT_SATISFIABLE,
+                            "RequestedRangeNotSatisfiedException",
+                        );
+                    };
+
+                    #[allow(clippy::cast_possible_truncation)]
+                    let body = data.slice(start as usize..end as usize);
+
+                    Response::new(body.into())
+                }
+                _ => xml_error(StatusCode::NOT_FOUND, "NoSuchFileException"),
+            }
+        }
+        (&Method::GET, "") => match state.nodes.get(path) {
+            Some(Node::Folder) if !path.contains('/') => {
+                xml(StatusCode::OK, device_xml(&state, path))
+            }
+            Some(Node::Folder) => xml(StatusCode::OK, folder_xml(&state, path)),
+            Some(Node::File(file)) => xml(StatusCode::OK, file_xml(path, file)),
+            None => xml_error(StatusCode::NOT_FOUND, "NoSuchPathException"),
+        },
+        (&Method::GET, query) if parse_page(query).is_some() => match state.nodes.get(path) {
+            Some(Node::Folder) => xml(
+                StatusCode::OK,
+                folder_page_xml(&state, path, parse_page(query)),
+            ),
+            _ => xml_error(StatusCode::NOT_FOUND, "NoSuchPathException"),
+        },
+        _ => xml_error(StatusCode::BAD_REQUEST, "InvalidArgumentException"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AllocReq {
+    path: String,
+    bytes: u64,
+    md5: String,
+    conflict_handler: String,
+}
+
+async fn allocate(state: &Mutex<State>, req: Request<Body>) -> Response<Body> {
+    let host = req.headers()[header::HOST].to_str().unwrap().to_owned();
+    let body = to_bytes(req.into_body()).await.unwrap();
+    let req: AllocReq = serde_json::from_slice(&body).unwrap();
+
+    // allocation paths are relative to the device
+    let path = format!("Jotta/{}", req.path.trim_start_matches('/'));
+    let mut md5 = [0; 16];
+    hex::decode_to_slice(&req.md5, &mut md5).unwrap();
+    let md5 = md5::Digest(md5);
+
+    let mut state = state.lock().unwrap();
+
+    let n = state.allocations_seen;
+    state.allocations_seen += 1;
+
+    if state.failing_allocations.contains(&n) {
+        return json(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &serde_json::json!({
+                "code": 503,
+                "message": "Service Unavailable",
+                "cause": null,
+                "error_id": null,
+                "x-id": null,
+            }),
+        );
+    }
+
+    let existing = match state.nodes.get(&path) {
+        Some(Node::File(file)) if file.corrupt => None,
+        Some(Node::File(file)) => file.revisions.last().map(md5::compute),
+        Some(Node::Folder) => return json_error(StatusCode::CONFLICT, "UniqueFileException"),
+        None => None,
+    };
+
+    if existing.is_some() && req.conflict_handler == "REJECT_CONFLICTS" {
+        return json_error(StatusCode::CONFLICT, "UniqueFileException");
+    }
+
+    // partial uploads are resumed
+    let resumed = state
+        .allocations
+        .iter()
+        .find(|(_, a)| a.path == path && a.md5 == md5 && !a.received.is_empty())
+        .map(|(&id, a)| (id, a.received.len()));
+
+    let (id, resume_pos) = resumed.unwrap_or_else(|| {
+        let id = state.next_id;
+        state.next_id += 1;
+        state.allocations.insert(
+            id,
+            Allocation {
+                path: path.clone(),
+                md5,
+                bytes: req.bytes,
+                received: Vec::new(),
+            },
+        );
+        (id, 0)
+    });
+
+    let completed = existing == Some(md5);
+
+    json(
+        StatusCode::OK,
+        &serde_json::json!({
+            "name": path.rsplit('/').next().unwrap(),
+            "path": req.path,
+            "state": if completed { "COMPLETED" } else { "INCOMPLETE" },
+            "upload_id": id.to_string(),
+            "upload_url": format!("http://{host}/upload/{id}"),
+            "bytes": req.bytes,
+            "resume_pos": resume_pos,
+        }),
+    )
+}
+
+async fn upload(state: &Mutex<State>, req: Request<Body>, id: u64) -> Response<Body> {
+    let (start, _) = parse_range(&req, u64::MAX).unwrap();
+    let body = to_bytes(req.into_body()).await.unwrap();
+    let mut state = state.lock().unwrap();
+    let max = state.max_upload_request.unwrap_or(usize::MAX);
+
+    state.upload_requests.push((start, body.len()));
+
+    let Some(alloc) = state.allocations.get_mut(&id) else {
+        return json_error(StatusCode::NOT_FOUND, "NoSuchFileException");
+    };
+
+    if start != alloc.received.len() as u64 {
+        return json_error(StatusCode::BAD_REQUEST, "InvalidArgumentException");
+    }
+
+    alloc
+        .received
+        .extend_from_slice(&body[..body.len().min(max)]);
+
+    if (alloc.received.len() as u64) < alloc.bytes {
+        return json_error(
+            StatusCode::from_u16(420).unwrap(),
+            "IncompleteUploadOpenApiException",
+        );
+    }
+
+    let Allocation {
+        path,
+        md5,
+        received,
+        ..
+    } = state.allocations.remove(&id).unwrap();
+    let mut body = Bytes::from(received);
+
+    if md5::compute(&body) != md5 {
+        return json_error(StatusCode::BAD_REQUEST, "CorruptUploadOpenApiException");
+    }
+
+    if state.truncated_uploads > 0 && !body.is_empty() {
+        state.truncated_uploads -= 1;
+        body.truncate(body.len() - 1);
+    }
+
+    state.create_parents(&path);
+    state.uploads.push(path.clone());
+
+    match state.nodes.entry(path.clone()).or_insert(Node::File(File {
+        revisions: vec![],
+        corrupt: false,
+    })) {
+        Node::File(file) => {
+            file.revisions.push(body.clone());
+            file.corrupt = false;
+        }
+        Node::Folder => return json_error(StatusCode::CONFLICT, "UniqueFileException"),
+    }
+
+    json(
+        StatusCode::CREATED,
+        &serde_json::json!({
+            "md5": format!("{:x}", md5::compute(&body)),
+            "bytes": body.len(),
+            "content_id": uuid(id),
+            "path": path,
+            "modified": 0,
+        }),
+    )
+}
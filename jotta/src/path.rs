@@ -1,24 +1,152 @@
 //! Jottacloud paths.
-use std::ops::Deref;
+use std::{fmt, ops::Deref, str::FromStr};
 
 use derive_more::Display;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
+/// The `Archive` mount point, for files that are only stored in the cloud.
+pub const ARCHIVE: &str = "Archive";
+
+/// The `Sync` mount point, mirrored by the official desktop client.
+pub const SYNC: &str = "Sync";
+
+/// The `Shared` mount point, containing folders shared with others.
+pub const SHARED: &str = "Shared";
+
 /// Path to a file or folder in Jottacloud, without specifying
 /// on what device.
 ///
 /// `<mount point>/...`
-#[derive(Debug, Serialize, Deserialize, Display)]
+///
+/// A path to the mount point itself is displayed without a trailing
+/// slash, so that it's addressed the same way as everything else:
+///
+/// ```
+/// use jotta::path::PathOnDevice;
+///
+/// assert_eq!(PathOnDevice("Archive/".into()).to_string(), "Archive");
+/// assert_eq!(PathOnDevice("Archive/foo".into()).to_string(), "Archive/foo");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(clippy::module_name_repetitions)]
 pub struct PathOnDevice(pub String);
 
+impl PathOnDevice {
+    /// Path to something in a mount point. An empty `path` refers to the
+    /// mount point itself.
+    ///
+    /// ```
+    /// use jotta::path::{PathOnDevice, SYNC};
+    ///
+    /// assert_eq!(PathOnDevice::new(SYNC, "foo/bar").0, "Sync/foo/bar");
+    /// assert_eq!(PathOnDevice::new(SYNC, "").0, "Sync");
+    /// ```
+    #[must_use]
+    pub fn new(mount_point: &str, path: &str) -> Self {
+        if path.is_empty() {
+            Self(mount_point.into())
+        } else {
+            Self(format!("{mount_point}/{path}"))
+        }
+    }
+
+    /// The mount point part of the path.
+    ///
+    /// ```
+    /// use jotta::path::PathOnDevice;
+    ///
+    /// assert_eq!(PathOnDevice("Sync/foo".into()).mount_point(), "Sync");
+    /// ```
+    #[must_use]
+    pub fn mount_point(&self) -> &str {
+        self.0
+            .split_once('/')
+            .map_or(&self.0, |(mount_point, _)| mount_point)
+    }
+
+    /// Prepend a device to the path.
+    ///
+    /// ```
+    /// use jotta::path::{PathOnDevice, ARCHIVE};
+    ///
+    /// let path = PathOnDevice::new(ARCHIVE, "foo");
+    ///
+    /// assert_eq!(path.on_device("Jotta").0, "Jotta/Archive/foo");
+    /// ```
+    #[must_use]
+    pub fn on_device(&self, device: &str) -> UserScopedPath {
+        UserScopedPath(format!("{device}/{self}"))
+    }
+}
+
+impl fmt::Display for PathOnDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.trim_end_matches('/'))
+    }
+}
+
+impl FromStr for PathOnDevice {
+    type Err = ParsePathError;
+
+    /// Parse a path, ignoring any trailing slash.
+    ///
+    /// ```
+    /// use jotta::path::PathOnDevice;
+    ///
+    /// assert_eq!("Sync/foo".parse::<PathOnDevice>().unwrap().0, "Sync/foo");
+    /// assert_eq!("Sync/".parse::<PathOnDevice>().unwrap().0, "Sync");
+    /// assert!("Sync//foo".parse::<PathOnDevice>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_suffix('/').unwrap_or(s);
+
+        if s.is_empty() {
+            return Err(ParsePathError::Empty);
+        }
+
+        if s.split('/').any(str::is_empty) {
+            return Err(ParsePathError::EmptySegment);
+        }
+
+        Ok(Self(s.into()))
+    }
+}
+
+/// Invalid path.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParsePathError {
+    /// The path is empty.
+    #[error("path is empty")]
+    Empty,
+    /// The path contains an empty segment, such as `a//b`.
+    #[error("path contains an empty segment")]
+    EmptySegment,
+}
+
 /// A path without the user part:
 ///
 /// `<device>/...`
-#[derive(Debug, Serialize, Deserialize, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Display)]
 #[allow(clippy::module_name_repetitions)]
 pub struct UserScopedPath(pub String);
 
+impl UserScopedPath {
+    /// Prepend a user to the path.
+    ///
+    /// ```
+    /// use jotta::path::UserScopedPath;
+    ///
+    /// let path = UserScopedPath("Jotta/Archive/foo".into());
+    ///
+    /// assert_eq!(path.to_absolute("alice").0, "/alice/Jotta/Archive/foo");
+    /// ```
+    #[must_use]
+    pub fn to_absolute(&self, username: &str) -> AbsolutePath {
+        AbsolutePath(format!("/{username}/{}", self.0))
+    }
+}
+
 impl Deref for UserScopedPath {
     type Target = str;
 
@@ -27,9 +155,182 @@ impl Deref for UserScopedPath {
     }
 }
 
+impl From<AbsolutePath> for UserScopedPath {
+    /// Strip the user, and any trailing slash, from an absolute path.
+    fn from(path: AbsolutePath) -> Self {
+        let path = path.0.trim_start_matches('/').trim_end_matches('/');
+
+        Self(
+            path.split_once('/')
+                .map_or_else(String::new, |(_, rest)| rest.to_owned()),
+        )
+    }
+}
+
 /// An absolute path:
 ///
 /// `<user>/<device>/...`
-#[derive(Debug, Serialize, Deserialize, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Display)]
 #[allow(clippy::module_name_repetitions)]
 pub struct AbsolutePath(pub String);
+
+impl AbsolutePath {
+    /// The user part of the path.
+    ///
+    /// ```
+    /// use jotta::path::AbsolutePath;
+    ///
+    /// assert_eq!(AbsolutePath("/alice/Jotta/Archive".into()).user(), "alice");
+    /// ```
+    #[must_use]
+    pub fn user(&self) -> &str {
+        let path = self.0.trim_start_matches('/');
+
+        path.split_once('/').map_or(path, |(user, _)| user)
+    }
+}
+
+/// Append a slash-separated `path` to `url`. Every segment is
+/// percent-encoded, so characters like `#`, `?` and `%` stay in the path
+/// instead of ending up in the query or fragment.
+///
+/// # Errors
+///
+/// [`Error::InvalidArgument`](crate::Error::InvalidArgument) if a segment
+/// is empty, `.` or `..`, since these can't be sent as is.
+pub(crate) fn push_path(url: &mut Url, path: &str) -> crate::Result<()> {
+    let mut segments = url
+        .path_segments_mut()
+        .map_err(|()| crate::Error::InvalidArgument)?;
+
+    segments.pop_if_empty();
+
+    if path.is_empty() {
+        return Ok(());
+    }
+
+    for segment in path.split('/') {
+        if matches!(segment, "" | "." | "..") {
+            return Err(crate::Error::InvalidArgument);
+        }
+
+        segments.push(segment);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::{push_path, AbsolutePath, ParsePathError, PathOnDevice, UserScopedPath};
+    use crate::Error;
+
+    #[test]
+    fn absolute_and_user_scoped() {
+        let cases = [
+            (
+                "/alice/Jotta/Archive/root/bucket",
+                "Jotta/Archive/root/bucket",
+            ),
+            ("/alice/Jotta/Archive", "Jotta/Archive"),
+            ("/alice/Jotta", "Jotta"),
+        ];
+
+        for (absolute, user_scoped) in cases {
+            let absolute = AbsolutePath(absolute.into());
+            let user_scoped = UserScopedPath(user_scoped.into());
+
+            assert_eq!(absolute.user(), "alice");
+            assert_eq!(UserScopedPath::from(absolute.clone()), user_scoped);
+            assert_eq!(user_scoped.to_absolute("alice"), absolute);
+        }
+
+        // without a leading slash
+        let absolute = AbsolutePath("alice/Jotta/Archive".into());
+        assert_eq!(absolute.user(), "alice");
+        assert_eq!(UserScopedPath::from(absolute).0, "Jotta/Archive");
+
+        // only a user
+        let absolute = AbsolutePath("/alice".into());
+        assert_eq!(absolute.user(), "alice");
+        assert_eq!(UserScopedPath::from(absolute).0, "");
+
+        // a mount point, with a trailing slash
+        let absolute = AbsolutePath("/alice/Jotta/Archive/".into());
+        assert_eq!(absolute.user(), "alice");
+        assert_eq!(UserScopedPath::from(absolute).0, "Jotta/Archive");
+    }
+
+    #[test]
+    fn path_on_device_round_trip() {
+        for path in ["Archive", "Archive/foo", "Sync/foo/bar.txt", "Shared/a b"] {
+            let parsed: PathOnDevice = path.parse().unwrap();
+
+            assert_eq!(parsed.to_string(), path);
+            assert_eq!(parsed.to_string().parse::<PathOnDevice>().unwrap(), parsed);
+        }
+
+        // only a mount point
+        let parsed: PathOnDevice = "Archive/".parse().unwrap();
+        assert_eq!(parsed, PathOnDevice::new("Archive", ""));
+        assert_eq!(parsed.to_string(), "Archive");
+        assert_eq!(parsed.on_device("Jotta").0, "Jotta/Archive");
+
+        // constructed by hand
+        let path = PathOnDevice("Archive/".into());
+        assert_eq!(path.to_string(), "Archive");
+        assert_eq!(path.on_device("Jotta").0, "Jotta/Archive");
+
+        for (path, err) in [
+            ("", ParsePathError::Empty),
+            ("/", ParsePathError::Empty),
+            ("/Archive", ParsePathError::EmptySegment),
+            ("Archive//foo", ParsePathError::EmptySegment),
+            ("Archive//", ParsePathError::EmptySegment),
+        ] {
+            assert_eq!(path.parse::<PathOnDevice>().unwrap_err(), err, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn special_characters() {
+        let base = Url::parse("https://jfs.jottacloud.com/jfs/").unwrap();
+
+        let cases = [
+            ("Jotta/Archive/a b", "/jfs/alice/Jotta/Archive/a%20b"),
+            ("Jotta/Archive/100%", "/jfs/alice/Jotta/Archive/100%25"),
+            ("Jotta/Archive/#1", "/jfs/alice/Jotta/Archive/%231"),
+            ("Jotta/Archive/a+b", "/jfs/alice/Jotta/Archive/a+b"),
+            ("Jotta/Archive/why?", "/jfs/alice/Jotta/Archive/why%3F"),
+        ];
+
+        for (path, expected) in cases {
+            let mut url = base.clone();
+            push_path(&mut url, "alice").unwrap();
+            push_path(&mut url, path).unwrap();
+
+            assert_eq!(url.path(), expected);
+            assert_eq!(url.query(), None);
+            assert_eq!(url.fragment(), None);
+        }
+    }
+
+    #[test]
+    fn unrepresentable_paths() {
+        let base = Url::parse("https://jfs.jottacloud.com/jfs/").unwrap();
+
+        for path in [
+            "Jotta//Archive",
+            "Jotta/Archive/..",
+            "Jotta/./Archive",
+            "/Jotta",
+        ] {
+            assert!(matches!(
+                push_path(&mut base.clone(), path),
+                Err(Error::InvalidArgument)
+            ));
+        }
+    }
+}
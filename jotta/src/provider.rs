@@ -0,0 +1,55 @@
+//! Jottacloud and its whitelabel resellers.
+//!
+//! Resellers such as Tele2 Cloud run on the Jottacloud platform but
+//! authenticate users differently. A [`Provider`] describes the endpoints
+//! of one such service, so new resellers can be supported by constructing
+//! a [`Provider`] rather than patching this crate.
+#![allow(clippy::doc_markdown)]
+
+use std::borrow::Cow;
+
+/// Endpoints and client credentials of a Jottacloud-based service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provider {
+    /// Human-readable name of the provider.
+    pub name: Cow<'static, str>,
+    /// OAuth2 token url.
+    pub token_url: Cow<'static, str>,
+    /// OAuth2 client id.
+    pub client_id: Cow<'static, str>,
+    /// Base url of the JFS, with a trailing slash.
+    pub jfs_base: Cow<'static, str>,
+    /// Base url of the files API, with a trailing slash.
+    pub files_base: Cow<'static, str>,
+    /// Base url of the API used by [`LegacyAuth`](crate::auth::LegacyAuth),
+    /// with a trailing slash.
+    pub legacy_auth_base: Cow<'static, str>,
+}
+
+/// Vanilla Jottacloud.
+pub static JOTTACLOUD: Provider = Provider {
+    name: Cow::Borrowed("Jottacloud"),
+    token_url: Cow::Borrowed(
+        "https://id.jottacloud.com/auth/realms/jottacloud/protocol/openid-connect/token",
+    ),
+    client_id: Cow::Borrowed("jottacli"),
+    jfs_base: Cow::Borrowed("https://jfs.jottacloud.com/jfs/"),
+    files_base: Cow::Borrowed("https://api.jottacloud.com/files/v1/"),
+    legacy_auth_base: Cow::Borrowed("https://api.jottacloud.com/auth/v1/"),
+};
+
+/// Tele2 Cloud (formerly ComHem Cloud).
+pub static TELE2: Provider = Provider {
+    name: Cow::Borrowed("Tele2 Cloud"),
+    token_url: Cow::Borrowed(crate::auth::TELE2_TOKEN_URL),
+    client_id: Cow::Borrowed("desktop"),
+    jfs_base: Cow::Borrowed("https://jfs.jottacloud.com/jfs/"),
+    files_base: Cow::Borrowed("https://api.jottacloud.com/files/v1/"),
+    legacy_auth_base: Cow::Borrowed("https://api.jottacloud.com/auth/v1/"),
+};
+
+impl Default for Provider {
+    fn default() -> Self {
+        JOTTACLOUD.clone()
+    }
+}
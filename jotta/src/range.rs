@@ -1,6 +1,7 @@
 //! Ranges of bytes.
 use std::{
     fmt::Debug,
+    iter,
     ops::{Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive},
 };
 
@@ -39,7 +40,7 @@ pub trait ByteRange: Debug {
     /// assert!(ClosedByteRange::new(5, 0).is_empty());
     /// ```
     fn is_empty(&self) -> bool {
-        self.len().map_or(false, |len| len == 0)
+        self.len() == Some(0)
     }
 
     /// Format a single "segment" of a HTTP `Range` header.
@@ -62,6 +63,11 @@ pub trait ByteRange: Debug {
 
     /// Format a [HTTP `Range` header](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Range).
     ///
+    /// ```
+    /// use jotta::range::{ByteRange, SuffixByteRange};
+    ///
+    /// assert_eq!(SuffixByteRange::new(500).to_http(), "bytes=-500");
+    /// ```
     fn to_http(&self) -> HeaderValue {
         let s = format!("bytes={}", self.to_http_range());
         HeaderValue::from_str(&s).unwrap()
@@ -202,6 +208,96 @@ impl ClosedByteRange {
     pub fn end(&self) -> u64 {
         self.start + self.len - 1
     }
+
+    /// Does the range include `offset`?
+    ///
+    /// ```
+    /// use jotta::range::ClosedByteRange;
+    ///
+    /// let range = ClosedByteRange::try_from_bounds(5, 20).unwrap();
+    ///
+    /// assert!(range.contains(5));
+    /// assert!(range.contains(20));
+    /// assert!(!range.contains(21));
+    /// assert!(!ClosedByteRange::new(5, 0).contains(5));
+    /// ```
+    #[must_use]
+    pub fn contains(&self, offset: u64) -> bool {
+        offset >= self.start && offset - self.start < self.len
+    }
+
+    /// The bytes included in both ranges, or `None` if there are none.
+    ///
+    /// ```
+    /// use jotta::range::ClosedByteRange;
+    ///
+    /// let a = ClosedByteRange::try_from_bounds(0, 9).unwrap();
+    /// let b = ClosedByteRange::try_from_bounds(5, 20).unwrap();
+    ///
+    /// assert_eq!(a.intersect(b), ClosedByteRange::try_from_bounds(5, 9).ok());
+    /// assert_eq!(a.intersect(ClosedByteRange::new(10, 5)), None);
+    /// assert_eq!(a.intersect(ClosedByteRange::new(3, 0)), None);
+    /// ```
+    #[must_use]
+    pub fn intersect(&self, other: ClosedByteRange) -> Option<ClosedByteRange> {
+        let start = self.start.max(other.start);
+        let end = self.end_exclusive().min(other.end_exclusive());
+
+        (start < end).then(|| Self::new(start, end - start))
+    }
+
+    /// Split the range where it crosses a multiple of `chunk_size`, so
+    /// that every part lies within a single chunk.
+    ///
+    /// ```
+    /// use jotta::range::ClosedByteRange;
+    ///
+    /// let parts = ClosedByteRange::try_from_bounds(5, 24)
+    ///     .unwrap()
+    ///     .split_at_chunk_boundaries(10)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert_eq!(
+    ///     parts,
+    ///     [
+    ///         ClosedByteRange::try_from_bounds(5, 9).unwrap(),
+    ///         ClosedByteRange::try_from_bounds(10, 19).unwrap(),
+    ///         ClosedByteRange::try_from_bounds(20, 24).unwrap(),
+    ///     ]
+    /// );
+    /// assert_eq!(ClosedByteRange::new(5, 0).split_at_chunk_boundaries(10).count(), 0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn split_at_chunk_boundaries(
+        &self,
+        chunk_size: u64,
+    ) -> impl Iterator<Item = ClosedByteRange> {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+
+        let end = self.end_exclusive();
+        let mut pos = self.start;
+
+        iter::from_fn(move || {
+            if pos >= end {
+                return None;
+            }
+
+            let boundary = (pos / chunk_size + 1).saturating_mul(chunk_size);
+            let part = Self::new(pos, boundary.min(end) - pos);
+
+            pos += part.len;
+
+            Some(part)
+        })
+    }
+
+    /// The first byte after the range, saturating at [`u64::MAX`].
+    fn end_exclusive(&self) -> u64 {
+        self.start.saturating_add(self.len)
+    }
 }
 
 impl ByteRange for ClosedByteRange {
@@ -214,14 +310,103 @@ impl ByteRange for ClosedByteRange {
     }
 }
 
+/// The last `len` bytes of a file, whatever its size. Useful for reading
+/// trailers of files with unknown sizes.
+///
+/// The first byte isn't known until the size of the file is, so
+/// [`ByteRange::start`] and [`ByteRange::end`] are meaningless. Only use
+/// this range for HTTP requests, or [`resolve`](SuffixByteRange::resolve)
+/// it first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SuffixByteRange {
+    len: u64,
+}
+
+impl SuffixByteRange {
+    /// Construct a range of the last `len` bytes.
+    #[must_use]
+    pub fn new(len: u64) -> Self {
+        Self { len }
+    }
+
+    /// Resolve the range for a file of `size` bytes. The whole file is
+    /// included if it's shorter than the suffix.
+    ///
+    /// ```
+    /// use jotta::range::{ClosedByteRange, SuffixByteRange};
+    ///
+    /// assert_eq!(SuffixByteRange::new(10).resolve(100), ClosedByteRange::new(90, 10));
+    /// assert_eq!(SuffixByteRange::new(10).resolve(4), ClosedByteRange::new(0, 4));
+    /// ```
+    #[must_use]
+    pub fn resolve(&self, size: u64) -> ClosedByteRange {
+        let len = self.len.min(size);
+
+        ClosedByteRange::new(size - len, len)
+    }
+}
+
+impl ByteRange for SuffixByteRange {
+    /// Unknown, so always zero.
+    fn start(&self) -> u64 {
+        0
+    }
+
+    /// Unknown.
+    fn end(&self) -> Option<u64> {
+        None
+    }
+
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    /// ```
+    /// use jotta::range::{ByteRange, SuffixByteRange};
+    ///
+    /// assert_eq!(SuffixByteRange::new(5).to_http_range(), "-5");
+    /// ```
+    fn to_http_range(&self) -> String {
+        format!("-{}", self.len)
+    }
+}
+
+/// An exclusive range may be empty, but not backwards.
+///
+/// ```
+/// use jotta::range::ClosedByteRange;
+///
+/// assert_eq!(ClosedByteRange::try_from(0..1).unwrap(), ClosedByteRange::new(0, 1));
+/// assert_eq!(ClosedByteRange::try_from(5..10).unwrap(), ClosedByteRange::new(5, 5));
+/// assert!(ClosedByteRange::try_from(0..0).unwrap().is_empty());
+/// assert!(ClosedByteRange::try_from(7..7).unwrap().is_empty());
+/// #[allow(clippy::reversed_empty_ranges)]
+/// let backwards = 10..5;
+/// assert!(ClosedByteRange::try_from(backwards).is_err());
+/// ```
 impl TryFrom<Range<u64>> for ClosedByteRange {
     type Error = InvalidRangeError;
 
     fn try_from(r: Range<u64>) -> Result<Self, Self::Error> {
-        Self::try_from_bounds(r.start, r.end - 1)
+        if r.start > r.end {
+            Err(InvalidRangeError::Backwards)
+        } else {
+            Ok(Self::new(r.start, r.end - r.start))
+        }
     }
 }
 
+/// An inclusive range always contains at least one byte, so `0..=0`
+/// is the first byte only.
+///
+/// ```
+/// use jotta::range::ClosedByteRange;
+///
+/// assert_eq!(ClosedByteRange::try_from(0..=0).unwrap(), ClosedByteRange::new(0, 1));
+/// assert_eq!(ClosedByteRange::try_from(0..=0).unwrap().end(), 0);
+/// assert_eq!(ClosedByteRange::try_from(0..=0).unwrap(), ClosedByteRange::try_from(0..1).unwrap());
+/// ```
 impl TryFrom<RangeInclusive<u64>> for ClosedByteRange {
     type Error = InvalidRangeError;
 
@@ -230,9 +415,15 @@ impl TryFrom<RangeInclusive<u64>> for ClosedByteRange {
     }
 }
 
+/// ```
+/// use jotta::range::ClosedByteRange;
+///
+/// assert_eq!(ClosedByteRange::from(..1), ClosedByteRange::from(..=0));
+/// assert!(ClosedByteRange::from(..0).is_empty());
+/// ```
 impl From<RangeTo<u64>> for ClosedByteRange {
     fn from(r: RangeTo<u64>) -> Self {
-        Self::new_to_including(r.end - 1)
+        Self::new(0, r.end)
     }
 }
 
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Deserializer, Serializer};
 use serde_with::{DeserializeAs, SerializeAs};
-use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime,
+    PrimitiveDateTime,
+};
 
 /// The folks at Jottacloud screwed up and added an extra dash in some of their dates:
 ///
@@ -17,6 +20,14 @@ fn parse_typo_datetime(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
     PrimitiveDateTime::parse(s, &format).map(PrimitiveDateTime::assume_utc) // as indicated by "Z"
 }
 
+/// Parse the typo format, falling back to RFC 3339 since Jottacloud
+/// sometimes gets it right. The error is that of the typo format.
+fn parse_lenient_datetime(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    parse_typo_datetime(s).or_else(|e| OffsetDateTime::parse(s, &Rfc3339).map_err(|_| e))
+}
+
+/// An optional date in either the typo format or RFC 3339. Empty strings
+/// are `None`.
 pub(crate) struct OptTypoDateTime;
 
 impl<'de> DeserializeAs<'de, Option<OffsetDateTime>> for OptTypoDateTime {
@@ -27,7 +38,7 @@ impl<'de> DeserializeAs<'de, Option<OffsetDateTime>> for OptTypoDateTime {
         let s = Option::<String>::deserialize(deserializer)?.filter(|s| !s.is_empty());
 
         match s {
-            Some(s) => parse_typo_datetime(&s)
+            Some(s) => parse_lenient_datetime(&s)
                 .map_err(serde::de::Error::custom)
                 .map(Some),
             None => Ok(None),
@@ -69,7 +80,7 @@ pub(crate) mod md5_hex {
         digest: &Digest,
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(&format!("{:x}", digest))
+        serializer.serialize_str(&format!("{digest:x}"))
     }
 
     pub(crate) fn hex_to_digest(str: &str) -> Result<Digest, FromHexError> {
@@ -88,9 +99,11 @@ pub(crate) mod md5_hex {
 
 #[cfg(test)]
 mod tests {
-    use time::macros::datetime;
+    use serde::Deserialize;
+    use serde_with::serde_as;
+    use time::{macros::datetime, OffsetDateTime};
 
-    use crate::serde::parse_typo_datetime;
+    use crate::serde::{parse_lenient_datetime, parse_typo_datetime, OptTypoDateTime};
 
     #[test]
     fn typo_datetime_parsing() {
@@ -101,4 +114,51 @@ mod tests {
 
         assert!(parse_typo_datetime("1970-11-04T23:58:11Z").is_err()); // missing dash before T
     }
+
+    #[test]
+    fn lenient_datetime_parsing() {
+        assert_eq!(
+            parse_lenient_datetime("2020-05-16-T10:46:05Z").unwrap(),
+            datetime!(2020-05-16 10:46:05 +00:00:00)
+        );
+        assert_eq!(
+            parse_lenient_datetime("2020-05-16T10:46:05Z").unwrap(),
+            datetime!(2020-05-16 10:46:05 +00:00:00)
+        );
+        assert_eq!(
+            parse_lenient_datetime("2020-05-16T12:46:05.5+02:00").unwrap(),
+            datetime!(2020-05-16 10:46:05.5 +00:00:00)
+        );
+
+        assert!(parse_lenient_datetime("2020-05-16").is_err());
+        assert!(parse_lenient_datetime("yesterday").is_err());
+    }
+
+    #[test]
+    fn opt_typo_datetime() {
+        #[serde_as]
+        #[derive(Deserialize)]
+        struct Wrapper {
+            #[serde_as(as = "OptTypoDateTime")]
+            modified: Option<OffsetDateTime>,
+        }
+
+        let parse = |json: &str| {
+            serde_json::from_str::<Wrapper>(json)
+                .map(|w| w.modified)
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            parse(r#"{"modified":"2020-05-16-T10:46:05Z"}"#),
+            Ok(Some(datetime!(2020-05-16 10:46:05 +00:00:00)))
+        );
+        assert_eq!(
+            parse(r#"{"modified":"2020-05-16T10:46:05Z"}"#),
+            Ok(Some(datetime!(2020-05-16 10:46:05 +00:00:00)))
+        );
+        assert_eq!(parse(r#"{"modified":""}"#), Ok(None));
+        assert_eq!(parse(r#"{"modified":null}"#), Ok(None));
+        assert!(parse(r#"{"modified":"soon"}"#).is_err());
+    }
 }